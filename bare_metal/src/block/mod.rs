@@ -1,16 +1,53 @@
+//! 块设备
+//!
+//! 同步接口直接读写扇区。异步接口 [`read_block`] 和 [`write_block`] 返回
+//! future：第一次 poll 时向驱动提交请求，驱动在中断处理函数中发现请求完成后
+//! 调用 [`Request::complete`]，通过 `interrupt_wake` 唤醒等待的任务。
+//!
+//! 目前只有 virtio-blk 真正异步。NVMe 用 `submit_read` 和 `submit_write` 的
+//! 默认实现，在提交时同步读写完再完成请求，等待期间不会让出处理器。
 mod virtio;
 mod nvme;
 
-use alloc::boxed::Box;
-use spin::Once;
+use alloc::sync::Arc;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+use futures_util::task::AtomicWaker;
+use riscv::register::sstatus;
 
-// use virtio as block driver
-// pub use virtio::init;
+use crate::{driver::{self, BlockDeviceRef}, sbi::shutdown, task::interrupt_wakeups::interrupt_wake, utils::memory::hexdump};
 
-// use nvme as block driver
-// pub use nvme::init;
+#[derive(Debug)]
+pub struct IoError;
 
-use crate::{mutex::Mutex, sbi::shutdown, utils::memory::hexdump};
+/// 一个异步请求，驱动在请求完成时调用 `complete`
+#[derive(Default)]
+pub struct Request {
+    done: AtomicBool,
+    failed: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl Request {
+    /// 标记完成并唤醒等待的任务，可以在中断处理函数中调用
+    pub fn complete(&self, ok: bool) {
+        self.failed.store(!ok, Ordering::Relaxed);
+        self.done.store(true, Ordering::Release);
+        if let Some(waker) = self.waker.take() {
+            interrupt_wake(waker);
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+
+    fn failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+}
 
 /// 定义trait
 pub trait BlockDevice {
@@ -18,23 +55,148 @@ pub trait BlockDevice {
     fn read_block(&mut self, sector_offset: usize, buf: &mut [u8]);
     // 写入扇区
     fn write_block(&mut self, sector_offset: usize, buf: &[u8]);
+    // 从 sector_offset 开始读取连续的扇区，依次填满每个缓冲区，缓冲区长度为扇区大小的整数倍
+    fn read_blocks(&mut self, sector_offset: usize, bufs: &mut [&mut [u8]]) {
+        let mut sector = sector_offset;
+        for buf in bufs.iter_mut() {
+            for block in buf.chunks_mut(512) {
+                self.read_block(sector, block);
+                sector += 1;
+            }
+        }
+    }
+    // 把缓冲区依次写入从 sector_offset 开始的连续扇区
+    fn write_blocks(&mut self, sector_offset: usize, bufs: &[&[u8]]) {
+        let mut sector = sector_offset;
+        for buf in bufs.iter() {
+            for block in buf.chunks(512) {
+                self.write_block(sector, block);
+                sector += 1;
+            }
+        }
+    }
+    // 提交读请求后立即返回，请求完成时调用 request.complete()，一般在中断处理函数中。
+    // 队列已满时返回 false。没有异步接口的驱动同步读取后直接完成。
+    // 调用者保证请求完成之前 buf 一直有效
+    unsafe fn submit_read(&mut self, sector_offset: usize, buf: &mut [u8], request: Arc<Request>) -> bool {
+        self.read_block(sector_offset, buf);
+        request.complete(true);
+        true
+    }
+    // 提交写请求，与 submit_read 相同
+    unsafe fn submit_write(&mut self, sector_offset: usize, buf: &[u8], request: Arc<Request>) -> bool {
+        self.write_block(sector_offset, buf);
+        request.complete(true);
+        true
+    }
     // 处理中断
     fn handle_irq(&mut self);
 }
 
-pub static mut DEVICE: Once<Mutex<Box<dyn BlockDevice>>> = Once::new();
+/// 关中断执行 `f`。中断处理函数也要锁设备，持有设备锁时不能进入中断
+pub fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    let enabled = sstatus::read().sie();
+    unsafe { sstatus::clear_sie() };
+    let result = f();
+    if enabled {
+        unsafe { sstatus::set_sie() };
+    }
+    result
+}
+
+enum Buffer<'a> {
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+}
+
+/// 异步读写一个扇区的 future
+pub struct BlockFuture<'a> {
+    device: BlockDeviceRef,
+    sector_offset: usize,
+    buf: Buffer<'a>,
+    request: Option<Arc<Request>>,
+}
+
+impl Future for BlockFuture<'_> {
+    type Output = Result<(), IoError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let request = match &this.request {
+            Some(request) => request.clone(),
+            None => {
+                let request = Arc::new(Request::default());
+                // 先登记 waker，请求可能在提交时就完成了
+                request.waker.register(cx.waker());
+                let device = &this.device;
+                let sector_offset = this.sector_offset;
+                let submitted = without_interrupts(|| {
+                    let mut device = device.lock();
+                    unsafe {
+                        match &mut this.buf {
+                            Buffer::Read(buf) => device.submit_read(sector_offset, buf, request.clone()),
+                            Buffer::Write(buf) => device.submit_write(sector_offset, buf, request.clone()),
+                        }
+                    }
+                });
+                if !submitted {
+                    // 队列满了，稍后重试
+                    interrupt_wake(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                this.request = Some(request.clone());
+                request
+            }
+        };
+        request.waker.register(cx.waker());
+        if !request.is_done() {
+            return Poll::Pending;
+        }
+        this.request = None;
+        if request.failed() {
+            Poll::Ready(Err(IoError))
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+impl Drop for BlockFuture<'_> {
+    /// 设备还可能访问缓冲区，等请求完成后才能释放
+    fn drop(&mut self) {
+        if let Some(request) = &self.request {
+            while !request.is_done() {
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+/// 异步读取一个扇区
+pub fn read_block<'a>(device: &BlockDeviceRef, sector_offset: usize, buf: &'a mut [u8]) -> BlockFuture<'a> {
+    BlockFuture { device: device.clone(), sector_offset, buf: Buffer::Read(buf), request: None }
+}
 
-// pub static mut DEVICE1: Once<Mutex<Box<dyn BlockDevice>>> = Once::new();
+/// 异步写入一个扇区
+#[allow(unused)]
+pub fn write_block<'a>(device: &BlockDeviceRef, sector_offset: usize, buf: &'a [u8]) -> BlockFuture<'a> {
+    BlockFuture { device: device.clone(), sector_offset, buf: Buffer::Write(buf), request: None }
+}
+
+/// 登记块设备驱动，遍历设备树和 PCI 总线之前调用
+pub fn register() {
+    driver::register(&virtio::DRIVER);
+    driver::register(&nvme::DRIVER);
+}
 
 pub fn init() {
-    // virtio::init();
-    nvme::init();
 
     // println!("start for compare");
 
     let mut arr = [0u8; 512];
     // let mut arr1 = [0u8; 512];
-    let mut device = unsafe{DEVICE.get()}.unwrap().lock();
+    let device = driver::block(0).expect("no block device");
+    let mut device = device.lock();
 
     device.read_block(0, &mut arr);
 
@@ -1,4 +1,10 @@
-use alloc::boxed::Box;
+//! NVMe 块设备，封装 `nvme_driver`
+//!
+//! `nvme_driver` 只提供阻塞的读写：提交命令后轮询完成队列，再更新队头门铃。
+//! 因此这里不用中断：`IrqController` 的两个方法什么也不做，驱动表在 PLIC 上
+//! 登记的中断线只会走到空的 `handle_irq`。要像 os 里的 NVMe 驱动那样由中断
+//! 完成请求，需要 `nvme_driver` 开放提交和完成队列，bare_metal 暂不处理。
+use alloc::{boxed::Box, sync::Arc};
 use core::sync::atomic::{AtomicUsize, Ordering};
 use nvme_driver::{DmaAllocator, IrqController, NvmeInterface};
 
@@ -56,42 +62,32 @@ impl BlockDevice for VirtIOBlock {
         self.0.write_block(sector_offset, buf)
     }
 
+    // nvme_driver 只有阻塞的读写接口，异步请求用默认实现，提交时同步完成
+
     fn handle_irq(&mut self) {
-        todo!()
+        // nvme_driver 读写时轮询完成队列并更新队头门铃，中断随之撤销
     }
 }
 
-use core::ptr::write_volatile;
-
-use crate::mutex::Mutex;
+use crate::{driver::{Device, Driver, Match, Resources}, mutex::Mutex};
 
-use super::{BlockDevice, DEVICE};
+use super::BlockDevice;
 
-// config pci
-pub fn config_pci(){
-    let ptr = 0x30008010 as *mut u32;
-    unsafe { write_volatile(ptr, 0xffffffff); }
-    let ptr = 0x30008010 as *mut u32;
-    unsafe { write_volatile(ptr, 0x4); }
-    let ptr = 0x30008010 as *mut u32;
-    unsafe { write_volatile(ptr, 0x40000000); }
-    let ptr = 0x30008004 as *mut u32;
-    unsafe { write_volatile(ptr, 0x100006); }
-    let ptr = 0x3000803c as *mut u32;
-    unsafe { write_volatile(ptr, 0x21); }
-}
+pub static DRIVER: Driver = Driver {
+    name: "nvme",
+    // 大容量存储类，非易失性存储控制器
+    matches: &[Match::PciClass(0x01, 0x08)],
+    probe,
+};
 
-pub fn init() {
-    // 初始化 pci
-    config_pci();
-
-    unsafe {
-        // 创建存储设备
-        DEVICE.call_once(|| {
-            let device = Box::new(VirtIOBlock(
-                NvmeInterface::<DmaAllocatorImpl, IrqControllerImpl>::new(0x40000000)
-            ));
-            Mutex::new(device)
-        });
+fn probe(resources: &Resources) -> Option<Device> {
+    if resources.base == 0 {
+        println!("nvme controller without registers");
+        return None;
     }
-}
\ No newline at end of file
+    // 创建存储设备
+    let device: Box<dyn BlockDevice> = Box::new(VirtIOBlock(
+        NvmeInterface::<DmaAllocatorImpl, IrqControllerImpl>::new(resources.base)
+    ));
+    Some(Device::Block(Arc::new(Mutex::new(device))))
+}
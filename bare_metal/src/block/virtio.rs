@@ -1,11 +1,11 @@
-use alloc::{boxed::Box};
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc};
 use core::ptr::NonNull;
 use core::sync::atomic::*;
 use lazy_static::lazy_static;
-use virtio_drivers::{Hal, MmioTransport, PhysAddr, VirtAddr, VirtIOBlk, VirtIOHeader};
-use crate::mutex::Mutex;
+use virtio_drivers::{BlkResp, DeviceType, Hal, MmioTransport, PhysAddr, RespStatus, VirtAddr, VirtIOBlk, VirtIOHeader};
+use crate::{driver::{Device, Driver, Match, Resources}, mutex::Mutex};
 
-use super::{DEVICE, BlockDevice};
+use super::{BlockDevice, Request};
 
 extern "C" {
     fn end();
@@ -38,30 +38,88 @@ impl Hal for HalImpl {
     }
 }
 
-pub struct VirtIOBlock(pub VirtIOBlk::<HalImpl, MmioTransport>);
+/// 设备和队列中的请求，请求按 virtqueue 返回的标识记录，响应放在堆上，
+/// 地址在请求完成前不变
+pub struct VirtIOBlock {
+    device: VirtIOBlk::<HalImpl, MmioTransport>,
+    pending: BTreeMap<u16, (Box<BlkResp>, Arc<Request>)>,
+}
+
+impl VirtIOBlock {
+    /// 完成设备已经处理完的请求
+    fn complete_used(&mut self) {
+        while let Ok(token) = self.device.pop_used() {
+            if let Some((resp, request)) = self.pending.remove(&token) {
+                request.complete(resp.status() == RespStatus::Ok);
+            }
+        }
+    }
+
+    /// 同步请求也走异步接口，免得它取走异步请求的完成记录
+    fn wait(&mut self, request: Arc<Request>) {
+        while !request.is_done() {
+            self.complete_used();
+            core::hint::spin_loop();
+        }
+        assert!(!request.failed(), "virtio-blk request failed");
+    }
+}
 
 impl BlockDevice for VirtIOBlock {
     fn read_block(&mut self, sector_offset: usize, buf: &mut [u8]) {
-        self.0.read_block(sector_offset, buf).expect("read error");
+        let request = Arc::new(Request::default());
+        while !unsafe { self.submit_read(sector_offset, buf, request.clone()) } {
+            self.complete_used();
+        }
+        self.wait(request);
     }
 
     fn write_block(&mut self, sector_offset: usize, buf: &[u8]) {
-        self.0.write_block(sector_offset, buf).expect("write error");
+        let request = Arc::new(Request::default());
+        while !unsafe { self.submit_write(sector_offset, buf, request.clone()) } {
+            self.complete_used();
+        }
+        self.wait(request);
+    }
+
+    unsafe fn submit_read(&mut self, sector_offset: usize, buf: &mut [u8], request: Arc<Request>) -> bool {
+        let mut resp = Box::new(BlkResp::default());
+        match self.device.read_block_nb(sector_offset, buf, &mut resp) {
+            Ok(token) => {
+                self.pending.insert(token, (resp, request));
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    unsafe fn submit_write(&mut self, sector_offset: usize, buf: &[u8], request: Arc<Request>) -> bool {
+        let mut resp = Box::new(BlkResp::default());
+        match self.device.write_block_nb(sector_offset, buf, &mut resp) {
+            Ok(token) => {
+                self.pending.insert(token, (resp, request));
+                true
+            }
+            Err(_) => false,
+        }
     }
 
     fn handle_irq(&mut self) {
-        todo!()
+        self.device.ack_interrupt();
+        self.complete_used();
     }
 }
 
-pub fn init() {
-    unsafe {
-        DEVICE.call_once(|| {
-            let header = NonNull::new(0x10001000 as *mut VirtIOHeader).unwrap();
-            let transport = unsafe { MmioTransport::new(header) }.unwrap();
-            let device = VirtIOBlk::<HalImpl, MmioTransport>::new(transport)
-                .expect("failed to create blk driver");
-            Mutex::new(Box::new(VirtIOBlock(device)))
-        });
-    }
-}
\ No newline at end of file
+pub static DRIVER: Driver = Driver {
+    name: "virtio-blk",
+    matches: &[Match::Virtio(DeviceType::Block)],
+    probe,
+};
+
+fn probe(resources: &Resources) -> Option<Device> {
+    let header = NonNull::new(resources.base as *mut VirtIOHeader)?;
+    let transport = unsafe { MmioTransport::new(header) }.ok()?;
+    let device = VirtIOBlk::<HalImpl, MmioTransport>::new(transport).ok()?;
+    let device: Box<dyn BlockDevice> = Box::new(VirtIOBlock { device, pending: BTreeMap::new() });
+    Some(Device::Block(Arc::new(Mutex::new(device))))
+}
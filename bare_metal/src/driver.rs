@@ -0,0 +1,125 @@
+//! 驱动注册表
+//!
+//! 驱动登记自己能驱动的设备：设备树的 compatible 字符串、virtio 设备类型
+//! 或者 PCI 类别。遍历设备树和 PCI 总线时，找到的设备交给第一个匹配的驱动
+//! 探测，探测成功的设备按类别记录下来，其余代码按类别和序号查找设备。
+//! 有中断号的设备在 PLIC 上登记，中断交给设备的 `handle_irq`。
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec::Vec};
+use core::ptr::NonNull;
+use virtio_drivers::{DeviceType, MmioTransport, Transport, VirtIOHeader};
+
+use crate::{block::BlockDevice, mutex::Mutex, pci, plic};
+
+/// 驱动能驱动的设备
+pub enum Match {
+    /// 设备树节点的 compatible 字符串
+    Compatible(&'static str),
+    /// virtio-mmio 传输层后面的设备类型
+    Virtio(DeviceType),
+    /// PCI 类别和子类别
+    PciClass(u8, u8),
+}
+
+/// 设备所在的位置
+pub struct Resources {
+    /// 第一段寄存器，PCI 设备为 BAR 0
+    pub base: usize,
+    pub size: usize,
+    pub irq: Option<usize>,
+}
+
+pub type BlockDeviceRef = Arc<Mutex<Box<dyn BlockDevice>>>;
+
+#[derive(Clone)]
+pub enum Device {
+    Block(BlockDeviceRef),
+}
+
+pub struct Driver {
+    pub name: &'static str,
+    pub matches: &'static [Match],
+    pub probe: fn(&Resources) -> Option<Device>,
+}
+
+pub struct DeviceEntry {
+    /// 类别前缀加类别内序号，如 `blk0`
+    pub name: String,
+    pub device: Device,
+    pub irq: Option<usize>,
+}
+
+static DRIVERS: Mutex<Vec<&'static Driver>> = Mutex::new(Vec::new());
+static DEVICES: Mutex<Vec<DeviceEntry>> = Mutex::new(Vec::new());
+
+pub fn register(driver: &'static Driver) {
+    DRIVERS.lock().push(driver);
+}
+
+/// 依次探测匹配的驱动，第一个成功的驱动得到设备
+fn bind<F: Fn(&Match) -> bool>(matches: F, resources: Resources) -> bool {
+    let drivers: Vec<&'static Driver> = DRIVERS
+        .lock()
+        .iter()
+        .filter(|driver| driver.matches.iter().any(&matches))
+        .copied()
+        .collect();
+    for driver in drivers {
+        if let Some(device) = (driver.probe)(&resources) {
+            let mut devices = DEVICES.lock();
+            let index = devices.iter().filter(|entry| matches!(entry.device, Device::Block(_))).count();
+            let name = format!("blk{}", index);
+            println!("[driver] {}: {} at {:#x} irq {:?}", name, driver.name, resources.base, resources.irq);
+            if let Some(irq) = resources.irq {
+                let irq_device = device.clone();
+                plic::register_irq(irq, Box::new(move || match &irq_device {
+                    Device::Block(block) => block.lock().handle_irq(),
+                }));
+            }
+            devices.push(DeviceEntry { name, device, irq: resources.irq });
+            return true;
+        }
+    }
+    false
+}
+
+/// 按 compatible 列表绑定设备树节点，virtio-mmio 节点按其后的设备类型绑定
+pub fn probe_compatible(compatible: &[&str], resources: Resources) -> bool {
+    if compatible.contains(&"virtio,mmio") {
+        return probe_virtio(resources);
+    }
+    bind(|m| matches!(*m, Match::Compatible(wanted) if compatible.contains(&wanted)), resources)
+}
+
+/// 空的传输层设备类型无效，创建传输层会失败
+fn probe_virtio(resources: Resources) -> bool {
+    let header = match NonNull::new(resources.base as *mut VirtIOHeader) {
+        Some(header) => header,
+        None => return false,
+    };
+    let device_type = match unsafe { MmioTransport::new(header) } {
+        Ok(transport) => transport.device_type(),
+        Err(_) => return false,
+    };
+    bind(|m| matches!(*m, Match::Virtio(wanted) if wanted == device_type), resources)
+}
+
+/// 绑定 `pci::init` 找到的功能
+pub fn probe_pci() {
+    for function in pci::functions() {
+        let (base, size) = function.bars[0].map_or((0, 0), |bar| (bar.addr, bar.size));
+        bind(
+            |m| matches!(*m, Match::PciClass(class, subclass) if class == function.class && subclass == function.subclass),
+            Resources { base, size, irq: function.irq },
+        );
+    }
+}
+
+pub fn block(index: usize) -> Option<BlockDeviceRef> {
+    DEVICES
+        .lock()
+        .iter()
+        .filter_map(|entry| match &entry.device {
+            Device::Block(device) => Some(device.clone()),
+        })
+        .nth(index)
+}
@@ -1,6 +1,11 @@
-use alloc::vec;
+//! 用 fatfs 读 FAT 卷
+//!
+//! fatfs 的读写接口是同步的，不能在里面等待异步请求。`DiskCursor` 只从
+//! 扇区缓存里读取，缺少的扇区以 `Missing` 错误报告出来，异步任务读入这个
+//! 扇区后从头重试。
+use alloc::{boxed::Box, collections::BTreeMap, string::String, sync::Arc, vec::Vec};
 use fatfs::{NullTimeProvider, LossyOemCpConverter};
-use crate::block::DEVICE;
+use crate::{block, driver, mutex::Mutex};
 #[derive(Debug)]
 struct AtaError;
 
@@ -8,6 +13,8 @@ struct AtaError;
 enum DiskCursorIoError {
     UnexpectedEof,
     WriteZero,
+    /// 扇区不在缓存里
+    Missing(usize),
 }
 impl fatfs::IoError for DiskCursorIoError {
     fn is_interrupted(&self) -> bool {
@@ -23,9 +30,12 @@ impl fatfs::IoError for DiskCursorIoError {
     }
 }
 
+type SectorCache = Arc<Mutex<BTreeMap<usize, Box<[u8; 512]>>>>;
+
 struct DiskCursor {
     sector: u64,
     offset: usize,
+    cache: SectorCache,
 }
 
 impl DiskCursor {
@@ -49,59 +59,52 @@ impl fatfs::IoBase for DiskCursor {
 
 impl fatfs::Read for DiskCursor {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, DiskCursorIoError> {
-        // 由于读取扇区内容还需要考虑跨 cluster，因此 read 函数只读取一个扇区
-        // 防止读取较多数据时超出限制
-        // 读取所有的数据的功能交给 read_exact 来实现
-
-        // 获取硬盘设备读取器（驱动？）
-        let mut block_device = unsafe { DEVICE.get().unwrap() }.lock();
-
-        // 如果 start 不是 0 或者 len 不是 512
-        let read_size = if self.offset != 0 || buf.len() < 512 {
-            // let mut data = [0u8; 512];
-            let mut data = vec![0u8; 512];
-            block_device.read_block(self.sector as usize, &mut data);
-
-            let start = self.offset;
-            let end = (self.offset + buf.len()).min(512);
-
-            buf.copy_from_slice(&data[start..end]);
-            end-start
-        } else {
-            block_device.read_block(self.sector as usize, &mut buf[0..512]);
-            512
-        };
-
-        self.move_cursor(read_size);
-        Ok(read_size)
+        // 只读取当前扇区，剩下的交给 read_exact 来实现
+        let cache = self.cache.lock();
+        let data = cache
+            .get(&(self.sector as usize))
+            .ok_or(DiskCursorIoError::Missing(self.sector as usize))?;
+
+        let start = self.offset;
+        let end = (self.offset + buf.len()).min(512);
+        buf[..end - start].copy_from_slice(&data[start..end]);
+        drop(cache);
+
+        self.move_cursor(end - start);
+        Ok(end - start)
     }
 }
 
 impl fatfs::Write for DiskCursor {
     fn write(&mut self, buf: &[u8]) -> Result<usize, DiskCursorIoError> {
-        // 由于写入扇区还需要考虑申请 cluster，因此 write 函数只写入一个扇区
-        // 防止写入较多数据时超出限制
-        // 写入所有的数据的功能交给 write_all 来实现
-
-        // 获取硬盘设备写入器（驱动？）
-        let mut block_device = unsafe { DEVICE.get_mut().unwrap() }.lock();
+        // fatfs 每次写入不会跨过 cluster，对齐的整扇区部分一次交给设备写入
+        // 不足一个扇区的部分在缓存里改好后写回，剩下的交给 write_all 来实现
+        let sector = self.sector as usize;
+        let block_device = driver::block(0).unwrap();
+        let mut cache = self.cache.lock();
 
-        // 如果 start 不是 0 或者 len 不是 512
+        // 如果 start 不是 0 或者 len 不足 512
         let write_size = if self.offset != 0 || buf.len() < 512 {
-            let mut data = vec![0u8; 512];
-            block_device.read_block(self.sector as usize, &mut data);
+            let data = cache.get_mut(&sector).ok_or(DiskCursorIoError::Missing(sector))?;
 
             let start = self.offset;
             let end = (self.offset + buf.len()).min(512);
-            
-            data[start..end].clone_from_slice(&buf);
-            block_device.write_block(self.sector as usize, &mut data);
+
+            data[start..end].clone_from_slice(&buf[..end - start]);
+            block::without_interrupts(|| block_device.lock().write_block(sector, &data[..]));
 
             end-start
         } else {
-            block_device.write_block(self.sector as usize, &buf[0..512]);
-            512
+            let size = buf.len() / 512 * 512;
+            block::without_interrupts(|| block_device.lock().write_blocks(sector, &[&buf[..size]]));
+            for (i, block) in buf[..size].chunks(512).enumerate() {
+                let mut data = Box::new([0u8; 512]);
+                data.copy_from_slice(block);
+                cache.insert(sector + i, data);
+            }
+            size
         };
+        drop(cache);
 
         self.move_cursor(write_size);
         Ok(write_size)
@@ -133,22 +136,55 @@ impl fatfs::Seek for DiskCursor {
 
 type Dir<'a> = fatfs::Dir<'a, DiskCursor, NullTimeProvider, LossyOemCpConverter>;
 
-pub fn ls_dir(path: &str) {
+/// 列出目录中的文件名，缺少扇区时返回 `Missing`
+fn list_dir(path: &str, cache: &SectorCache) -> Result<Vec<String>, fatfs::Error<DiskCursorIoError>> {
     let c = DiskCursor {
         sector: 0,
         offset: 0,
+        cache: cache.clone(),
     };
 
     // 获取文件
-    let fs = fatfs::FileSystem::new(c, fatfs::FsOptions::new()).expect("open fs fai");
-    let mut cursor =fs.root_dir();
-
-    for file in cursor.iter() {
-        if let Ok(file) = file {
-            println!("{:>2$}├──{}", "", file.file_name(), 0);
-            // info!("{:>2$}├──{}", "", sub_node.get_filename(), space);
-            // info!("{:>2$}└──{}", "", sub_node.get_filename(), space);
+    let fs = fatfs::FileSystem::new(c, fatfs::FsOptions::new())?;
+    let root = fs.root_dir();
+    let dir = match path.trim_matches('/') {
+        "" => root,
+        path => root.open_dir(path)?,
+    };
+
+    let mut names = Vec::new();
+    for file in dir.iter() {
+        names.push(file?.file_name());
+    }
+    Ok(names)
+}
+
+/// 异步列出目录，可以和其他任务一起在执行器上运行
+pub async fn ls_dir(path: &str) {
+    let device = driver::block(0).expect("no block device");
+    let cache: SectorCache = Arc::new(Mutex::new(BTreeMap::new()));
+    let names = loop {
+        match list_dir(path, &cache) {
+            Ok(names) => break names,
+            Err(fatfs::Error::Io(DiskCursorIoError::Missing(sector))) => {
+                let mut data = Box::new([0u8; 512]);
+                if block::read_block(&device, sector, &mut data[..]).await.is_err() {
+                    println!("ls {}: failed to read sector {}", path, sector);
+                    return;
+                }
+                cache.lock().insert(sector, data);
+            }
+            Err(err) => {
+                println!("ls {}: {:?}", path, err);
+                return;
+            }
         }
+    };
+
+    for name in names.iter() {
+        println!("{:>2$}├──{}", "", name, 0);
+        // info!("{:>2$}├──{}", "", sub_node.get_filename(), space);
+        // info!("{:>2$}└──{}", "", sub_node.get_filename(), space);
     }
     // let mut cursor = fs.root_dir();
     // let mut file;
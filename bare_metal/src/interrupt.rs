@@ -1,35 +1,86 @@
 use core::arch::asm;
 
-use riscv::register::{sstatus, stvec, utvec::TrapMode, self, scause};
+use riscv::register::{sstatus, stvec, utvec::TrapMode, self, scause::{self, Interrupt, Trap}};
 
-use crate::sbi;
+use crate::{plic, sbi, task::timer};
 
+/// QEMU virt 的 time 每秒增加 10_000_000，每 10ms 一次时钟中断
+const TIMER_INTERVAL: usize = 100_000;
+
+fn set_next_timer() {
+    sbi::set_timer(register::time::read() + TIMER_INTERVAL);
+}
+
+/// 保存调用者保存的寄存器后进入 `trap_handler`，返回后恢复并 sret
 #[naked]
-unsafe fn trap_entry() {
+unsafe extern "C" fn trap_entry() {
     asm!("
     .align 4
+    addi sp, sp, -16*8
+    sd ra, 0*8(sp)
+    sd t0, 1*8(sp)
+    sd t1, 2*8(sp)
+    sd t2, 3*8(sp)
+    sd t3, 4*8(sp)
+    sd t4, 5*8(sp)
+    sd t5, 6*8(sp)
+    sd t6, 7*8(sp)
+    sd a0, 8*8(sp)
+    sd a1, 9*8(sp)
+    sd a2, 10*8(sp)
+    sd a3, 11*8(sp)
+    sd a4, 12*8(sp)
+    sd a5, 13*8(sp)
+    sd a6, 14*8(sp)
+    sd a7, 15*8(sp)
     call trap_handler
+    ld ra, 0*8(sp)
+    ld t0, 1*8(sp)
+    ld t1, 2*8(sp)
+    ld t2, 3*8(sp)
+    ld t3, 4*8(sp)
+    ld t4, 5*8(sp)
+    ld t5, 6*8(sp)
+    ld t6, 7*8(sp)
+    ld a0, 8*8(sp)
+    ld a1, 9*8(sp)
+    ld a2, 10*8(sp)
+    ld a3, 11*8(sp)
+    ld a4, 12*8(sp)
+    ld a5, 13*8(sp)
+    ld a6, 14*8(sp)
+    ld a7, 15*8(sp)
+    addi sp, sp, 16*8
+    sret
     ", options(noreturn));
 }
 
 #[no_mangle]
-fn trap_handler() {
-
-    println!("trap {:?}", scause::read().cause());
-
-    panic!("trap")
+extern "C" fn trap_handler() {
+    match scause::read().cause() {
+        // 外部中断交给 PLIC 分发
+        Trap::Interrupt(Interrupt::SupervisorExternal) => plic::handle_interrupt(),
+        // 时钟中断推进执行器的 tick
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            set_next_timer();
+            timer::tick();
+        }
+        cause => {
+            println!("trap {:?}", cause);
+            panic!("trap")
+        }
+    }
 }
 
 pub fn init() {
     unsafe {
         stvec::write(trap_entry as usize, TrapMode::Direct);
-        // register::sie::set_stimer();
+        register::sie::set_stimer();
         register::sie::set_sext();
         // register::sie::set_ssoft();
         // register::sie::set_uext();
+        set_next_timer();
         sstatus::set_sie();
-        
-        // sbi::set_timer(register::time::read() + 100);
 
         // asm!("ebreak");
     }
@@ -26,12 +26,14 @@ mod uart;
 // mod loopback;
 mod utils;
 mod plic;
+mod pci;
+mod driver;
 
-use core::{arch::{asm, riscv64::wfi}, ptr::NonNull};
+use core::arch::{asm, riscv64::wfi};
 
-use fdt::{Fdt, node::FdtNode, standard_nodes::Compatible};
+use alloc::vec::Vec;
+use fdt::{Fdt, node::FdtNode};
 use riscv::register::{medeleg, mideleg, mhartid};
-use virtio_drivers::{VirtIOHeader, MmioTransport, Transport};
 
 /// 汇编入口函数
 /// 
@@ -66,17 +68,25 @@ pub extern "C" fn rust_main(hart_id: usize, _device_tree_addr: usize) -> ! {
         support_hart_resume(hart_id, 0);
     }
 
-    init_dt(_device_tree_addr);
-    
     memory::init();
+    // 驱动绑定设备时登记中断，PLIC 要先初始化
+    plic::init();
+    pci::init();
+    block::register();
+    init_dt(_device_tree_addr);
+    driver::probe_pci();
     block::init();
     interrupt::init();
     uart::init();
-
-    plic::init();
-//    fs::ls_dir("var");
     // loopback::net_main(); 
 
+    // 列目录和时钟任务一起在执行器上运行
+    task::init();
+    let mut executor = task::Executor::new();
+    executor.spawn(fs::ls_dir("/"));
+    executor.spawn(task::timer::timer_task());
+    executor.run();
+
     loop {
         unsafe {
             wfi();
@@ -108,39 +118,24 @@ fn init_dt(dtb: usize) {
     walk_dt(fdt);
 }
 
+/// 把有 compatible 的节点交给驱动注册表
 fn walk_dt(fdt: Fdt) {
     for node in fdt.all_nodes() {
         if let Some(compatible) = node.compatible() {
-            if compatible.all().any(|s| s == "virtio,mmio") {
-                virtio_probe(node);
+            let compatible: Vec<&str> = compatible.all().collect();
+            if let Some(resources) = node_resources(&node) {
+                driver::probe_compatible(&compatible, resources);
             }
         }
     }
 }
 
-fn virtio_probe(node: FdtNode) {
-    if let Some(reg) = node.reg().and_then(|mut reg| reg.next()) {
-        let paddr = reg.starting_address as usize;
-        let size = reg.size.unwrap();
-        let vaddr = paddr;
-        println!("walk dt addr={:#x}, size={:#x}", paddr, size);
-        println!(
-            "Device tree node {}: {:?}",
-            node.name,
-            node.compatible().map(Compatible::first),
-        );
-        let header = NonNull::new(vaddr as *mut VirtIOHeader).unwrap();
-        match unsafe { MmioTransport::new(header) } {
-            Err(e) => println!("Error creating VirtIO MMIO transport: {}", e),
-            Ok(transport) => {
-                println!(
-                    "[mmio] Detected virtio MMIO device with vendor id {:#X}, device type {:?}, version {:?}",
-                    transport.vendor_id(),
-                    transport.device_type(),
-                    transport.version(),
-                );
-                // virtio_device(transport);
-            }
-        }
-    }
+/// 节点的第一段寄存器和第一个中断
+fn node_resources(node: &FdtNode) -> Option<driver::Resources> {
+    let reg = node.reg().and_then(|mut reg| reg.next())?;
+    Some(driver::Resources {
+        base: reg.starting_address as usize,
+        size: reg.size.unwrap_or(0),
+        irq: node.interrupts().and_then(|mut irqs| irqs.next()),
+    })
 }
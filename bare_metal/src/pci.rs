@@ -0,0 +1,183 @@
+//! PCI 总线枚举
+//!
+//! QEMU virt 机器上没有固件配置 PCI 总线，这里遍历 ECAM，测量每个功能的
+//! 内存 BAR 并从主桥的 MMIO 窗口中分配地址，打开内存译码和总线主控，
+//! 并记录中断引脚对应的 PLIC 中断号。驱动注册表把驱动绑定到
+//! [`functions`] 列出的功能上。
+use alloc::vec::Vec;
+
+use crate::mutex::Mutex;
+
+/// 主桥的 ECAM，每个功能 4K 配置空间
+const PCI_BASE: usize = 0x3000_0000;
+/// 主桥 32 位 MMIO 窗口
+const PCI_MMIO_BASE: usize = 0x4000_0000;
+const PCI_MMIO_SIZE: usize = 0x4000_0000;
+/// 0 号插槽 INTA 在 PLIC 上的中断号，引脚按插槽轮换
+const PCI_IRQ_BASE: usize = 32;
+
+const PCI_VENDOR_ID: usize = 0x00;
+const PCI_COMMAND: usize = 0x04;
+const PCI_CLASS_REVISION: usize = 0x08;
+const PCI_HEADER_TYPE: usize = 0x0e;
+const PCI_BAR0: usize = 0x10;
+const PCI_INTERRUPT_LINE: usize = 0x3c;
+const PCI_INTERRUPT_PIN: usize = 0x3d;
+
+const PCI_COMMAND_MEMORY: u16 = 1 << 1;
+const PCI_COMMAND_MASTER: u16 = 1 << 2;
+const PCI_HEADER_MULTI_FUNCTION: u8 = 0x80;
+
+const PCI_BAR_IO: u32 = 0x1;
+const PCI_BAR_64: u32 = 0x4;
+const PCI_BARS: usize = 6;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bar {
+    pub addr: usize,
+    pub size: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct PciFunction {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    /// 已分配的内存 BAR，64 位 BAR 占用下一个槽位，I/O BAR 不分配
+    pub bars: [Option<Bar>; PCI_BARS],
+    /// INTx 引脚对应的 PLIC 中断号
+    pub irq: Option<usize>,
+}
+
+static FUNCTIONS: Mutex<Vec<PciFunction>> = Mutex::new(Vec::new());
+/// MMIO 窗口中下一个空闲地址
+static MMIO_NEXT: Mutex<usize> = Mutex::new(PCI_MMIO_BASE);
+
+fn config_base(bus: u8, device: u8, function: u8) -> usize {
+    PCI_BASE + ((bus as usize) << 20 | (device as usize) << 15 | (function as usize) << 12)
+}
+
+fn read<T>(addr: usize) -> T {
+    unsafe { (addr as *const T).read_volatile() }
+}
+
+fn write<T>(addr: usize, value: T) {
+    unsafe { (addr as *mut T).write_volatile(value) }
+}
+
+/// 从 MMIO 窗口分配 `size` 字节，按大小对齐
+fn alloc_mmio(size: usize) -> Option<usize> {
+    let mut next = MMIO_NEXT.lock();
+    let addr = (*next + size - 1) & !(size - 1);
+    if addr + size > PCI_MMIO_BASE + PCI_MMIO_SIZE {
+        return None;
+    }
+    *next = addr + size;
+    Some(addr)
+}
+
+/// 写全 1 后读回可写的地址位得到 BAR 大小，再分配地址
+fn assign_bars(config: usize) -> [Option<Bar>; PCI_BARS] {
+    let mut bars = [None; PCI_BARS];
+    let mut index = 0;
+    while index < PCI_BARS {
+        let reg = config + PCI_BAR0 + index * 4;
+        let orig: u32 = read(reg);
+        let is_64 = orig & PCI_BAR_IO == 0 && orig & 0x6 == PCI_BAR_64;
+        write(reg, u32::MAX);
+        let mask: u32 = read(reg);
+        write(reg, orig);
+        if mask == 0 || orig & PCI_BAR_IO != 0 {
+            index += 1;
+            continue;
+        }
+        let size = (!(mask & !0xf)).wrapping_add(1) as usize;
+        if let Some(addr) = alloc_mmio(size) {
+            write(reg, addr as u32);
+            if is_64 {
+                write(reg + 4, 0u32);
+            }
+            bars[index] = Some(Bar { addr, size });
+        }
+        index += if is_64 { 2 } else { 1 };
+    }
+    bars
+}
+
+fn probe(bus: u8, device: u8, function: u8) -> Option<PciFunction> {
+    let config = config_base(bus, device, function);
+    let id: u32 = read(config + PCI_VENDOR_ID);
+    if id & 0xffff == 0xffff {
+        return None;
+    }
+    let class: u32 = read(config + PCI_CLASS_REVISION);
+    // 只处理普通设备，桥的配置头不同
+    let header: u8 = read(config + PCI_HEADER_TYPE);
+    let bars = if header & !PCI_HEADER_MULTI_FUNCTION == 0 {
+        assign_bars(config)
+    } else {
+        [None; PCI_BARS]
+    };
+
+    let pin: u8 = read(config + PCI_INTERRUPT_PIN);
+    let irq = if pin == 0 {
+        None
+    } else {
+        let irq = PCI_IRQ_BASE + (device as usize + pin as usize - 1) % 4;
+        write(config + PCI_INTERRUPT_LINE, irq as u8);
+        Some(irq)
+    };
+
+    let command: u16 = read(config + PCI_COMMAND);
+    write(config + PCI_COMMAND, command | PCI_COMMAND_MEMORY | PCI_COMMAND_MASTER);
+
+    Some(PciFunction {
+        bus,
+        device,
+        function,
+        vendor_id: id as u16,
+        device_id: (id >> 16) as u16,
+        class: (class >> 24) as u8,
+        subclass: (class >> 16) as u8,
+        bars,
+        irq,
+    })
+}
+
+/// 枚举 0 号总线
+pub fn init() {
+    let mut functions = FUNCTIONS.lock();
+    for device in 0..32 {
+        let id: u32 = read(config_base(0, device, 0) + PCI_VENDOR_ID);
+        let header: u8 = read(config_base(0, device, 0) + PCI_HEADER_TYPE);
+        let count = if id & 0xffff != 0xffff && header & PCI_HEADER_MULTI_FUNCTION != 0 { 8 } else { 1 };
+        for function in 0..count {
+            if let Some(found) = probe(0, device, function) {
+                println!(
+                    "[pci] {:02x}:{:02x}.{} {:04x}:{:04x} class {:02x}.{:02x} irq {:?}",
+                    found.bus, found.device, found.function, found.vendor_id, found.device_id, found.class,
+                    found.subclass, found.irq
+                );
+                functions.push(found);
+            }
+        }
+    }
+}
+
+/// 找到的所有功能
+pub fn functions() -> Vec<PciFunction> {
+    FUNCTIONS.lock().clone()
+}
+
+#[allow(unused)]
+pub fn find_class(class: u8, subclass: u8) -> Option<PciFunction> {
+    FUNCTIONS
+        .lock()
+        .iter()
+        .find(|function| function.class == class && function.subclass == subclass)
+        .cloned()
+}
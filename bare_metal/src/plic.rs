@@ -1,60 +1,75 @@
-use core::{ptr, arch::asm};
+//! 平台级中断控制器
+//!
+//! 驱动通过 [`register_irq`] 登记中断处理函数，登记时设置中断源的优先级并
+//! 在当前核心的 S 态上下文中打开。发生 S 态外部中断时 [`handle_interrupt`]
+//! 逐个领取待处理的中断源，调用处理函数后通知完成。
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::arch::asm;
+use core::ptr;
 
+use crate::mutex::Mutex;
 
 const PLIC_BASE: usize = 0x0c00_0000;
-const UART0_IRQ: u32 = 10;
-const VIRTIO0_IRQ: u32 = 1;
-
 
 const PLIC_PRIORITY: usize = PLIC_BASE;
-const PLIC_PENDING: usize = PLIC_BASE + 0x1000;
+const PLIC_ENABLE: usize = PLIC_BASE + 0x2000;
+const PLIC_ENABLE_STRIDE: usize = 0x80;
+const PLIC_CONTEXT: usize = PLIC_BASE + 0x20_0000;
+const PLIC_CONTEXT_STRIDE: usize = 0x1000;
+const PLIC_THRESHOLD: usize = 0x0;
+const PLIC_CLAIM: usize = 0x4;
 
-fn PLIC_MENABLE(hart_id: usize) -> usize {
-    PLIC_BASE + 0x2000 + hart_id * 0x100
-}
+/// QEMU virt 上中断源编号 1 到 127，0 表示没有中断
+const MAX_IRQ: usize = 127;
+const DEFAULT_PRIORITY: u32 = 1;
 
-fn PLIC_SENABLE(hart_id: usize) -> usize {
-    PLIC_BASE + 0x2080 + hart_id * 0x100
-}
+pub type IrqHandler = Box<dyn Fn() + Send + Sync>;
 
-fn PLIC_MPRIORITY(hart_id: usize) -> usize {
-    PLIC_BASE + 0x200000 + hart_id * 0x2000
-}
+static HANDLERS: Mutex<BTreeMap<usize, Vec<IrqHandler>>> = Mutex::new(BTreeMap::new());
 
-fn PLIC_SPRIORITY(hart_id: usize) -> usize {
-    PLIC_BASE + 0x201000 + hart_id * 0x2000
+/// 核心的 S 态上下文，每个核心的 M 态上下文排在前面
+fn context(hart_id: usize) -> usize {
+    hart_id * 2 + 1
 }
 
-fn PLIC_MCLAIM(hart_id: usize) -> usize {
-    PLIC_BASE + 0x200004 + hart_id * 0x2000
+fn enable_addr(hart_id: usize, irq: usize) -> usize {
+    PLIC_ENABLE + context(hart_id) * PLIC_ENABLE_STRIDE + irq / 32 * 4
 }
 
-fn PLIC_SCLAIM(hart_id: usize) -> usize {
-    PLIC_BASE + 0x201004 + hart_id * 0x2000
+fn context_addr(hart_id: usize, register: usize) -> usize {
+    PLIC_CONTEXT + context(hart_id) * PLIC_CONTEXT_STRIDE + register
 }
 
+/// 关闭所有中断源，优先级阈值设为 0，等驱动登记
 pub fn init() {
-    // set desired IRQ priorities non-zero (otherwise disable)
-    write(PLIC_BASE + (UART0_IRQ * 4) as usize, 1);
-    // plic_init_hart();
+    for irq in 1..=MAX_IRQ {
+        write(PLIC_PRIORITY + irq * 4, 0);
+    }
+    plic_init_hart();
 }
 
 pub fn plic_init_hart() {
-    let hart_id = unsafe{ cpuid() } as usize;
-
-    // Set UART's enable bit for this hart's S-mode. 
-    write(PLIC_SENABLE(hart_id), 1 << UART0_IRQ);
+    let hart_id = cpuid() as usize;
+    for word in 0..=MAX_IRQ / 32 {
+        write(enable_addr(hart_id, word * 32), 0);
+    }
+    write(context_addr(hart_id, PLIC_THRESHOLD), 0);
+}
 
-    // Set this hart's S-mode pirority threshold to 0. 
-    write(PLIC_SPRIORITY(hart_id), 0);
+/// `irq` 发生时调用 `handler`，一个中断源可以有多个处理函数
+pub fn register_irq(irq: usize, handler: IrqHandler) {
+    assert!(irq > 0 && irq <= MAX_IRQ, "no interrupt source {}", irq);
+    HANDLERS.lock().entry(irq).or_insert_with(Vec::new).push(handler);
+    let hart_id = cpuid() as usize;
+    write(PLIC_PRIORITY + irq * 4, DEFAULT_PRIORITY);
+    let enable = read(enable_addr(hart_id, irq));
+    write(enable_addr(hart_id, irq), enable | 1 << (irq % 32));
 }
 
-/// Ask the PLIC what interrupt we should serve. 
+/// Ask the PLIC what interrupt we should serve.
 pub fn plic_claim() -> Option<u32> {
-    let hart_id = unsafe {
-        cpuid()
-    } as usize;
-    let interrupt = read(PLIC_SCLAIM(hart_id));
+    let hart_id = cpuid() as usize;
+    let interrupt = read(context_addr(hart_id, PLIC_CLAIM));
     if interrupt == 0 {
         None
     } else {
@@ -64,22 +79,30 @@ pub fn plic_claim() -> Option<u32> {
 
 /// Tell the PLIC we've served the IRQ
 pub fn plic_complete(interrupt: u32) {
-    let hart_id = unsafe {
-        cpuid()
-    } as usize;
-    write(PLIC_SCLAIM(hart_id), interrupt);
+    let hart_id = cpuid() as usize;
+    write(context_addr(hart_id, PLIC_CLAIM), interrupt);
 }
 
+/// 领取并分发所有待处理的中断
+pub fn handle_interrupt() {
+    while let Some(irq) = plic_claim() {
+        match HANDLERS.lock().get(&(irq as usize)) {
+            Some(handlers) => handlers.iter().for_each(|handler| handler()),
+            None => println!("unexpected interrupt {}", irq),
+        }
+        plic_complete(irq);
+    }
+}
 
 fn write(addr: usize, val: u32) {
     unsafe {
-        ptr::write(addr as *mut u32, val);
+        ptr::write_volatile(addr as *mut u32, val);
     }
 }
 
 fn read(addr: usize) -> u32 {
     unsafe {
-        ptr::read(addr as *const u32)
+        ptr::read_volatile(addr as *const u32)
     }
 }
 
@@ -89,4 +112,4 @@ fn cpuid() -> u8 {
         asm!("mv {0}, tp", out(reg) cpu_id);
     }
     cpu_id
-}
\ No newline at end of file
+}
@@ -14,7 +14,9 @@ use core::{
     pin::Pin,
     task::{Context, Poll},
 };
+use core::arch::riscv64::wfi;
 use crossbeam_queue::SegQueue;
+use riscv::register::sstatus;
 use crate::test_async::add_t1;
 
 pub type Task = Pin<Box<dyn Future<Output = ()>>>;
@@ -46,7 +48,8 @@ impl Executor {
             self.wake_waiting_tasks();
             self.hlt_if_idle();
 
-            if self.task_queue.len() == 0 {
+            // 等待中断唤醒的任务也要等它完成
+            if self.task_queue.is_empty() && self.pending_tasks.is_empty() {
                 break;
             }
         }
@@ -90,21 +93,19 @@ impl Executor {
         }
     }
 
-    /// Executes the `hlt` instruction if there are no ready tasks
+    /// Executes the `wfi` instruction if there are no ready tasks
     fn hlt_if_idle(&self) {
         add_t1();
-        // if self.task_queue.is_empty() {
-        //     // disable interrupts to avoid races
-        //     x86_64::instructions::interrupts::disable();
-        //     // check if relevant interrupts occured since the last check
-        //     if interrupt_wakeups().is_empty() {
-        //         // no interrupts occured -> hlt to wait for next interrupt
-        //         x86_64::instructions::interrupts::enable_interrupts_and_hlt();
-        //     } else {
-        //         // there were some new wakeups -> continue execution
-        //         x86_64::instructions::interrupts::enable();
-        //     }
-        // }
+        if self.task_queue.is_empty() && self.wake_queue.is_empty() {
+            // disable interrupts to avoid races
+            unsafe { sstatus::clear_sie() };
+            // check if relevant interrupts occured since the last check,
+            // wfi still returns on a pending interrupt with sstatus.SIE clear
+            if interrupt_wakeups().is_empty() {
+                unsafe { wfi() };
+            }
+            unsafe { sstatus::set_sie() };
+        }
     }
 
     fn task_id(task: &Task) -> TaskId {
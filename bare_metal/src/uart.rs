@@ -5,6 +5,8 @@ use core::fmt::{self, Write, Error};
 use core::sync::atomic::Ordering;
 
 const UART0: usize = 0x1000_0000;
+/// UART0 在 PLIC 上的中断号
+const UART0_IRQ: usize = 10;
 
 /// receive holding register (for input bytes)
 const RHR: usize = 0;
@@ -56,6 +58,16 @@ pub fn init() {
 
     // enable transmit and receive interrupts. 
     write_reg(UART_BASE_ADDR + IER, IER_TX_ENABLE as u8 | IER_RX_ENABLE as u8);
+    crate::plic::register_irq(UART0_IRQ, alloc::boxed::Box::new(handle_interrupt));
+}
+
+/// 读 ISR 清除发送空中断，再把收到的字符回显出来
+fn handle_interrupt() {
+    read_reg(UART_BASE_ADDR + ISR);
+    while read_reg(UART_BASE_ADDR + LSR) as usize & LSR_RX_READY != 0 {
+        let c = read_reg(UART_BASE_ADDR + RHR);
+        print!("{}", c as char);
+    }
 }
 
 
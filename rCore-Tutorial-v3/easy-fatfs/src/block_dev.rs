@@ -1,3 +1,4 @@
+use crate::BLOCK_SZ;
 use core::any::Any;
 /// Trait for block devices
 /// which reads and writes data in the unit of blocks
@@ -6,4 +7,25 @@ pub trait BlockDevice: Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     ///Write data from buffer to block
     fn write_block(&self, block_id: usize, buf: &[u8]);
+    ///Read consecutive blocks starting at `block_id` into the buffers in
+    ///turn, each buffer a multiple of the block size
+    fn read_blocks(&self, block_id: usize, bufs: &mut [&mut [u8]]) {
+        let mut block_id = block_id;
+        for buf in bufs.iter_mut() {
+            for block in buf.chunks_mut(BLOCK_SZ) {
+                self.read_block(block_id, block);
+                block_id += 1;
+            }
+        }
+    }
+    ///Write the buffers in turn to consecutive blocks starting at `block_id`
+    fn write_blocks(&self, block_id: usize, bufs: &[&[u8]]) {
+        let mut block_id = block_id;
+        for buf in bufs.iter() {
+            for block in buf.chunks(BLOCK_SZ) {
+                self.write_block(block_id, block);
+                block_id += 1;
+            }
+        }
+    }
 }
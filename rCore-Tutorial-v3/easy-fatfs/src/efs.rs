@@ -1,11 +1,8 @@
 use crate::{BLOCK_SZ, BlockDevice, Inode};
-use alloc::{sync::Arc, vec};
-use spin::{Mutex, Once};
+use alloc::{boxed::Box, sync::Arc, vec};
+use spin::Mutex;
 use fatfs::{NullTimeProvider, LossyOemCpConverter};
 
-static FILE_SYSTEM: Once<FileSystem> = Once::new();
-// static FILE_SYSTEM: Lazy<FileSystem<DiskCursor, NullTimeProvider, LossyOemCpConverter>> = Lazy::new(); 
-
 pub struct FileSystem(fatfs::FileSystem<DiskCursor, NullTimeProvider, LossyOemCpConverter>);
 
 unsafe impl Sync for FileSystem {}
@@ -13,7 +10,10 @@ unsafe impl Sync for FileSystem {}
 unsafe impl Send for FileSystem {}
 
 ///An easy file system on block
-pub struct EasyFileSystem {}
+pub struct EasyFileSystem {
+    /// file systems stay mounted and inodes borrow them, so they are leaked
+    fs: &'static FileSystem,
+}
 
 /// An easy fs over a block device
 impl EasyFileSystem {
@@ -25,21 +25,21 @@ impl EasyFileSystem {
     // ) -> Arc<Mutex<Self>> {
     //     todo!()
     // }
-    /// Open a block device as a filesystem
-    pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+    /// Open a block device as a filesystem, every device can hold one,
+    /// e.g. the disk and a loop device over an image file on it. None if
+    /// there is no FAT volume on it.
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> Option<Arc<Mutex<Self>>> {
         let c = DiskCursor {
             sector: 0,
             offset: 0,
             block_device: block_device
         };
-        FILE_SYSTEM.call_once(|| FileSystem(
-            fatfs::FileSystem::new(c, fatfs::FsOptions::new()).expect("open fs fai")
-        ));
-        Arc::new(Mutex::new(Self {}))
+        let fs = FileSystem(fatfs::FileSystem::new(c, fatfs::FsOptions::new()).ok()?);
+        Some(Arc::new(Mutex::new(Self { fs: Box::leak(Box::new(fs)) })))
     }
     /// Get the root inode of the filesystem
-    pub fn root_inode(_efs: &Arc<Mutex<Self>>) -> Inode {
-        Inode::Dir(Arc::new(Mutex::new(FILE_SYSTEM.get().unwrap().0.root_dir())))
+    pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
+        Inode::Dir(Arc::new(Mutex::new(efs.lock().fs.0.root_dir())))
     }
 }
 
@@ -98,27 +98,27 @@ impl fatfs::IoBase for DiskCursor {
 
 impl fatfs::Read for DiskCursor {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, DiskCursorIoError> {
-        // 由于读取扇区内容还需要考虑跨 cluster，因此 read 函数只读取一个扇区
-        // 防止读取较多数据时超出限制
-        // 读取所有的数据的功能交给 read_exact 来实现
+        // fatfs 每次读取不会跨过 cluster，对齐的整扇区部分一次交给设备读取，
+        // 一个 cluster 只需要一条命令
+        // 不足一个扇区的部分只读取当前扇区，剩下的交给 read_exact 来实现
 
         // 获取硬盘设备读取器（驱动？）
         let block_device = &self.block_device;
 
-        // 如果 start 不是 0 或者 len 不是 512
-        let read_size = if self.offset != 0 || buf.len() < 512 {
-            // let mut data = [0u8; 512];
-            let mut data = vec![0u8; 512];
+        // 如果 start 不是 0 或者 len 不足 512
+        let read_size = if self.offset != 0 || buf.len() < BLOCK_SZ {
+            let mut data = vec![0u8; BLOCK_SZ];
             block_device.read_block(self.sector as usize, &mut data);
 
             let start = self.offset;
-            let end = (self.offset + buf.len()).min(512);
-            
-            buf.copy_from_slice(&data[start..end]);
-            end-start
+            let end = (self.offset + buf.len()).min(BLOCK_SZ);
+
+            buf[..end - start].copy_from_slice(&data[start..end]);
+            end - start
         } else {
-            block_device.read_block(self.sector as usize, &mut buf[0..512]);
-            512
+            let size = buf.len() / BLOCK_SZ * BLOCK_SZ;
+            block_device.read_blocks(self.sector as usize, &mut [&mut buf[..size]]);
+            size
         };
 
         self.move_cursor(read_size);
@@ -128,28 +128,28 @@ impl fatfs::Read for DiskCursor {
 
 impl fatfs::Write for DiskCursor {
     fn write(&mut self, buf: &[u8]) -> Result<usize, DiskCursorIoError> {
-        // 由于写入扇区还需要考虑申请 cluster，因此 write 函数只写入一个扇区
-        // 防止写入较多数据时超出限制
-        // 写入所有的数据的功能交给 write_all 来实现
+        // fatfs 每次写入不会跨过 cluster，对齐的整扇区部分一次交给设备写入
+        // 不足一个扇区的部分先读出当前扇区，改好后写回，剩下的交给 write_all 来实现
 
         // 获取硬盘设备写入器（驱动？）
         let block_device = &self.block_device;
 
-        // 如果 start 不是 0 或者 len 不是 512
-        let write_size = if self.offset != 0 || buf.len() < 512 {
-            let mut data = vec![0u8; 512];
+        // 如果 start 不是 0 或者 len 不足 512
+        let write_size = if self.offset != 0 || buf.len() < BLOCK_SZ {
+            let mut data = vec![0u8; BLOCK_SZ];
             block_device.read_block(self.sector as usize, &mut data);
 
             let start = self.offset;
-            let end = (self.offset + buf.len()).min(512);
-            
-            data[start..end].clone_from_slice(&buf);
-            block_device.write_block(self.sector as usize, &mut data);
+            let end = (self.offset + buf.len()).min(BLOCK_SZ);
+
+            data[start..end].clone_from_slice(&buf[..end - start]);
+            block_device.write_block(self.sector as usize, &data);
 
-            end-start
+            end - start
         } else {
-            block_device.write_block(self.sector as usize, &buf[0..512]);
-            512
+            let size = buf.len() / BLOCK_SZ * BLOCK_SZ;
+            block_device.write_blocks(self.sector as usize, &[&buf[..size]]);
+            size
         };
 
         self.move_cursor(write_size);
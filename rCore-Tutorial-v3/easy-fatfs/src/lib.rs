@@ -1,9 +1,10 @@
 //!An easy file system isolated from the kernel
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![deny(missing_docs)]
 extern crate alloc;
 mod block_dev;
 mod efs;
+pub mod partition;
 mod vfs;
 /// Use a block size of 512 bytes
 pub const BLOCK_SZ: usize = 512;
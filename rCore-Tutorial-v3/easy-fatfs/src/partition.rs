@@ -0,0 +1,365 @@
+//! Partition tables
+//!
+//! A disk made with `fdisk` or `sfdisk` starts with an MBR, or with a
+//! protective MBR followed by a GPT, and the FAT volume is in one of its
+//! partitions. `partitions` reads the table and `open` hands out one
+//! partition as a block device of its own. Only the primary MBR entries
+//! are read, extended partitions are not followed. A GPT whose header or
+//! entry array fails its CRC32, or with more than 128 entries, is not
+//! read at all.
+use crate::{BlockDevice, BLOCK_SZ};
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
+
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_ENTRIES: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+/// UTF-16 characters of a GPT partition name
+const GPT_NAME_LEN: usize = 36;
+/// What every partitioning tool writes, 16KiB of 128 byte entries
+const GPT_MAX_ENTRIES: usize = 128;
+/// The header fields up to the entry array CRC
+const GPT_HEADER_MIN: usize = 92;
+
+/// A GUID in its on-disk byte order
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    /// The GUID written as `a-b-c-d`, the first three fields are stored
+    /// little-endian and the last eight bytes as they are
+    pub const fn new(a: u32, b: u16, c: u16, d: [u8; 8]) -> Self {
+        let a = a.to_le_bytes();
+        let b = b.to_le_bytes();
+        let c = c.to_le_bytes();
+        Self([
+            a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5], d[6], d[7],
+        ])
+    }
+}
+
+/// Microsoft basic data, what `sfdisk` and `gdisk` give FAT partitions
+pub const GUID_BASIC_DATA: Guid = Guid::new(0xebd0a0a2, 0xb9e5, 0x4433, [0x87, 0xc0, 0x68, 0xb6, 0xb7, 0x26, 0x99, 0xc7]);
+
+/// What the table says a partition holds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartitionType {
+    /// MBR system id, 0x0c for FAT32 with LBA
+    Mbr(u8),
+    /// GPT partition type GUID
+    Gpt(Guid),
+}
+
+/// An entry of the partition table
+#[derive(Clone, Debug)]
+pub struct PartitionInfo {
+    /// position in the table, unused entries are skipped
+    pub index: usize,
+    /// first block
+    pub start: usize,
+    /// length in blocks
+    pub blocks: usize,
+    /// what the partition holds
+    pub kind: PartitionType,
+    /// GPT partition name, empty for MBR
+    pub label: String,
+}
+
+/// Which partition to open
+#[derive(Clone, Copy, Debug)]
+pub enum Selector<'a> {
+    /// position among the used entries
+    Index(usize),
+    /// the first GPT partition of this type
+    TypeGuid(Guid),
+    /// the GPT partition with this name
+    Label(&'a str),
+}
+
+/// A partition as a block device, block 0 is its first block
+pub struct Partition {
+    device: Arc<dyn BlockDevice>,
+    start: usize,
+    blocks: usize,
+}
+
+impl Partition {
+    /// `blocks` blocks of `device` from `start` on
+    pub fn new(device: Arc<dyn BlockDevice>, start: usize, blocks: usize) -> Self {
+        Self { device, start, blocks }
+    }
+
+    fn check(&self, block_id: usize, len: usize) {
+        let end = block_id + (len + BLOCK_SZ - 1) / BLOCK_SZ;
+        assert!(end <= self.blocks, "block {} is past the end of the partition", end - 1);
+    }
+}
+
+impl BlockDevice for Partition {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.check(block_id, buf.len());
+        self.device.read_block(self.start + block_id, buf);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.check(block_id, buf.len());
+        self.device.write_block(self.start + block_id, buf);
+    }
+
+    fn read_blocks(&self, block_id: usize, bufs: &mut [&mut [u8]]) {
+        self.check(block_id, bufs.iter().map(|buf| buf.len()).sum());
+        self.device.read_blocks(self.start + block_id, bufs);
+    }
+
+    fn write_blocks(&self, block_id: usize, bufs: &[&[u8]]) {
+        self.check(block_id, bufs.iter().map(|buf| buf.len()).sum());
+        self.device.write_blocks(self.start + block_id, bufs);
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// CRC32 as GPT uses it, the reflected IEEE polynomial
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// A FAT boot sector also ends in 0x55aa, but starts with a jump and
+/// has the sector size in its BPB
+fn is_fat_boot_sector(sector: &[u8]) -> bool {
+    (sector[0] == 0xeb || sector[0] == 0xe9) && u16_at(sector, 11) as usize == BLOCK_SZ
+}
+
+fn mbr_partitions(sector: &[u8]) -> Vec<PartitionInfo> {
+    (0..4)
+        .map(|i| &sector[MBR_ENTRIES + i * MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE])
+        .filter(|entry| entry[4] != 0 && u32_at(entry, 12) != 0)
+        .enumerate()
+        .map(|(index, entry)| PartitionInfo {
+            index,
+            start: u32_at(entry, 8) as usize,
+            blocks: u32_at(entry, 12) as usize,
+            kind: PartitionType::Mbr(entry[4]),
+            label: String::new(),
+        })
+        .collect()
+}
+
+fn gpt_partitions(device: &Arc<dyn BlockDevice>) -> Vec<PartitionInfo> {
+    let mut header = vec![0u8; BLOCK_SZ];
+    device.read_block(1, &mut header);
+    if &header[..8] != GPT_SIGNATURE {
+        return Vec::new();
+    }
+    // the CRC is taken with its own field zeroed
+    let header_size = u32_at(&header, 12) as usize;
+    if !(GPT_HEADER_MIN..=BLOCK_SZ).contains(&header_size) {
+        return Vec::new();
+    }
+    let header_crc = u32_at(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc {
+        return Vec::new();
+    }
+    let entries_lba = u64_at(&header, 72) as usize;
+    let count = u32_at(&header, 80) as usize;
+    let entry_size = u32_at(&header, 84) as usize;
+    if count > GPT_MAX_ENTRIES || entry_size < 128 || BLOCK_SZ % entry_size != 0 {
+        return Vec::new();
+    }
+
+    let per_block = BLOCK_SZ / entry_size;
+    let mut table = vec![0u8; (count + per_block - 1) / per_block * BLOCK_SZ];
+    device.read_blocks(entries_lba, &mut [&mut table[..]]);
+    if crc32(&table[..count * entry_size]) != u32_at(&header, 88) {
+        return Vec::new();
+    }
+    table
+        .chunks(entry_size)
+        .take(count)
+        .filter(|entry| entry[..16].iter().any(|byte| *byte != 0))
+        .filter(|entry| u64_at(entry, 40) >= u64_at(entry, 32))
+        .enumerate()
+        .map(|(index, entry)| {
+            let mut kind = [0u8; 16];
+            kind.copy_from_slice(&entry[..16]);
+            let first = u64_at(entry, 32) as usize;
+            let last = u64_at(entry, 40) as usize;
+            let name: Vec<u16> = (0..GPT_NAME_LEN)
+                .map(|i| u16_at(entry, 56 + i * 2))
+                .take_while(|c| *c != 0)
+                .collect();
+            PartitionInfo {
+                index,
+                start: first,
+                blocks: last + 1 - first,
+                kind: PartitionType::Gpt(Guid(kind)),
+                label: String::from_utf16_lossy(&name),
+            }
+        })
+        .collect()
+}
+
+/// The partitions on `device`, none if it holds a bare volume or has no
+/// table this understands
+pub fn partitions(device: &Arc<dyn BlockDevice>) -> Vec<PartitionInfo> {
+    let mut sector = vec![0u8; BLOCK_SZ];
+    device.read_block(0, &mut sector);
+    if sector[510..512] != BOOT_SIGNATURE || is_fat_boot_sector(&sector) {
+        return Vec::new();
+    }
+    let mbr = mbr_partitions(&sector);
+    if mbr.iter().any(|partition| partition.kind == PartitionType::Mbr(MBR_TYPE_GPT_PROTECTIVE)) {
+        gpt_partitions(device)
+    } else {
+        mbr
+    }
+}
+
+/// Open the partition of `device` that `selector` picks
+pub fn open(device: Arc<dyn BlockDevice>, selector: Selector) -> Option<Arc<dyn BlockDevice>> {
+    let partition = partitions(&device).into_iter().find(|partition| match selector {
+        Selector::Index(index) => partition.index == index,
+        Selector::TypeGuid(guid) => partition.kind == PartitionType::Gpt(guid),
+        Selector::Label(label) => partition.label == label,
+    })?;
+    Some(Arc::new(Partition::new(device, partition.start, partition.blocks)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spin::Mutex;
+
+    /// A disk in memory
+    struct MemDisk(Mutex<Vec<u8>>);
+
+    impl BlockDevice for MemDisk {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+            buf.copy_from_slice(&self.0.lock()[block_id * BLOCK_SZ..][..buf.len()]);
+        }
+
+        fn write_block(&self, block_id: usize, buf: &[u8]) {
+            self.0.lock()[block_id * BLOCK_SZ..][..buf.len()].copy_from_slice(buf);
+        }
+    }
+
+    /// A protective MBR, the GPT header and its 128 entries take 34 blocks
+    fn disk(sectors: &[(usize, &[u8])]) -> Arc<dyn BlockDevice> {
+        let disk = MemDisk(Mutex::new(vec![0u8; 64 * BLOCK_SZ]));
+        for (block_id, data) in sectors {
+            disk.0.lock()[block_id * BLOCK_SZ..][..data.len()].copy_from_slice(data);
+        }
+        Arc::new(disk)
+    }
+
+    fn mbr(entries: &[(u8, u32, u32)]) -> Vec<u8> {
+        let mut sector = vec![0u8; BLOCK_SZ];
+        for (i, (kind, start, blocks)) in entries.iter().enumerate() {
+            let entry = &mut sector[MBR_ENTRIES + i * MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE];
+            entry[4] = *kind;
+            entry[8..12].copy_from_slice(&start.to_le_bytes());
+            entry[12..16].copy_from_slice(&blocks.to_le_bytes());
+        }
+        sector[510..512].copy_from_slice(&BOOT_SIGNATURE);
+        sector
+    }
+
+    fn gpt_entry(kind: Guid, first: u64, last: u64, name: &str) -> Vec<u8> {
+        let mut entry = vec![0u8; 128];
+        entry[..16].copy_from_slice(&kind.0);
+        entry[32..40].copy_from_slice(&first.to_le_bytes());
+        entry[40..48].copy_from_slice(&last.to_le_bytes());
+        for (i, c) in name.encode_utf16().enumerate() {
+            entry[56 + i * 2..58 + i * 2].copy_from_slice(&c.to_le_bytes());
+        }
+        entry
+    }
+
+    /// A GPT header for 128 entries from block 2 on and the entry array
+    fn gpt(entries: &[Vec<u8>]) -> (Vec<u8>, Vec<u8>) {
+        let mut table = vec![0u8; GPT_MAX_ENTRIES * 128];
+        for (i, entry) in entries.iter().enumerate() {
+            table[i * 128..][..128].copy_from_slice(entry);
+        }
+        let mut header = vec![0u8; BLOCK_SZ];
+        header[..8].copy_from_slice(GPT_SIGNATURE);
+        header[12..16].copy_from_slice(&(GPT_HEADER_MIN as u32).to_le_bytes());
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&(GPT_MAX_ENTRIES as u32).to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        header[88..92].copy_from_slice(&crc32(&table).to_le_bytes());
+        let crc = crc32(&header[..GPT_HEADER_MIN]);
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+        (header, table)
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn bare_fat_volume_has_no_table() {
+        let mut sector = vec![0u8; BLOCK_SZ];
+        sector[0] = 0xeb;
+        sector[11..13].copy_from_slice(&(BLOCK_SZ as u16).to_le_bytes());
+        sector[510..512].copy_from_slice(&BOOT_SIGNATURE);
+        assert!(partitions(&disk(&[(0, &sector)])).is_empty());
+    }
+
+    #[test]
+    fn mbr_skips_unused_entries() {
+        let sector = mbr(&[(0x0c, 2048, 81920), (0, 0, 0), (0x83, 83968, 1024)]);
+        let table = partitions(&disk(&[(0, &sector)]));
+        assert_eq!(table.len(), 2);
+        assert_eq!((table[0].index, table[0].start, table[0].blocks), (0, 2048, 81920));
+        assert_eq!(table[0].kind, PartitionType::Mbr(0x0c));
+        assert_eq!((table[1].index, table[1].start), (1, 83968));
+    }
+
+    #[test]
+    fn gpt_behind_protective_mbr() {
+        let entries = [
+            gpt_entry(GUID_BASIC_DATA, 2048, 83967, "root"),
+            gpt_entry(GUID_BASIC_DATA, 4096, 4095, "backwards"),
+        ];
+        let (header, table) = gpt(&entries);
+        let device = disk(&[(0, &mbr(&[(MBR_TYPE_GPT_PROTECTIVE, 1, 0xffff_ffff)])), (1, &header), (2, &table)]);
+        let table = partitions(&device);
+        assert_eq!(table.len(), 1);
+        assert_eq!((table[0].index, table[0].start, table[0].blocks), (0, 2048, 81920));
+        assert_eq!(table[0].kind, PartitionType::Gpt(GUID_BASIC_DATA));
+        assert_eq!(table[0].label, "root");
+    }
+
+    #[test]
+    fn gpt_with_bad_header_crc_is_ignored() {
+        let (mut header, table) = gpt(&[gpt_entry(GUID_BASIC_DATA, 2048, 83967, "root")]);
+        header[80] = 0xff;
+        let device = disk(&[(0, &mbr(&[(MBR_TYPE_GPT_PROTECTIVE, 1, 0xffff_ffff)])), (1, &header), (2, &table)]);
+        assert!(partitions(&device).is_empty());
+    }
+}
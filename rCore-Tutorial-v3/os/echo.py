@@ -0,0 +1,39 @@
+import socket
+import sys
+import threading
+
+port = int(sys.argv[1])
+
+
+def serve_udp():
+        sock = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+        sock.bind(('localhost', port))
+        while True:
+                buf, raddr = sock.recvfrom(4096)
+                print("udp receive: " + buf.decode("utf-8", "replace"), end="")
+                sock.sendto(buf, raddr)
+
+
+def serve_tcp(conn, raddr):
+        print("tcp connection from %s:%d" % raddr, file=sys.stderr)
+        while True:
+                buf = conn.recv(4096)
+                if not buf:
+                        break
+                print("tcp receive: " + buf.decode("utf-8", "replace"), end="")
+                conn.sendall(buf)
+        conn.close()
+        print("tcp connection closed", file=sys.stderr)
+
+
+threading.Thread(target=serve_udp, daemon=True).start()
+
+listener = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+listener.setsockopt(socket.SOL_SOCKET, socket.SO_REUSEADDR, 1)
+listener.bind(('localhost', port))
+listener.listen()
+
+print("echoing on tcp and udp port %d..." % port, file=sys.stderr)
+while True:
+        conn, raddr = listener.accept()
+        threading.Thread(target=serve_tcp, args=(conn, raddr), daemon=True).start()
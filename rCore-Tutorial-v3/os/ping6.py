@@ -0,0 +1,16 @@
+import socket
+import sys
+import time
+
+sock = socket.socket(socket.AF_INET6, socket.SOCK_DGRAM)
+addr = ('::1', int(sys.argv[1]))
+sock.bind(addr)
+
+
+print("pinging over ipv6...", file=sys.stderr)
+while True:
+        buf, raddr = sock.recvfrom(4096)
+        print("receive: " + buf.decode("utf-8"))
+        buf = "this is a ping6 to reply!".encode('utf-8')
+        sock.sendto(buf, raddr)
+        time.sleep(1)
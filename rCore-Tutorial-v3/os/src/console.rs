@@ -1,9 +1,48 @@
 //! SBI console driver, for text output
+//!
+//! Once [`init`] finds a virtio console, kernel messages go there instead,
+//! a write is one trap to the host rather than one per byte. The console
+//! of user space, stdin and stdout, stays on the SBI, and so do panics and
+//! everything after the virtio console stops taking output.
+use crate::drivers::chardev::CharDevice;
+use crate::drivers::registry;
 use crate::sbi::console_putchar;
+use crate::sync::UPSafeCell;
+use alloc::sync::Arc;
 use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::*;
+
+lazy_static! {
+    static ref DEVICE: UPSafeCell<Option<Arc<dyn CharDevice>>> = unsafe { UPSafeCell::new(None) };
+}
+
+/// `DEVICE` is set, printing starts before the heap and `.bss` are
+static HOOKED: AtomicBool = AtomicBool::new(false);
+/// A print is in the device, one from inside it, a panic say, takes the SBI
+static BUSY: AtomicBool = AtomicBool::new(false);
 
 struct Stdout;
 
+/// Writes to the device, what it doesn't take goes to the SBI
+struct DeviceOut<'a> {
+    device: &'a dyn CharDevice,
+    failed: bool,
+}
+
+impl Write for DeviceOut<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let written = if self.failed { 0 } else { self.device.write(s.as_bytes()) };
+        if written < s.len() {
+            self.failed = true;
+            for byte in &s.as_bytes()[written..] {
+                console_putchar(*byte as usize);
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         for c in s.chars() {
@@ -13,7 +52,38 @@ impl Write for Stdout {
     }
 }
 
+/// Print through the virtio console from now on if there is one, after the
+/// drivers are bound
+pub fn init() {
+    if let Some(device) = registry::char_device_of("virtio-console") {
+        *DEVICE.exclusive_access() = Some(device);
+        HOOKED.store(true, Ordering::Release);
+        println!("[kernel] console: kernel messages go to hvc0");
+    }
+}
+
+/// Print through the SBI from now on, the panic handler calls it so that
+/// the last words don't depend on a device
+pub fn unhook() {
+    HOOKED.store(false, Ordering::Release);
+}
+
 pub fn print(args: fmt::Arguments) {
+    if HOOKED.load(Ordering::Acquire) && !BUSY.swap(true, Ordering::Acquire) {
+        let device = DEVICE.exclusive_access().clone();
+        let mut failed = false;
+        if let Some(device) = device {
+            let mut out = DeviceOut { device: device.as_ref(), failed: false };
+            out.write_fmt(args).unwrap();
+            failed = out.failed;
+        }
+        BUSY.store(false, Ordering::Release);
+        if failed {
+            unhook();
+            Stdout.write_str("[kernel] console: hvc0 stopped taking output, back to the SBI\n").unwrap();
+        }
+        return;
+    }
     Stdout.write_fmt(args).unwrap();
 }
 
@@ -1,23 +1,45 @@
 pub mod virtio_blk;
-mod nvme;
+pub mod nvme;
+pub mod ramdisk;
 
-#[cfg(feature = "virtio")]
-pub use virtio_blk::VirtIOBlock;
-#[cfg(feature = "nvme")]
-pub use nvme::VirtIOBlock;
-
-use crate::board::BlockDeviceImpl;
+use super::registry;
 use alloc::sync::Arc;
+use easy_fs::partition::{self, Selector};
 use easy_fs::BlockDevice;
-use lazy_static::*;
 
-lazy_static! {
-    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
+/// The image the ram disk starts from, `make RAMDISK=on` builds the
+/// file system image into the kernel
+#[cfg(feature = "ramdisk")]
+pub static RAMDISK_IMAGE: &[u8] = include_bytes!(env!("RAMDISK_IMAGE"));
+
+/// Partition the root file system is on, when the first disk has a
+/// partition table
+pub const ROOT_PARTITION: Selector = Selector::Index(0);
+
+/// The root file system, the first disk bound or the partition of it
+/// `ROOT_PARTITION` picks
+pub fn root_device() -> Arc<dyn BlockDevice> {
+    let disk = registry::block_device(0).expect("no block device");
+    let table = partition::partitions(&disk);
+    if table.is_empty() {
+        return disk;
+    }
+    for entry in table.iter() {
+        println!(
+            "[kernel] partition {}: blocks {:#x}..{:#x} {:?} {}",
+            entry.index,
+            entry.start,
+            entry.start + entry.blocks,
+            entry.kind,
+            entry.label
+        );
+    }
+    partition::open(disk, ROOT_PARTITION).expect("no root partition")
 }
 
 #[allow(unused)]
 pub fn block_device_test() {
-    let block_device = BLOCK_DEVICE.clone();
+    let block_device = root_device();
     let mut write_buffer = [0u8; 512];
     let mut read_buffer = [0u8; 512];
     for i in 0..512 {
@@ -1,117 +1,581 @@
-use alloc::{boxed::Box, vec::Vec};
+//! NVMe controller with interrupt driven I/O queues
+//!
+//! The controller gets an admin queue and up to `IO_QUEUES` I/O queue
+//! pairs, each with `QUEUE_DEPTH` command slots. Commands are submitted to
+//! the queue with a free slot and complete into its completion queue, which
+//! raises the INTx pin of the function on the PLIC. The interrupt handler
+//! only reaps completions; whoever waits for a command yields to other
+//! tasks until its slot is done, reaping as well in case nobody took the
+//! interrupt yet.
+//!
+//! A run of blocks moves straight between the caller's buffers and the
+//! controller, as few commands as the PRP rules allow, each with a PRP
+//! list in its slot for the pages past the second. Buffers that aren't
+//! dword aligned go through the bounce page of the slot instead.
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::slice;
 use core::sync::atomic::{AtomicUsize, Ordering};
-use nvme_driver::{DmaAllocator, IrqController, NvmeInterface};
-use core::ptr::write_volatile;
 
 use crate::config::PAGE_SIZE;
-use crate::mm::StepByOne;
-use crate::mm::frame_alloc_trackers;
+use crate::drivers::registry::{Bound, Device, Driver, Match, Resources};
+use crate::mm::{frame_alloc, kernel_token, FrameTracker, PageTable, PhysAddr, VirtAddr};
 use crate::sync::UPSafeCell;
-use crate::mm::FrameTracker;
-use crate::mm::VirtAddr;
-use crate::mm::kernel_token;
-use crate::mm::PageTable;
-use crate::mm::frame_dealloc;
-use crate::mm::PhysAddr;
-use crate::mm::frame_alloc;
-use crate::mm::PhysPageNum;
+use crate::task::{current_task, suspend_current_and_run_next};
 
 use super::BlockDevice;
 
-use lazy_static::lazy_static;
+/// Controller registers
+const NVME_CAP: usize = 0x00;
+const NVME_INTMC: usize = 0x10;
+const NVME_CC: usize = 0x14;
+const NVME_CSTS: usize = 0x1c;
+const NVME_AQA: usize = 0x24;
+const NVME_ASQ: usize = 0x28;
+const NVME_ACQ: usize = 0x30;
+const NVME_DOORBELL: usize = 0x1000;
 
-lazy_static! {
-    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe { UPSafeCell::new(Vec::new()) };
+const NVME_CC_EN: u32 = 1 << 0;
+/// 64 byte submission and 16 byte completion queue entries
+const NVME_CC_IOSQES: u32 = 6 << 16;
+const NVME_CC_IOCQES: u32 = 4 << 20;
+const NVME_CSTS_RDY: u32 = 1 << 0;
+
+/// Admin commands
+const ADMIN_CREATE_SQ: u8 = 0x01;
+const ADMIN_CREATE_CQ: u8 = 0x05;
+const ADMIN_IDENTIFY: u8 = 0x06;
+const ADMIN_SET_FEATURES: u8 = 0x09;
+const FEATURE_NUMBER_OF_QUEUES: u32 = 0x07;
+const IDENTIFY_NAMESPACE: u32 = 0x00;
+
+/// NVM commands
+const NVM_WRITE: u8 = 0x01;
+const NVM_READ: u8 = 0x02;
+
+/// Queue flags of the create commands
+const QUEUE_PHYS_CONTIGUOUS: u32 = 1 << 0;
+const CQ_IRQ_ENABLED: u32 = 1 << 1;
+
+/// The namespace the file system is on
+const NAMESPACE: u32 = 1;
+const BLOCK_SIZE: usize = 512;
+/// Most bytes one command moves, the PRP list then has 32 entries
+const MAX_TRANSFER: usize = 128 * 1024;
+
+/// Entries of every ring, a page holds the submission ring
+const QUEUE_ENTRIES: usize = 32;
+/// Commands in flight per I/O queue, fewer than the ring entries so that
+/// a submission ring can never fill up
+const QUEUE_DEPTH: usize = 16;
+/// I/O queue pairs asked for, the controller may give fewer
+const IO_QUEUES: usize = 4;
+
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy, Default)]
+struct Command {
+    opcode: u8,
+    flags: u8,
+    cid: u16,
+    nsid: u32,
+    reserved: u64,
+    metadata: u64,
+    prp1: u64,
+    prp2: u64,
+    cdw10: u32,
+    cdw11: u32,
+    cdw12: u32,
+    cdw13: u32,
+    cdw14: u32,
+    cdw15: u32,
 }
 
-pub struct DmaAllocatorImpl;
-impl DmaAllocator for DmaAllocatorImpl {
-    fn dma_alloc(size: usize) -> usize{
-        let trakcers = frame_alloc_trackers(size / PAGE_SIZE);
-        let ppn_base = trakcers.as_ref().unwrap().last().unwrap().ppn;
-        QUEUE_FRAMES.exclusive_access().append(&mut trakcers.unwrap());
-        let pa: PhysAddr = ppn_base.into();
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy)]
+struct Completion {
+    result: u32,
+    reserved: u32,
+    sq_head: u16,
+    sq_id: u16,
+    cid: u16,
+    /// phase tag in bit 0, status above it
+    status: u16,
+}
 
-        pa.0
-    }
+#[derive(Clone, Copy, PartialEq)]
+enum SlotState {
+    Free,
+    Pending,
+    /// status and dword 0 of the completion
+    Done(u16, u32),
+}
 
-    fn dma_dealloc(addr: usize, size: usize) -> usize{
-        let addr = PhysAddr::from(addr);
-        let mut ppn_base: PhysPageNum = addr.into();
-        for _ in 0..(size / 0x1000) {
-            frame_dealloc(ppn_base);
-            ppn_base.step();
+struct Slot {
+    /// data that can't be handed to the controller where it is
+    bounce: FrameTracker,
+    /// PRP entries of the pages after the second
+    prp_list: FrameTracker,
+    state: SlotState,
+}
+
+struct Queue {
+    id: u16,
+    sq: FrameTracker,
+    cq: FrameTracker,
+    sq_tail: usize,
+    cq_head: usize,
+    /// phase tag of the entries the controller writes on this pass
+    phase: bool,
+    slots: Vec<Slot>,
+}
+
+fn page_addr(frame: &FrameTracker) -> usize {
+    let pa: PhysAddr = frame.ppn.into();
+    pa.0
+}
+
+impl Queue {
+    fn new(id: u16, depth: usize) -> Self {
+        Self {
+            id,
+            sq: frame_alloc().expect("no memory for an nvme queue"),
+            cq: frame_alloc().expect("no memory for an nvme queue"),
+            sq_tail: 0,
+            cq_head: 0,
+            phase: true,
+            slots: (0..depth)
+                .map(|_| Slot {
+                    bounce: frame_alloc().expect("no memory for nvme buffers"),
+                    prp_list: frame_alloc().expect("no memory for nvme buffers"),
+                    state: SlotState::Free,
+                })
+                .collect(),
         }
-        0
     }
 
-    fn phys_to_virt(phys: usize) -> usize{
-        phys
+    fn free_slot(&self) -> Option<usize> {
+        self.slots.iter().position(|slot| slot.state == SlotState::Free)
     }
 
-    fn virt_to_phys(virt: usize) -> usize{
-        PageTable::from_token(kernel_token())
-            .translate_va(VirtAddr::from(virt))
-            .unwrap()
-            .0
+    fn in_flight(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.state != SlotState::Free).count()
     }
 }
 
-pub struct IrqControllerImpl;
+struct NvmeController {
+    regs: usize,
+    doorbell_stride: usize,
+}
 
-impl IrqController for IrqControllerImpl {
-    fn enable_irq(_irq: usize){
+impl NvmeController {
+    fn read32(&self, offset: usize) -> u32 {
+        unsafe { ((self.regs + offset) as *const u32).read_volatile() }
+    }
 
+    fn write32(&self, offset: usize, value: u32) {
+        unsafe { ((self.regs + offset) as *mut u32).write_volatile(value) }
     }
 
-    fn disable_irq(_irq: usize){
+    fn read64(&self, offset: usize) -> u64 {
+        self.read32(offset) as u64 | (self.read32(offset + 4) as u64) << 32
+    }
 
+    fn write64(&self, offset: usize, value: u64) {
+        self.write32(offset, value as u32);
+        self.write32(offset + 4, (value >> 32) as u32);
+    }
+
+    fn wait_ready(&self, ready: bool) {
+        while (self.read32(NVME_CSTS) & NVME_CSTS_RDY != 0) != ready {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Put `command` in `slot` of the queue and ring its doorbell
+    fn submit(&self, queue: &mut Queue, slot: usize, mut command: Command) {
+        command.cid = slot as u16;
+        queue.slots[slot].state = SlotState::Pending;
+        let entries = page_addr(&queue.sq) as *mut Command;
+        unsafe { entries.add(queue.sq_tail).write_volatile(command) };
+        queue.sq_tail = (queue.sq_tail + 1) % QUEUE_ENTRIES;
+        let doorbell = NVME_DOORBELL + 2 * queue.id as usize * self.doorbell_stride;
+        self.write32(doorbell, queue.sq_tail as u32);
+    }
+
+    /// Mark the commands the controller completed and hand their entries
+    /// back, which also drops the interrupt of the queue
+    fn reap(&self, queue: &mut Queue) {
+        let entries = page_addr(&queue.cq) as *const Completion;
+        let mut reaped = false;
+        loop {
+            let completion = unsafe { entries.add(queue.cq_head).read_volatile() };
+            if (completion.status & 1 != 0) != queue.phase {
+                break;
+            }
+            if let Some(slot) = queue.slots.get_mut(completion.cid as usize) {
+                slot.state = SlotState::Done(completion.status >> 1, completion.result);
+            }
+            queue.cq_head += 1;
+            if queue.cq_head == QUEUE_ENTRIES {
+                queue.cq_head = 0;
+                queue.phase = !queue.phase;
+            }
+            reaped = true;
+        }
+        if reaped {
+            let doorbell = NVME_DOORBELL + (2 * queue.id as usize + 1) * self.doorbell_stride;
+            self.write32(doorbell, queue.cq_head as u32);
+        }
+    }
+
+    /// Run an admin command to completion, only used while probing
+    fn admin(&self, queue: &mut Queue, command: Command) -> Result<u32, u16> {
+        self.submit(queue, 0, command);
+        loop {
+            self.reap(queue);
+            if let SlotState::Done(status, result) = queue.slots[0].state {
+                queue.slots[0].state = SlotState::Free;
+                return if status == 0 { Ok(result) } else { Err(status) };
+            }
+            core::hint::spin_loop();
+        }
     }
 }
 
+/// A command in flight, `NvmeBlock::wait` returns when it is done
+struct NvmeRequest {
+    queue: usize,
+    slot: usize,
+}
 
-// 虚拟IO设备
-// pub struct VirtIOBlock(pub NvmeInterface::<DmaAllocatorImpl, IrqControllerImpl>);
+/// Blocks that one command moves
+struct Transfer {
+    block_id: usize,
+    /// bytes, whole blocks
+    len: usize,
+    /// physical address of each page the data is in, only the first may
+    /// start inside its page. Empty if the data goes through the bounce
+    /// page.
+    pages: Vec<usize>,
+    /// physical address right after the data
+    end: usize,
+    /// kernel address of the data
+    addr: usize,
+}
 
-impl BlockDevice for VirtIOBlock {
-    fn read_block(&self, sector_offset: usize, buf: &mut [u8]) {
-        assert_eq!(buf.len(), 512);
-        // 读取文件
-        self.0.exclusive_access().read_block(sector_offset, buf)
+impl Transfer {
+    /// Whether `next` can be moved by the same command, which needs this
+    /// to end and `next` to start on a page boundary
+    fn joins(&self, next: &Transfer) -> bool {
+        !self.pages.is_empty()
+            && !next.pages.is_empty()
+            && self.len + next.len <= MAX_TRANSFER
+            && self.end % PAGE_SIZE == 0
+            && next.pages[0] % PAGE_SIZE == 0
+    }
+}
+
+/// Split `len` bytes at kernel address `addr`, which go to or come from
+/// `block_id` onwards, into transfers and append them to `transfers`
+fn plan(transfers: &mut Vec<Transfer>, block_id: usize, addr: usize, len: usize) {
+    assert_eq!(len % BLOCK_SIZE, 0, "nvme moves whole blocks");
+    let page_table = PageTable::from_token(kernel_token());
+    let mut done = 0;
+    while done < len {
+        let start = addr + done;
+        let mut transfer = Transfer {
+            block_id: block_id + done / BLOCK_SIZE,
+            len: (len - done).min(MAX_TRANSFER),
+            pages: Vec::new(),
+            end: 0,
+            addr: start,
+        };
+        if start % 4 == 0 {
+            let mut offset = 0;
+            while offset < transfer.len {
+                let va = start + offset;
+                let size = (transfer.len - offset).min(PAGE_SIZE - va % PAGE_SIZE);
+                let pa = page_table.translate_va(VirtAddr::from(va)).expect("nvme buffer isn't mapped").0;
+                transfer.pages.push(pa);
+                transfer.end = pa + size;
+                offset += size;
+            }
+        } else {
+            // PRP entries have to be dword aligned
+            transfer.len = transfer.len.min(PAGE_SIZE);
+        }
+        done += transfer.len;
+        match transfers.last_mut() {
+            Some(last) if last.joins(&transfer) => {
+                last.len += transfer.len;
+                last.pages.append(&mut transfer.pages);
+                last.end = transfer.end;
+            }
+            _ => transfers.push(transfer),
+        }
     }
+}
+
+struct NvmeInner {
+    /// kept alive for the controller, only used while probing
+    _admin: Queue,
+    queues: Vec<Queue>,
+}
+
+pub struct NvmeBlock {
+    controller: NvmeController,
+    inner: UPSafeCell<NvmeInner>,
+    /// queue the next submission looks at first
+    next_queue: AtomicUsize,
+}
 
-    fn write_block(&self, sector_offset: usize, buf: &[u8]) {
-        assert_eq!(buf.len(), 512);
-        self.0.exclusive_access().write_block(sector_offset, buf)
+#[allow(unused)]
+pub static DRIVER: Driver = Driver {
+    name: "nvme",
+    // mass storage, non-volatile memory controller
+    matches: &[Match::PciClass(0x01, 0x08)],
+    probe,
+};
+
+fn probe(resources: &Resources) -> Option<Bound> {
+    if resources.base == 0 {
+        println!("[kernel] nvme controller without registers");
+        return None;
     }
+    let device = Arc::new(NvmeBlock::new(resources.base)?);
+    let irq_device = device.clone();
+    Some(Bound {
+        device: Device::Block(device),
+        handler: Some(Arc::new(move || irq_device.reap_all())),
+    })
 }
 
-pub struct VirtIOBlock(UPSafeCell<NvmeInterface::<DmaAllocatorImpl, IrqControllerImpl>>);
+impl NvmeBlock {
+    fn new(regs: usize) -> Option<Self> {
+        let mut controller = NvmeController { regs, doorbell_stride: 4 };
+        let cap = controller.read64(NVME_CAP);
+        controller.doorbell_stride = 4 << ((cap >> 32) & 0xf);
+
+        // reset, then give the controller its admin queue
+        controller.write32(NVME_CC, 0);
+        controller.wait_ready(false);
+        let mut admin = Queue::new(0, 1);
+        let size = (QUEUE_ENTRIES - 1) as u32;
+        controller.write32(NVME_AQA, size << 16 | size);
+        controller.write64(NVME_ASQ, page_addr(&admin.sq) as u64);
+        controller.write64(NVME_ACQ, page_addr(&admin.cq) as u64);
+        controller.write32(NVME_CC, NVME_CC_EN | NVME_CC_IOSQES | NVME_CC_IOCQES);
+        controller.wait_ready(true);
+
+        // the bounce page of the admin slot takes the identify data
+        let identify = Command {
+            opcode: ADMIN_IDENTIFY,
+            nsid: NAMESPACE,
+            prp1: page_addr(&admin.slots[0].bounce) as u64,
+            cdw10: IDENTIFY_NAMESPACE,
+            ..Default::default()
+        };
+        controller.admin(&mut admin, identify).ok()?;
+        let namespace = admin.slots[0].bounce.ppn.get_bytes_array();
+        let format = (namespace[26] & 0xf) as usize;
+        let block_size = 1 << namespace[128 + format * 4 + 2];
+        if block_size != BLOCK_SIZE {
+            println!("[kernel] nvme: {} byte blocks aren't supported", block_size);
+            return None;
+        }
+
+        // both counts are zero based, in the completion and the request
+        let wanted = (IO_QUEUES - 1) as u32;
+        let set_queues = Command {
+            opcode: ADMIN_SET_FEATURES,
+            cdw10: FEATURE_NUMBER_OF_QUEUES,
+            cdw11: wanted << 16 | wanted,
+            ..Default::default()
+        };
+        let granted = controller.admin(&mut admin, set_queues).ok()?;
+        let count = IO_QUEUES.min((granted & 0xffff) as usize + 1).min((granted >> 16) as usize + 1);
 
-impl VirtIOBlock {
-    #[allow(unused)]
-    pub fn new() -> Self {
-        println!("start log nvme block device");
-        config_pci();
-        println!("log nvme block device");
-        unsafe {
-            Self(UPSafeCell::new(
-                NvmeInterface::<DmaAllocatorImpl, IrqControllerImpl>::new(0x40000000)
-            ))
+        let mut queues = Vec::new();
+        for id in 1..=count as u16 {
+            let queue = Queue::new(id, QUEUE_DEPTH);
+            // pin based interrupts have only vector 0
+            let create_cq = Command {
+                opcode: ADMIN_CREATE_CQ,
+                prp1: page_addr(&queue.cq) as u64,
+                cdw10: size << 16 | id as u32,
+                cdw11: CQ_IRQ_ENABLED | QUEUE_PHYS_CONTIGUOUS,
+                ..Default::default()
+            };
+            controller.admin(&mut admin, create_cq).ok()?;
+            let create_sq = Command {
+                opcode: ADMIN_CREATE_SQ,
+                prp1: page_addr(&queue.sq) as u64,
+                cdw10: size << 16 | id as u32,
+                cdw11: (id as u32) << 16 | QUEUE_PHYS_CONTIGUOUS,
+                ..Default::default()
+            };
+            controller.admin(&mut admin, create_sq).ok()?;
+            queues.push(queue);
+        }
+        // unmask the interrupt
+        controller.write32(NVME_INTMC, 1);
+        println!("[kernel] nvme: {} I/O queues of {} commands", queues.len(), QUEUE_DEPTH);
+
+        Some(Self {
+            controller,
+            inner: unsafe { UPSafeCell::new(NvmeInner { _admin: admin, queues }) },
+            next_queue: AtomicUsize::new(0),
+        })
+    }
+
+    /// Take completions off every I/O queue
+    fn reap_all(&self) {
+        let mut inner = self.inner.exclusive_access();
+        for queue in inner.queues.iter_mut() {
+            self.controller.reap(queue);
+        }
+    }
+
+    /// Let other tasks run while a command is outstanding. Before the
+    /// first task runs there is nobody to yield to.
+    fn sleep(&self) {
+        if current_task().is_some() {
+            suspend_current_and_run_next();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Submit `transfer` to the queue with the most free slots, None if
+    /// every queue is full
+    fn try_submit(&self, transfer: &Transfer, write: bool) -> Option<NvmeRequest> {
+        let mut inner = self.inner.exclusive_access();
+        let count = inner.queues.len();
+        let first = self.next_queue.fetch_add(1, Ordering::Relaxed) % count;
+        let (index, slot) = (0..count)
+            .map(|i| (first + i) % count)
+            .filter_map(|index| inner.queues[index].free_slot().map(|slot| (index, slot)))
+            .min_by_key(|(index, _)| inner.queues[*index].in_flight())?;
+        let queue = &mut inner.queues[index];
+        let (prp1, prp2) = {
+            let slot = &queue.slots[slot];
+            let pages = &transfer.pages;
+            match pages.len() {
+                0 => {
+                    if write {
+                        let data = unsafe { slice::from_raw_parts(transfer.addr as *const u8, transfer.len) };
+                        slot.bounce.ppn.get_bytes_array()[..transfer.len].copy_from_slice(data);
+                    }
+                    (page_addr(&slot.bounce), 0)
+                }
+                1 => (pages[0], 0),
+                2 => (pages[0], pages[1]),
+                _ => {
+                    let list = slot.prp_list.ppn.get_bytes_array().as_mut_ptr() as *mut u64;
+                    for (i, page) in pages[1..].iter().enumerate() {
+                        unsafe { list.add(i).write_volatile(*page as u64) };
+                    }
+                    (pages[0], page_addr(&slot.prp_list))
+                }
+            }
+        };
+        let command = Command {
+            opcode: if write { NVM_WRITE } else { NVM_READ },
+            nsid: NAMESPACE,
+            prp1: prp1 as u64,
+            prp2: prp2 as u64,
+            cdw10: transfer.block_id as u32,
+            cdw11: (transfer.block_id >> 32) as u32,
+            // the block count is zero based
+            cdw12: (transfer.len / BLOCK_SIZE - 1) as u32,
+            ..Default::default()
+        };
+        self.controller.submit(queue, slot, command);
+        Some(NvmeRequest { queue: index, slot })
+    }
+
+    /// Sleep until `request` is done, copy what it read into the bounce
+    /// page to `buf` and free its slot
+    fn wait(&self, request: NvmeRequest, mut buf: Option<&mut [u8]>) {
+        let status = loop {
+            {
+                let mut inner = self.inner.exclusive_access();
+                let queue = &mut inner.queues[request.queue];
+                self.controller.reap(queue);
+                if let SlotState::Done(status, _) = queue.slots[request.slot].state {
+                    if let Some(buf) = buf.as_deref_mut() {
+                        let len = buf.len();
+                        buf.copy_from_slice(&queue.slots[request.slot].bounce.ppn.get_bytes_array()[..len]);
+                    }
+                    queue.slots[request.slot].state = SlotState::Free;
+                    break status;
+                }
+            }
+            self.sleep();
+        };
+        if status != 0 {
+            panic!("nvme command failed with status {:#x}", status);
+        }
+    }
+
+    fn finish(&self, transfer: &Transfer, request: NvmeRequest, write: bool) {
+        let bounced = !write && transfer.pages.is_empty();
+        let buf = bounced.then(|| unsafe { slice::from_raw_parts_mut(transfer.addr as *mut u8, transfer.len) });
+        self.wait(request, buf);
+    }
+
+    /// Move the blocks from `block_id` onwards to or from `buffers`, given
+    /// as kernel address and length. All the commands are outstanding at
+    /// once as far as there are free slots; when there are none, the
+    /// oldest command of this run is waited for first.
+    fn transfer(&self, block_id: usize, buffers: &[(usize, usize)], write: bool) {
+        let mut transfers = Vec::new();
+        let mut next = block_id;
+        for &(addr, len) in buffers {
+            plan(&mut transfers, next, addr, len);
+            next += len / BLOCK_SIZE;
+        }
+        let mut pending: VecDeque<(&Transfer, NvmeRequest)> = VecDeque::new();
+        for transfer in transfers.iter() {
+            let request = loop {
+                if let Some(request) = self.try_submit(transfer, write) {
+                    break request;
+                }
+                match pending.pop_front() {
+                    Some((transfer, request)) => self.finish(transfer, request, write),
+                    None => {
+                        self.reap_all();
+                        self.sleep();
+                    }
+                }
+            };
+            pending.push_back((transfer, request));
+        }
+        while let Some((transfer, request)) = pending.pop_front() {
+            self.finish(transfer, request, write);
         }
     }
 }
 
-// config pci
-pub fn config_pci(){
-    let ptr = 0x30008010 as *mut u32;
-    unsafe { write_volatile(ptr, 0xffffffff); }
-    let ptr = 0x30008010 as *mut u32;
-    unsafe { write_volatile(ptr, 0x4); }
-    let ptr = 0x30008010 as *mut u32;
-    unsafe { write_volatile(ptr, 0x40000000); }
-    let ptr = 0x30008004 as *mut u32;
-    unsafe { write_volatile(ptr, 0x100006); }
-    let ptr = 0x3000803c as *mut u32;
-    unsafe { write_volatile(ptr, 0x21); }
-}
\ No newline at end of file
+impl BlockDevice for NvmeBlock {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        assert_eq!(buf.len(), BLOCK_SIZE);
+        self.read_blocks(block_id, &mut [buf]);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        assert_eq!(buf.len(), BLOCK_SIZE);
+        self.write_blocks(block_id, &[buf]);
+    }
+
+    fn read_blocks(&self, block_id: usize, bufs: &mut [&mut [u8]]) {
+        let buffers: Vec<(usize, usize)> = bufs.iter_mut().map(|buf| (buf.as_mut_ptr() as usize, buf.len())).collect();
+        self.transfer(block_id, &buffers, false);
+    }
+
+    fn write_blocks(&self, block_id: usize, bufs: &[&[u8]]) {
+        let buffers: Vec<(usize, usize)> = bufs.iter().map(|buf| (buf.as_ptr() as usize, buf.len())).collect();
+        self.transfer(block_id, &buffers, true);
+    }
+}
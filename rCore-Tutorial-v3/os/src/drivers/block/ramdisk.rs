@@ -0,0 +1,58 @@
+//! A disk in kernel frames
+//!
+//! Like xv6's `ramdisk.c`, the disk can start out as a copy of an image
+//! built into the kernel, so a file system can run without NVMe or
+//! virtio. The frames aren't contiguous, block `i` lives in the page
+//! `i / BLOCKS_PER_PAGE`.
+use alloc::vec::Vec;
+
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_alloc, FrameTracker};
+
+use super::BlockDevice;
+
+const BLOCK_SIZE: usize = 512;
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE / BLOCK_SIZE;
+
+pub struct RamDisk {
+    pages: Vec<FrameTracker>,
+}
+
+impl RamDisk {
+    /// A zeroed disk of `blocks` blocks
+    pub fn new(blocks: usize) -> Self {
+        let pages = (blocks + BLOCKS_PER_PAGE - 1) / BLOCKS_PER_PAGE;
+        Self {
+            pages: (0..pages).map(|_| frame_alloc().expect("no memory for the ram disk")).collect(),
+        }
+    }
+
+    /// A disk holding a copy of `image`, rounded up to whole blocks
+    pub fn from_image(image: &[u8]) -> Self {
+        let disk = Self::new((image.len() + BLOCK_SIZE - 1) / BLOCK_SIZE);
+        for (page, chunk) in disk.pages.iter().zip(image.chunks(PAGE_SIZE)) {
+            page.ppn.get_bytes_array()[..chunk.len()].copy_from_slice(chunk);
+        }
+        disk
+    }
+
+    pub fn blocks(&self) -> usize {
+        self.pages.len() * BLOCKS_PER_PAGE
+    }
+
+    fn block(&self, block_id: usize) -> &'static mut [u8] {
+        assert!(block_id < self.blocks(), "block {} is past the end of the ram disk", block_id);
+        let offset = block_id % BLOCKS_PER_PAGE * BLOCK_SIZE;
+        &mut self.pages[block_id / BLOCKS_PER_PAGE].ppn.get_bytes_array()[offset..offset + BLOCK_SIZE]
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(self.block(block_id));
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.block(block_id).copy_from_slice(buf);
+    }
+}
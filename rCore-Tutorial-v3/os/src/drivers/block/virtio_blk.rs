@@ -1,46 +1,191 @@
+//! virtio-blk behind a virtio-mmio transport
+//!
+//! A request is a chain of a header, the data and a status byte. The
+//! `VirtIOBlk` of virtio-drivers at rev 4ee80e5 only puts one block in the
+//! data, so the driver runs its own queue on `drivers::virtio`, and a run
+//! of blocks goes to the device as one request with a descriptor for each
+//! physically contiguous run of the buffers, split over several requests
+//! when there are too many runs. Requests are polled for, one at a time,
+//! and one that fails is reported and ends the transfer, the file systems
+//! above have no way to take an error.
 use super::BlockDevice;
+use crate::drivers::registry::{Bound, Device, Driver, Match, Resources, VIRTIO_DEVICE_BLOCK};
+use crate::drivers::virtio::{Transport, VirtQueue};
 use crate::mm::{
     frame_alloc, frame_dealloc, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum,
     StepByOne, VirtAddr, frame_alloc_trackers,
 };
+use crate::config::PAGE_SIZE;
 use crate::sync::UPSafeCell;
-use alloc::vec::Vec;
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
 use lazy_static::*;
-use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
+use virtio_drivers::Hal;
+
+const BLOCK_SIZE: usize = 512;
+const QUEUE_SIZE: usize = 32;
+/// Data descriptors of a request, the header and the status take the rest
+const MAX_SEGMENTS: usize = QUEUE_SIZE - 2;
+
+const REQ_IN: u32 = 0;
+const REQ_OUT: u32 = 1;
+const STATUS_OK: u8 = 0;
+/// type, reserved and sector of a request
+const HEADER_SIZE: usize = 16;
+/// Where the status byte goes in the request page, after the header
+const STATUS_OFFSET: usize = HEADER_SIZE;
 
 #[allow(unused)]
-const VIRTIO0: usize = 0x10001000;
+pub static DRIVER: Driver = Driver {
+    name: "virtio-blk",
+    matches: &[Match::Virtio(VIRTIO_DEVICE_BLOCK)],
+    probe,
+};
 
-pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static, VirtioHal>>);
+pub struct VirtIOBlock {
+    transport: Transport,
+    inner: UPSafeCell<VirtIOBlockInner>,
+}
+
+struct VirtIOBlockInner {
+    queue: VirtQueue,
+    /// header and status of the request in flight
+    request: FrameTracker,
+}
 
 lazy_static! {
     static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe { UPSafeCell::new(Vec::new()) };
 }
 
+impl VirtIOBlock {
+    /// Read or write the blocks from `block_id` on, `segments` are the
+    /// physical address and length of the buffers in turn. False if the
+    /// device reported an error.
+    fn request(&self, kind: u32, block_id: usize, segments: &[(usize, usize)]) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        let pa: PhysAddr = inner.request.ppn.into();
+        let page = inner.request.ppn.get_bytes_array();
+        page[0..4].copy_from_slice(&kind.to_le_bytes());
+        page[4..8].fill(0);
+        page[8..16].copy_from_slice(&(block_id as u64).to_le_bytes());
+        page[STATUS_OFFSET] = 0xff;
+
+        let mut chain: Vec<(usize, usize, bool)> = Vec::with_capacity(segments.len() + 2);
+        chain.push((pa.0, HEADER_SIZE, false));
+        chain.extend(segments.iter().map(|&(addr, len)| (addr, len, kind == REQ_IN)));
+        chain.push((pa.0 + STATUS_OFFSET, 1, true));
+        // one request at a time, a chain of at most QUEUE_SIZE always fits
+        if inner.queue.add(&chain).is_none() {
+            println!("[kernel] virtio-blk: no room for a request at block {}", block_id);
+            return false;
+        }
+        self.transport.notify(&inner.queue);
+        while inner.queue.pop_used().is_none() {
+            core::hint::spin_loop();
+        }
+        let status = inner.request.ppn.get_bytes_array()[STATUS_OFFSET];
+        if status != STATUS_OK {
+            let op = if kind == REQ_IN { "read" } else { "write" };
+            println!("[kernel] virtio-blk: {} at block {} failed, status {}", op, block_id, status);
+            return false;
+        }
+        true
+    }
+
+    /// As few requests as the segments fit in, each one ending on a block.
+    /// A block whose segments don't all fit goes to the next request, split
+    /// where the block begins.
+    fn transfer(&self, kind: u32, block_id: usize, segments: &[(usize, usize)]) {
+        let mut block_id = block_id;
+        let mut segments: VecDeque<(usize, usize)> = segments.iter().copied().collect();
+        while !segments.is_empty() {
+            let mut chain = Vec::with_capacity(MAX_SEGMENTS);
+            let mut bytes = 0;
+            while chain.len() < MAX_SEGMENTS {
+                match segments.pop_front() {
+                    Some(segment) => {
+                        bytes += segment.1;
+                        chain.push(segment);
+                    }
+                    None => break,
+                }
+            }
+            // a block spans two segments at most, so some are left
+            let mut excess = bytes % BLOCK_SIZE;
+            bytes -= excess;
+            while excess > 0 {
+                let (addr, len) = chain.pop().unwrap();
+                let keep = len.saturating_sub(excess);
+                if keep > 0 {
+                    chain.push((addr, keep));
+                }
+                segments.push_front((addr + keep, len - keep));
+                excess -= len - keep;
+            }
+            if !self.request(kind, block_id, &chain) {
+                return;
+            }
+            block_id += bytes / BLOCK_SIZE;
+        }
+    }
+}
+
+/// Add the physically contiguous runs of `buf` to `segments`. Kernel
+/// stacks aren't identity mapped, their pages may be anywhere.
+fn add_segments(buf: &[u8], segments: &mut Vec<(usize, usize)>) {
+    assert_eq!(buf.len() % BLOCK_SIZE, 0, "VirtIOBlk buffers are whole blocks");
+    let mut va = buf.as_ptr() as usize;
+    let end = va + buf.len();
+    while va < end {
+        let len = ((va / PAGE_SIZE + 1) * PAGE_SIZE).min(end) - va;
+        let pa = VirtioHal::virt_to_phys(va);
+        match segments.last_mut() {
+            Some(last) if last.0 + last.1 == pa => last.1 += len,
+            _ => segments.push((pa, len)),
+        }
+        va += len;
+    }
+}
+
 impl BlockDevice for VirtIOBlock {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        self.0
-            .exclusive_access()
-            .read_block(block_id, buf)
-            .expect("Error when reading VirtIOBlk");
+        self.read_blocks(block_id, &mut [buf]);
     }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
-        self.0
-            .exclusive_access()
-            .write_block(block_id, buf)
-            .expect("Error when writing VirtIOBlk");
+        self.write_blocks(block_id, &[buf]);
     }
-}
-
-impl VirtIOBlock {
-    #[allow(unused)]
-    pub fn new() -> Self {
-        unsafe {
-            Self(UPSafeCell::new(
-                VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
-            ))
+    fn read_blocks(&self, block_id: usize, bufs: &mut [&mut [u8]]) {
+        let mut segments = Vec::new();
+        for buf in bufs.iter() {
+            add_segments(buf, &mut segments);
         }
+        self.transfer(REQ_IN, block_id, &segments);
     }
+    fn write_blocks(&self, block_id: usize, bufs: &[&[u8]]) {
+        let mut segments = Vec::new();
+        for buf in bufs.iter() {
+            add_segments(buf, &mut segments);
+        }
+        self.transfer(REQ_OUT, block_id, &segments);
+    }
+}
+
+fn probe(resources: &Resources) -> Option<Bound> {
+    let transport = Transport::new(resources.base)?;
+    transport.negotiate(0);
+    let queue = transport.queue(0, QUEUE_SIZE)?;
+    transport.driver_ok();
+    let device = Arc::new(VirtIOBlock {
+        transport,
+        inner: unsafe { UPSafeCell::new(VirtIOBlockInner { queue, request: frame_alloc()? }) },
+    });
+    let irq_device = device.clone();
+    Some(Bound {
+        device: Device::Block(device),
+        // requests are polled for, the interrupt only needs to be taken
+        handler: Some(Arc::new(move || {
+            irq_device.transport.ack_interrupt();
+        })),
+    })
 }
 
 pub struct VirtioHal;
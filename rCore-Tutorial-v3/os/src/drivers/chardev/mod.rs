@@ -0,0 +1,10 @@
+pub mod ns16550a;
+pub mod virtio_console;
+
+/// A byte stream device, a serial port or a console
+pub trait CharDevice: Send + Sync {
+    /// Take a received byte if there is one
+    fn read(&self) -> Option<u8>;
+    /// Send `buf`, return how much of it the device took
+    fn write(&self, buf: &[u8]) -> usize;
+}
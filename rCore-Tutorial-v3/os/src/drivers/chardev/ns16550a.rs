@@ -0,0 +1,64 @@
+//! 16550 UART, bound by the `compatible` of its device tree node
+//!
+//! The SBI console goes through the same UART, this gives user space the
+//! raw byte stream as `/dev/ttyS0`. It is polled like the SBI does it, the
+//! interrupts of the UART stay off.
+use alloc::sync::Arc;
+
+use crate::drivers::registry::{Bound, Device, Driver, Match, Resources};
+
+use super::CharDevice;
+
+/// Registers, one byte apart on QEMU's virt machine
+const RBR: usize = 0;
+const THR: usize = 0;
+const LSR: usize = 5;
+
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+pub static DRIVER: Driver = Driver {
+    name: "ns16550a",
+    matches: &[Match::Compatible("ns16550a")],
+    probe,
+};
+
+pub struct Uart {
+    base: usize,
+}
+
+fn probe(resources: &Resources) -> Option<Bound> {
+    if resources.base == 0 {
+        return None;
+    }
+    Some(Bound { device: Device::Char(Arc::new(Uart { base: resources.base })), handler: None })
+}
+
+impl Uart {
+    fn read_reg(&self, offset: usize) -> u8 {
+        unsafe { ((self.base + offset) as *const u8).read_volatile() }
+    }
+
+    fn write_reg(&self, offset: usize, value: u8) {
+        unsafe { ((self.base + offset) as *mut u8).write_volatile(value) }
+    }
+}
+
+impl CharDevice for Uart {
+    fn read(&self) -> Option<u8> {
+        if self.read_reg(LSR) & LSR_DATA_READY == 0 {
+            return None;
+        }
+        Some(self.read_reg(RBR))
+    }
+
+    fn write(&self, buf: &[u8]) -> usize {
+        for byte in buf {
+            while self.read_reg(LSR) & LSR_THR_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            self.write_reg(THR, *byte);
+        }
+        buf.len()
+    }
+}
@@ -0,0 +1,170 @@
+//! virtio-console behind a virtio-mmio transport
+//!
+//! Port 0 of a virtio-serial device without the multiport feature, QEMU's
+//! `-device virtio-serial-device -device virtconsole`. Queue 0 receives and
+//! queue 1 transmits. Unlike the SBI console every write is one trap to
+//! the host instead of one per byte. The receive queue is kept full of
+//! buffers carved out of one page, the interrupt moves what came in to
+//! `input` and hands the buffers back. A write the host doesn't finish
+//! within `TX_POLLS` stops there, the page stays on the queue until the
+//! next write sees it come back.
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+
+use crate::config::PAGE_SIZE;
+use crate::drivers::registry::{Bound, Device, Driver, Match, Resources, VIRTIO_DEVICE_CONSOLE};
+use crate::drivers::virtio::{Transport, VirtQueue};
+use crate::mm::{frame_alloc, FrameTracker, PhysAddr};
+use crate::sync::UPSafeCell;
+
+use super::CharDevice;
+
+const RECEIVEQ: u32 = 0;
+const TRANSMITQ: u32 = 1;
+const QUEUE_SIZE: usize = 8;
+/// Received bytes kept before the oldest are dropped
+const INPUT_LIMIT: usize = 4096;
+/// Looks at the used ring before a write gives up
+const TX_POLLS: usize = 1 << 20;
+
+pub static DRIVER: Driver = Driver {
+    name: "virtio-console",
+    matches: &[Match::Virtio(VIRTIO_DEVICE_CONSOLE)],
+    probe,
+};
+
+pub struct VirtIOConsole {
+    transport: Transport,
+    inner: UPSafeCell<VirtIOConsoleInner>,
+}
+
+struct VirtIOConsoleInner {
+    receiveq: VirtQueue,
+    transmitq: VirtQueue,
+    /// split into `QUEUE_SIZE` receive buffers
+    rx_page: FrameTracker,
+    /// which receive buffer a posted chain is
+    rx_posted: BTreeMap<u16, usize>,
+    tx_page: FrameTracker,
+    /// `tx_page` is on the transmit queue, a write gave up on it
+    tx_pending: bool,
+    input: VecDeque<u8>,
+}
+
+fn probe(resources: &Resources) -> Option<Bound> {
+    let transport = Transport::new(resources.base)?;
+    transport.negotiate(0);
+    let receiveq = transport.queue(RECEIVEQ, QUEUE_SIZE)?;
+    let transmitq = transport.queue(TRANSMITQ, QUEUE_SIZE)?;
+    let mut inner = VirtIOConsoleInner {
+        receiveq,
+        transmitq,
+        rx_page: frame_alloc()?,
+        rx_posted: BTreeMap::new(),
+        tx_page: frame_alloc()?,
+        tx_pending: false,
+        input: VecDeque::new(),
+    };
+    for buffer in 0..QUEUE_SIZE {
+        inner.post_rx(buffer);
+    }
+    transport.driver_ok();
+    transport.notify(&inner.receiveq);
+    let device = Arc::new(VirtIOConsole {
+        transport,
+        inner: unsafe { UPSafeCell::new(inner) },
+    });
+    let irq_device = device.clone();
+    Some(Bound {
+        device: Device::Char(device),
+        handler: Some(Arc::new(move || irq_device.handle_irq())),
+    })
+}
+
+impl VirtIOConsoleInner {
+    fn rx_buffer(&self, buffer: usize) -> (usize, usize) {
+        let pa: PhysAddr = self.rx_page.ppn.into();
+        let len = PAGE_SIZE / QUEUE_SIZE;
+        (pa.0 + buffer * len, len)
+    }
+
+    fn post_rx(&mut self, buffer: usize) {
+        let (addr, len) = self.rx_buffer(buffer);
+        let head = self.receiveq.add(&[(addr, len, true)]).expect("virtio-console receive queue is full");
+        self.rx_posted.insert(head, buffer);
+    }
+
+    /// Move what the device received to `input`, true if there was any
+    fn poll_rx(&mut self) -> bool {
+        let mut received = false;
+        while let Some((head, len)) = self.receiveq.pop_used() {
+            let buffer = self.rx_posted.remove(&head).unwrap();
+            let (offset, size) = (buffer * PAGE_SIZE / QUEUE_SIZE, PAGE_SIZE / QUEUE_SIZE);
+            let bytes = &self.rx_page.ppn.get_bytes_array()[offset..offset + len.min(size)];
+            for &byte in bytes {
+                if self.input.len() == INPUT_LIMIT {
+                    self.input.pop_front();
+                }
+                self.input.push_back(byte);
+            }
+            self.post_rx(buffer);
+            received = true;
+        }
+        received
+    }
+
+    /// Wait a bounded time for the host to hand `tx_page` back
+    fn wait_tx(&mut self) -> bool {
+        for _ in 0..TX_POLLS {
+            if self.transmitq.pop_used().is_some() {
+                self.tx_pending = false;
+                return true;
+            }
+            core::hint::spin_loop();
+        }
+        false
+    }
+}
+
+impl VirtIOConsole {
+    fn handle_irq(&self) {
+        self.transport.ack_interrupt();
+        let mut inner = self.inner.exclusive_access();
+        if inner.poll_rx() {
+            self.transport.notify(&inner.receiveq);
+        }
+    }
+}
+
+impl CharDevice for VirtIOConsole {
+    fn read(&self) -> Option<u8> {
+        let mut inner = self.inner.exclusive_access();
+        // the kernel takes no interrupts, look at the queue as well
+        if inner.poll_rx() {
+            self.transport.notify(&inner.receiveq);
+        }
+        inner.input.pop_front()
+    }
+
+    fn write(&self, buf: &[u8]) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        if inner.tx_pending && !inner.wait_tx() {
+            return 0;
+        }
+        let pa: PhysAddr = inner.tx_page.ppn.into();
+        let mut written = 0;
+        for chunk in buf.chunks(PAGE_SIZE) {
+            inner.tx_page.ppn.get_bytes_array()[..chunk.len()].copy_from_slice(chunk);
+            if inner.transmitq.add(&[(pa.0, chunk.len(), false)]).is_none() {
+                break;
+            }
+            self.transport.notify(&inner.transmitq);
+            inner.tx_pending = true;
+            if !inner.wait_tx() {
+                break;
+            }
+            written += chunk.len();
+        }
+        written
+    }
+}
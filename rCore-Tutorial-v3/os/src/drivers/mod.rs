@@ -1,3 +1,31 @@
 pub mod block;
+pub mod chardev;
+pub mod net;
+pub mod registry;
+pub mod rng;
+pub mod virtio;
 
-pub use block::BLOCK_DEVICE;
+pub use block::root_device;
+
+/// Register the drivers and bind them to what the buses have
+pub fn init() {
+    // added first, so the root file system is on it
+    #[cfg(feature = "ramdisk")]
+    registry::add(
+        "ramdisk",
+        registry::Device::Block(alloc::sync::Arc::new(block::ramdisk::RamDisk::from_image(block::RAMDISK_IMAGE))),
+    );
+    #[cfg(feature = "nvme")]
+    registry::register(&block::nvme::DRIVER);
+    #[cfg(feature = "virtio")]
+    registry::register(&block::virtio_blk::DRIVER);
+    registry::register(&net::e1000::DRIVER);
+    registry::register(&net::e82574::DRIVER);
+    registry::register(&net::virtio_net::DRIVER);
+    registry::register(&rng::virtio_rng::DRIVER);
+    registry::register(&chardev::virtio_console::DRIVER);
+    registry::register(&chardev::ns16550a::DRIVER);
+    registry::probe_pci();
+    registry::probe_virtio_mmio();
+    registry::probe_device_tree();
+}
@@ -0,0 +1,512 @@
+//! Intel 82540EM (QEMU's `e1000`) on PCI
+//!
+//! Both rings have `RING_SIZE` 16-byte descriptors in a page each, every
+//! descriptor with a page of its own as buffer. Frames are picked up when
+//! the stack polls, the only interrupt asked for is the link status
+//! change, which is remembered for `link_changed`.
+//!
+//! The NIC checks the IPv4 header and TCP/UDP checksums of what it
+//! receives and fills them in on transmit: a context descriptor goes
+//! before every IPv4 TCP or UDP frame and tells it where they are.
+//!
+//! With CTRL.VME the NIC also takes 802.1Q tags off what it receives and
+//! leaves the tag control information in `special`, and puts the tag
+//! from `special` into frames whose descriptor has VLE set. There is no
+//! VLAN filter, every tagged frame comes in.
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{fence, AtomicBool, Ordering};
+
+use crate::drivers::registry::{Bound, Device, Driver, Match, Resources};
+use crate::mm::{frame_alloc, FrameTracker, PhysAddr};
+use crate::net::Offload;
+use crate::sync::UPSafeCell;
+
+use super::NetDevice;
+
+/// Registers [E1000 13.4]
+const E1000_CTL: usize = 0x00000;
+const E1000_STATUS: usize = 0x00008;
+const E1000_VET: usize = 0x00038;
+const E1000_ICR: usize = 0x000c0;
+const E1000_IMS: usize = 0x000d0;
+const E1000_IMC: usize = 0x000d8;
+const E1000_RCTL: usize = 0x00100;
+const E1000_TCTL: usize = 0x00400;
+const E1000_TIPG: usize = 0x00410;
+const E1000_RDBAL: usize = 0x02800;
+const E1000_RDBAH: usize = 0x02804;
+const E1000_RDLEN: usize = 0x02808;
+const E1000_RDH: usize = 0x02810;
+const E1000_RDT: usize = 0x02818;
+const E1000_RDTR: usize = 0x02820;
+const E1000_RADV: usize = 0x0282c;
+const E1000_TDBAL: usize = 0x03800;
+const E1000_TDBAH: usize = 0x03804;
+const E1000_TDLEN: usize = 0x03808;
+const E1000_TDH: usize = 0x03810;
+const E1000_TDT: usize = 0x03818;
+const E1000_RXCSUM: usize = 0x05000;
+const E1000_MTA: usize = 0x05200;
+const E1000_RAH: usize = 0x05404;
+
+const E1000_CTL_SLU: u32 = 1 << 6;
+const E1000_CTL_RST: u32 = 1 << 26;
+const E1000_CTL_VME: u32 = 1 << 30;
+const E1000_STATUS_LU: u32 = 1 << 1;
+const E1000_ICR_LSC: u32 = 1 << 2;
+/// Address valid bit of the receive address the EEPROM loaded
+const E1000_RAH_AV: u32 = 1 << 31;
+
+const E1000_RCTL_EN: u32 = 1 << 1;
+const E1000_RCTL_BAM: u32 = 1 << 15;
+/// with BSEX clear, 2048-byte buffers
+const E1000_RCTL_SZ_2048: u32 = 0;
+const E1000_RCTL_SECRC: u32 = 1 << 26;
+const E1000_RXCSUM_IPOFL: u32 = 1 << 8;
+const E1000_RXCSUM_TUOFL: u32 = 1 << 9;
+
+const E1000_TCTL_EN: u32 = 1 << 1;
+const E1000_TCTL_PSP: u32 = 1 << 3;
+const E1000_TCTL_CT: u32 = 0x10 << 4;
+const E1000_TCTL_COLD: u32 = 0x40 << 12;
+
+/// Transmit descriptor commands [E1000 3.3.3.1]
+const E1000_TXD_CMD_EOP: u8 = 0x01;
+const E1000_TXD_CMD_IFCS: u8 = 0x02;
+const E1000_TXD_CMD_RS: u8 = 0x08;
+const E1000_TXD_CMD_DEXT: u8 = 0x20;
+const E1000_TXD_CMD_VLE: u8 = 0x40;
+/// Types of the extended transmit descriptors [E1000 3.3.5]
+const E1000_TXD_DTYP_C: u32 = 0x0;
+const E1000_TXD_DTYP_D: u32 = 0x1;
+/// Context descriptor TUCMD [E1000 3.3.6]
+const E1000_TXD_TUCMD_TCP: u8 = 0x01;
+const E1000_TXD_TUCMD_IP: u8 = 0x02;
+/// Data descriptor POPTS [E1000 3.3.7.1]
+const E1000_TXD_POPTS_IXSM: u8 = 0x01;
+const E1000_TXD_POPTS_TXSM: u8 = 0x02;
+const E1000_TXD_STAT_DD: u8 = 0x01;
+
+/// Receive descriptor status and errors [E1000 3.2.3]
+const E1000_RXD_STAT_DD: u8 = 0x01;
+const E1000_RXD_STAT_EOP: u8 = 0x02;
+const E1000_RXD_STAT_IXSM: u8 = 0x04;
+const E1000_RXD_STAT_VP: u8 = 0x08;
+const E1000_RXD_STAT_TCPCS: u8 = 0x20;
+const E1000_RXD_STAT_IPCS: u8 = 0x40;
+const E1000_RXD_ERR_TCPE: u8 = 0x20;
+const E1000_RXD_ERR_IPE: u8 = 0x40;
+
+/// Descriptors of each ring, RDLEN and TDLEN have to be a multiple of 128
+/// bytes, 8 descriptors
+const RING_SIZE: usize = 16;
+const BUFFER_SIZE: usize = 2048;
+/// 4096 bits of multicast hash
+const MTA_WORDS: usize = 128;
+
+const ETH_TYPE_IPV4: u16 = 0x0800;
+const ETH_TYPE_VLAN: u16 = 0x8100;
+
+pub static DRIVER: Driver = Driver {
+    name: "e1000",
+    // 82540EM, and the 82545EM QEMU also has
+    matches: &[Match::PciId(0x8086, 0x100e), Match::PciId(0x8086, 0x100f)],
+    probe,
+};
+
+/// Legacy transmit descriptor [E1000 3.3.3]
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy)]
+struct TxDesc {
+    addr: u64,
+    length: u16,
+    cso: u8,
+    cmd: u8,
+    status: u8,
+    css: u8,
+    special: u16,
+}
+
+/// TCP/IP context descriptor [E1000 3.3.6], it takes a slot of the
+/// transmit ring and tells the NIC where the checksums of the data
+/// descriptors after it are
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy)]
+struct ContextDesc {
+    ipcss: u8,
+    ipcso: u8,
+    ipcse: u16,
+    tucss: u8,
+    tucso: u8,
+    tucse: u16,
+    cmd_and_length: u32,
+    status: u8,
+    hdrlen: u8,
+    mss: u16,
+}
+
+/// Receive descriptor [E1000 3.2.3]
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy)]
+struct RxDesc {
+    addr: u64,
+    length: u16,
+    csum: u16,
+    status: u8,
+    errors: u8,
+    special: u16,
+}
+
+impl RxDesc {
+    /// Whether the NIC checked both the IPv4 header and the TCP/UDP
+    /// checksum and found them right
+    fn checksum_verified(&self) -> bool {
+        let checked = E1000_RXD_STAT_IPCS | E1000_RXD_STAT_TCPCS;
+        self.status & E1000_RXD_STAT_IXSM == 0
+            && self.status & checked == checked
+            && self.errors & (E1000_RXD_ERR_IPE | E1000_RXD_ERR_TCPE) == 0
+    }
+}
+
+/// Where the headers of an IPv4 TCP or UDP frame are
+struct Headers {
+    ip: usize,
+    l4: usize,
+    /// one past the IPv4 packet, short frames may be padded after it
+    end: usize,
+    tcp: bool,
+}
+
+impl Headers {
+    fn parse(frame: &[u8]) -> Option<Self> {
+        let mut ip = 14;
+        let mut eth_type = u16::from_be_bytes([*frame.get(12)?, *frame.get(13)?]);
+        if eth_type == ETH_TYPE_VLAN {
+            ip += 4;
+            eth_type = u16::from_be_bytes([*frame.get(16)?, *frame.get(17)?]);
+        }
+        if eth_type != ETH_TYPE_IPV4 || frame.len() < ip + 20 {
+            return None;
+        }
+        let l4 = ip + ((frame[ip] & 0xf) as usize) * 4;
+        let end = ip + u16::from_be_bytes([frame[ip + 2], frame[ip + 3]]) as usize;
+        // fragments have no complete segment to sum up
+        let fragmented = u16::from_be_bytes([frame[ip + 6], frame[ip + 7]]) & 0x3fff != 0;
+        let tcp = match frame[ip + 9] {
+            6 => true,
+            17 => false,
+            _ => return None,
+        };
+        let l4_len = if tcp { 20 } else { 8 };
+        if fragmented || end > frame.len() || end < l4 + l4_len {
+            return None;
+        }
+        Some(Self { ip, l4, end, tcp })
+    }
+
+    /// Offset of the TCP or UDP checksum in the frame
+    fn checksum(&self) -> usize {
+        self.l4 + if self.tcp { 16 } else { 6 }
+    }
+
+    /// The NIC adds the segment to the checksum field, it has to start out
+    /// as the pseudo header sum. The IPv4 header checksum is computed over
+    /// a zero field.
+    fn seed(&self, frame: &mut [u8]) {
+        let mut sum = frame[self.ip + 9] as u32 + (self.end - self.l4) as u32;
+        for i in (self.ip + 12..self.ip + 20).step_by(2) {
+            sum += u16::from_be_bytes([frame[i], frame[i + 1]]) as u32;
+        }
+        while sum >> 16 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        let at = self.checksum();
+        frame[at..at + 2].copy_from_slice(&(sum as u16).to_be_bytes());
+        frame[self.ip + 10..self.ip + 12].copy_from_slice(&[0, 0]);
+    }
+
+    fn context(&self) -> ContextDesc {
+        let tucmd = E1000_TXD_CMD_DEXT | E1000_TXD_CMD_RS | E1000_TXD_TUCMD_IP
+            | if self.tcp { E1000_TXD_TUCMD_TCP } else { 0 };
+        ContextDesc {
+            ipcss: self.ip as u8,
+            ipcso: (self.ip + 10) as u8,
+            ipcse: (self.l4 - 1) as u16,
+            tucss: self.l4 as u8,
+            tucso: self.checksum() as u8,
+            tucse: (self.end - 1) as u16,
+            cmd_and_length: (E1000_TXD_DTYP_C << 20) | ((tucmd as u32) << 24),
+            status: 0,
+            hdrlen: 0,
+            mss: 0,
+        }
+    }
+}
+
+fn page_addr(frame: &FrameTracker) -> usize {
+    let pa: PhysAddr = frame.ppn.into();
+    pa.0
+}
+
+struct Rings {
+    tx: FrameTracker,
+    rx: FrameTracker,
+    /// buffer i belongs to descriptor i
+    tx_buffers: Vec<FrameTracker>,
+    rx_buffers: Vec<FrameTracker>,
+    /// next descriptor the NIC hands back
+    rx_next: usize,
+    /// whether the NIC verified the checksums of the frame `recv` took last
+    rx_verified: bool,
+    /// the tag the NIC stripped off the frame `recv` took last
+    rx_vlan: Option<u16>,
+}
+
+impl Rings {
+    fn new() -> Self {
+        let page = || frame_alloc().expect("no memory for e1000 rings");
+        Self {
+            tx: page(),
+            rx: page(),
+            tx_buffers: (0..RING_SIZE).map(|_| page()).collect(),
+            rx_buffers: (0..RING_SIZE).map(|_| page()).collect(),
+            rx_next: 0,
+            rx_verified: false,
+            rx_vlan: None,
+        }
+    }
+
+    fn tx_desc(&self, index: usize) -> *mut TxDesc {
+        unsafe { (page_addr(&self.tx) as *mut TxDesc).add(index) }
+    }
+
+    fn rx_desc(&self, index: usize) -> *mut RxDesc {
+        unsafe { (page_addr(&self.rx) as *mut RxDesc).add(index) }
+    }
+}
+
+pub struct E1000 {
+    regs: usize,
+    rings: UPSafeCell<Rings>,
+    link_changed: AtomicBool,
+}
+
+fn probe(resources: &Resources) -> Option<Bound> {
+    if resources.base == 0 {
+        return None;
+    }
+    let device = Arc::new(E1000::new(resources.base));
+    let irq_device = device.clone();
+    Some(Bound {
+        device: Device::Net(device),
+        handler: Some(Arc::new(move || irq_device.ack_interrupt())),
+    })
+}
+
+impl E1000 {
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { ((self.regs + offset) as *const u32).read_volatile() }
+    }
+
+    fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { ((self.regs + offset) as *mut u32).write_volatile(value) }
+    }
+
+    fn new(regs: usize) -> Self {
+        let nic = Self { regs, rings: unsafe { UPSafeCell::new(Rings::new()) }, link_changed: AtomicBool::new(false) };
+        // [E1000 14.3] reset with interrupts masked
+        nic.write_reg(E1000_IMC, u32::MAX);
+        nic.write_reg(E1000_CTL, nic.read_reg(E1000_CTL) | E1000_CTL_RST);
+        while nic.read_reg(E1000_CTL) & E1000_CTL_RST != 0 {
+            core::hint::spin_loop();
+        }
+        nic.write_reg(E1000_IMC, u32::MAX);
+        nic.write_reg(E1000_VET, ETH_TYPE_VLAN as u32);
+        nic.write_reg(E1000_CTL, nic.read_reg(E1000_CTL) | E1000_CTL_SLU | E1000_CTL_VME);
+        nic.init_tx();
+        nic.init_rx();
+        nic.write_reg(E1000_IMS, E1000_ICR_LSC);
+        nic
+    }
+
+    /// [E1000 14.5] transmit initialization, every descriptor starts out
+    /// done so that it can be taken
+    fn init_tx(&self) {
+        let rings = self.rings.exclusive_access();
+        for i in 0..RING_SIZE {
+            let desc = TxDesc { addr: 0, length: 0, cso: 0, cmd: 0, status: E1000_TXD_STAT_DD, css: 0, special: 0 };
+            unsafe { rings.tx_desc(i).write_volatile(desc) };
+        }
+        let ring = page_addr(&rings.tx);
+        self.write_reg(E1000_TDBAL, ring as u32);
+        self.write_reg(E1000_TDBAH, (ring >> 32) as u32);
+        self.write_reg(E1000_TDLEN, (RING_SIZE * core::mem::size_of::<TxDesc>()) as u32);
+        self.write_reg(E1000_TDH, 0);
+        self.write_reg(E1000_TDT, 0);
+        self.write_reg(E1000_TCTL, E1000_TCTL_EN | E1000_TCTL_PSP | E1000_TCTL_CT | E1000_TCTL_COLD);
+        self.write_reg(E1000_TIPG, 10 | (8 << 10) | (6 << 20));
+    }
+
+    /// [E1000 14.4] receive initialization. The receive address the EEPROM
+    /// loaded stays, the multicast table starts out empty until the stack
+    /// sets it.
+    fn init_rx(&self) {
+        let rings = self.rings.exclusive_access();
+        for (i, buffer) in rings.rx_buffers.iter().enumerate() {
+            let desc = RxDesc { addr: page_addr(buffer) as u64, length: 0, csum: 0, status: 0, errors: 0, special: 0 };
+            unsafe { rings.rx_desc(i).write_volatile(desc) };
+        }
+        let ring = page_addr(&rings.rx);
+        self.write_reg(E1000_RDBAL, ring as u32);
+        self.write_reg(E1000_RDBAH, (ring >> 32) as u32);
+        self.write_reg(E1000_RDLEN, (RING_SIZE * core::mem::size_of::<RxDesc>()) as u32);
+        self.write_reg(E1000_RDH, 0);
+        self.write_reg(E1000_RDT, (RING_SIZE - 1) as u32);
+        self.write_reg(E1000_RAH, self.read_reg(E1000_RAH) | E1000_RAH_AV);
+        for i in 0..MTA_WORDS {
+            self.write_reg(E1000_MTA + i * 4, 0);
+        }
+        self.write_reg(E1000_RXCSUM, E1000_RXCSUM_IPOFL | E1000_RXCSUM_TUOFL);
+        self.write_reg(E1000_RDTR, 0);
+        self.write_reg(E1000_RADV, 0);
+        self.write_reg(E1000_RCTL, E1000_RCTL_EN | E1000_RCTL_BAM | E1000_RCTL_SZ_2048 | E1000_RCTL_SECRC);
+    }
+
+    /// Reading ICR takes the causes, which drops the line
+    fn ack_interrupt(&self) {
+        if self.read_reg(E1000_ICR) & E1000_ICR_LSC != 0 {
+            self.link_changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Frames that don't fit a buffer are dropped, a full ring waits for
+    /// the NIC. With `tci` the NIC tags the frame.
+    fn transmit(&self, frame: &[u8], tci: Option<u16>) {
+        if frame.len() > BUFFER_SIZE {
+            return;
+        }
+        let rings = self.rings.exclusive_access();
+        let tail = self.read_reg(E1000_TDT) as usize;
+        let headers = Headers::parse(frame);
+        let slots = if headers.is_some() { 2 } else { 1 };
+        for i in 0..slots {
+            let desc = rings.tx_desc((tail + i) % RING_SIZE);
+            while unsafe { desc.read_volatile() }.status & E1000_TXD_STAT_DD == 0 {
+                core::hint::spin_loop();
+            }
+        }
+
+        let mut index = tail;
+        let mut popts = 0;
+        let mut cmd = E1000_TXD_CMD_EOP | E1000_TXD_CMD_IFCS | E1000_TXD_CMD_RS;
+        let mut cso = 0;
+        let buffer = page_addr(&rings.tx_buffers[(tail + slots - 1) % RING_SIZE]);
+        let data = unsafe { core::slice::from_raw_parts_mut(buffer as *mut u8, frame.len()) };
+        data.copy_from_slice(frame);
+        if let Some(headers) = headers {
+            headers.seed(data);
+            unsafe { (rings.tx_desc(index) as *mut ContextDesc).write_volatile(headers.context()) };
+            index = (index + 1) % RING_SIZE;
+            // an extended data descriptor [E1000 3.3.7], type and the top of
+            // the length take the place of cso, popts that of css
+            cmd |= E1000_TXD_CMD_DEXT;
+            cso = ((E1000_TXD_DTYP_D << 4) | ((frame.len() as u32 >> 16) & 0xf)) as u8;
+            popts = E1000_TXD_POPTS_IXSM | E1000_TXD_POPTS_TXSM;
+        }
+        // the NIC puts the tag in after the MAC addresses, the offsets of
+        // the context descriptor are those of the frame as it is here
+        if tci.is_some() {
+            cmd |= E1000_TXD_CMD_VLE;
+        }
+        let special = tci.unwrap_or(0);
+        let desc = TxDesc { addr: buffer as u64, length: frame.len() as u16, cso, cmd, status: 0, css: popts, special };
+        unsafe { rings.tx_desc(index).write_volatile(desc) };
+        fence(Ordering::SeqCst);
+        self.write_reg(E1000_TDT, ((index + 1) % RING_SIZE) as u32);
+    }
+}
+
+impl NetDevice for E1000 {
+    fn can_recv(&self) -> bool {
+        let rings = self.rings.exclusive_access();
+        unsafe { rings.rx_desc(rings.rx_next).read_volatile() }.status & E1000_RXD_STAT_DD != 0
+    }
+
+    /// Frames that span buffers or have errors are skipped
+    fn recv(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut rings = self.rings.exclusive_access();
+        loop {
+            let index = rings.rx_next;
+            let desc = unsafe { rings.rx_desc(index).read_volatile() };
+            if desc.status & E1000_RXD_STAT_DD == 0 {
+                return None;
+            }
+            fence(Ordering::SeqCst);
+            let len = desc.length as usize;
+            let good = desc.status & E1000_RXD_STAT_EOP != 0 && desc.errors & !(E1000_RXD_ERR_IPE | E1000_RXD_ERR_TCPE) == 0;
+            let taken = good && len <= buf.len();
+            if taken {
+                let data = unsafe { core::slice::from_raw_parts(page_addr(&rings.rx_buffers[index]) as *const u8, len) };
+                buf[..len].copy_from_slice(data);
+                rings.rx_verified = desc.checksum_verified();
+                rings.rx_vlan = (desc.status & E1000_RXD_STAT_VP != 0).then_some(desc.special);
+            }
+            // hand the descriptor back
+            unsafe {
+                let desc = rings.rx_desc(index);
+                (*desc).status = 0;
+                (*desc).errors = 0;
+            }
+            rings.rx_next = (index + 1) % RING_SIZE;
+            fence(Ordering::SeqCst);
+            self.write_reg(E1000_RDT, index as u32);
+            if taken {
+                return Some(len);
+            }
+        }
+    }
+
+    fn send(&self, frame: &[u8]) {
+        self.transmit(frame, None);
+    }
+
+    fn send_vlan(&self, frame: &[u8], tci: u16) {
+        self.transmit(frame, Some(tci));
+    }
+
+    fn rx_vlan(&self) -> Option<u16> {
+        self.rings.exclusive_access().rx_vlan
+    }
+
+    fn link_up(&self) -> bool {
+        self.read_reg(E1000_STATUS) & E1000_STATUS_LU != 0
+    }
+
+    fn link_changed(&self) -> bool {
+        self.link_changed.swap(false, Ordering::Relaxed)
+    }
+
+    /// With RCTL.MO 0 the 12-bit hash is bits 47:36 of the address, it
+    /// picks one bit of the multicast table
+    fn set_multicast(&self, macs: &[[u8; 6]]) {
+        let mut table = [0u32; MTA_WORDS];
+        for mac in macs {
+            let hash = ((mac[4] >> 4) as usize | ((mac[5] as usize) << 4)) & 0xfff;
+            table[hash >> 5] |= 1 << (hash & 0x1f);
+        }
+        for (i, bits) in table.iter().enumerate() {
+            self.write_reg(E1000_MTA + i * 4, *bits);
+        }
+    }
+
+    fn offload(&self) -> Offload {
+        Offload::RX_CHECKSUM | Offload::TX_CHECKSUM | Offload::VLAN
+    }
+
+    fn rx_checksum_verified(&self) -> bool {
+        self.rings.exclusive_access().rx_verified
+    }
+}
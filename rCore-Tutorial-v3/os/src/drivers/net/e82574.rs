@@ -0,0 +1,557 @@
+//! Intel 82574L Gigabit Network Connection (QEMU's `e1000e`) on PCI
+//! Datasheet: https://www.intel.ca/content/dam/doc/datasheet/82574l-gbe-controller-datasheet.pdf
+//!
+//! Unlike the 82540EM it has extended descriptors, two receive and two
+//! transmit queues and MSI-X. Receive side scaling spreads the flows over
+//! the receive queues, every queue has a vector of its own and one more
+//! takes the other causes, the link status change among them. The vectors
+//! raise identities in the IMSIC, so they only come with
+//! `-machine virt,aia=aplic-imsic`; without it the NIC stays masked and
+//! the queues are only polled.
+//!
+//! The interrupt throttling interval of every vector is set at build time
+//! with `E82574_ITR_NS` (0, the default, interrupts for every write-back),
+//! to compare throughput with and without coalescing. What every vector and queue did
+//! is logged whenever the link changes, `set_link net0 off` in the QEMU
+//! monitor prints it.
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{fence, AtomicBool, Ordering};
+
+use crate::config::PAGE_SIZE;
+use crate::drivers::registry::{Bound, Device, Driver, Match, Resources};
+use crate::imsic;
+use crate::mm::{frame_alloc, FrameTracker, PhysAddr};
+use crate::pci::PciFunction;
+use crate::sync::UPSafeCell;
+
+use super::NetDevice;
+
+const RING_SIZE: usize = 64;
+const BUFFER_SIZE: usize = 2048;
+const BUFFERS_PER_PAGE: usize = PAGE_SIZE / BUFFER_SIZE;
+
+const RX_QUEUES: usize = 2;
+const TX_QUEUES: usize = 2;
+/// rx queue 0, rx queue 1, tx queue 0, tx queue 1, other
+const MSIX_VECTORS: usize = RX_QUEUES + TX_QUEUES + 1;
+const VECTOR_OTHER: usize = RX_QUEUES + TX_QUEUES;
+
+/// PCI config space
+const PCI_COMMAND: usize = 0x04;
+const PCI_CAP_PTR: usize = 0x34;
+const PCI_COMMAND_INTX_DISABLE: u32 = 1 << 10;
+const PCI_CAP_ID_MSIX: u8 = 0x11;
+/// MSI-X capability, message control in the upper half of its first dword
+const PCI_MSIX_TABLE: usize = 4;
+const PCI_MSIX_ENABLE: u32 = 1 << 31;
+const PCI_MSIX_FUNCTION_MASK: u32 = 1 << 30;
+const PCI_MSIX_ENTRY_SIZE: usize = 16;
+
+/// Registers
+const CTRL: usize = 0x00000;
+const STATUS: usize = 0x00008;
+const CTRL_EXT: usize = 0x00018;
+const ICR: usize = 0x000c0;
+const IMS: usize = 0x000d0;
+const IMC: usize = 0x000d8;
+const EIAC: usize = 0x000dc;
+const IVAR: usize = 0x000e4;
+const RCTL: usize = 0x00100;
+const TCTL: usize = 0x00400;
+const TIPG: usize = 0x00410;
+const RXCSUM: usize = 0x05000;
+const RFCTL: usize = 0x05008;
+const MTA: usize = 0x05200;
+const RAL: usize = 0x05400;
+const RAH: usize = 0x05404;
+const MRQC: usize = 0x05818;
+const RETA: usize = 0x05c00;
+const RSSRK: usize = 0x05c80;
+/// 4096 bits of multicast hash
+const MTA_WORDS: usize = 128;
+
+/// Per vector and per queue registers
+const fn eitr(vector: usize) -> usize { 0x000e8 + vector * 4 }
+const fn rdbal(queue: usize) -> usize { 0x02800 + queue * 0x100 }
+const fn rdbah(queue: usize) -> usize { 0x02804 + queue * 0x100 }
+const fn rdlen(queue: usize) -> usize { 0x02808 + queue * 0x100 }
+const fn rdh(queue: usize) -> usize { 0x02810 + queue * 0x100 }
+const fn rdt(queue: usize) -> usize { 0x02818 + queue * 0x100 }
+const fn tdbal(queue: usize) -> usize { 0x03800 + queue * 0x100 }
+const fn tdbah(queue: usize) -> usize { 0x03804 + queue * 0x100 }
+const fn tdlen(queue: usize) -> usize { 0x03808 + queue * 0x100 }
+const fn tdh(queue: usize) -> usize { 0x03810 + queue * 0x100 }
+const fn tdt(queue: usize) -> usize { 0x03818 + queue * 0x100 }
+const fn txdctl(queue: usize) -> usize { 0x03828 + queue * 0x100 }
+const fn tarc(queue: usize) -> usize { 0x03840 + queue * 0x100 }
+
+const CTRL_SLU: u32 = 1 << 6;
+const CTRL_RST: u32 = 1 << 26;
+const STATUS_LU: u32 = 1 << 1;
+/// MSI-X needs the pending bit array, the causes auto clear in EIAC
+const CTRL_EXT_PBA_SUPPORT: u32 = 1 << 31;
+
+/// Interrupt causes in MSI-X mode
+const ICR_LSC: u32 = 1 << 2;
+const ICR_RXQ0: u32 = 1 << 20;
+const ICR_OTHER: u32 = 1 << 24;
+/// IVAR takes a 3-bit vector and a valid bit for each cause
+const IVAR_VALID: u32 = 0x8;
+const IVAR_TX_ALL_WRITEBACKS: u32 = 1 << 31;
+
+const RCTL_EN: u32 = 1 << 1;
+const RCTL_BAM: u32 = 1 << 15;
+const RCTL_SECRC: u32 = 1 << 26;
+/// Extended receive descriptors
+const RFCTL_EXSTEN: u32 = 1 << 15;
+/// The rss hash replaces the packet checksum in the write-back
+const RXCSUM_IPOFL: u32 = 1 << 8;
+const RXCSUM_TUOFL: u32 = 1 << 9;
+const RXCSUM_PCSD: u32 = 1 << 13;
+const MRQC_RSS: u32 = 0x1;
+const MRQC_TCP_IPV4: u32 = 1 << 16;
+const MRQC_IPV4: u32 = 1 << 17;
+
+const TCTL_EN: u32 = 1 << 1;
+const TCTL_PSP: u32 = 1 << 3;
+const TCTL_CT: u32 = 0x0f << 4;
+const TCTL_COLD: u32 = 0x3f << 12;
+/// Fetch from both transmit queues
+const TCTL_MULR: u32 = 1 << 28;
+const TXDCTL_GRAN: u32 = 1 << 24;
+const TXDCTL_WTHRESH_1: u32 = 1 << 16;
+const TARC_ENABLE: u32 = 1 << 10;
+
+/// Extended descriptors
+const RXD_STAT_DD: u64 = 1 << 0;
+const RXD_STAT_EOP: u64 = 1 << 1;
+const RXD_ERRORS: u64 = 0xfff << 20;
+const TXD_DTYP_DATA: u64 = 0x1 << 20;
+const TXD_CMD_EOP: u64 = 0x01 << 24;
+const TXD_CMD_IFCS: u64 = 0x02 << 24;
+const TXD_CMD_RS: u64 = 0x08 << 24;
+const TXD_CMD_DEXT: u64 = 0x20 << 24;
+const TXD_STAT_DD: u64 = 1 << 32;
+
+/// Interrupt throttling counts in 256 ns
+const ITR_UNIT_NS: usize = 256;
+
+/// A fixed key, the spread of the flows over the queues doesn't need to be
+/// unpredictable here
+const RSS_KEY: [u32; 10] = [
+    0xda565a6d, 0xc20e5b25, 0x3d256741, 0xb08fa343, 0xcb2bcad0,
+    0xb4307bae, 0xa32dcb77, 0x0cf23080, 0x3bb7426a, 0xfa01acbe,
+];
+
+pub static DRIVER: Driver = Driver {
+    name: "e82574",
+    matches: &[Match::PciId(0x8086, 0x10d3)],
+    probe,
+};
+
+/// Interrupt throttling interval of every vector
+fn itr_ns() -> usize {
+    option_env!("E82574_ITR_NS").and_then(|ns| ns.parse().ok()).unwrap_or(0)
+}
+
+/// Extended Rx Descriptor [82574 7.1.4.2], the driver writes the buffer
+/// address into the first word and the nic writes back the rss hash there,
+/// the second word gets status, errors, length and vlan tag.
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy)]
+struct RxDesc {
+    buffer: u64,
+    status: u64,
+}
+
+/// Extended Tx Data Descriptor [82574 7.2.7.2]: buffer address, then
+/// length, type and command below the status and the options.
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy)]
+struct TxDesc {
+    buffer: u64,
+    cmd: u64,
+}
+
+fn page_addr(frame: &FrameTracker) -> usize {
+    let pa: PhysAddr = frame.ppn.into();
+    pa.0
+}
+
+struct Ring<T> {
+    desc: FrameTracker,
+    /// buffer i belongs to descriptor i, two to a page
+    buffers: Vec<FrameTracker>,
+    next: usize,
+    _desc: core::marker::PhantomData<T>,
+}
+
+impl<T: Copy> Ring<T> {
+    fn new(empty: T) -> Self {
+        let page = || frame_alloc().expect("no memory for e82574 rings");
+        let ring = Self {
+            desc: page(),
+            buffers: (0..RING_SIZE / BUFFERS_PER_PAGE).map(|_| page()).collect(),
+            next: 0,
+            _desc: core::marker::PhantomData,
+        };
+        for i in 0..RING_SIZE {
+            unsafe { ring.desc(i).write_volatile(empty) };
+        }
+        ring
+    }
+
+    fn paddr(&self) -> usize {
+        page_addr(&self.desc)
+    }
+
+    fn desc(&self, index: usize) -> *mut T {
+        unsafe { (page_addr(&self.desc) as *mut T).add(index) }
+    }
+
+    fn buffer(&self, index: usize) -> usize {
+        page_addr(&self.buffers[index / BUFFERS_PER_PAGE]) + (index % BUFFERS_PER_PAGE) * BUFFER_SIZE
+    }
+}
+
+/// What happened on each vector and queue since the driver came up
+#[derive(Clone, Copy, Default, Debug)]
+struct Stats {
+    interrupts: [usize; MSIX_VECTORS],
+    rx_packets: [usize; RX_QUEUES],
+    rx_bytes: [usize; RX_QUEUES],
+    tx_packets: [usize; TX_QUEUES],
+    tx_bytes: [usize; TX_QUEUES],
+}
+
+struct Queues {
+    rx: Vec<Ring<RxDesc>>,
+    tx: Vec<Ring<TxDesc>>,
+    stats: Stats,
+}
+
+pub struct E82574 {
+    regs: usize,
+    /// whether the vectors were set up, otherwise nothing interrupts
+    msix: bool,
+    queues: UPSafeCell<Queues>,
+    link_changed: AtomicBool,
+}
+
+fn probe(resources: &Resources) -> Option<Bound> {
+    let function = resources.pci.as_ref()?;
+    if resources.base == 0 {
+        return None;
+    }
+    let first_id = enable_msix(function);
+    let nic = Arc::new(E82574::new(resources.base, first_id.is_some()));
+    println!(
+        "[kernel] e82574: mac {:02x?}, {} rx and {} tx queues, {}",
+        nic.mac(),
+        RX_QUEUES,
+        TX_QUEUES,
+        if first_id.is_some() { "msi-x" } else { "polled" }
+    );
+    if let Some(first_id) = first_id {
+        for vector in 0..MSIX_VECTORS {
+            let nic = nic.clone();
+            imsic::register_msi(first_id + vector, Arc::new(move || nic.handle_interrupt(vector)));
+        }
+    }
+    Some(Bound { device: Device::Net(nic), handler: None })
+}
+
+fn config8(function: &PciFunction, offset: usize) -> u8 {
+    (function.read_config32(offset & !3) >> ((offset & 3) * 8)) as u8
+}
+
+fn write_table(addr: usize, value: u32) {
+    unsafe { (addr as *mut u32).write_volatile(value) }
+}
+
+/// Point the MSI-X table of the function at the IMSIC, vector `i` raising
+/// the identity returned plus `i`. None without AIA or MSI-X.
+fn enable_msix(function: &PciFunction) -> Option<usize> {
+    let address = imsic::msi_address()?;
+    let mut cap = config8(function, PCI_CAP_PTR) as usize;
+    while cap != 0 && config8(function, cap) != PCI_CAP_ID_MSIX {
+        cap = config8(function, cap + 1) as usize;
+    }
+    if cap == 0 {
+        return None;
+    }
+    let control = function.read_config32(cap);
+    if ((control >> 16) & 0x7ff) as usize + 1 < MSIX_VECTORS {
+        return None;
+    }
+    // the table sits in one of the memory BARs, at an offset
+    let table = function.read_config32(cap + PCI_MSIX_TABLE);
+    let bar = function.bars.get((table & 0x7) as usize).copied().flatten()?;
+    let table = bar.addr + (table & !0x7) as usize;
+    let first_id = imsic::alloc_ids(MSIX_VECTORS)?;
+
+    let command = function.read_config32(PCI_COMMAND) & 0xffff;
+    function.write_config32(PCI_COMMAND, command | PCI_COMMAND_INTX_DISABLE);
+    function.write_config32(cap, control | PCI_MSIX_ENABLE | PCI_MSIX_FUNCTION_MASK);
+    for i in 0..MSIX_VECTORS {
+        let entry = table + i * PCI_MSIX_ENTRY_SIZE;
+        write_table(entry, address as u32);
+        write_table(entry + 4, (address >> 32) as u32);
+        write_table(entry + 8, (first_id + i) as u32);
+        // unmasked
+        write_table(entry + 12, 0);
+    }
+    function.write_config32(cap, (control | PCI_MSIX_ENABLE) & !PCI_MSIX_FUNCTION_MASK);
+    Some(first_id)
+}
+
+impl E82574 {
+    fn read(&self, reg: usize) -> u32 {
+        unsafe { ((self.regs + reg) as *const u32).read_volatile() }
+    }
+
+    fn write(&self, reg: usize, value: u32) {
+        unsafe { ((self.regs + reg) as *mut u32).write_volatile(value) }
+    }
+
+    /// `msix` tells whether the vectors were set up, otherwise the nic
+    /// stays masked and the queues are polled
+    fn new(regs: usize, msix: bool) -> Self {
+        let nic = Self {
+            regs,
+            msix,
+            queues: unsafe {
+                UPSafeCell::new(Queues { rx: Vec::new(), tx: Vec::new(), stats: Stats::default() })
+            },
+            link_changed: AtomicBool::new(false),
+        };
+
+        // [82574 4.6] reset with interrupts masked
+        nic.write(IMC, u32::MAX);
+        nic.write(CTRL, nic.read(CTRL) | CTRL_RST);
+        while nic.read(CTRL) & CTRL_RST != 0 {
+            core::hint::spin_loop();
+        }
+        nic.write(IMC, u32::MAX);
+        nic.write(CTRL, nic.read(CTRL) | CTRL_SLU);
+
+        // the address loaded from the eeprom
+        nic.write(RAH, nic.read(RAH) | (1 << 31));
+        for i in 0..MTA_WORDS {
+            nic.write(MTA + i * 4, 0);
+        }
+
+        nic.init_rx();
+        nic.init_tx();
+        if msix {
+            nic.init_msix();
+        }
+        nic
+    }
+
+    /// [82574 4.6.5] receive initialization
+    fn init_rx(&self) {
+        let mut queues = self.queues.exclusive_access();
+        for queue in 0..RX_QUEUES {
+            let ring = Ring::new(RxDesc { buffer: 0, status: 0 });
+            for i in 0..RING_SIZE {
+                unsafe { ring.desc(i).write_volatile(RxDesc { buffer: ring.buffer(i) as u64, status: 0 }) };
+            }
+            self.write(rdbal(queue), ring.paddr() as u32);
+            self.write(rdbah(queue), (ring.paddr() >> 32) as u32);
+            self.write(rdlen(queue), (RING_SIZE * core::mem::size_of::<RxDesc>()) as u32);
+            self.write(rdh(queue), 0);
+            self.write(rdt(queue), (RING_SIZE - 1) as u32);
+            queues.rx.push(ring);
+        }
+
+        // hash tcp/ipv4 and ipv4 flows, the redirection table alternates
+        // between the queues, its queue bit is bit 7 of each entry
+        for (i, word) in RSS_KEY.iter().enumerate() {
+            self.write(RSSRK + i * 4, *word);
+        }
+        for i in 0..32 {
+            let entries = (0..4).fold(0u32, |reta, j| reta | ((((i * 4 + j) % RX_QUEUES) as u32) << (8 * j + 7)));
+            self.write(RETA + i * 4, entries);
+        }
+        self.write(MRQC, MRQC_RSS | MRQC_TCP_IPV4 | MRQC_IPV4);
+
+        self.write(RFCTL, self.read(RFCTL) | RFCTL_EXSTEN);
+        self.write(RXCSUM, RXCSUM_PCSD | RXCSUM_IPOFL | RXCSUM_TUOFL);
+        // 2048-byte buffers
+        self.write(RCTL, RCTL_EN | RCTL_BAM | RCTL_SECRC);
+    }
+
+    /// [82574 4.6.6] transmit initialization
+    fn init_tx(&self) {
+        let mut queues = self.queues.exclusive_access();
+        for queue in 0..TX_QUEUES {
+            // every descriptor starts out done, so that it can be taken
+            let ring = Ring::new(TxDesc { buffer: 0, cmd: TXD_STAT_DD });
+            self.write(tdbal(queue), ring.paddr() as u32);
+            self.write(tdbah(queue), (ring.paddr() >> 32) as u32);
+            self.write(tdlen(queue), (RING_SIZE * core::mem::size_of::<TxDesc>()) as u32);
+            self.write(tdh(queue), 0);
+            self.write(tdt(queue), 0);
+            self.write(txdctl(queue), TXDCTL_GRAN | TXDCTL_WTHRESH_1);
+            self.write(tarc(queue), self.read(tarc(queue)) | TARC_ENABLE);
+            queues.tx.push(ring);
+        }
+        self.write(TCTL, TCTL_EN | TCTL_PSP | TCTL_CT | TCTL_COLD | TCTL_MULR);
+        self.write(TIPG, 10 | (8 << 10) | (6 << 20));
+    }
+
+    /// [82574 7.4.2] one vector per queue and one for the rest
+    fn init_msix(&self) {
+        self.write(CTRL_EXT, self.read(CTRL_EXT) | CTRL_EXT_PBA_SUPPORT);
+        let mut ivar = IVAR_TX_ALL_WRITEBACKS;
+        for cause in 0..MSIX_VECTORS {
+            ivar |= (IVAR_VALID | cause as u32) << (cause * 4);
+        }
+        self.write(IVAR, ivar);
+        let interval = (itr_ns() / ITR_UNIT_NS).min(0xffff) as u32;
+        for vector in 0..MSIX_VECTORS {
+            self.write(eitr(vector), interval);
+        }
+        let queues = (0..RX_QUEUES + TX_QUEUES).fold(0, |causes, i| causes | (ICR_RXQ0 << i));
+        self.write(EIAC, queues | ICR_OTHER);
+        self.write(IMS, queues | ICR_OTHER | ICR_LSC);
+    }
+
+    fn mac(&self) -> [u8; 6] {
+        let low = self.read(RAL).to_le_bytes();
+        let high = self.read(RAH).to_le_bytes();
+        [low[0], low[1], low[2], low[3], high[0], high[1]]
+    }
+
+    /// Take a received frame from `queue` into `buf`, broken frames and
+    /// those that don't fit are skipped
+    fn receive(&self, queues: &mut Queues, queue: usize, buf: &mut [u8]) -> Option<usize> {
+        loop {
+            let ring = &mut queues.rx[queue];
+            let index = ring.next;
+            let status = unsafe { ring.desc(index).read_volatile() }.status;
+            if status & RXD_STAT_DD == 0 {
+                return None;
+            }
+            fence(Ordering::SeqCst);
+            let len = ((status >> 32) & 0xffff) as usize;
+            // frames larger than a buffer aren't enabled
+            let taken = status & RXD_STAT_EOP != 0 && status & RXD_ERRORS == 0 && len <= buf.len();
+            if taken {
+                let data = unsafe { core::slice::from_raw_parts(ring.buffer(index) as *const u8, len) };
+                buf[..len].copy_from_slice(data);
+            }
+
+            // hand the descriptor back, the write-back overwrote the address
+            unsafe { ring.desc(index).write_volatile(RxDesc { buffer: ring.buffer(index) as u64, status: 0 }) };
+            ring.next = (index + 1) % RING_SIZE;
+            fence(Ordering::SeqCst);
+            self.write(rdt(queue), index as u32);
+            if taken {
+                queues.stats.rx_packets[queue] += 1;
+                queues.stats.rx_bytes[queue] += len;
+                return Some(len);
+            }
+        }
+    }
+
+    /// Transmit queue of a frame, the same flow always takes the same one
+    fn queue_for(frame: &[u8]) -> usize {
+        // ports of ipv4 tcp/udp, or nothing
+        let ports = if frame.len() >= 38 && frame[12..14] == [0x08, 0x00] {
+            let l4 = 14 + ((frame[14] & 0xf) as usize) * 4;
+            frame.get(l4..l4 + 4).map_or(0, |ports| ports.iter().fold(0usize, |h, b| h ^ *b as usize))
+        } else {
+            0
+        };
+        ports % TX_QUEUES
+    }
+
+    /// Handle the interrupt of `vector`. The queue causes were cleared
+    /// through EIAC, the frames wait in the rings for `recv`.
+    fn handle_interrupt(&self, vector: usize) {
+        let stats = {
+            let mut queues = self.queues.exclusive_access();
+            queues.stats.interrupts[vector] += 1;
+            queues.stats
+        };
+        if vector == VECTOR_OTHER {
+            // reading ICR clears the other causes
+            if self.read(ICR) & ICR_LSC != 0 {
+                self.link_changed.store(true, Ordering::Relaxed);
+                println!("[kernel] e82574: {:?}", stats);
+            }
+            self.write(IMS, ICR_OTHER | ICR_LSC);
+        } else {
+            self.write(IMS, ICR_RXQ0 << vector);
+        }
+    }
+}
+
+impl NetDevice for E82574 {
+    fn can_recv(&self) -> bool {
+        let queues = self.queues.exclusive_access();
+        queues.rx.iter().any(|ring| unsafe { ring.desc(ring.next).read_volatile() }.status & RXD_STAT_DD != 0)
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut queues = self.queues.exclusive_access();
+        (0..RX_QUEUES).find_map(|queue| self.receive(&mut queues, queue, buf))
+    }
+
+    /// Frames that don't fit a buffer are dropped, a full ring waits for
+    /// the NIC
+    fn send(&self, frame: &[u8]) {
+        if frame.len() > BUFFER_SIZE {
+            return;
+        }
+        let queue = Self::queue_for(frame);
+        let mut queues = self.queues.exclusive_access();
+        let ring = &mut queues.tx[queue];
+        let index = ring.next;
+        while unsafe { ring.desc(index).read_volatile() }.cmd & TXD_STAT_DD == 0 {
+            core::hint::spin_loop();
+        }
+        let buffer = ring.buffer(index);
+        unsafe { core::slice::from_raw_parts_mut(buffer as *mut u8, frame.len()) }.copy_from_slice(frame);
+        let desc = TxDesc {
+            buffer: buffer as u64,
+            cmd: frame.len() as u64 | TXD_DTYP_DATA | TXD_CMD_DEXT | TXD_CMD_EOP | TXD_CMD_IFCS | TXD_CMD_RS,
+        };
+        unsafe { ring.desc(index).write_volatile(desc) };
+        let next = (index + 1) % RING_SIZE;
+        ring.next = next;
+        fence(Ordering::SeqCst);
+        self.write(tdt(queue), next as u32);
+        queues.stats.tx_packets[queue] += 1;
+        queues.stats.tx_bytes[queue] += frame.len();
+    }
+
+    fn link_up(&self) -> bool {
+        self.read(STATUS) & STATUS_LU != 0
+    }
+
+    /// The multicast table hashes bits 47:36 of the address, as on the
+    /// 82540EM
+    fn set_multicast(&self, macs: &[[u8; 6]]) {
+        let mut table = [0u32; MTA_WORDS];
+        for mac in macs {
+            let hash = ((mac[4] >> 4) as usize | ((mac[5] as usize) << 4)) & 0xfff;
+            table[hash >> 5] |= 1 << (hash & 0x1f);
+        }
+        for (i, bits) in table.iter().enumerate() {
+            self.write(MTA + i * 4, *bits);
+        }
+    }
+
+    /// Without MSI-X the causes are only latched, reading ICR takes them
+    fn link_changed(&self) -> bool {
+        if self.link_changed.swap(false, Ordering::Relaxed) {
+            return true;
+        }
+        !self.msix && self.read(ICR) & ICR_LSC != 0
+    }
+}
@@ -0,0 +1,45 @@
+pub mod e1000;
+pub mod e82574;
+pub mod virtio_net;
+
+use crate::net::{vlan, Offload};
+
+/// A NIC as the network stack sees it
+pub trait NetDevice: Send + Sync {
+    fn can_recv(&self) -> bool;
+    /// Take one received frame into `buf`, return its length
+    fn recv(&self, buf: &mut [u8]) -> Option<usize>;
+    fn send(&self, frame: &[u8]);
+    /// Send `frame` on the VLAN of the tag control information `tci`. NICs
+    /// without VLAN offload get the tag put in by the stack.
+    fn send_vlan(&self, frame: &[u8], tci: u16) {
+        self.send(&vlan::insert_tag(frame, tci));
+    }
+    /// The tag control information the NIC took off the frame `recv` took
+    /// last, None if it came untagged or the NIC doesn't strip tags
+    fn rx_vlan(&self) -> Option<u16> {
+        None
+    }
+    /// Whether the carrier is there, NICs that can't tell are always up
+    fn link_up(&self) -> bool {
+        true
+    }
+    /// Take a pending link change notification
+    fn link_changed(&self) -> bool {
+        false
+    }
+    /// Work the NIC does in hardware
+    fn offload(&self) -> Offload {
+        Offload::empty()
+    }
+    /// Whether the NIC found the checksums of the frame `recv` took last
+    /// right
+    fn rx_checksum_verified(&self) -> bool {
+        false
+    }
+    /// Let frames to the multicast addresses `macs` through the receive
+    /// filter. NICs that don't filter multicast take all of it: QEMU's
+    /// virtio-net only filters with the control queue, which virtio-drivers
+    /// doesn't negotiate.
+    fn set_multicast(&self, _macs: &[[u8; 6]]) {}
+}
@@ -0,0 +1,114 @@
+//! virtio-net behind a virtio-mmio transport
+//!
+//! virtio-net reports link changes as a configuration change and keeps the
+//! link bit in its config space, QEMU's monitor toggles it with
+//! `set_link net0 off`. virtio-drivers knows neither, so they are read
+//! from the transport registers here. The interrupt is acknowledged as it
+//! comes, a configuration change is remembered for `link_changed`.
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use virtio_drivers::{VirtIOHeader, VirtIONet};
+
+use crate::drivers::block::virtio_blk::VirtioHal;
+use crate::drivers::registry::{Bound, Device, Driver, Match, Resources, VIRTIO_DEVICE_NET};
+use crate::sync::UPSafeCell;
+
+use super::NetDevice;
+
+/// virtio-mmio registers
+const DEVICE_FEATURES: usize = 0x010;
+const DEVICE_FEATURES_SEL: usize = 0x014;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const CONFIG: usize = 0x100;
+
+const VIRTIO_INTERRUPT_CONFIG_CHANGE: u32 = 1 << 1;
+/// The device has the status field in its config space
+const VIRTIO_NET_F_STATUS: u32 = 1 << 16;
+const VIRTIO_NET_S_LINK_UP: u16 = 1;
+/// Offset of the status field in the config space, after the MAC
+const VIRTIO_NET_CONFIG_STATUS: usize = 6;
+
+pub static DRIVER: Driver = Driver {
+    name: "virtio-net",
+    matches: &[Match::Virtio(VIRTIO_DEVICE_NET)],
+    probe,
+};
+
+pub struct VirtIONetDevice {
+    base: usize,
+    net: UPSafeCell<VirtIONet<'static, VirtioHal>>,
+    config_changed: AtomicBool,
+}
+
+fn probe(resources: &Resources) -> Option<Bound> {
+    let base = resources.base;
+    let net = unsafe { VirtIONet::<VirtioHal>::new(&mut *(base as *mut VirtIOHeader)).ok()? };
+    let device = Arc::new(VirtIONetDevice {
+        base,
+        net: unsafe { UPSafeCell::new(net) },
+        config_changed: AtomicBool::new(false),
+    });
+    let irq_device = device.clone();
+    Some(Bound {
+        device: Device::Net(device),
+        handler: Some(Arc::new(move || irq_device.ack_interrupt())),
+    })
+}
+
+impl VirtIONetDevice {
+    fn read_reg(&self, offset: usize) -> u32 {
+        unsafe { ((self.base + offset) as *const u32).read_volatile() }
+    }
+
+    fn write_reg(&self, offset: usize, value: u32) {
+        unsafe { ((self.base + offset) as *mut u32).write_volatile(value) }
+    }
+
+    /// Frames are picked up when the stack polls, the interrupt only has
+    /// to be taken so that the line drops
+    fn ack_interrupt(&self) {
+        let status = self.read_reg(INTERRUPT_STATUS);
+        if status & VIRTIO_INTERRUPT_CONFIG_CHANGE != 0 {
+            self.config_changed.store(true, Ordering::Relaxed);
+        }
+        self.write_reg(INTERRUPT_ACK, status);
+    }
+}
+
+impl NetDevice for VirtIONetDevice {
+    fn can_recv(&self) -> bool {
+        self.net.exclusive_access().can_recv()
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> Option<usize> {
+        self.net.exclusive_access().recv(buf).ok()
+    }
+
+    fn send(&self, frame: &[u8]) {
+        self.net.exclusive_access().send(frame).expect("can't send net data");
+    }
+
+    /// A device without the status feature always has its link up
+    fn link_up(&self) -> bool {
+        self.write_reg(DEVICE_FEATURES_SEL, 0);
+        if self.read_reg(DEVICE_FEATURES) & VIRTIO_NET_F_STATUS == 0 {
+            return true;
+        }
+        let status = unsafe { ((self.base + CONFIG + VIRTIO_NET_CONFIG_STATUS) as *const u16).read_volatile() };
+        status & VIRTIO_NET_S_LINK_UP != 0
+    }
+
+    /// Take a configuration change the interrupt handler saw, or one still
+    /// pending in the device
+    fn link_changed(&self) -> bool {
+        if self.config_changed.swap(false, Ordering::Relaxed) {
+            return true;
+        }
+        if self.read_reg(INTERRUPT_STATUS) & VIRTIO_INTERRUPT_CONFIG_CHANGE == 0 {
+            return false;
+        }
+        self.write_reg(INTERRUPT_ACK, VIRTIO_INTERRUPT_CONFIG_CHANGE);
+        true
+    }
+}
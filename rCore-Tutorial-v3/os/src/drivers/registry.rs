@@ -0,0 +1,260 @@
+//! Binding drivers to devices
+//!
+//! Drivers register a table of what they can drive, PCI ids or classes,
+//! virtio device types or device tree `compatible` strings. The bus
+//! walkers hand every device they find to the first driver with a match,
+//! and what its probe returns lands in the device list, grouped by class.
+//! The rest of the kernel looks its devices up here by class and index.
+//! Devices that no bus has, like a ram disk, are added directly.
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+use easy_fs::BlockDevice;
+use lazy_static::lazy_static;
+
+use crate::dtb;
+use crate::pci::{self, PciFunction};
+use crate::plic::{self, IrqHandler};
+use crate::sync::UPSafeCell;
+
+use super::chardev::CharDevice;
+use super::net::NetDevice;
+use super::rng::RngDevice;
+
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+/// virtio-mmio registers
+const VIRTIO_MMIO_MAGIC: usize = 0x000;
+const VIRTIO_MMIO_DEVICE_ID: usize = 0x008;
+
+/// virtio device types
+pub const VIRTIO_DEVICE_NET: u32 = 1;
+pub const VIRTIO_DEVICE_BLOCK: u32 = 2;
+pub const VIRTIO_DEVICE_CONSOLE: u32 = 3;
+pub const VIRTIO_DEVICE_RNG: u32 = 4;
+
+/// What a driver can drive
+pub enum Match {
+    /// vendor and device id
+    PciId(u16, u16),
+    /// class and subclass
+    PciClass(u8, u8),
+    /// device type behind a virtio-mmio transport
+    Virtio(u32),
+    /// `compatible` string of a device tree node
+    Compatible(&'static str),
+}
+
+/// Where the device a driver is probed for lives
+pub struct Resources {
+    /// first register window, BAR 0 for PCI
+    pub base: usize,
+    pub size: usize,
+    pub irq: Option<usize>,
+    /// the PCI function, for drivers that need more than BAR 0
+    pub pci: Option<PciFunction>,
+}
+
+#[derive(Clone)]
+pub enum Device {
+    Block(Arc<dyn BlockDevice>),
+    Net(Arc<dyn NetDevice>),
+    Char(Arc<dyn CharDevice>),
+    Rng(Arc<dyn RngDevice>),
+}
+
+impl Device {
+    fn prefix(&self) -> &'static str {
+        match self {
+            Device::Block(_) => "blk",
+            Device::Net(_) => "eth",
+            Device::Char(_) => "tty",
+            Device::Rng(_) => "rng",
+        }
+    }
+}
+
+/// What a successful probe gives back
+pub struct Bound {
+    pub device: Device,
+    /// registered with the PLIC for the interrupt of the device
+    pub handler: Option<IrqHandler>,
+}
+
+pub struct Driver {
+    pub name: &'static str,
+    pub matches: &'static [Match],
+    pub probe: fn(&Resources) -> Option<Bound>,
+}
+
+pub struct DeviceEntry {
+    /// class prefix and index in the class, `eth0`, `blk1`
+    pub name: String,
+    pub driver: &'static str,
+    pub device: Device,
+    pub irq: Option<usize>,
+    pub handler: Option<IrqHandler>,
+}
+
+lazy_static! {
+    static ref DRIVERS: UPSafeCell<Vec<&'static Driver>> = unsafe { UPSafeCell::new(Vec::new()) };
+    static ref DEVICES: UPSafeCell<Vec<DeviceEntry>> = unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+pub fn register(driver: &'static Driver) {
+    DRIVERS.exclusive_access().push(driver);
+}
+
+/// Probe the first driver that has an entry `matches` accepts
+fn bind<F: Fn(&Match) -> bool>(matches: F, resources: Resources) -> bool {
+    let drivers: Vec<&'static Driver> = DRIVERS
+        .exclusive_access()
+        .iter()
+        .filter(|driver| driver.matches.iter().any(&matches))
+        .copied()
+        .collect();
+    for driver in drivers {
+        // probes may look up devices themselves, so the list isn't held
+        let bound = match (driver.probe)(&resources) {
+            Some(bound) => bound,
+            None => continue,
+        };
+        let name = push(driver.name, bound, resources.irq);
+        println!("[kernel] {}: {} at {:#x} irq {:?}", name, driver.name, resources.base, resources.irq);
+        return true;
+    }
+    false
+}
+
+/// Name the device after its class and put it in the list
+fn push(driver: &'static str, bound: Bound, irq: Option<usize>) -> String {
+    let mut devices = DEVICES.exclusive_access();
+    let prefix = bound.device.prefix();
+    let index = devices.iter().filter(|entry| entry.device.prefix() == prefix).count();
+    let name = format!("{}{}", prefix, index);
+    if let (Some(irq), Some(handler)) = (irq, bound.handler.as_ref()) {
+        plic::register_irq(irq, handler.clone());
+    }
+    devices.push(DeviceEntry {
+        name: name.clone(),
+        driver,
+        device: bound.device,
+        irq,
+        handler: bound.handler,
+    });
+    name
+}
+
+/// Add a device that is on no bus, it takes no interrupts
+#[allow(unused)]
+pub fn add(driver: &'static str, device: Device) {
+    let name = push(driver, Bound { device, handler: None }, None);
+    println!("[kernel] {}: {}", name, driver);
+}
+
+/// Bind the functions found by `pci::init`
+pub fn probe_pci() {
+    for function in pci::functions() {
+        let (base, size) = function.bars[0].map_or((0, 0), |bar| (bar.addr, bar.size));
+        let resources = Resources { base, size, irq: function.irq, pci: Some(function.clone()) };
+        bind(
+            |m| match *m {
+                Match::PciId(vendor, device) => vendor == function.vendor_id && device == function.device_id,
+                Match::PciClass(class, subclass) => class == function.class && subclass == function.subclass,
+                _ => false,
+            },
+            resources,
+        );
+    }
+}
+
+/// Bind the device behind a virtio-mmio transport, empty transports have
+/// device id 0
+pub fn probe_virtio(base: usize, size: usize, irq: Option<usize>) -> bool {
+    let read = |offset: usize| unsafe { ((base + offset) as *const u32).read_volatile() };
+    if read(VIRTIO_MMIO_MAGIC) != VIRTIO_MAGIC {
+        return false;
+    }
+    let id = read(VIRTIO_MMIO_DEVICE_ID);
+    if id == 0 {
+        return false;
+    }
+    bind(|m| matches!(*m, Match::Virtio(wanted) if wanted == id), Resources { base, size, irq, pci: None })
+}
+
+/// Walk the virtio-mmio transports of the device tree, in the order QEMU
+/// fills them
+pub fn probe_virtio_mmio() {
+    for slot in dtb::virtio_slots() {
+        probe_virtio(slot.region.base, slot.region.size, slot.irq);
+    }
+}
+
+/// Bind a device tree node by its `compatible` list
+pub fn probe_compatible(compatible: &[&str], resources: Resources) -> bool {
+    bind(|m| matches!(*m, Match::Compatible(wanted) if compatible.contains(&wanted)), resources)
+}
+
+/// Walk the nodes of the device tree that have registers, the virtio-mmio
+/// transports and the PCI host bridge have walkers of their own
+pub fn probe_device_tree() {
+    for node in dtb::devices() {
+        let compatible: Vec<&str> = node.compatible.iter().map(|name| name.as_str()).collect();
+        let resources = Resources { base: node.region.base, size: node.region.size, irq: node.irq, pci: None };
+        probe_compatible(&compatible, resources);
+    }
+}
+
+pub fn block_device(index: usize) -> Option<Arc<dyn BlockDevice>> {
+    DEVICES
+        .exclusive_access()
+        .iter()
+        .filter_map(|entry| match &entry.device {
+            Device::Block(device) => Some(device.clone()),
+            _ => None,
+        })
+        .nth(index)
+}
+
+pub fn net_device(index: usize) -> Option<Arc<dyn NetDevice>> {
+    DEVICES
+        .exclusive_access()
+        .iter()
+        .filter_map(|entry| match &entry.device {
+            Device::Net(device) => Some(device.clone()),
+            _ => None,
+        })
+        .nth(index)
+}
+
+pub fn char_device(index: usize) -> Option<Arc<dyn CharDevice>> {
+    DEVICES
+        .exclusive_access()
+        .iter()
+        .filter_map(|entry| match &entry.device {
+            Device::Char(device) => Some(device.clone()),
+            _ => None,
+        })
+        .nth(index)
+}
+
+/// The first character device `driver` bound, the classes mix consoles
+/// and serial ports
+pub fn char_device_of(driver: &str) -> Option<Arc<dyn CharDevice>> {
+    DEVICES
+        .exclusive_access()
+        .iter()
+        .filter(|entry| entry.driver == driver)
+        .find_map(|entry| match &entry.device {
+            Device::Char(device) => Some(device.clone()),
+            _ => None,
+        })
+}
+
+pub fn rng_device(index: usize) -> Option<Arc<dyn RngDevice>> {
+    DEVICES
+        .exclusive_access()
+        .iter()
+        .filter_map(|entry| match &entry.device {
+            Device::Rng(device) => Some(device.clone()),
+            _ => None,
+        })
+        .nth(index)
+}
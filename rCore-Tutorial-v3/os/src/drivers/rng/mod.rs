@@ -0,0 +1,8 @@
+pub mod virtio_rng;
+
+/// A hardware entropy source
+pub trait RngDevice: Send + Sync {
+    /// Fill `buf` with entropy, return how many bytes were filled, 0 if
+    /// the source has nothing for now
+    fn fill(&self, buf: &mut [u8]) -> usize;
+}
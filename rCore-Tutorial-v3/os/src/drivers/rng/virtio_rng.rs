@@ -0,0 +1,82 @@
+//! virtio-rng behind a virtio-mmio transport
+//!
+//! The device has one queue, every buffer put on it comes back filled with
+//! entropy from the host, QEMU's `-device virtio-rng-device` reads it from
+//! `/dev/urandom`. Requests are rare, the CSPRNG only reseeds from it, so
+//! they are polled for and the interrupt is only acknowledged. A host that
+//! doesn't answer within `FILL_POLLS` gets the request left on the queue,
+//! the next fill waits for it instead of posting another.
+use alloc::sync::Arc;
+
+use crate::config::PAGE_SIZE;
+use crate::drivers::registry::{Bound, Device, Driver, Match, Resources, VIRTIO_DEVICE_RNG};
+use crate::drivers::virtio::{Transport, VirtQueue};
+use crate::mm::{frame_alloc, FrameTracker, PhysAddr};
+use crate::sync::UPSafeCell;
+
+use super::RngDevice;
+
+const QUEUE_SIZE: usize = 8;
+/// Looks at the used ring before a fill gives up
+const FILL_POLLS: usize = 1 << 20;
+
+pub static DRIVER: Driver = Driver {
+    name: "virtio-rng",
+    matches: &[Match::Virtio(VIRTIO_DEVICE_RNG)],
+    probe,
+};
+
+pub struct VirtIORng {
+    transport: Transport,
+    inner: UPSafeCell<VirtIORngInner>,
+}
+
+struct VirtIORngInner {
+    queue: VirtQueue,
+    /// the device writes here
+    buffer: FrameTracker,
+    /// a request is on the queue that a fill gave up on
+    pending: bool,
+}
+
+fn probe(resources: &Resources) -> Option<Bound> {
+    let transport = Transport::new(resources.base)?;
+    transport.negotiate(0);
+    let queue = transport.queue(0, QUEUE_SIZE)?;
+    transport.driver_ok();
+    let buffer = frame_alloc()?;
+    let device = Arc::new(VirtIORng {
+        transport,
+        inner: unsafe { UPSafeCell::new(VirtIORngInner { queue, buffer, pending: false }) },
+    });
+    let irq_device = device.clone();
+    Some(Bound {
+        device: Device::Rng(device),
+        handler: Some(Arc::new(move || {
+            irq_device.transport.ack_interrupt();
+        })),
+    })
+}
+
+impl RngDevice for VirtIORng {
+    fn fill(&self, buf: &mut [u8]) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        let pa: PhysAddr = inner.buffer.ppn.into();
+        let len = buf.len().min(PAGE_SIZE);
+        if !inner.pending {
+            inner.queue.add(&[(pa.0, len, true)]).expect("virtio-rng queue is full");
+            self.transport.notify(&inner.queue);
+            inner.pending = true;
+        }
+        for _ in 0..FILL_POLLS {
+            if let Some((_, written)) = inner.queue.pop_used() {
+                inner.pending = false;
+                let written = written.min(len);
+                buf[..written].copy_from_slice(&inner.buffer.ppn.get_bytes_array()[..written]);
+                return written;
+            }
+            core::hint::spin_loop();
+        }
+        0
+    }
+}
@@ -0,0 +1,210 @@
+//! Legacy virtio-mmio transport and split virtqueues
+//!
+//! For the devices virtio-drivers doesn't have. QEMU's virtio-mmio
+//! transports are legacy ones by default, where the guest picks the ring
+//! alignment; with an alignment of 4 the descriptors, the available ring
+//! and the used ring of a small queue all fit in one page.
+use alloc::vec::Vec;
+use core::sync::atomic::{fence, Ordering};
+
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_alloc, FrameTracker, PhysAddr};
+
+/// virtio-mmio registers of a legacy transport
+const VERSION: usize = 0x004;
+const DEVICE_FEATURES: usize = 0x010;
+const DRIVER_FEATURES: usize = 0x020;
+const GUEST_PAGE_SIZE: usize = 0x028;
+const QUEUE_SEL: usize = 0x030;
+const QUEUE_NUM_MAX: usize = 0x034;
+const QUEUE_NUM: usize = 0x038;
+const QUEUE_ALIGN: usize = 0x03c;
+const QUEUE_PFN: usize = 0x040;
+const QUEUE_NOTIFY: usize = 0x050;
+const INTERRUPT_STATUS: usize = 0x060;
+const INTERRUPT_ACK: usize = 0x064;
+const STATUS: usize = 0x070;
+pub const CONFIG: usize = 0x100;
+
+const LEGACY_VERSION: u32 = 1;
+
+const STATUS_ACKNOWLEDGE: u32 = 1;
+const STATUS_DRIVER: u32 = 2;
+const STATUS_DRIVER_OK: u32 = 4;
+
+const RING_ALIGN: usize = 4;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+/// A legacy virtio-mmio transport
+pub struct Transport {
+    base: usize,
+}
+
+impl Transport {
+    /// Reset the device at `base` and acknowledge it, None if it isn't a
+    /// legacy transport
+    pub fn new(base: usize) -> Option<Self> {
+        let transport = Self { base };
+        if transport.read(VERSION) != LEGACY_VERSION {
+            println!("[kernel] virtio-mmio at {:#x} isn't a legacy transport", base);
+            return None;
+        }
+        transport.write(STATUS, 0);
+        transport.write(STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+        transport.write(GUEST_PAGE_SIZE, PAGE_SIZE as u32);
+        Some(transport)
+    }
+
+    pub fn read(&self, offset: usize) -> u32 {
+        unsafe { ((self.base + offset) as *const u32).read_volatile() }
+    }
+
+    pub fn write(&self, offset: usize, value: u32) {
+        unsafe { ((self.base + offset) as *mut u32).write_volatile(value) }
+    }
+
+    /// Take the device features out of `wanted` that the device has
+    pub fn negotiate(&self, wanted: u32) -> u32 {
+        let features = self.read(DEVICE_FEATURES) & wanted;
+        self.write(DRIVER_FEATURES, features);
+        features
+    }
+
+    /// Give the device queue `index`, None if it has no such queue
+    pub fn queue(&self, index: u32, size: usize) -> Option<VirtQueue> {
+        self.write(QUEUE_SEL, index);
+        let max = self.read(QUEUE_NUM_MAX) as usize;
+        if max == 0 {
+            return None;
+        }
+        let queue = VirtQueue::new(index, size.min(max));
+        self.write(QUEUE_NUM, queue.size as u32);
+        self.write(QUEUE_ALIGN, RING_ALIGN as u32);
+        self.write(QUEUE_PFN, queue.frame.ppn.0 as u32);
+        Some(queue)
+    }
+
+    /// Done setting up, the device may use its queues now
+    pub fn driver_ok(&self) {
+        self.write(STATUS, self.read(STATUS) | STATUS_DRIVER_OK);
+    }
+
+    pub fn notify(&self, queue: &VirtQueue) {
+        fence(Ordering::SeqCst);
+        self.write(QUEUE_NOTIFY, queue.index);
+    }
+
+    /// Acknowledge what the device interrupted for, which drops the line
+    pub fn ack_interrupt(&self) -> u32 {
+        let status = self.read(INTERRUPT_STATUS);
+        self.write(INTERRUPT_ACK, status);
+        status
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+/// A split virtqueue in one page
+pub struct VirtQueue {
+    index: u32,
+    size: usize,
+    frame: FrameTracker,
+    /// descriptors not in a chain the device owns
+    free: Vec<u16>,
+    avail_idx: u16,
+    last_used: u16,
+}
+
+impl VirtQueue {
+    fn new(index: u32, size: usize) -> Self {
+        let queue = Self {
+            index,
+            size,
+            frame: frame_alloc().expect("no memory for a virtqueue"),
+            free: (0..size as u16).rev().collect(),
+            avail_idx: 0,
+            last_used: 0,
+        };
+        assert!(queue.used_offset() + 4 + 8 * size <= PAGE_SIZE, "virtqueue of {} entries needs more than a page", size);
+        queue
+    }
+
+    fn base(&self) -> usize {
+        let pa: PhysAddr = self.frame.ppn.into();
+        pa.0
+    }
+
+    fn avail_offset(&self) -> usize {
+        16 * self.size
+    }
+
+    fn used_offset(&self) -> usize {
+        let end = self.avail_offset() + 4 + 2 * self.size + 2;
+        (end + RING_ALIGN - 1) / RING_ALIGN * RING_ALIGN
+    }
+
+    fn at<T>(&self, offset: usize) -> *mut T {
+        (self.base() + offset) as *mut T
+    }
+
+    /// Hand the device a chain of physical buffers, the ones marked true
+    /// are for the device to write. None if there aren't enough free
+    /// descriptors.
+    pub fn add(&mut self, buffers: &[(usize, usize, bool)]) -> Option<u16> {
+        if buffers.is_empty() || buffers.len() > self.free.len() {
+            return None;
+        }
+        let ids: Vec<u16> = (0..buffers.len()).map(|_| self.free.pop().unwrap()).collect();
+        for (i, &(addr, len, writable)) in buffers.iter().enumerate() {
+            let mut flags = if writable { DESC_F_WRITE } else { 0 };
+            let next = ids.get(i + 1).copied().unwrap_or(0);
+            if i + 1 < ids.len() {
+                flags |= DESC_F_NEXT;
+            }
+            let descriptor = Descriptor { addr: addr as u64, len: len as u32, flags, next };
+            unsafe { self.at::<Descriptor>(16 * ids[i] as usize).write_volatile(descriptor) };
+        }
+        // a ring entry, then the index the device reads up to
+        let slot = self.avail_offset() + 4 + 2 * (self.avail_idx as usize % self.size);
+        unsafe { self.at::<u16>(slot).write_volatile(ids[0]) };
+        fence(Ordering::SeqCst);
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        unsafe { self.at::<u16>(self.avail_offset() + 2).write_volatile(self.avail_idx) };
+        Some(ids[0])
+    }
+
+    /// Take a chain the device is done with, its head and how many bytes
+    /// the device wrote
+    pub fn pop_used(&mut self) -> Option<(u16, usize)> {
+        let used_idx = unsafe { self.at::<u16>(self.used_offset() + 2).read_volatile() };
+        if used_idx == self.last_used {
+            return None;
+        }
+        fence(Ordering::SeqCst);
+        let entry = self.used_offset() + 4 + 8 * (self.last_used as usize % self.size);
+        let head = unsafe { self.at::<u32>(entry).read_volatile() } as u16;
+        let len = unsafe { self.at::<u32>(entry + 4).read_volatile() } as usize;
+        self.last_used = self.last_used.wrapping_add(1);
+
+        // give the chain's descriptors back
+        let mut id = head;
+        loop {
+            let descriptor = unsafe { self.at::<Descriptor>(16 * id as usize).read_volatile() };
+            self.free.push(id);
+            if descriptor.flags & DESC_F_NEXT == 0 {
+                break;
+            }
+            id = descriptor.next;
+        }
+        Some((head, len))
+    }
+}
@@ -0,0 +1,270 @@
+//! What the machine has, read from the device tree
+//!
+//! The SBI hands the kernel a flattened device tree in `a1`. `init` parses
+//! it once, before the frame allocator and the kernel address space exist,
+//! and keeps what the kernel needs: where RAM ends, which MMIO regions to
+//! map and where the interrupt controller, the virtio-mmio transports and
+//! the PCIe host bridge are. Changing QEMU's `-m` or its device list needs
+//! no code changes.
+use alloc::{string::String, vec::Vec};
+use core::slice;
+use device_tree::util::SliceRead;
+use device_tree::{DeviceTree, Node};
+use lazy_static::lazy_static;
+
+use crate::sync::UPSafeCell;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+
+/// Defaults of `#address-cells` and `#size-cells` when a node has none
+const DEFAULT_ADDRESS_CELLS: usize = 2;
+const DEFAULT_SIZE_CELLS: usize = 1;
+
+/// Space code in the high cell of a PCI address
+const PCI_SPACE_MASK: u32 = 0x0300_0000;
+const PCI_SPACE_MEM32: u32 = 0x0200_0000;
+
+/// Only bus 0 is scanned, so only its part of the ECAM is mapped
+const PCI_ECAM_BUS_SIZE: usize = 1 << 20;
+/// The part of the host bridge's 32-bit MMIO window that the kernel maps,
+/// it is 1G on QEMU and page tables for all of it aren't worth it
+const PCI_MMIO_MAX: usize = 0x0100_0000;
+
+/// Local interrupt the S-mode interrupt files of the IMSIC raise
+const IRQ_S_EXT: u32 = 9;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Region {
+    pub base: usize,
+    pub size: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct VirtioSlot {
+    pub region: Region,
+    pub irq: Option<usize>,
+}
+
+/// A node drivers can be bound to by its `compatible` list
+#[derive(Clone, Debug)]
+pub struct DtDevice {
+    pub compatible: Vec<String>,
+    pub region: Region,
+    pub irq: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PciHost {
+    pub ecam: Region,
+    /// 32-bit memory window BARs are assigned from
+    pub mmio: Region,
+}
+
+/// The devices of the machine the kernel cares about
+#[derive(Default)]
+pub struct Machine {
+    pub memory: Option<Region>,
+    pub uart: Option<Region>,
+    pub plic: Option<Region>,
+    /// sifive,test for shutting QEMU down and the goldfish RTC next to it
+    pub test: Option<Region>,
+    pub rtc: Option<Region>,
+    pub virtio: Vec<VirtioSlot>,
+    pub pci: Option<PciHost>,
+    /// S-mode interrupt files of the IMSIC, with AIA
+    pub imsic: Option<Region>,
+    /// every node with a `compatible` list and registers
+    pub devices: Vec<DtDevice>,
+}
+
+lazy_static! {
+    static ref MACHINE: UPSafeCell<Machine> = unsafe { UPSafeCell::new(Machine::default()) };
+}
+
+#[repr(C)]
+struct FdtHeader {
+    magic: u32,
+    size: u32,
+}
+
+/// Read `count` big-endian cells from `offset` as one number
+fn read_cells(data: &[u8], offset: usize, count: usize) -> usize {
+    (0..count).fold(0, |value, i| {
+        (value << 32) | data.read_be_u32(offset + i * 4).unwrap_or(0) as usize
+    })
+}
+
+/// The `reg` entries of a node, in the cells of its parent
+fn regs(node: &Node, address_cells: usize, size_cells: usize) -> Vec<Region> {
+    let data = match node.prop_raw("reg") {
+        Some(data) => data,
+        None => return Vec::new(),
+    };
+    let entry = (address_cells + size_cells) * 4;
+    (0..data.len() / entry)
+        .map(|i| Region {
+            base: read_cells(data, i * entry, address_cells),
+            size: read_cells(data, i * entry + address_cells * 4, size_cells),
+        })
+        .collect()
+}
+
+fn first_reg(node: &Node, address_cells: usize, size_cells: usize) -> Option<Region> {
+    regs(node, address_cells, size_cells).first().copied()
+}
+
+/// Whether `compatible`, a list of NUL separated strings, has `name`
+fn is_compatible(node: &Node, name: &str) -> bool {
+    node.prop_raw("compatible").map_or(false, |data| {
+        data.split(|byte| *byte == 0).any(|entry| entry == name.as_bytes())
+    })
+}
+
+fn compatible(node: &Node) -> Vec<String> {
+    node.prop_raw("compatible").map_or(Vec::new(), |data| {
+        data.split(|byte| *byte == 0)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| core::str::from_utf8(entry).ok().map(String::from))
+            .collect()
+    })
+}
+
+fn cells(node: &Node, name: &str, default: usize) -> usize {
+    node.prop_u32(name).map_or(default, |cells| cells as usize)
+}
+
+/// The 32-bit memory window among the `ranges` of a PCI host bridge. A
+/// range is a 3 cell PCI address, a parent address and a 2 cell size.
+fn pci_mmio_window(node: &Node, address_cells: usize) -> Option<Region> {
+    let data = node.prop_raw("ranges")?;
+    let entry = (3 + address_cells + 2) * 4;
+    (0..data.len() / entry).find_map(|i| {
+        let offset = i * entry;
+        let space = data.read_be_u32(offset).ok()? & PCI_SPACE_MASK;
+        if space != PCI_SPACE_MEM32 {
+            return None;
+        }
+        Some(Region {
+            base: read_cells(data, offset + 12, address_cells),
+            size: read_cells(data, offset + 12 + address_cells * 4, 2),
+        })
+    })
+}
+
+fn walk(node: &Node, address_cells: usize, size_cells: usize, machine: &mut Machine) {
+    let reg = first_reg(node, address_cells, size_cells);
+    let irq = node.prop_u32("interrupts").ok().map(|irq| irq as usize);
+    if let Some(region) = reg {
+        let compatible = compatible(node);
+        if !compatible.is_empty() {
+            machine.devices.push(DtDevice { compatible, region, irq });
+        }
+    }
+    if node.prop_str("device_type").map_or(false, |kind| kind == "memory") {
+        machine.memory = machine.memory.or(reg);
+    } else if is_compatible(node, "ns16550a") {
+        machine.uart = machine.uart.or(reg);
+    } else if is_compatible(node, "riscv,plic0") || is_compatible(node, "sifive,plic-1.0.0") {
+        machine.plic = reg;
+    } else if is_compatible(node, "sifive,test0") {
+        machine.test = reg;
+    } else if is_compatible(node, "google,goldfish-rtc") {
+        machine.rtc = reg;
+    } else if is_compatible(node, "virtio,mmio") {
+        if let Some(region) = reg {
+            machine.virtio.push(VirtioSlot { region, irq });
+        }
+    } else if is_compatible(node, "riscv,imsics") {
+        // there is a node for the M-mode and one for the S-mode files, they
+        // differ in the local interrupt they raise
+        let local = node.prop_raw("interrupts-extended").and_then(|data| data.read_be_u32(4).ok());
+        if local == Some(IRQ_S_EXT) {
+            machine.imsic = reg;
+        }
+    } else if is_compatible(node, "pci-host-ecam-generic") {
+        if let (Some(ecam), Some(mmio)) = (reg, pci_mmio_window(node, address_cells)) {
+            machine.pci = Some(PciHost { ecam, mmio });
+        }
+    }
+
+    let child_address_cells = cells(node, "#address-cells", DEFAULT_ADDRESS_CELLS);
+    let child_size_cells = cells(node, "#size-cells", DEFAULT_SIZE_CELLS);
+    for child in node.children.iter() {
+        walk(child, child_address_cells, child_size_cells, machine);
+    }
+}
+
+/// Parse the device tree at `dtb`, the heap has to be up
+pub fn init(dtb: usize) {
+    let header = unsafe { &*(dtb as *const FdtHeader) };
+    assert_eq!(u32::from_be(header.magic), FDT_MAGIC, "no device tree at {:#x}", dtb);
+    let size = u32::from_be(header.size) as usize;
+    let data = unsafe { slice::from_raw_parts(dtb as *const u8, size) };
+    let tree = DeviceTree::load(data).expect("failed to parse the device tree");
+
+    let mut machine = Machine::default();
+    walk(&tree.root, DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS, &mut machine);
+    // QEMU fills the transports from the top, the first `-device` on the
+    // command line sits at the highest address
+    machine.virtio.sort_by(|a, b| b.region.base.cmp(&a.region.base));
+    let memory = machine.memory.expect("no memory in the device tree");
+    println!(
+        "[kernel] device tree: memory {:#x}..{:#x}, {} virtio-mmio slots, pci {}",
+        memory.base,
+        memory.base + memory.size,
+        machine.virtio.len(),
+        if machine.pci.is_some() { "yes" } else { "no" }
+    );
+    *MACHINE.exclusive_access() = machine;
+}
+
+/// End of RAM
+pub fn memory_end() -> usize {
+    let memory = MACHINE.exclusive_access().memory.expect("device tree not parsed");
+    memory.base + memory.size
+}
+
+pub fn plic() -> Option<Region> {
+    MACHINE.exclusive_access().plic
+}
+
+pub fn imsic() -> Option<Region> {
+    MACHINE.exclusive_access().imsic
+}
+
+/// Nodes for `registry::probe_device_tree`. Only the regions of the
+/// devices the kernel knows are mapped, see `mmio_regions`.
+pub fn devices() -> Vec<DtDevice> {
+    MACHINE.exclusive_access().devices.clone()
+}
+
+pub fn virtio_slots() -> Vec<VirtioSlot> {
+    MACHINE.exclusive_access().virtio.clone()
+}
+
+/// The host bridge, with the ECAM cut to bus 0 and the MMIO window to what
+/// the kernel maps
+pub fn pci_host() -> Option<PciHost> {
+    MACHINE.exclusive_access().pci.map(|host| PciHost {
+        ecam: Region { base: host.ecam.base, size: host.ecam.size.min(PCI_ECAM_BUS_SIZE) },
+        mmio: Region { base: host.mmio.base, size: host.mmio.size.min(PCI_MMIO_MAX) },
+    })
+}
+
+/// Register regions the kernel maps, only those of devices that exist
+pub fn mmio_regions() -> Vec<Region> {
+    let mut regions: Vec<Region> = {
+        let machine = MACHINE.exclusive_access();
+        [machine.test, machine.rtc, machine.plic, machine.uart]
+            .iter()
+            .flatten()
+            .copied()
+            .chain(machine.virtio.iter().map(|slot| slot.region))
+            .collect()
+    };
+    if let Some(host) = pci_host() {
+        regions.push(host.ecam);
+        regions.push(host.mmio);
+    }
+    regions
+}
@@ -0,0 +1,95 @@
+//! Device files
+//!
+//! There is no devfs, `open` asks here before it looks in the file system.
+//! `/dev/urandom` and `/dev/random` read from the kernel CSPRNG, which
+//! never blocks once seeded, `/dev/hvc0` is the virtio console and
+//! `/dev/ttyS0` the UART.
+use super::{File, WRITE_FAILED};
+use crate::drivers::chardev::CharDevice;
+use crate::drivers::registry;
+use crate::mm::UserBuffer;
+use crate::random;
+use crate::task::suspend_current_and_run_next;
+use alloc::sync::Arc;
+
+/// `/dev/urandom` and `/dev/random`
+pub struct Urandom;
+
+impl File for Urandom {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, mut user_buf: UserBuffer) -> usize {
+        for buffer in user_buf.buffers.iter_mut() {
+            random::fill(buffer);
+        }
+        user_buf.len()
+    }
+    /// What is written is mixed in, it isn't counted as entropy
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        for buffer in user_buf.buffers.iter() {
+            random::add_entropy(buffer);
+        }
+        user_buf.len()
+    }
+}
+
+/// A character device, reads wait for at least one byte
+pub struct CharFile {
+    device: Arc<dyn CharDevice>,
+}
+
+impl File for CharFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        true
+    }
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        let mut read = 0;
+        for byte in user_buf.into_iter() {
+            let c = loop {
+                match self.device.read() {
+                    Some(c) => break Some(c),
+                    None if read > 0 => break None,
+                    None => suspend_current_and_run_next(),
+                }
+            };
+            match c {
+                Some(c) => unsafe { byte.write_volatile(c) },
+                None => break,
+            }
+            read += 1;
+        }
+        read
+    }
+    /// Stops at what the device didn't take, fails if it took nothing
+    fn write(&self, user_buf: UserBuffer) -> usize {
+        let mut written = 0;
+        for buffer in user_buf.buffers.iter() {
+            let len = self.device.write(buffer);
+            written += len;
+            if len < buffer.len() {
+                break;
+            }
+        }
+        if written == 0 && user_buf.len() > 0 {
+            return WRITE_FAILED;
+        }
+        written
+    }
+}
+
+/// The device file at `path`, None if it isn't one
+pub fn open_device(path: &str) -> Option<Arc<dyn File + Send + Sync>> {
+    match path {
+        "/dev/urandom" | "/dev/random" => Some(Arc::new(Urandom)),
+        "/dev/hvc0" => Some(Arc::new(CharFile { device: registry::char_device_of("virtio-console")? })),
+        "/dev/ttyS0" => Some(Arc::new(CharFile { device: registry::char_device_of("ns16550a")? })),
+        _ => None,
+    }
+}
@@ -4,10 +4,11 @@
 //!
 //! `UPSafeCell<OSInodeInner>` -> `OSInode`: for static `ROOT_INODE`,we
 //! need to wrap `OSInodeInner` into `UPSafeCell`
+use super::loop_device::mounted;
 use super::File;
-use crate::drivers::BLOCK_DEVICE;
+use crate::drivers::root_device;
 use crate::mm::UserBuffer;
-use crate::sync::UPSafeCell;
+use crate::sync::{SleepLock, UPSafeCell};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use bitflags::*;
@@ -37,6 +38,7 @@ impl OSInode {
     }
     /// Read all data inside a inode into vector
     pub fn read_all(&self) -> Vec<u8> {
+        let _fs = FS_LOCK.lock();
         let mut inner = self.inner.exclusive_access();
         let mut buffer = [0u8; 512];
         let mut v: Vec<u8> = Vec::new();
@@ -52,14 +54,19 @@ impl OSInode {
     }
 }
 
+/// Disk reads yield while the file system is in the middle of an
+/// operation that isn't reentrant, so only one task is in it at a time
+pub(super) static FS_LOCK: SleepLock = SleepLock::new();
+
 lazy_static! {
     pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
+        let efs = EasyFileSystem::open(root_device()).expect("no FAT volume on the root device");
         Arc::new(EasyFileSystem::root_inode(&efs))
     };
 }
 /// List all files in the filesystems
 pub fn list_apps() {
+    let _fs = FS_LOCK.lock();
     println!("/**** APPS ****");
     for app in ROOT_INODE.ls() {
         println!("{}", app);
@@ -96,22 +103,26 @@ impl OpenFlags {
         }
     }
 }
-///Open file with flags
+///Open file with flags, `dir/name` is `name` on the volume mounted on
+///`dir` if there is one
 pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();
+    let _fs = FS_LOCK.lock();
+    let mount = name.split_once('/').and_then(|(dir, rest)| mounted(dir).map(|dir| (dir, rest)));
+    let (dir, name) = mount.unwrap_or_else(|| (ROOT_INODE.clone(), name));
     if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
+        if let Some(inode) = dir.find(name) {
             // clear size
             inode.clear();
             Some(Arc::new(OSInode::new(readable, writable, inode)))
         } else {
             // create file
-            ROOT_INODE
+            dir
                 .create(name)
                 .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
         }
     } else {
-        ROOT_INODE.find(name).map(|inode| {
+        dir.find(name).map(|inode| {
             if flags.contains(OpenFlags::TRUNC) {
                 inode.clear();
             }
@@ -128,6 +139,7 @@ impl File for OSInode {
         self.writable
     }
     fn read(&self, mut buf: UserBuffer) -> usize {
+        let _fs = FS_LOCK.lock();
         let mut inner = self.inner.exclusive_access();
         let mut total_read_size = 0usize;
         for slice in buf.buffers.iter_mut() {
@@ -141,6 +153,7 @@ impl File for OSInode {
         total_read_size
     }
     fn write(&self, buf: UserBuffer) -> usize {
+        let _fs = FS_LOCK.lock();
         let mut inner = self.inner.exclusive_access();
         let mut total_write_size = 0usize;
         for slice in buf.buffers.iter() {
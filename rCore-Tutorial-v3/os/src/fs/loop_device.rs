@@ -0,0 +1,92 @@
+//! A file on a mounted volume as a block device
+//!
+//! A disk image kept as a file on the root volume can be mounted on a name
+//! with [`mount_image`], `sys_mount` from user space, and `name/file` then
+//! opens `file` on it. Only FAT images can be mounted, the kernel has no
+//! driver for easy-fs ones. The loop device reads and writes the file
+//! directly: whoever uses the inner file system already holds `FS_LOCK`,
+//! which covers every mounted volume.
+use alloc::{collections::BTreeMap, string::String, sync::Arc};
+use easy_fs::{BlockDevice, EasyFileSystem, Inode};
+use lazy_static::*;
+
+use super::inode::{FS_LOCK, ROOT_INODE};
+use crate::sync::UPSafeCell;
+
+const BLOCK_SIZE: usize = 512;
+
+pub struct LoopDevice {
+    inode: Arc<Inode>,
+}
+
+impl LoopDevice {
+    pub fn new(inode: Arc<Inode>) -> Self {
+        Self { inode }
+    }
+}
+
+impl BlockDevice for LoopDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.read_blocks(block_id, &mut [buf]);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.write_blocks(block_id, &[buf]);
+    }
+
+    /// Reads stop at cluster ends, so a buffer may take several. Past the
+    /// end of the file reads as zeroes.
+    fn read_blocks(&self, block_id: usize, bufs: &mut [&mut [u8]]) {
+        let mut offset = block_id * BLOCK_SIZE;
+        for buf in bufs.iter_mut() {
+            let mut done = 0;
+            while done < buf.len() {
+                let size = self.inode.read_at(offset + done, &mut buf[done..]);
+                if size == 0 {
+                    buf[done..].fill(0);
+                    break;
+                }
+                done += size;
+            }
+            offset += buf.len();
+        }
+    }
+
+    fn write_blocks(&self, block_id: usize, bufs: &[&[u8]]) {
+        let mut offset = block_id * BLOCK_SIZE;
+        for buf in bufs.iter() {
+            self.inode.write_at(offset, buf);
+            offset += buf.len();
+        }
+    }
+}
+
+lazy_static! {
+    /// Root directories of the mounted images by the name they are on
+    static ref MOUNTS: UPSafeCell<BTreeMap<String, Arc<Inode>>> = unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Mount the image file `image` of the root volume on `target`. False if
+/// there is no such file, it holds no FAT volume or `target` is taken.
+pub fn mount_image(image: &str, target: &str) -> bool {
+    if target.is_empty() || target.contains('/') || MOUNTS.exclusive_access().contains_key(target) {
+        return false;
+    }
+    let _fs = FS_LOCK.lock();
+    let image = match ROOT_INODE.find(image) {
+        Some(image) => image,
+        None => return false,
+    };
+    let efs = match EasyFileSystem::open(Arc::new(LoopDevice::new(image))) {
+        Some(efs) => efs,
+        None => return false,
+    };
+    let root = Arc::new(EasyFileSystem::root_inode(&efs));
+    MOUNTS.exclusive_access().insert(String::from(target), root);
+    true
+}
+
+/// Root directory of the image mounted on `name`, the caller holds `FS_LOCK`
+pub(super) fn mounted(name: &str) -> Option<Arc<Inode>> {
+    MOUNTS.exclusive_access().get(name).cloned()
+}
@@ -1,8 +1,16 @@
 //! File system in os
+mod dev;
 mod inode;
+mod loop_device;
+mod pipe;
 mod stdio;
 
 use crate::mm::UserBuffer;
+use crate::net::Socket;
+use alloc::sync::Arc;
+/// What `File::write` returns for an error, user space sees -1
+pub const WRITE_FAILED: usize = usize::MAX;
+
 /// File trait
 pub trait File: Send + Sync {
     /// If readable
@@ -11,9 +19,16 @@ pub trait File: Send + Sync {
     fn writable(&self) -> bool;
     /// Read file to `UserBuffer`
     fn read(&self, buf: UserBuffer) -> usize;
-    /// Write `UserBuffer` to file
+    /// Write `UserBuffer` to file, [`WRITE_FAILED`] on an error
     fn write(&self, buf: UserBuffer) -> usize;
+    /// Socket operations, if the file is a socket
+    fn as_socket(self: Arc<Self>) -> Option<Arc<dyn Socket>> {
+        None
+    }
 }
 
+pub use dev::open_device;
 pub use inode::{list_apps, open_file, OSInode, OpenFlags};
+pub use loop_device::mount_image;
+pub use pipe::{make_pipe, Pipe};
 pub use stdio::{Stdin, Stdout};
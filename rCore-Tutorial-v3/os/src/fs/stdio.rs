@@ -1,7 +1,7 @@
 //!Stdin & Stdout
 use super::File;
 use crate::mm::UserBuffer;
-use crate::sbi::console_getchar;
+use crate::sbi::{console_getchar, console_putchar};
 use crate::task::suspend_current_and_run_next;
 ///Standard input
 pub struct Stdin;
@@ -50,8 +50,11 @@ impl File for Stdout {
         panic!("Cannot read from stdout!");
     }
     fn write(&self, user_buf: UserBuffer) -> usize {
+        // bytes from the network need not be valid utf-8
         for buffer in user_buf.buffers.iter() {
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
+            for c in buffer.iter() {
+                console_putchar(*c as usize);
+            }
         }
         user_buf.len()
     }
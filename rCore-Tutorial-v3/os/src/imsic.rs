@@ -0,0 +1,132 @@
+//! S-mode interrupt file of the RISC-V AIA IMSIC
+//!
+//! It takes the MSI and MSI-X writes of PCIe devices, each message data is
+//! an interrupt identity that is pending until it is claimed. QEMU has one
+//! file per hart with `-machine virt,aia=aplic-imsic`, which then has no
+//! PLIC. The file is reached through the siselect/sireg window and stopei.
+//!
+//! Drivers take identities with [`alloc_ids`], point their devices at
+//! [`msi_address`] and hand a handler for each identity to
+//! [`register_msi`]. The trap handler calls [`handle_external`] on a
+//! supervisor external interrupt when [`present`].
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::arch::asm;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use riscv::register::sie;
+
+use crate::dtb;
+use crate::plic::IrqHandler;
+use crate::sync::UPSafeCell;
+
+/// Identities go from 1 to 63 with the smallest implementation
+const MAX_ID: usize = 63;
+/// Each hart has a page of its own in the S-mode files
+const FILE_SIZE: usize = 0x1000;
+
+/// Registers behind siselect
+const EIDELIVERY: usize = 0x70;
+const EITHRESHOLD: usize = 0x72;
+const EIE0: usize = 0xc0;
+
+lazy_static! {
+    static ref HANDLERS: UPSafeCell<BTreeMap<usize, Vec<IrqHandler>>> = unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Where devices write their messages for the boot hart, 0 without AIA
+static MSI_ADDRESS: AtomicUsize = AtomicUsize::new(0);
+/// Next identity `alloc_ids` hands out
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+unsafe fn write_indirect(reg: usize, value: usize) {
+    // siselect = 0x150, sireg = 0x151
+    asm!("csrw 0x150, {0}", "csrw 0x151, {1}", in(reg) reg, in(reg) value);
+}
+
+unsafe fn read_indirect(reg: usize) -> usize {
+    let value: usize;
+    asm!("csrw 0x150, {0}", "csrr {1}, 0x151", in(reg) reg, out(reg) value);
+    value
+}
+
+/// Deliver the interrupts of the file of `hart`, with every identity
+/// disabled until a driver registers for it
+pub fn init(hart: usize) {
+    let files = match dtb::imsic() {
+        Some(files) => files,
+        None => return,
+    };
+    MSI_ADDRESS.store(files.base + hart * FILE_SIZE, Ordering::Relaxed);
+    unsafe {
+        for id in (0..=MAX_ID).step_by(64) {
+            write_indirect(EIE0 + (id / 64) * 2, 0);
+        }
+        write_indirect(EIDELIVERY, 1);
+        // no priority threshold
+        write_indirect(EITHRESHOLD, 0);
+        sie::set_sext();
+    }
+    println!("[kernel] imsic: interrupt file at {:#x}", MSI_ADDRESS.load(Ordering::Relaxed));
+}
+
+/// Whether external interrupts come through the IMSIC instead of the PLIC
+pub fn present() -> bool {
+    MSI_ADDRESS.load(Ordering::Relaxed) != 0
+}
+
+/// Address MSI writes have to go to
+pub fn msi_address() -> Option<usize> {
+    match MSI_ADDRESS.load(Ordering::Relaxed) {
+        0 => None,
+        address => Some(address),
+    }
+}
+
+/// Take `count` consecutive identities, the first one is returned
+pub fn alloc_ids(count: usize) -> Option<usize> {
+    let first = NEXT_ID.fetch_add(count, Ordering::Relaxed);
+    if first + count - 1 > MAX_ID {
+        NEXT_ID.fetch_sub(count, Ordering::Relaxed);
+        return None;
+    }
+    Some(first)
+}
+
+/// Run `handler` whenever a message with identity `id` comes in
+pub fn register_msi(id: usize, handler: IrqHandler) {
+    assert!(id > 0 && id <= MAX_ID, "no interrupt identity {}", id);
+    HANDLERS.exclusive_access().entry(id).or_insert_with(Vec::new).push(handler);
+    // eie registers are 64 bits wide on rv64, only the even ones exist
+    let reg = EIE0 + (id / 64) * 2;
+    unsafe {
+        let bits = read_indirect(reg);
+        write_indirect(reg, bits | (1 << (id % 64)));
+    }
+}
+
+/// Take the highest priority pending identity
+fn claim() -> Option<usize> {
+    let top: usize;
+    // stopei = 0x15c, writing it claims what it read
+    unsafe {
+        asm!("csrrw {0}, 0x15c, zero", out(reg) top);
+    }
+    match top >> 16 {
+        0 => None,
+        id => Some(id),
+    }
+}
+
+/// Claim the pending identities and dispatch them to their handlers
+pub fn handle_external() {
+    while let Some(id) = claim() {
+        // handlers may register others, so the table isn't held
+        let handlers = HANDLERS.exclusive_access().get(&id).cloned().unwrap_or_default();
+        if handlers.is_empty() {
+            println!("[kernel] unexpected message {}", id);
+        }
+        for handler in handlers.iter() {
+            handler();
+        }
+    }
+}
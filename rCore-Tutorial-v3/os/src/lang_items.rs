@@ -1,9 +1,11 @@
 //! The panic handler
+use crate::console;
 use crate::sbi::shutdown;
 use core::panic::PanicInfo;
 
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    console::unhook();
     if let Some(location) = info.location() {
         println!(
             "[kernel] Panicked at {}:{} {}",
@@ -51,7 +51,11 @@ pub mod task;
 pub mod timer;
 pub mod trap;
 pub mod pci;
+pub mod plic;
+pub mod imsic;
+mod dtb;
 pub mod net;
+mod random;
 
 use core::arch::{global_asm, asm};
 
@@ -71,19 +75,27 @@ fn clear_bss() {
 }
 
 #[no_mangle]
-/// the rust entry-point of os
-pub fn rust_main() -> ! {
+/// the rust entry-point of os, the SBI passes the hart id and the device tree
+pub fn rust_main(hart_id: usize, dtb: usize) -> ! {
 
 
     println!("[kernel] Hello, world!");
     clear_bss();
     println!("[kernel] Hello, world!");
+    mm::init_heap();
+    dtb::init(dtb);
     mm::init();
     mm::remap_test();
     trap::init();
+    plic::init(hart_id);
+    imsic::init(hart_id);
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
     pci::init();
+    drivers::init();
+    console::init();
+    random::init();
+    net::init();
     fs::list_apps();
     task::add_initproc();
     task::run_tasks();
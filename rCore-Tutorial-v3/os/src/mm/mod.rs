@@ -14,6 +14,7 @@ mod page_table;
 use address::VPNRange;
 pub use address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum};
 pub use frame_allocator::{frame_alloc, frame_alloc_trackers, frame_dealloc, FrameTracker};
+pub use heap_allocator::init_heap;
 pub use memory_set::remap_test;
 pub use memory_set::{kernel_token, MapPermission, MemorySet, KERNEL_SPACE};
 use page_table::PTEFlags;
@@ -21,9 +22,9 @@ pub use page_table::{
     translated_byte_buffer, translated_ref, translated_refmut, translated_str, PageTable,
     PageTableEntry, UserBuffer, UserBufferIterator,
 };
-/// initiate heap allocator, frame allocator and kernel space
+/// initiate frame allocator and kernel space, the heap is set up before
+/// the device tree is parsed
 pub fn init() {
-    heap_allocator::init_heap();
     frame_allocator::init_frame_allocator();
     KERNEL_SPACE.exclusive_access().activate();
 }
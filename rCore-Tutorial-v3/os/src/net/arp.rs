@@ -0,0 +1,67 @@
+//! ARP for the interfaces `lose_net_stack` doesn't serve, the LAN port and
+//! the VLAN sub-interfaces
+use alloc::vec::Vec;
+
+use super::ipv6::ETH_HEADER_LEN;
+
+pub const ETH_TYPE_ARP: u16 = 0x0806;
+const ARP_LEN: usize = 28;
+const ARP_REQUEST: u16 = 1;
+const ARP_REPLY: u16 = 2;
+
+pub struct ArpPacket {
+    pub operation: u16,
+    pub sender_mac: [u8; 6],
+    pub sender_ip: u32,
+    pub target_ip: u32,
+}
+
+impl ArpPacket {
+    pub fn is_request_for(&self, ip: u32) -> bool {
+        self.operation == ARP_REQUEST && self.target_ip == ip
+    }
+}
+
+pub fn parse(frame: &[u8]) -> Option<ArpPacket> {
+    if frame.len() < ETH_HEADER_LEN + ARP_LEN || u16::from_be_bytes([frame[12], frame[13]]) != ETH_TYPE_ARP {
+        return None;
+    }
+    let arp = &frame[ETH_HEADER_LEN..ETH_HEADER_LEN + ARP_LEN];
+    Some(ArpPacket {
+        operation: u16::from_be_bytes([arp[6], arp[7]]),
+        sender_mac: arp[8..14].try_into().unwrap(),
+        sender_ip: u32::from_be_bytes(arp[14..18].try_into().unwrap()),
+        target_ip: u32::from_be_bytes(arp[24..28].try_into().unwrap()),
+    })
+}
+
+/// Ask who has `target_ip`, from `mac` and `ip`
+pub fn build_request(mac: [u8; 6], ip: u32, target_ip: u32) -> Vec<u8> {
+    let mut request = Vec::with_capacity(ETH_HEADER_LEN + ARP_LEN);
+    request.extend_from_slice(&[0xff; 6]);
+    request.extend_from_slice(&mac);
+    request.extend_from_slice(&ETH_TYPE_ARP.to_be_bytes());
+    request.extend_from_slice(&[0, 1, 0x08, 0x00, 6, 4]);
+    request.extend_from_slice(&ARP_REQUEST.to_be_bytes());
+    request.extend_from_slice(&mac);
+    request.extend_from_slice(&ip.to_be_bytes());
+    request.extend_from_slice(&[0; 6]);
+    request.extend_from_slice(&target_ip.to_be_bytes());
+    request
+}
+
+/// Answer `request` with `mac` as the owner of `ip`
+pub fn build_reply(request: &ArpPacket, mac: [u8; 6], ip: u32) -> Vec<u8> {
+    let mut reply = Vec::with_capacity(ETH_HEADER_LEN + ARP_LEN);
+    reply.extend_from_slice(&request.sender_mac);
+    reply.extend_from_slice(&mac);
+    reply.extend_from_slice(&ETH_TYPE_ARP.to_be_bytes());
+    // ethernet, ipv4, and the lengths of their addresses
+    reply.extend_from_slice(&[0, 1, 0x08, 0x00, 6, 4]);
+    reply.extend_from_slice(&ARP_REPLY.to_be_bytes());
+    reply.extend_from_slice(&mac);
+    reply.extend_from_slice(&ip.to_be_bytes());
+    reply.extend_from_slice(&request.sender_mac);
+    reply.extend_from_slice(&request.sender_ip.to_be_bytes());
+    reply
+}
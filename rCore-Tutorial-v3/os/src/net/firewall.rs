@@ -0,0 +1,240 @@
+//! Packet filter between the network device and the protocol stack
+//!
+//! Rules are kept in one ordered table and evaluated first match wins, once
+//! for every received frame (input chain) and once for every frame sent
+//! (output chain). Packets no rule matches are accepted. Frames that are
+//! not IP, like ARP, are never filtered.
+//!
+//! On the output chain reject behaves like drop, the local sender isn't told.
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+
+use crate::sync::UPSafeCell;
+
+use super::{
+    icmpv6,
+    ipv4::{self, PROTOCOL_ICMP, PROTOCOL_TCP, PROTOCOL_UDP},
+    ipv6::{self, Ipv6Addr, NEXT_HEADER_ICMPV6, NEXT_HEADER_TCP},
+    tcp, IpAddr,
+};
+
+pub const FW_CHAIN_INPUT: u8 = 0;
+pub const FW_CHAIN_OUTPUT: u8 = 1;
+
+pub const FW_ACTION_ACCEPT: u8 = 0;
+pub const FW_ACTION_DROP: u8 = 1;
+pub const FW_ACTION_REJECT: u8 = 2;
+
+/// `interface` of a rule that matches every interface
+pub const FW_ANY_INTERFACE: u8 = 0xff;
+
+/// A rule as it is stored and exchanged with user space.
+///
+/// Zero in `protocol` or `family` matches anything, and addresses are only
+/// compared when `family` is set. IPv4 addresses use the first 4 bytes of
+/// the address fields. Port ranges are inclusive and only apply to TCP and
+/// UDP, other protocols have port 0.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FwRule {
+    pub chain: u8,
+    pub action: u8,
+    pub protocol: u8,
+    pub family: u8,
+    pub interface: u8,
+    pub src_prefix: u8,
+    pub dst_prefix: u8,
+    pub _reserved: u8,
+    pub src_addr: [u8; 16],
+    pub dst_addr: [u8; 16],
+    pub sport: [u16; 2],
+    pub dport: [u16; 2],
+    /// packets that matched the rule
+    pub hits: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Accept,
+    Drop,
+    Reject,
+}
+
+/// What the rules look at in a packet
+struct Flow {
+    family: u8,
+    protocol: u8,
+    source: IpAddr,
+    dest: IpAddr,
+    sport: u16,
+    dport: u16,
+}
+
+fn prefix_matches(addr: &[u8], network: &[u8], prefix: u8) -> bool {
+    let prefix = (prefix as usize).min(addr.len() * 8);
+    let bytes = prefix / 8;
+    if addr[..bytes] != network[..bytes] {
+        return false;
+    }
+    let bits = prefix % 8;
+    bits == 0 || (addr[bytes] ^ network[bytes]) & (0xffu8 << (8 - bits)) == 0
+}
+
+fn addr_matches(addr: &IpAddr, network: &[u8; 16], prefix: u8) -> bool {
+    match addr {
+        IpAddr::V4(addr) => prefix_matches(&addr.to_u32().to_be_bytes(), &network[..4], prefix),
+        IpAddr::V6(addr) => prefix_matches(&addr.0, network, prefix),
+    }
+}
+
+impl FwRule {
+    fn is_valid(&self) -> bool {
+        let max_prefix = match self.family {
+            0 => 0,
+            4 => 32,
+            6 => 128,
+            _ => return false,
+        };
+        self.chain <= FW_CHAIN_OUTPUT
+            && self.action <= FW_ACTION_REJECT
+            && self.src_prefix <= max_prefix
+            && self.dst_prefix <= max_prefix
+            && self.sport[0] <= self.sport[1]
+            && self.dport[0] <= self.dport[1]
+    }
+
+    fn matches(&self, chain: u8, interface: u8, flow: &Flow) -> bool {
+        self.chain == chain
+            && (self.interface == FW_ANY_INTERFACE || self.interface == interface)
+            && (self.protocol == 0 || self.protocol == flow.protocol)
+            && (self.family == 0
+                || (self.family == flow.family
+                    && addr_matches(&flow.source, &self.src_addr, self.src_prefix)
+                    && addr_matches(&flow.dest, &self.dst_addr, self.dst_prefix)))
+            && (self.sport[0]..=self.sport[1]).contains(&flow.sport)
+            && (self.dport[0]..=self.dport[1]).contains(&flow.dport)
+    }
+}
+
+lazy_static! {
+    static ref FW_TABLE: UPSafeCell<Vec<FwRule>> = unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+fn ports(protocol: u8, payload: &[u8]) -> (u16, u16) {
+    if (protocol == PROTOCOL_TCP || protocol == PROTOCOL_UDP) && payload.len() >= 4 {
+        (
+            u16::from_be_bytes([payload[0], payload[1]]),
+            u16::from_be_bytes([payload[2], payload[3]]),
+        )
+    } else {
+        (0, 0)
+    }
+}
+
+fn flow(frame: &[u8]) -> Option<Flow> {
+    if let Some(packet) = ipv4::parse(frame) {
+        let (sport, dport) = ports(packet.protocol, packet.payload);
+        return Some(Flow {
+            family: 4,
+            protocol: packet.protocol,
+            source: IpAddr::V4(packet.source),
+            dest: IpAddr::V4(packet.dest),
+            sport,
+            dport,
+        });
+    }
+    if ipv6::is_ipv6_frame(frame) {
+        let packet = ipv6::parse(frame)?;
+        let (sport, dport) = ports(packet.next_header, packet.payload);
+        return Some(Flow {
+            family: 6,
+            protocol: packet.next_header,
+            source: IpAddr::V6(packet.source),
+            dest: IpAddr::V6(packet.dest),
+            sport,
+            dport,
+        });
+    }
+    None
+}
+
+/// Run `frame` through the rules of `chain`, counting the hit
+pub fn filter(chain: u8, interface: u8, frame: &[u8]) -> Verdict {
+    let mut table = FW_TABLE.exclusive_access();
+    if table.is_empty() {
+        return Verdict::Accept;
+    }
+    let flow = match flow(frame) {
+        Some(flow) => flow,
+        None => return Verdict::Accept,
+    };
+    match table.iter_mut().find(|rule| rule.matches(chain, interface, &flow)) {
+        Some(rule) => {
+            rule.hits += 1;
+            match rule.action {
+                FW_ACTION_ACCEPT => Verdict::Accept,
+                FW_ACTION_DROP => Verdict::Drop,
+                _ => Verdict::Reject,
+            }
+        }
+        None => Verdict::Accept,
+    }
+}
+
+/// Tell the sender of a rejected frame: a reset for TCP, otherwise an ICMP
+/// port unreachable. Broadcasts, multicasts and ICMP errors get no answer.
+pub fn reject(frame: &[u8]) {
+    if let Some(packet) = ipv4::parse(frame) {
+        if packet.protocol == PROTOCOL_TCP {
+            tcp::reject(IpAddr::V4(packet.dest), IpAddr::V4(packet.source), packet.payload);
+        } else if packet.dest == ipv4::local_ip()
+            && !(packet.protocol == PROTOCOL_ICMP && ipv4::is_icmp_error(packet.payload))
+        {
+            ipv4::send_unreachable(frame, ipv4::ICMP_PORT_UNREACHABLE);
+        }
+    } else if let Some(packet) = ipv6::parse(frame) {
+        if packet.next_header == NEXT_HEADER_TCP {
+            tcp::reject(IpAddr::V6(packet.dest), IpAddr::V6(packet.source), packet.payload);
+        } else if !packet.dest.is_multicast()
+            && packet.source != Ipv6Addr::UNSPECIFIED
+            && !(packet.next_header == NEXT_HEADER_ICMPV6
+                && packet.payload.first().map_or(true, |kind| *kind < 128))
+        {
+            icmpv6::send_unreachable(&packet, frame, icmpv6::ICMPV6_PORT_UNREACHABLE);
+        }
+    }
+}
+
+pub const FW_APPEND: usize = 1;
+pub const FW_DELETE: usize = 2;
+pub const FW_LIST: usize = 3;
+pub const FW_FLUSH: usize = 4;
+
+/// Insert `rule` before `index`, or at the end if `index` is past it
+pub fn insert_rule(index: usize, mut rule: FwRule) -> isize {
+    if !rule.is_valid() {
+        return -1;
+    }
+    rule.hits = 0;
+    let mut table = FW_TABLE.exclusive_access();
+    let index = index.min(table.len());
+    table.insert(index, rule);
+    index as isize
+}
+
+pub fn delete_rule(index: usize) -> isize {
+    let mut table = FW_TABLE.exclusive_access();
+    if index >= table.len() {
+        return -1;
+    }
+    table.remove(index);
+    0
+}
+
+pub fn rules() -> Vec<FwRule> {
+    FW_TABLE.exclusive_access().clone()
+}
+
+pub fn flush() {
+    FW_TABLE.exclusive_access().clear();
+}
@@ -0,0 +1,219 @@
+//! ICMPv6 echo and neighbour discovery (RFC 4861), with stateless address
+//! autoconfiguration from router advertisements (RFC 4862).
+//!
+//! Duplicate address detection is not performed.
+use alloc::vec::Vec;
+
+use super::{
+    ipv6::{self, checksum, Ipv6Addr, Ipv6Packet, IPV6_INTERFACE, MAX_GLOBAL_ADDRESSES, NEXT_HEADER_ICMPV6},
+    transmit, update_multicast, LOCAL_MAC,
+};
+
+/// An error message must fit into the minimum IPv6 MTU
+const ICMPV6_ERROR_MAX: usize = 1280 - ipv6::IPV6_HEADER_LEN;
+
+const ICMPV6_DEST_UNREACHABLE: u8 = 1;
+pub const ICMPV6_PORT_UNREACHABLE: u8 = 4;
+
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const ICMPV6_ROUTER_SOLICITATION: u8 = 133;
+const ICMPV6_ROUTER_ADVERTISEMENT: u8 = 134;
+const ICMPV6_NEIGHBOUR_SOLICITATION: u8 = 135;
+const ICMPV6_NEIGHBOUR_ADVERTISEMENT: u8 = 136;
+
+const NDP_OPT_SOURCE_LL_ADDR: u8 = 1;
+const NDP_OPT_TARGET_LL_ADDR: u8 = 2;
+const NDP_OPT_PREFIX_INFO: u8 = 3;
+
+const NA_FLAG_SOLICITED: u8 = 0x40;
+const NA_FLAG_OVERRIDE: u8 = 0x20;
+
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+
+/// Neighbour discovery messages must arrive with a hop limit of 255
+const NDP_HOP_LIMIT: u8 = 255;
+
+fn send_icmpv6(source: &Ipv6Addr, dest: &Ipv6Addr, dest_mac: [u8; 6], hop_limit: u8, mut message: Vec<u8>) {
+    let csum = checksum(source, dest, NEXT_HEADER_ICMPV6, &message);
+    message[2..4].copy_from_slice(&csum.to_be_bytes());
+    let frame = ipv6::build_frame(dest_mac, source, dest, NEXT_HEADER_ICMPV6, hop_limit, &message);
+    transmit(&frame);
+}
+
+fn link_layer_option(kind: u8) -> [u8; 8] {
+    let mut option = [kind, 1, 0, 0, 0, 0, 0, 0];
+    option[2..].copy_from_slice(&LOCAL_MAC);
+    option
+}
+
+/// Iterate over the `(type, body)` pairs of the NDP options in `data`
+fn options(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    core::iter::from_fn(move || {
+        if data.len() < 8 || data[1] == 0 || data.len() < data[1] as usize * 8 {
+            return None;
+        }
+        let len = data[1] as usize * 8;
+        let option = (data[0], &data[2..len]);
+        data = &data[len..];
+        Some(option)
+    })
+}
+
+pub fn send_router_solicitation() {
+    let source = IPV6_INTERFACE.exclusive_access().link_local;
+    let mut message = vec![ICMPV6_ROUTER_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+    message.extend_from_slice(&link_layer_option(NDP_OPT_SOURCE_LL_ADDR));
+    let dest = Ipv6Addr::ALL_ROUTERS;
+    send_icmpv6(&source, &dest, dest.multicast_mac(), NDP_HOP_LIMIT, message);
+}
+
+pub fn send_neighbour_solicitation(source: &Ipv6Addr, target: &Ipv6Addr) {
+    let mut message = vec![ICMPV6_NEIGHBOUR_SOLICITATION, 0, 0, 0, 0, 0, 0, 0];
+    message.extend_from_slice(&target.0);
+    message.extend_from_slice(&link_layer_option(NDP_OPT_SOURCE_LL_ADDR));
+    let dest = target.solicited_node();
+    send_icmpv6(source, &dest, dest.multicast_mac(), NDP_HOP_LIMIT, message);
+}
+
+fn handle_neighbour_solicitation(packet: &Ipv6Packet) {
+    let message = packet.payload;
+    if message.len() < 24 || packet.hop_limit != NDP_HOP_LIMIT {
+        return;
+    }
+    let target = Ipv6Addr::from_slice(&message[8..24]);
+    if !IPV6_INTERFACE.exclusive_access().is_local(&target) {
+        return;
+    }
+
+    for (kind, body) in options(&message[24..]) {
+        if kind == NDP_OPT_SOURCE_LL_ADDR && !packet.source.is_unspecified() {
+            let mut mac = [0u8; 6];
+            mac.copy_from_slice(&body[..6]);
+            ipv6::learn_neighbour(&packet.source, mac);
+        }
+    }
+
+    // a solicitation from the unspecified address is part of DAD, answer all nodes
+    let (dest, dest_mac, flags) = if packet.source.is_unspecified() {
+        let dest = Ipv6Addr::ALL_NODES;
+        (dest, dest.multicast_mac(), NA_FLAG_OVERRIDE)
+    } else {
+        (packet.source, packet.source_mac, NA_FLAG_SOLICITED | NA_FLAG_OVERRIDE)
+    };
+    let mut message = vec![ICMPV6_NEIGHBOUR_ADVERTISEMENT, 0, 0, 0, flags, 0, 0, 0];
+    message.extend_from_slice(&target.0);
+    message.extend_from_slice(&link_layer_option(NDP_OPT_TARGET_LL_ADDR));
+    send_icmpv6(&target, &dest, dest_mac, NDP_HOP_LIMIT, message);
+}
+
+fn handle_neighbour_advertisement(packet: &Ipv6Packet) {
+    let message = packet.payload;
+    if message.len() < 24 || packet.hop_limit != NDP_HOP_LIMIT {
+        return;
+    }
+    let target = Ipv6Addr::from_slice(&message[8..24]);
+    // without an entry for the target the advertisement is dropped
+    // (RFC 4861 7.2.5), it doesn't create one
+    {
+        let iface = IPV6_INTERFACE.exclusive_access();
+        if !iface.neighbours.contains_key(&target) && !iface.pending.contains_key(&target) {
+            return;
+        }
+    }
+    let mut mac = packet.source_mac;
+    for (kind, body) in options(&message[24..]) {
+        if kind == NDP_OPT_TARGET_LL_ADDR {
+            mac.copy_from_slice(&body[..6]);
+        }
+    }
+    ipv6::learn_neighbour(&target, mac);
+}
+
+fn handle_router_advertisement(packet: &Ipv6Packet) {
+    let message = packet.payload;
+    if message.len() < 16 || packet.hop_limit != NDP_HOP_LIMIT || !packet.source.is_link_local() {
+        return;
+    }
+    let router_lifetime = u16::from_be_bytes([message[6], message[7]]);
+
+    for (kind, body) in options(&message[16..]) {
+        match kind {
+            NDP_OPT_SOURCE_LL_ADDR => {
+                let mut mac = [0u8; 6];
+                mac.copy_from_slice(&body[..6]);
+                ipv6::learn_neighbour(&packet.source, mac);
+            }
+            NDP_OPT_PREFIX_INFO if body.len() >= 30 => {
+                let prefix_len = body[0];
+                let flags = body[1];
+                let valid_lifetime = u32::from_be_bytes([body[2], body[3], body[4], body[5]]);
+                let mut prefix = [0u8; 16];
+                prefix.copy_from_slice(&body[14..30]);
+                // SLAAC only works with a /64 and an EUI-64 interface identifier
+                if flags & PREFIX_FLAG_AUTONOMOUS == 0 || prefix_len != 64 || valid_lifetime == 0 {
+                    continue;
+                }
+                let addr = Ipv6Addr::from_prefix(&prefix, LOCAL_MAC);
+                let mut iface = IPV6_INTERFACE.exclusive_access();
+                if !iface.global.contains(&addr) && iface.global.len() < MAX_GLOBAL_ADDRESSES {
+                    println!("[kernel] ipv6 slaac address {:x?}", addr.0);
+                    iface.global.push(addr);
+                    drop(iface);
+                    // its solicited-node group
+                    update_multicast();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut iface = IPV6_INTERFACE.exclusive_access();
+    if router_lifetime != 0 {
+        iface.router = Some(packet.source);
+    } else if iface.router == Some(packet.source) {
+        iface.router = None;
+    }
+}
+
+fn handle_echo_request(packet: &Ipv6Packet) {
+    let mut message = packet.payload.to_vec();
+    message[0] = ICMPV6_ECHO_REPLY;
+    message[2] = 0;
+    message[3] = 0;
+    let source = if packet.dest.is_multicast() {
+        IPV6_INTERFACE.exclusive_access().source_for(&packet.source)
+    } else {
+        packet.dest
+    };
+    send_icmpv6(&source, &packet.source, packet.source_mac, ipv6::DEFAULT_HOP_LIMIT, message);
+}
+
+/// Answer `packet`, which arrived in `frame`, with a destination unreachable
+/// that quotes as much of it as fits
+pub fn send_unreachable(packet: &Ipv6Packet, frame: &[u8], code: u8) {
+    let mut message = vec![ICMPV6_DEST_UNREACHABLE, code, 0, 0, 0, 0, 0, 0];
+    let invoking = &frame[ipv6::ETH_HEADER_LEN..];
+    let quoted = invoking.len().min(ICMPV6_ERROR_MAX - message.len());
+    message.extend_from_slice(&invoking[..quoted]);
+    let csum = checksum(&packet.dest, &packet.source, NEXT_HEADER_ICMPV6, &message);
+    message[2..4].copy_from_slice(&csum.to_be_bytes());
+    ipv6::send(&packet.dest, &packet.source, NEXT_HEADER_ICMPV6, &message);
+}
+
+pub fn handle(packet: &Ipv6Packet) {
+    if packet.payload.len() < 8 {
+        return;
+    }
+    if checksum(&packet.source, &packet.dest, NEXT_HEADER_ICMPV6, packet.payload) != 0 {
+        println!("[kernel] drop icmpv6 packet with bad checksum");
+        return;
+    }
+    match packet.payload[0] {
+        ICMPV6_ECHO_REQUEST => handle_echo_request(packet),
+        ICMPV6_ROUTER_ADVERTISEMENT => handle_router_advertisement(packet),
+        ICMPV6_NEIGHBOUR_SOLICITATION => handle_neighbour_solicitation(packet),
+        ICMPV6_NEIGHBOUR_ADVERTISEMENT => handle_neighbour_advertisement(packet),
+        _ => {}
+    }
+}
@@ -0,0 +1,121 @@
+//! IPv4 multicast group membership with IGMPv2 (RFC 2236)
+//!
+//! UDP sockets join groups with `IP_ADD_MEMBERSHIP`, the host is a member as
+//! long as one socket is. Joining and leaving are announced right away and
+//! queries are answered without the random delay, there are few enough
+//! groups that the reports don't need to be spread out.
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use lose_net_stack::IPv4;
+
+use crate::sync::UPSafeCell;
+
+use super::{
+    ipv4::{self, Ipv4Packet, PROTOCOL_IGMP},
+    update_multicast,
+};
+
+const IGMP_MEMBERSHIP_QUERY: u8 = 0x11;
+const IGMP_V2_MEMBERSHIP_REPORT: u8 = 0x16;
+const IGMP_LEAVE_GROUP: u8 = 0x17;
+const IGMP_LEN: usize = 8;
+
+/// 224.0.0.1, every host is always a member
+const ALL_SYSTEMS: u32 = 0xe000_0001;
+/// 224.0.0.2, where leaves are sent
+const ALL_ROUTERS: u32 = 0xe000_0002;
+
+lazy_static! {
+    /// Joined groups and how many sockets joined each
+    static ref GROUPS: UPSafeCell<Vec<(u32, usize)>> = unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+/// 224.0.0.0/4
+pub fn is_multicast(addr: IPv4) -> bool {
+    addr.to_u32() >> 28 == 0xe
+}
+
+/// Ethernet address of a group, 01:00:5e and the low 23 bits of it
+pub fn multicast_mac(group: IPv4) -> [u8; 6] {
+    let group = group.to_u32().to_be_bytes();
+    [0x01, 0x00, 0x5e, group[1] & 0x7f, group[2], group[3]]
+}
+
+pub fn is_member(group: IPv4) -> bool {
+    let group = group.to_u32();
+    group == ALL_SYSTEMS || GROUPS.exclusive_access().iter().any(|(joined, _)| *joined == group)
+}
+
+/// Addresses the receive filter of the NIC has to let through for IPv4,
+/// `update_multicast` hands them to it
+pub fn multicast_macs() -> Vec<[u8; 6]> {
+    let mut macs: Vec<[u8; 6]> = GROUPS
+        .exclusive_access()
+        .iter()
+        .map(|(group, _)| multicast_mac(IPv4::from_u32(*group)))
+        .collect();
+    macs.push(multicast_mac(IPv4::from_u32(ALL_SYSTEMS)));
+    macs
+}
+
+fn send(kind: u8, group: u32, dest: u32) {
+    let mut message = [0u8; IGMP_LEN];
+    message[0] = kind;
+    message[4..8].copy_from_slice(&group.to_be_bytes());
+    ipv4::icmp_checksum(&mut message);
+    ipv4::send(ipv4::local_ip(), IPv4::from_u32(dest), PROTOCOL_IGMP, &message);
+}
+
+/// Join `group` for one more socket, false if it isn't a multicast address
+pub fn join(group: IPv4) -> bool {
+    if !is_multicast(group) {
+        return false;
+    }
+    let group = group.to_u32();
+    let mut groups = GROUPS.exclusive_access();
+    match groups.iter_mut().find(|(joined, _)| *joined == group) {
+        Some((_, users)) => *users += 1,
+        None => {
+            groups.push((group, 1));
+            drop(groups);
+            update_multicast();
+            send(IGMP_V2_MEMBERSHIP_REPORT, group, group);
+        }
+    }
+    true
+}
+
+/// Leave `group` for one socket, the host leaves with the last one
+pub fn leave(group: IPv4) {
+    let group = group.to_u32();
+    let mut groups = GROUPS.exclusive_access();
+    let index = match groups.iter().position(|(joined, _)| *joined == group) {
+        Some(index) => index,
+        None => return,
+    };
+    groups[index].1 -= 1;
+    if groups[index].1 == 0 {
+        groups.remove(index);
+        drop(groups);
+        update_multicast();
+        send(IGMP_LEAVE_GROUP, group, ALL_ROUTERS);
+    }
+}
+
+/// Answer membership queries, reports of other hosts are ignored
+pub fn handle(packet: &Ipv4Packet) {
+    let message = packet.payload;
+    if message.len() < IGMP_LEN || message[0] != IGMP_MEMBERSHIP_QUERY {
+        return;
+    }
+    let queried = u32::from_be_bytes([message[4], message[5], message[6], message[7]]);
+    let groups: Vec<u32> = GROUPS
+        .exclusive_access()
+        .iter()
+        .map(|(group, _)| *group)
+        .filter(|group| queried == 0 || *group == queried)
+        .collect();
+    for group in groups {
+        send(IGMP_V2_MEMBERSHIP_REPORT, group, group);
+    }
+}
@@ -0,0 +1,163 @@
+//! IPv4 framing for the protocols `lose_net_stack` doesn't handle (TCP)
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU16, Ordering};
+use lose_net_stack::IPv4;
+
+use super::{
+    igmp, vlan,
+    ipv6::{fold, ones_complement_add, ETH_HEADER_LEN},
+    rx_checksum_verified, transmit, LOCAL_MAC, LOSE_NET_STACK, WAN_INTERFACE,
+};
+
+pub const ETH_TYPE_IPV4: u16 = 0x0800;
+pub const IPV4_HEADER_LEN: usize = 20;
+
+pub const PROTOCOL_ICMP: u8 = 1;
+pub const PROTOCOL_IGMP: u8 = 2;
+pub const PROTOCOL_TCP: u8 = 6;
+pub const PROTOCOL_UDP: u8 = 17;
+
+pub const DEFAULT_TTL: u8 = 64;
+/// Multicast stays on the link unless asked otherwise, like on linux
+pub const MULTICAST_TTL: u8 = 1;
+
+pub const ICMP_ECHO_REPLY: u8 = 0;
+pub const ICMP_DEST_UNREACHABLE: u8 = 3;
+pub const ICMP_ECHO_REQUEST: u8 = 8;
+pub const ICMP_TIME_EXCEEDED: u8 = 11;
+
+pub const ICMP_PORT_UNREACHABLE: u8 = 3;
+
+static IDENTIFICATION: AtomicU16 = AtomicU16::new(0);
+
+pub struct Ipv4Packet<'a> {
+    pub source: IPv4,
+    pub dest: IPv4,
+    pub protocol: u8,
+    pub ttl: u8,
+    pub payload: &'a [u8],
+}
+
+pub fn local_ip() -> IPv4 {
+    LOSE_NET_STACK.exclusive_access().ip
+}
+
+/// Our address towards `dest`, the one of the VLAN whose subnet holds it
+pub fn source_for(dest: IPv4) -> IPv4 {
+    vlan::address_for(dest).unwrap_or_else(local_ip)
+}
+
+/// The interface packets to `dest` leave through
+pub fn interface_for(dest: IPv4) -> u8 {
+    vlan::route(dest).unwrap_or(WAN_INTERFACE)
+}
+
+pub fn parse(frame: &[u8]) -> Option<Ipv4Packet> {
+    if frame.len() < ETH_HEADER_LEN + IPV4_HEADER_LEN
+        || u16::from_be_bytes([frame[12], frame[13]]) != ETH_TYPE_IPV4
+    {
+        return None;
+    }
+    let ip = &frame[ETH_HEADER_LEN..];
+    let header_len = ((ip[0] & 0xf) as usize) * 4;
+    let total_len = u16::from_be_bytes([ip[2], ip[3]]) as usize;
+    if ip[0] >> 4 != 4 || header_len < IPV4_HEADER_LEN || total_len > ip.len() || total_len < header_len {
+        return None;
+    }
+    if !rx_checksum_verified() && fold(ones_complement_add(0, &ip[..header_len])) != 0xffff {
+        return None;
+    }
+    Some(Ipv4Packet {
+        source: IPv4::from_u32(u32::from_be_bytes([ip[12], ip[13], ip[14], ip[15]])),
+        dest: IPv4::from_u32(u32::from_be_bytes([ip[16], ip[17], ip[18], ip[19]])),
+        protocol: ip[9],
+        ttl: ip[8],
+        payload: &ip[header_len..total_len],
+    })
+}
+
+/// One's complement sum over the IPv4 pseudo header and `payload`.
+/// The checksum field inside `payload` must be zero.
+pub fn checksum(source: IPv4, dest: IPv4, protocol: u8, payload: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    sum = ones_complement_add(sum, &source.to_u32().to_be_bytes());
+    sum = ones_complement_add(sum, &dest.to_u32().to_be_bytes());
+    sum = ones_complement_add(sum, &[0, protocol]);
+    sum = ones_complement_add(sum, &(payload.len() as u16).to_be_bytes());
+    sum = ones_complement_add(sum, payload);
+    !fold(sum)
+}
+
+pub fn build_frame(dest_mac: [u8; 6], source: IPv4, dest: IPv4, protocol: u8, ttl: u8, payload: &[u8]) -> Vec<u8> {
+    let total_len = IPV4_HEADER_LEN + payload.len();
+    let mut frame = Vec::with_capacity(ETH_HEADER_LEN + total_len);
+    frame.extend_from_slice(&dest_mac);
+    frame.extend_from_slice(&LOCAL_MAC);
+    frame.extend_from_slice(&ETH_TYPE_IPV4.to_be_bytes());
+
+    let mut header = [0u8; IPV4_HEADER_LEN];
+    header[0] = 0x45;
+    header[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+    header[4..6].copy_from_slice(&IDENTIFICATION.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+    // don't fragment
+    header[6] = 0x40;
+    header[8] = ttl;
+    header[9] = protocol;
+    header[12..16].copy_from_slice(&source.to_u32().to_be_bytes());
+    header[16..20].copy_from_slice(&dest.to_u32().to_be_bytes());
+    let csum = !fold(ones_complement_add(0, &header));
+    header[10..12].copy_from_slice(&csum.to_be_bytes());
+
+    frame.extend_from_slice(&header);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Send an upper layer `payload` to `dest`. Like the udp path, the frame is
+/// broadcast on the link and slirp's gateway picks it up. Multicast goes
+/// to the group's ethernet address, VLAN subnets through their sub-interface.
+pub fn send(source: IPv4, dest: IPv4, protocol: u8, payload: &[u8]) {
+    if let Some(interface) = vlan::route(dest) {
+        vlan::send_ipv4(interface, source, dest, protocol, payload);
+        return;
+    }
+    let frame = if igmp::is_multicast(dest) {
+        build_frame(igmp::multicast_mac(dest), source, dest, protocol, MULTICAST_TTL, payload)
+    } else {
+        build_frame([0xff; 6], source, dest, protocol, DEFAULT_TTL, payload)
+    };
+    transmit(&frame);
+}
+
+/// Set the checksum of an ICMP message, which covers only the message
+pub fn icmp_checksum(message: &mut [u8]) {
+    message[2..4].copy_from_slice(&[0, 0]);
+    let csum = !fold(ones_complement_add(0, message));
+    message[2..4].copy_from_slice(&csum.to_be_bytes());
+}
+
+/// ICMPv4 types that report errors, which must not be answered with errors
+pub fn is_icmp_error(payload: &[u8]) -> bool {
+    matches!(payload.first(), Some(3) | Some(4) | Some(5) | Some(11) | Some(12))
+}
+
+/// Build an ICMP error about the packet in `frame`, which quotes its IP
+/// header and the first 8 bytes of its payload
+pub fn icmp_error(frame: &[u8], kind: u8, code: u8) -> Vec<u8> {
+    let ip = &frame[ETH_HEADER_LEN..];
+    let header_len = ((ip[0] & 0xf) as usize) * 4;
+    let total_len = u16::from_be_bytes([ip[2], ip[3]]) as usize;
+    let quoted = (header_len + 8).min(total_len);
+
+    let mut message = vec![kind, code, 0, 0, 0, 0, 0, 0];
+    message.extend_from_slice(&ip[..quoted]);
+    icmp_checksum(&mut message);
+    message
+}
+
+/// Answer the packet in `frame` with an ICMP destination unreachable
+pub fn send_unreachable(frame: &[u8], code: u8) {
+    if let Some(packet) = parse(frame) {
+        send(packet.dest, packet.source, PROTOCOL_ICMP, &icmp_error(frame, ICMP_DEST_UNREACHABLE, code));
+    }
+}
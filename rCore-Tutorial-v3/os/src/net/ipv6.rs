@@ -0,0 +1,432 @@
+//! IPv6 addressing and framing
+//!
+//! `lose_net_stack` only understands IPv4, so IPv6 frames are recognised by
+//! their ethertype in [`super::net_interrupt_handler`] and handled here.
+use alloc::{collections::{BTreeMap, VecDeque}, vec::Vec};
+use lazy_static::lazy_static;
+
+use crate::{sync::UPSafeCell, timer::get_time_ms};
+
+use super::{icmpv6, tcp, socket::{get_socket, push_data}, IpAddr, transmit, LOCAL_MAC};
+
+pub const ETH_TYPE_IPV6: u16 = 0x86dd;
+pub const ETH_HEADER_LEN: usize = 14;
+pub const IPV6_HEADER_LEN: usize = 40;
+pub const UDP_HEADER_LEN: usize = 8;
+
+pub const NEXT_HEADER_TCP: u8 = 6;
+pub const NEXT_HEADER_UDP: u8 = 17;
+pub const NEXT_HEADER_ICMPV6: u8 = 58;
+
+pub const DEFAULT_HOP_LIMIT: u8 = 64;
+
+/// Frames kept per unresolved neighbour, older ones are dropped
+const PENDING_FRAMES: usize = 3;
+/// Solicitations sent before a neighbour is given up (RFC 4861 MAX_MULTICAST_SOLICIT)
+const MAX_SOLICITATIONS: usize = 3;
+/// Time between solicitations (RFC 4861 RETRANS_TIMER)
+const RETRANS_TIMER_MS: usize = 1000;
+/// Size of the neighbour cache, and of the neighbours being solicited
+const MAX_NEIGHBOURS: usize = 64;
+/// Global addresses taken from router advertisements, one per prefix
+pub const MAX_GLOBAL_ADDRESSES: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Ipv6Addr(pub [u8; 16]);
+
+impl Ipv6Addr {
+    pub const UNSPECIFIED: Self = Self([0; 16]);
+    /// ff02::1
+    pub const ALL_NODES: Self = Self([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+    /// ff02::2
+    pub const ALL_ROUTERS: Self = Self([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+
+    pub fn from_slice(data: &[u8]) -> Self {
+        let mut addr = [0u8; 16];
+        addr.copy_from_slice(&data[..16]);
+        Self(addr)
+    }
+
+    /// fe80::/64 with the modified EUI-64 interface identifier of `mac`
+    pub fn link_local(mac: [u8; 6]) -> Self {
+        let mut prefix = [0u8; 16];
+        prefix[0] = 0xfe;
+        prefix[1] = 0x80;
+        Self::from_prefix(&prefix, mac)
+    }
+
+    /// Combine the upper 64 bits of `prefix` with the EUI-64 of `mac` (SLAAC)
+    pub fn from_prefix(prefix: &[u8; 16], mac: [u8; 6]) -> Self {
+        let mut addr = [0u8; 16];
+        addr[..8].copy_from_slice(&prefix[..8]);
+        addr[8] = mac[0] ^ 0x02;
+        addr[9] = mac[1];
+        addr[10] = mac[2];
+        addr[11] = 0xff;
+        addr[12] = 0xfe;
+        addr[13] = mac[3];
+        addr[14] = mac[4];
+        addr[15] = mac[5];
+        Self(addr)
+    }
+
+    /// ff02::1:ffXX:XXXX, where neighbour solicitations for `self` are sent
+    pub fn solicited_node(&self) -> Self {
+        let mut addr = [0u8; 16];
+        addr[0] = 0xff;
+        addr[1] = 0x02;
+        addr[11] = 0x01;
+        addr[12] = 0xff;
+        addr[13..].copy_from_slice(&self.0[13..]);
+        Self(addr)
+    }
+
+    /// 33:33:XX:XX:XX:XX, the ethernet address of a multicast group
+    pub fn multicast_mac(&self) -> [u8; 6] {
+        [0x33, 0x33, self.0[12], self.0[13], self.0[14], self.0[15]]
+    }
+
+    pub fn is_unspecified(&self) -> bool {
+        *self == Self::UNSPECIFIED
+    }
+
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] == 0xff
+    }
+
+    pub fn is_link_local(&self) -> bool {
+        self.0[0] == 0xfe && (self.0[1] & 0xc0) == 0x80
+    }
+
+    pub fn same_prefix64(&self, other: &Self) -> bool {
+        self.0[..8] == other.0[..8]
+    }
+}
+
+/// Interface state: our addresses, the default router and the neighbour cache
+/// that takes the place of ARP.
+pub struct Ipv6Interface {
+    pub link_local: Ipv6Addr,
+    pub global: Vec<Ipv6Addr>,
+    pub router: Option<Ipv6Addr>,
+    pub neighbours: BTreeMap<Ipv6Addr, [u8; 6]>,
+    /// packets waiting for a neighbour advertisement, keyed by next hop
+    pub pending: BTreeMap<Ipv6Addr, PendingNeighbour>,
+}
+
+/// A neighbour that is being solicited
+pub struct PendingNeighbour {
+    frames: VecDeque<Vec<u8>>,
+    /// where the solicitations are sent from
+    source: Ipv6Addr,
+    solicitations: usize,
+    sent_at: usize,
+}
+
+impl Ipv6Interface {
+    pub fn is_local(&self, addr: &Ipv6Addr) -> bool {
+        *addr == self.link_local || self.global.contains(addr)
+    }
+
+    /// Pick a source address of the right scope for `dst`
+    pub fn source_for(&self, dst: &Ipv6Addr) -> Ipv6Addr {
+        if dst.is_link_local() || dst.is_multicast() {
+            return self.link_local;
+        }
+        self.global
+            .iter()
+            .find(|addr| addr.same_prefix64(dst))
+            .or(self.global.first())
+            .copied()
+            .unwrap_or(self.link_local)
+    }
+
+    /// Destinations outside our prefixes go through the default router
+    fn next_hop(&self, dst: &Ipv6Addr) -> Ipv6Addr {
+        if dst.is_link_local() || self.global.iter().any(|addr| addr.same_prefix64(dst)) {
+            return *dst;
+        }
+        self.router.unwrap_or(*dst)
+    }
+}
+
+lazy_static! {
+    pub static ref IPV6_INTERFACE: UPSafeCell<Ipv6Interface> = unsafe {
+        UPSafeCell::new(Ipv6Interface {
+            link_local: Ipv6Addr::link_local(LOCAL_MAC),
+            global: Vec::new(),
+            router: None,
+            neighbours: BTreeMap::new(),
+            pending: BTreeMap::new(),
+        })
+    };
+}
+
+pub struct Ipv6Packet<'a> {
+    pub source_mac: [u8; 6],
+    pub source: Ipv6Addr,
+    pub dest: Ipv6Addr,
+    pub next_header: u8,
+    pub hop_limit: u8,
+    pub payload: &'a [u8],
+}
+
+pub fn is_ipv6_frame(frame: &[u8]) -> bool {
+    frame.len() >= ETH_HEADER_LEN + IPV6_HEADER_LEN
+        && u16::from_be_bytes([frame[12], frame[13]]) == ETH_TYPE_IPV6
+}
+
+pub fn parse(frame: &[u8]) -> Option<Ipv6Packet> {
+    if !is_ipv6_frame(frame) {
+        return None;
+    }
+    let ip = &frame[ETH_HEADER_LEN..];
+    if ip[0] >> 4 != 6 {
+        return None;
+    }
+    let payload_len = u16::from_be_bytes([ip[4], ip[5]]) as usize;
+    if IPV6_HEADER_LEN + payload_len > ip.len() {
+        return None;
+    }
+    let mut source_mac = [0u8; 6];
+    source_mac.copy_from_slice(&frame[6..12]);
+    Some(Ipv6Packet {
+        source_mac,
+        source: Ipv6Addr::from_slice(&ip[8..24]),
+        dest: Ipv6Addr::from_slice(&ip[24..40]),
+        next_header: ip[6],
+        hop_limit: ip[7],
+        payload: &ip[IPV6_HEADER_LEN..IPV6_HEADER_LEN + payload_len],
+    })
+}
+
+/// One's complement sum over the IPv6 pseudo header and `payload`.
+/// The checksum field inside `payload` must be zero.
+pub fn checksum(source: &Ipv6Addr, dest: &Ipv6Addr, next_header: u8, payload: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    sum = ones_complement_add(sum, &source.0);
+    sum = ones_complement_add(sum, &dest.0);
+    sum = ones_complement_add(sum, &(payload.len() as u32).to_be_bytes());
+    sum = ones_complement_add(sum, &[0, 0, 0, next_header]);
+    sum = ones_complement_add(sum, payload);
+    !fold(sum)
+}
+
+pub fn ones_complement_add(mut sum: u32, data: &[u8]) -> u32 {
+    for chunk in data.chunks(2) {
+        let word = if chunk.len() == 2 {
+            u16::from_be_bytes([chunk[0], chunk[1]])
+        } else {
+            u16::from_be_bytes([chunk[0], 0])
+        };
+        sum += word as u32;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum
+}
+
+pub fn fold(mut sum: u32) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+pub fn build_frame(
+    dest_mac: [u8; 6],
+    source: &Ipv6Addr,
+    dest: &Ipv6Addr,
+    next_header: u8,
+    hop_limit: u8,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(ETH_HEADER_LEN + IPV6_HEADER_LEN + payload.len());
+    frame.extend_from_slice(&dest_mac);
+    frame.extend_from_slice(&LOCAL_MAC);
+    frame.extend_from_slice(&ETH_TYPE_IPV6.to_be_bytes());
+    frame.extend_from_slice(&[0x60, 0, 0, 0]);
+    frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    frame.push(next_header);
+    frame.push(hop_limit);
+    frame.extend_from_slice(&source.0);
+    frame.extend_from_slice(&dest.0);
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Send an upper layer `payload` to `dest`. If the next hop is not in the
+/// neighbour cache yet, a solicitation is sent and the packet is queued
+/// until the advertisement arrives, only the last few are kept.
+pub fn send(source: &Ipv6Addr, dest: &Ipv6Addr, next_header: u8, payload: &[u8]) {
+    let mut iface = IPV6_INTERFACE.exclusive_access();
+    let next_hop = iface.next_hop(dest);
+    let dest_mac = if dest.is_multicast() {
+        Some(dest.multicast_mac())
+    } else {
+        iface.neighbours.get(&next_hop).copied()
+    };
+
+    match dest_mac {
+        Some(mac) => {
+            drop(iface);
+            let frame = build_frame(mac, source, dest, next_header, DEFAULT_HOP_LIMIT, payload);
+            transmit(&frame);
+        }
+        None => {
+            // the real destination mac is filled in when the packet is flushed
+            let frame = build_frame([0; 6], source, dest, next_header, DEFAULT_HOP_LIMIT, payload);
+            let first = !iface.pending.contains_key(&next_hop);
+            if first && iface.pending.len() >= MAX_NEIGHBOURS {
+                return;
+            }
+            let source = iface.source_for(&next_hop);
+            let pending = iface.pending.entry(next_hop).or_insert_with(|| PendingNeighbour {
+                frames: VecDeque::new(),
+                source,
+                solicitations: 1,
+                sent_at: get_time_ms(),
+            });
+            if pending.frames.len() == PENDING_FRAMES {
+                pending.frames.pop_front();
+            }
+            pending.frames.push_back(frame);
+            drop(iface);
+            if first {
+                icmpv6::send_neighbour_solicitation(&source, &next_hop);
+            }
+        }
+    }
+}
+
+/// Ethernet addresses of the groups the host is in, all nodes and the
+/// solicited-node group of each of its addresses
+pub fn multicast_macs() -> Vec<[u8; 6]> {
+    let iface = IPV6_INTERFACE.exclusive_access();
+    let mut macs = vec![Ipv6Addr::ALL_NODES.multicast_mac()];
+    for addr in core::iter::once(&iface.link_local).chain(iface.global.iter()) {
+        let mac = addr.solicited_node().multicast_mac();
+        if !macs.contains(&mac) {
+            macs.push(mac);
+        }
+    }
+    macs
+}
+
+/// Record `mac` for `addr` and flush the packets that were waiting for it.
+/// When the cache is full a neighbour we are soliciting replaces another
+/// one, but unsolicited ones are not added.
+pub fn learn_neighbour(addr: &Ipv6Addr, mac: [u8; 6]) {
+    let mut iface = IPV6_INTERFACE.exclusive_access();
+    if iface.neighbours.len() >= MAX_NEIGHBOURS && !iface.neighbours.contains_key(addr) {
+        if !iface.pending.contains_key(addr) {
+            return;
+        }
+        let router = iface.router;
+        let victim = iface.neighbours.keys().find(|neighbour| Some(**neighbour) != router).copied();
+        if let Some(victim) = victim {
+            iface.neighbours.remove(&victim);
+        }
+    }
+    iface.neighbours.insert(*addr, mac);
+    let pending = iface.pending.remove(addr);
+    drop(iface);
+
+    if let Some(pending) = pending {
+        for mut frame in pending.frames {
+            frame[..6].copy_from_slice(&mac);
+            transmit(&frame);
+        }
+    }
+}
+
+/// Solicit the neighbours that haven't answered again, and give up on
+/// the ones that were solicited often enough together with their frames
+pub fn poll() {
+    let now = get_time_ms();
+    let mut resend = Vec::new();
+    {
+        let mut iface = IPV6_INTERFACE.exclusive_access();
+        iface.pending.retain(|addr, pending| {
+            if now < pending.sent_at + RETRANS_TIMER_MS {
+                return true;
+            }
+            if pending.solicitations == MAX_SOLICITATIONS {
+                println!("[kernel] ipv6 neighbour {:x?} unreachable, dropping {} packets", addr.0, pending.frames.len());
+                return false;
+            }
+            pending.solicitations += 1;
+            pending.sent_at = now;
+            resend.push((pending.source, *addr));
+            true
+        });
+    }
+    for (source, target) in resend {
+        icmpv6::send_neighbour_solicitation(&source, &target);
+    }
+}
+
+pub fn send_udp(dest: &Ipv6Addr, sport: u16, dport: u16, data: &[u8]) {
+    let source = IPV6_INTERFACE.exclusive_access().source_for(dest);
+    let len = UDP_HEADER_LEN + data.len();
+    let mut segment = Vec::with_capacity(len);
+    segment.extend_from_slice(&sport.to_be_bytes());
+    segment.extend_from_slice(&dport.to_be_bytes());
+    segment.extend_from_slice(&(len as u16).to_be_bytes());
+    segment.extend_from_slice(&[0, 0]);
+    segment.extend_from_slice(data);
+    // a zero udp checksum is not allowed over ipv6
+    let csum = match checksum(&source, dest, NEXT_HEADER_UDP, &segment) {
+        0 => 0xffff,
+        csum => csum,
+    };
+    segment[6..8].copy_from_slice(&csum.to_be_bytes());
+    send(&source, dest, NEXT_HEADER_UDP, &segment);
+}
+
+fn handle_udp(packet: &Ipv6Packet) {
+    let udp = packet.payload;
+    if udp.len() < UDP_HEADER_LEN {
+        return;
+    }
+    if checksum(&packet.source, &packet.dest, NEXT_HEADER_UDP, udp) != 0 {
+        println!("[kernel] drop udp6 packet with bad checksum");
+        return;
+    }
+    let sport = u16::from_be_bytes([udp[0], udp[1]]);
+    let dport = u16::from_be_bytes([udp[2], udp[3]]);
+    let len = (u16::from_be_bytes([udp[4], udp[5]]) as usize).min(udp.len());
+    // a length shorter than the header is malformed, the checksum
+    // doesn't cover that
+    if len < UDP_HEADER_LEN {
+        return;
+    }
+
+    if let Some(socket_index) = get_socket(IpAddr::V6(packet.source), dport, sport) {
+        push_data(socket_index, IpAddr::V6(packet.source), sport, udp[UDP_HEADER_LEN..len].to_vec());
+    }
+}
+
+pub fn handle_frame(frame: &[u8]) {
+    let packet = match parse(frame) {
+        Some(packet) => packet,
+        None => return,
+    };
+
+    let accept = {
+        let iface = IPV6_INTERFACE.exclusive_access();
+        iface.is_local(&packet.dest)
+            || packet.dest == Ipv6Addr::ALL_NODES
+            || packet.dest == iface.link_local.solicited_node()
+            || iface.global.iter().any(|addr| addr.solicited_node() == packet.dest)
+    };
+    if !accept {
+        return;
+    }
+
+    match packet.next_header {
+        NEXT_HEADER_ICMPV6 => icmpv6::handle(&packet),
+        NEXT_HEADER_UDP => handle_udp(&packet),
+        NEXT_HEADER_TCP => tcp::handle(IpAddr::V6(packet.source), IpAddr::V6(packet.dest), packet.payload),
+        _ => {}
+    }
+}
@@ -0,0 +1,74 @@
+//! Carrier state of the NICs
+//!
+//! The NICs tell about link changes through `NetDevice::link_changed`,
+//! QEMU's monitor toggles the link of virtio-net with `set_link net0 off`.
+//! The carrier is only looked at when the stack polls for frames, the
+//! interrupt handler of the NIC just remembers that something changed.
+//! While the link of an interface is down, sends through it fail.
+use lazy_static::lazy_static;
+
+use crate::sync::UPSafeCell;
+
+use super::{device, icmpv6, ipv4, vlan, IpAddr, LAN_INTERFACE, WAN_INTERFACE};
+
+lazy_static! {
+    /// Carrier of the WAN and the LAN interface
+    static ref CARRIER: UPSafeCell<[bool; 2]> = unsafe {
+        UPSafeCell::new([WAN_INTERFACE, LAN_INTERFACE].map(|interface| {
+            device(interface).map_or(false, |nic| nic.link_up())
+        }))
+    };
+}
+
+/// Whether frames sent through `interface` can get anywhere, sub-interfaces
+/// have the carrier of their parent
+pub fn is_up(interface: u8) -> bool {
+    let interface = if vlan::is_vlan(interface) {
+        match vlan::parent(interface) {
+            Some(parent) => parent,
+            None => return false,
+        }
+    } else {
+        interface
+    };
+    CARRIER.exclusive_access().get(interface as usize).copied().unwrap_or(false)
+}
+
+/// Whether the link towards `dest` is up
+pub fn route_up(dest: &IpAddr) -> bool {
+    match dest {
+        IpAddr::V4(dest) => is_up(ipv4::interface_for(*dest)),
+        IpAddr::V6(_) => is_up(WAN_INTERFACE),
+    }
+}
+
+/// Look for link changes of the NICs and log them
+pub fn poll() {
+    for interface in [WAN_INTERFACE, LAN_INTERFACE] {
+        let nic = match device(interface) {
+            Some(nic) if nic.link_changed() => nic,
+            _ => continue,
+        };
+        let up = nic.link_up();
+        let changed = {
+            let mut carrier = CARRIER.exclusive_access();
+            let changed = carrier[interface as usize] != up;
+            carrier[interface as usize] = up;
+            changed
+        };
+        if !changed {
+            continue;
+        }
+        println!("[kernel] eth{}: link {}", interface, if up { "up" } else { "down" });
+        if up && interface == WAN_INTERFACE {
+            renew_address();
+        }
+    }
+}
+
+/// The network behind the uplink may have changed while the cable was out,
+/// ask for a prefix again. The uplink is configured by SLAAC, there is no
+/// DHCP lease to renew.
+fn renew_address() {
+    icmpv6::send_router_solicitation();
+}
@@ -1,25 +1,132 @@
 pub mod syscall;
 pub mod udp;
 pub mod socket;
+pub mod ipv6;
+pub mod icmpv6;
+pub mod packet;
+pub mod unix;
+pub mod ipv4;
+pub mod tcp;
+pub mod firewall;
+pub mod nat;
+pub mod igmp;
+pub mod arp;
+pub mod vlan;
+pub mod link;
 
 use core::arch::riscv64::wfi;
 
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+
+use alloc::{string::String, sync::Arc};
 use lose_net_stack::{LoseStack, IPv4, MacAddress, results::Packet};
-use virtio_drivers::{VirtIONet, VirtIOHeader};
 
-use crate::{drivers::block::virtio_blk::VirtioHal, sync::UPSafeCell, net::{socket::{get_socket, push_data}, udp::hexdump}};
+use crate::{fs::File, drivers::{net::NetDevice, registry}, mm::UserBuffer, random, sync::UPSafeCell, net::{socket::{get_socket, push_data}, udp::hexdump}, task::suspend_current_and_run_next, timer::get_time_ms};
 
-lazy_static::lazy_static! {
-    static ref NET_DEVICE:UPSafeCell<VirtIONet<'static, VirtioHal>> = unsafe {
-        UPSafeCell::new(VirtIONet::<VirtioHal>::new(
-            &mut *(0x1000_8000 as *mut VirtIOHeader)
-        ).expect("failed to create net driver"))
-    };
+use self::{firewall::{Verdict, FW_CHAIN_INPUT, FW_CHAIN_OUTPUT}, ipv6::Ipv6Addr};
+
+pub const LOCAL_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+
+/// Interface 0 is the slirp uplink, interface 1 the optional LAN port.
+/// VLAN sub-interfaces are numbered from `vlan::VLAN_INTERFACE_BASE`.
+pub const WAN_INTERFACE: u8 = 0;
+pub const LAN_INTERFACE: u8 = 1;
+
+/// How long `init` waits for a router advertisement
+const SLAAC_TIMEOUT_MS: usize = 500;
+
+pub const AF_INET: usize = 2;
+pub const AF_INET6: usize = 10;
+pub const SOCK_STREAM: usize = 1;
+pub const SOCK_DGRAM: usize = 2;
+
+/// Socket option levels and names, for `sys_setsockopt`
+pub const IPPROTO_IP: usize = 0;
+pub const IP_ADD_MEMBERSHIP: usize = 35;
+pub const IP_DROP_MEMBERSHIP: usize = 36;
+
+/// Ports handed out to sockets that were never bound
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+static NEXT_EPHEMERAL_PORT: AtomicU16 = AtomicU16::new(EPHEMERAL_PORT_START);
+
+bitflags! {
+    /// Work a NIC does in hardware, which the stack then leaves out
+    pub struct Offload: u32 {
+        /// IPv4 header and TCP/UDP checksums of received frames are checked
+        const RX_CHECKSUM = 1 << 0;
+        /// IPv4 header and TCP/UDP checksums are filled in on transmit
+        const TX_CHECKSUM = 1 << 1;
+        /// 802.1Q tags are inserted on transmit and stripped on receive
+        const VLAN = 1 << 2;
+    }
+}
+
+/// Whether the NIC found the checksums of the frame being handled right
+static RX_CHECKSUM_VERIFIED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum IpAddr {
+    V4(IPv4),
+    V6(Ipv6Addr),
+}
+
+/// A decoded `sockaddr` from user space
+pub enum SockAddr {
+    Unix(String),
+    Inet(IpAddr, u16),
+}
 
+/// Operations of the socket syscalls, reached through [`File::as_socket`]
+pub trait Socket {
+    fn bind(self: Arc<Self>, _addr: SockAddr) -> isize {
+        -1
+    }
+    fn listen(self: Arc<Self>, _backlog: usize) -> isize {
+        -1
+    }
+    /// Block until a connection arrives, return the new connected socket
+    fn accept(self: Arc<Self>) -> Option<(Arc<dyn File + Send + Sync>, SockAddr)> {
+        None
+    }
+    fn connect(self: Arc<Self>, _addr: SockAddr) -> isize {
+        -1
+    }
+    fn send_to(self: Arc<Self>, _data: &[u8], _addr: SockAddr) -> isize {
+        -1
+    }
+    /// Block for one datagram, return its length and where it came from
+    fn recv_from(self: Arc<Self>, _buf: UserBuffer) -> Option<(usize, SockAddr)> {
+        None
+    }
+    /// Stop sending. A tcp peer sees end of file, blocked udp readers return.
+    fn shutdown(self: Arc<Self>) -> isize {
+        -1
+    }
+    fn set_option(self: Arc<Self>, _level: usize, _name: usize, _value: &[u8]) -> isize {
+        -1
+    }
+}
+
+/// Pick a port for a socket that is used without being bound
+pub fn alloc_port() -> u16 {
+    let port = NEXT_EPHEMERAL_PORT.fetch_add(1, Ordering::Relaxed);
+    if port == u16::MAX {
+        NEXT_EPHEMERAL_PORT.store(EPHEMERAL_PORT_START, Ordering::Relaxed);
+    }
+    port
+}
+
+/// The NIC of a physical interface, the n-th one the registry bound
+pub fn device(interface: u8) -> Option<Arc<dyn NetDevice>> {
+    registry::net_device(interface as usize)
+}
+
+lazy_static::lazy_static! {
     static ref LOSE_NET_STACK: UPSafeCell<LoseStack> = unsafe {
         UPSafeCell::new(LoseStack::new(
             IPv4::new(10, 0, 2, 15),
-            MacAddress::new([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]) 
+            MacAddress::new(LOCAL_MAC)
         ))
     };
 }
@@ -28,15 +135,223 @@ lazy_static::lazy_static! {
 // net related function
 pub const SYS_SOCKET: usize = 41;
 pub const SYS_CONNECT: usize = 29;
+pub const SYS_CONNECT6: usize = 30;
+pub const SYS_SOCK_CONNECT: usize = 42;
+pub const SYS_ACCEPT: usize = 43;
+pub const SYS_SENDTO: usize = 44;
+pub const SYS_RECVFROM: usize = 45;
+pub const SYS_SHUTDOWN: usize = 48;
+pub const SYS_BIND: usize = 49;
+pub const SYS_LISTEN: usize = 50;
+pub const SYS_FIREWALL: usize = 31;
+pub const SYS_VLAN: usize = 32;
+pub const SYS_SETSOCKOPT: usize = 54;
 
 pub fn init() {
+    // start the ephemeral ports somewhere random, so that they can't be guessed
+    let range = (u16::MAX - EPHEMERAL_PORT_START) as u32 + 1;
+    NEXT_EPHEMERAL_PORT.store(EPHEMERAL_PORT_START + (random::next_u32() % range) as u16, Ordering::Relaxed);
+    update_multicast();
+    // ask the router for a prefix so that we get a global address
+    icmpv6::send_router_solicitation();
+    let start = get_time_ms();
+    while get_time_ms() < start + SLAAC_TIMEOUT_MS {
+        if can_recv() {
+            net_interrupt_handler();
+        }
+        if !ipv6::IPV6_INTERFACE.exclusive_access().global.is_empty() {
+            break;
+        }
+    }
+}
+
+/// Program the receive filter of the uplink with the groups of IGMP and of
+/// IPv6, whenever either changes
+pub fn update_multicast() {
+    let mut macs = igmp::multicast_macs();
+    macs.extend(ipv6::multicast_macs());
+    if let Some(nic) = device(WAN_INTERFACE) {
+        nic.set_multicast(&macs);
+    }
+}
+
+/// Handle a received frame if there is one, otherwise let other tasks run.
+/// Blocking socket calls loop on this.
+pub fn poll_or_yield() {
+    link::poll();
+    ipv6::poll();
+    vlan::poll();
+    tcp::poll();
+    if can_recv() {
+        net_interrupt_handler();
+    } else {
+        suspend_current_and_run_next();
+    }
+}
+
+/// Whether a frame is waiting on any interface
+fn can_recv() -> bool {
+    [WAN_INTERFACE, LAN_INTERFACE]
+        .iter()
+        .any(|interface| device(*interface).map_or(false, |nic| nic.can_recv()))
+}
+
+/// Take one received frame, return the interface, its length, whether
+/// the NIC verified its checksums and the VLAN tag it stripped. The virtio
+/// devices never do either.
+fn receive(buf: &mut [u8]) -> Option<(u8, usize, bool, Option<u16>)> {
+    for interface in [WAN_INTERFACE, LAN_INTERFACE] {
+        match device(interface) {
+            Some(nic) if nic.can_recv() => {
+                let len = nic.recv(buf)?;
+                return Some((interface, len, nic.rx_checksum_verified(), nic.rx_vlan()));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Offloads of the NIC behind `interface`. virtio-drivers negotiates
+/// none of the virtio-net checksum features, so the virtio devices have
+/// none; the e1000 does both checksums and the VLAN tags. Sub-interfaces
+/// have none.
+pub fn offload(interface: u8) -> Offload {
+    device(interface).map_or(Offload::empty(), |nic| nic.offload())
+}
+
+/// Whether the checksums of the frame being handled were verified by the
+/// NIC, so that the stack can skip its own check
+pub fn rx_checksum_verified() -> bool {
+    RX_CHECKSUM_VERIFIED.load(Ordering::Relaxed)
+}
+
+/// Hand a frame to the device of `interface` if the output chain of the
+/// firewall lets it pass
+pub fn transmit_on(interface: u8, frame: &[u8]) {
+    if firewall::filter(FW_CHAIN_OUTPUT, interface, frame) != Verdict::Accept {
+        return;
+    }
+    if vlan::is_vlan(interface) {
+        vlan::send_tagged(interface, frame);
+    } else {
+        send_frame(interface, frame);
+    }
+}
 
+/// Put a frame on the wire of a NIC, past the firewall
+pub fn send_frame(interface: u8, frame: &[u8]) {
+    if let Some(nic) = device(interface) {
+        nic.send(frame);
+    }
+}
+
+/// Put a frame on the wire of a NIC tagged for a VLAN, past the firewall
+pub fn send_frame_vlan(interface: u8, frame: &[u8], tci: u16) {
+    if let Some(nic) = device(interface) {
+        nic.send_vlan(frame, tci);
+    }
+}
+
+/// Send a frame on the uplink, the stack itself only lives there
+pub fn transmit(frame: &[u8]) {
+    transmit_on(WAN_INTERFACE, frame);
+}
+
+/// Hand a datagram lose_net_stack doesn't see, one to a joined group or
+/// to a VLAN address, to the socket bound to its port
+pub fn deliver_udp(packet: &ipv4::Ipv4Packet) {
+    let segment = packet.payload;
+    if segment.len() < ipv6::UDP_HEADER_LEN {
+        return;
+    }
+    let sport = u16::from_be_bytes([segment[0], segment[1]]);
+    let dport = u16::from_be_bytes([segment[2], segment[3]]);
+    let len = (u16::from_be_bytes([segment[4], segment[5]]) as usize).clamp(ipv6::UDP_HEADER_LEN, segment.len());
+    let source = IpAddr::V4(packet.source);
+    if let Some(socket_index) = get_socket(source, dport, sport) {
+        push_data(socket_index, source, sport, segment[ipv6::UDP_HEADER_LEN..len].to_vec());
+    }
 }
 
 pub fn net_interrupt_handler() {
-    let mut recv_buf = vec![0u8; 1024];
+    let mut recv_buf = vec![0u8; 2048];
+
+    let (interface, len, verified, stripped) = match receive(&mut recv_buf) {
+        Some(received) => received,
+        None => return,
+    };
+    RX_CHECKSUM_VERIFIED.store(verified && offload(interface).contains(Offload::RX_CHECKSUM), Ordering::Relaxed);
 
-    let len = NET_DEVICE.exclusive_access().recv(&mut recv_buf).expect("can't receive from net dev");
+    // raw packet sockets get a copy of everything before the stack sees
+    // it, as it was on the wire
+    match stripped {
+        Some(tci) => packet::deliver(&vlan::insert_tag(&recv_buf[..len], tci)),
+        None => packet::deliver(&recv_buf[..len]),
+    }
+
+    // sub-interfaces take the tagged frames, without their tag, which the
+    // NIC may have taken off already
+    if let Some(tci) = stripped {
+        if let Some(interface) = vlan::sub_interface(interface, vlan::vlan_id(tci)) {
+            if firewall::filter(FW_CHAIN_INPUT, interface, &recv_buf[..len]) == Verdict::Accept {
+                vlan::handle(interface, &recv_buf[..len]);
+            }
+        }
+        return;
+    }
+    if vlan::is_tagged(&recv_buf[..len]) {
+        if let Some((interface, frame)) = vlan::untag(interface, &recv_buf[..len]) {
+            if firewall::filter(FW_CHAIN_INPUT, interface, &frame) == Verdict::Accept {
+                vlan::handle(interface, &frame);
+            }
+        }
+        return;
+    }
+
+    match firewall::filter(FW_CHAIN_INPUT, interface, &recv_buf[..len]) {
+        Verdict::Accept => {}
+        Verdict::Drop => return,
+        // the answers go out on the uplink, on the lan reject is a drop
+        Verdict::Reject => {
+            if interface == WAN_INTERFACE {
+                firewall::reject(&recv_buf[..len]);
+            }
+            return;
+        }
+    }
+
+    // the lan is only routed, the local stack lives on the uplink
+    if interface == LAN_INTERFACE {
+        nat::handle_lan(&recv_buf[..len]);
+        return;
+    }
+    if nat::handle_wan(&recv_buf[..len]) {
+        return;
+    }
+
+    if ipv6::is_ipv6_frame(&recv_buf[..len]) {
+        ipv6::handle_frame(&recv_buf[..len]);
+        return;
+    }
+
+    // lose_net_stack only knows arp and unicast udp
+    if let Some(packet) = ipv4::parse(&recv_buf[..len]) {
+        if packet.protocol == ipv4::PROTOCOL_TCP && packet.dest == ipv4::local_ip() {
+            tcp::handle(IpAddr::V4(packet.source), IpAddr::V4(packet.dest), packet.payload);
+            return;
+        }
+        if packet.protocol == ipv4::PROTOCOL_IGMP {
+            igmp::handle(&packet);
+            return;
+        }
+        if igmp::is_multicast(packet.dest) {
+            if packet.protocol == ipv4::PROTOCOL_UDP && igmp::is_member(packet.dest) {
+                deliver_udp(&packet);
+            }
+            return;
+        }
+    }
         
     let packet = LOSE_NET_STACK.exclusive_access().analysis(&recv_buf[..len]);
     
@@ -48,7 +363,8 @@ pub fn net_interrupt_handler() {
             let lose_stack = LOSE_NET_STACK.exclusive_access();
             let reply_packet = arp_packet.reply_packet(lose_stack.ip, lose_stack.mac).expect("can't build reply");
             let reply_data = reply_packet.build_data();
-            NET_DEVICE.exclusive_access().send(&reply_data).expect("can't send net data");
+            drop(lose_stack);
+            transmit(&reply_data);
         },
 
         Packet::UDP(udp_packet) => {
@@ -56,8 +372,8 @@ pub fn net_interrupt_handler() {
             let lport = udp_packet.dest_port;
             let rport = udp_packet.source_port;
             
-            if let Some(socket_index) = get_socket(target, lport, rport) {
-                push_data(socket_index, udp_packet.data.to_vec());
+            if let Some(socket_index) = get_socket(IpAddr::V4(target), lport, rport) {
+                push_data(socket_index, IpAddr::V4(target), rport, udp_packet.data.to_vec());
             }
         }
         _ => {}
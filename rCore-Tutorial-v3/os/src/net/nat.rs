@@ -0,0 +1,341 @@
+//! IPv4 router between the slirp uplink and a LAN on the second NIC
+//!
+//! Hosts on the LAN use `LAN_IP` as their gateway. What they send to other
+//! addresses is forwarded to the uplink with source NAT: the source becomes
+//! our uplink address and the port, or the ICMP echo identifier, one from a
+//! pool. The mapping is kept in a connection-tracking table so that replies
+//! can be translated back, which means connections can only be opened from
+//! the LAN side. Fragments and ICMP errors about translated flows are not
+//! forwarded, and neither is anything not sent to `LAN_MAC`, to the LAN
+//! itself or to broadcast and multicast addresses.
+//!
+//! See `LAN=1` in os/Makefile for how to attach a second guest.
+use alloc::{collections::BTreeMap, vec::Vec};
+use lazy_static::lazy_static;
+use lose_net_stack::IPv4;
+
+use crate::{sync::UPSafeCell, timer::get_time_ms};
+
+use super::{
+    arp::{self, ETH_TYPE_ARP},
+    ipv4::{
+        self, icmp_checksum, icmp_error, is_icmp_error, DEFAULT_TTL, ETH_TYPE_IPV4, ICMP_ECHO_REPLY,
+        ICMP_ECHO_REQUEST, ICMP_TIME_EXCEEDED, PROTOCOL_ICMP, PROTOCOL_TCP, PROTOCOL_UDP,
+    },
+    ipv6::{fold, ones_complement_add, ETH_HEADER_LEN},
+    transmit_on, LAN_INTERFACE, LOCAL_MAC, WAN_INTERFACE,
+};
+
+pub const LAN_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x57];
+/// Our address on the LAN, 192.168.100.1/24
+pub const LAN_IP: u32 = 0xc0a8_6401;
+const LAN_NETMASK: u32 = 0xffff_ff00;
+/// LAN hosts whose MAC is remembered, the others get broadcast frames
+const LAN_NEIGHBOURS_MAX: usize = 64;
+
+/// Outer ports of translated flows, below the ephemeral ports of local sockets
+const NAT_PORT_START: u16 = 40000;
+const NAT_PORT_END: u16 = 49151;
+
+/// How long a mapping lives without traffic
+const TCP_TIMEOUT_MS: usize = 600_000;
+const UDP_TIMEOUT_MS: usize = 60_000;
+const ICMP_TIMEOUT_MS: usize = 30_000;
+
+/// Offsets inside a frame
+const IP_TTL: usize = ETH_HEADER_LEN + 8;
+const IP_CHECKSUM: usize = ETH_HEADER_LEN + 10;
+const IP_SOURCE: usize = ETH_HEADER_LEN + 12;
+const IP_DEST: usize = ETH_HEADER_LEN + 16;
+
+/// A translated flow, `inner_port` is the echo identifier for ICMP
+struct NatEntry {
+    protocol: u8,
+    inner_ip: u32,
+    inner_port: u16,
+    outer_port: u16,
+    last_used: usize,
+}
+
+impl NatEntry {
+    fn expired(&self, now: usize) -> bool {
+        let timeout = match self.protocol {
+            PROTOCOL_TCP => TCP_TIMEOUT_MS,
+            PROTOCOL_UDP => UDP_TIMEOUT_MS,
+            _ => ICMP_TIMEOUT_MS,
+        };
+        now > self.last_used + timeout
+    }
+}
+
+lazy_static! {
+    static ref NAT_TABLE: UPSafeCell<Vec<Option<NatEntry>>> = unsafe { UPSafeCell::new(Vec::new()) };
+    /// MAC addresses of the LAN hosts, learned from what they send, they
+    /// don't expire but only addresses of the LAN are taken
+    static ref LAN_NEIGHBOURS: UPSafeCell<BTreeMap<u32, [u8; 6]>> = unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Get the outer port of a flow leaving the LAN, creating the mapping
+fn outbound_port(protocol: u8, inner_ip: u32, inner_port: u16) -> Option<u16> {
+    let now = get_time_ms();
+    let mut table = NAT_TABLE.exclusive_access();
+    for slot in table.iter_mut() {
+        if slot.as_ref().map_or(false, |entry| entry.expired(now)) {
+            *slot = None;
+        }
+    }
+    if let Some(entry) = table.iter_mut().flatten().find(|entry| {
+        entry.protocol == protocol && entry.inner_ip == inner_ip && entry.inner_port == inner_port
+    }) {
+        entry.last_used = now;
+        return Some(entry.outer_port);
+    }
+
+    let outer_port = (NAT_PORT_START..=NAT_PORT_END).find(|port| {
+        !table
+            .iter()
+            .flatten()
+            .any(|entry| entry.protocol == protocol && entry.outer_port == *port)
+    })?;
+    let entry = NatEntry {
+        protocol,
+        inner_ip,
+        inner_port,
+        outer_port,
+        last_used: now,
+    };
+    match table.iter().position(Option::is_none) {
+        Some(index) => table[index] = Some(entry),
+        None => table.push(Some(entry)),
+    }
+    Some(outer_port)
+}
+
+/// Find the LAN host and port a reply to `outer_port` is for
+fn inbound_flow(protocol: u8, outer_port: u16) -> Option<(u32, u16)> {
+    let now = get_time_ms();
+    let mut table = NAT_TABLE.exclusive_access();
+    let entry = table
+        .iter_mut()
+        .flatten()
+        .find(|entry| entry.protocol == protocol && entry.outer_port == outer_port && !entry.expired(now))?;
+    entry.last_used = now;
+    Some((entry.inner_ip, entry.inner_port))
+}
+
+fn header_len(frame: &[u8]) -> usize {
+    ((frame[ETH_HEADER_LEN] & 0xf) as usize) * 4
+}
+
+/// The limited broadcast and the class D and E addresses
+fn is_broadcast_or_multicast(ip: u32) -> bool {
+    ip >= 0xe000_0000
+}
+
+fn is_fragment(frame: &[u8]) -> bool {
+    // more fragments or a fragment offset
+    u16::from_be_bytes([frame[ETH_HEADER_LEN + 6], frame[ETH_HEADER_LEN + 7]]) & 0x3fff != 0
+}
+
+/// Where the port a flow is tracked by sits in the payload, `source` for
+/// packets leaving the LAN. Only ICMP echo is translated.
+fn port_offset(protocol: u8, payload: &[u8], source: bool) -> Option<usize> {
+    match protocol {
+        PROTOCOL_TCP if payload.len() >= 20 => Some(if source { 0 } else { 2 }),
+        PROTOCOL_UDP if payload.len() >= 8 => Some(if source { 0 } else { 2 }),
+        PROTOCOL_ICMP if payload.len() >= 8 => {
+            let kind = if source { ICMP_ECHO_REQUEST } else { ICMP_ECHO_REPLY };
+            if payload[0] == kind {
+                Some(4)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Recompute the IP header checksum and the one of the upper layer after
+/// addresses or ports were rewritten
+fn update_checksums(frame: &mut [u8]) {
+    let header_len = header_len(frame);
+    let total_len = u16::from_be_bytes([frame[ETH_HEADER_LEN + 2], frame[ETH_HEADER_LEN + 3]]) as usize;
+    frame[IP_CHECKSUM..IP_CHECKSUM + 2].copy_from_slice(&[0, 0]);
+    let csum = !fold(ones_complement_add(0, &frame[ETH_HEADER_LEN..ETH_HEADER_LEN + header_len]));
+    frame[IP_CHECKSUM..IP_CHECKSUM + 2].copy_from_slice(&csum.to_be_bytes());
+
+    let source = IPv4::from_u32(u32::from_be_bytes(frame[IP_SOURCE..IP_SOURCE + 4].try_into().unwrap()));
+    let dest = IPv4::from_u32(u32::from_be_bytes(frame[IP_DEST..IP_DEST + 4].try_into().unwrap()));
+    let protocol = frame[ETH_HEADER_LEN + 9];
+    let payload = &mut frame[ETH_HEADER_LEN + header_len..ETH_HEADER_LEN + total_len];
+    match protocol {
+        PROTOCOL_TCP => {
+            payload[16..18].copy_from_slice(&[0, 0]);
+            let csum = ipv4::checksum(source, dest, protocol, payload);
+            payload[16..18].copy_from_slice(&csum.to_be_bytes());
+        }
+        // a zero udp checksum means there is none
+        PROTOCOL_UDP if payload[6..8] != [0, 0] => {
+            payload[6..8].copy_from_slice(&[0, 0]);
+            let csum = match ipv4::checksum(source, dest, protocol, payload) {
+                0 => 0xffff,
+                csum => csum,
+            };
+            payload[6..8].copy_from_slice(&csum.to_be_bytes());
+        }
+        PROTOCOL_ICMP => icmp_checksum(payload),
+        _ => {}
+    }
+}
+
+/// Remember the MAC of a LAN host. Addresses outside the LAN, ours and
+/// the network and broadcast addresses are ignored, and once the table is
+/// full only the hosts in it are updated.
+fn learn_neighbour(ip: u32, mac: [u8; 6]) {
+    let host = ip & !LAN_NETMASK;
+    if ip & LAN_NETMASK != LAN_IP & LAN_NETMASK || ip == LAN_IP || host == 0 || host == !LAN_NETMASK {
+        return;
+    }
+    let mut neighbours = LAN_NEIGHBOURS.exclusive_access();
+    if neighbours.len() < LAN_NEIGHBOURS_MAX || neighbours.contains_key(&ip) {
+        neighbours.insert(ip, mac);
+    }
+}
+
+/// Send an IP packet from us to a LAN host
+fn send_lan(dest: u32, protocol: u8, payload: &[u8]) {
+    let mac = LAN_NEIGHBOURS.exclusive_access().get(&dest).copied().unwrap_or([0xff; 6]);
+    let mut frame = ipv4::build_frame(
+        mac,
+        IPv4::from_u32(LAN_IP),
+        IPv4::from_u32(dest),
+        protocol,
+        DEFAULT_TTL,
+        payload,
+    );
+    frame[6..12].copy_from_slice(&LAN_MAC);
+    transmit_on(LAN_INTERFACE, &frame);
+}
+
+fn handle_arp(frame: &[u8]) {
+    let request = match arp::parse(frame) {
+        Some(request) => request,
+        None => return,
+    };
+    learn_neighbour(request.sender_ip, request.sender_mac);
+    if request.is_request_for(LAN_IP) {
+        transmit_on(LAN_INTERFACE, &arp::build_reply(&request, LAN_MAC, LAN_IP));
+    }
+}
+
+/// Route a packet from the LAN to the uplink
+fn forward_outbound(frame: &[u8]) {
+    let packet = match ipv4::parse(frame) {
+        Some(packet) => packet,
+        None => return,
+    };
+    let source = packet.source.to_u32();
+    let dest = packet.dest.to_u32();
+    learn_neighbour(source, frame[6..12].try_into().unwrap());
+    // only what is sent to us as the gateway is routed, broadcasts and
+    // traffic between LAN hosts that we see are not
+    if frame[..6] != LAN_MAC {
+        return;
+    }
+
+    if dest == LAN_IP {
+        // we only answer pings on the LAN side
+        if packet.protocol == PROTOCOL_ICMP
+            && packet.payload.len() >= 8
+            && packet.payload[0] == ICMP_ECHO_REQUEST
+        {
+            let mut reply = packet.payload.to_vec();
+            reply[0] = ICMP_ECHO_REPLY;
+            icmp_checksum(&mut reply);
+            send_lan(source, PROTOCOL_ICMP, &reply);
+        }
+        return;
+    }
+    if dest & LAN_NETMASK == LAN_IP & LAN_NETMASK || is_broadcast_or_multicast(dest) {
+        return;
+    }
+    if packet.ttl <= 1 {
+        if !(packet.protocol == PROTOCOL_ICMP && is_icmp_error(packet.payload)) {
+            send_lan(source, PROTOCOL_ICMP, &icmp_error(frame, ICMP_TIME_EXCEEDED, 0));
+        }
+        return;
+    }
+    if is_fragment(frame) {
+        return;
+    }
+    let offset = match port_offset(packet.protocol, packet.payload, true) {
+        Some(offset) => offset,
+        None => return,
+    };
+    let inner_port = u16::from_be_bytes([packet.payload[offset], packet.payload[offset + 1]]);
+    let outer_port = match outbound_port(packet.protocol, source, inner_port) {
+        Some(port) => port,
+        None => return,
+    };
+
+    let mut out = frame[..ETH_HEADER_LEN + header_len(frame) + packet.payload.len()].to_vec();
+    let port_at = ETH_HEADER_LEN + header_len(frame) + offset;
+    // slirp picks up broadcast frames, like in `ipv4::send`
+    out[..6].copy_from_slice(&[0xff; 6]);
+    out[6..12].copy_from_slice(&LOCAL_MAC);
+    out[IP_TTL] -= 1;
+    out[IP_SOURCE..IP_SOURCE + 4].copy_from_slice(&ipv4::local_ip().to_u32().to_be_bytes());
+    out[port_at..port_at + 2].copy_from_slice(&outer_port.to_be_bytes());
+    update_checksums(&mut out);
+    transmit_on(WAN_INTERFACE, &out);
+}
+
+/// Handle a frame received on the LAN interface
+pub fn handle_lan(frame: &[u8]) {
+    if frame.len() < ETH_HEADER_LEN {
+        return;
+    }
+    match u16::from_be_bytes([frame[12], frame[13]]) {
+        ETH_TYPE_ARP => handle_arp(frame),
+        ETH_TYPE_IPV4 => forward_outbound(frame),
+        _ => {}
+    }
+}
+
+/// Translate a reply from the uplink back to the LAN host it is for.
+/// Return false if the frame doesn't belong to a translated flow.
+pub fn handle_wan(frame: &[u8]) -> bool {
+    let packet = match ipv4::parse(frame) {
+        Some(packet) => packet,
+        None => return false,
+    };
+    if packet.dest != ipv4::local_ip() || is_fragment(frame) {
+        return false;
+    }
+    let offset = match port_offset(packet.protocol, packet.payload, false) {
+        Some(offset) => offset,
+        None => return false,
+    };
+    let outer_port = u16::from_be_bytes([packet.payload[offset], packet.payload[offset + 1]]);
+    let (inner_ip, inner_port) = match inbound_flow(packet.protocol, outer_port) {
+        Some(flow) => flow,
+        None => return false,
+    };
+    // replies are only echo replies here, never ICMP errors
+    if packet.ttl <= 1 {
+        ipv4::send(packet.dest, packet.source, PROTOCOL_ICMP, &icmp_error(frame, ICMP_TIME_EXCEEDED, 0));
+        return true;
+    }
+
+    let mut out = frame[..ETH_HEADER_LEN + header_len(frame) + packet.payload.len()].to_vec();
+    let port_at = ETH_HEADER_LEN + header_len(frame) + offset;
+    let mac = LAN_NEIGHBOURS.exclusive_access().get(&inner_ip).copied().unwrap_or([0xff; 6]);
+    out[..6].copy_from_slice(&mac);
+    out[6..12].copy_from_slice(&LAN_MAC);
+    out[IP_TTL] -= 1;
+    out[IP_DEST..IP_DEST + 4].copy_from_slice(&inner_ip.to_be_bytes());
+    out[port_at..port_at + 2].copy_from_slice(&inner_port.to_be_bytes());
+    update_checksums(&mut out);
+    transmit_on(LAN_INTERFACE, &out);
+    true
+}
@@ -0,0 +1,130 @@
+use alloc::{collections::VecDeque, vec::Vec};
+use lazy_static::lazy_static;
+
+use crate::{fs::File, mm::UserBuffer, sync::UPSafeCell};
+
+use super::{link, poll_or_yield, transmit, WAN_INTERFACE};
+
+pub const AF_PACKET: usize = 17;
+pub const SOCK_RAW: usize = 3;
+/// Receive frames of every ethertype
+pub const ETH_P_ALL: u16 = 0x0003;
+
+/// Frames kept per socket before the oldest is dropped
+const PACKET_QUEUE_LEN: usize = 64;
+
+// a raw socket that sees whole ethernet frames, like AF_PACKET on linux
+pub struct PacketQueue {
+    pub ethertype: u16,
+    pub frames: VecDeque<Vec<u8>>,
+}
+
+lazy_static! {
+    static ref PACKET_TABLE: UPSafeCell<Vec<Option<PacketQueue>>> = unsafe {
+        UPSafeCell::new(vec![])
+    };
+}
+
+fn add_packet_queue(ethertype: u16) -> usize {
+    let mut packet_table = PACKET_TABLE.exclusive_access();
+    let queue = PacketQueue {
+        ethertype,
+        frames: VecDeque::new(),
+    };
+
+    if let Some(index) = (0..packet_table.len()).find(|i| packet_table[*i].is_none()) {
+        packet_table[index] = Some(queue);
+        index
+    } else {
+        packet_table.push(Some(queue));
+        packet_table.len() - 1
+    }
+}
+
+fn pop_frame(index: usize) -> Option<Vec<u8>> {
+    let mut packet_table = PACKET_TABLE.exclusive_access();
+
+    assert!(packet_table.len() > index);
+    assert!(packet_table[index].is_some());
+
+    packet_table[index].as_mut().unwrap().frames.pop_front()
+}
+
+/// Hand a copy of a received frame to every packet socket listening for its ethertype
+pub fn deliver(frame: &[u8]) {
+    if frame.len() < 14 {
+        return;
+    }
+    let ethertype = u16::from_be_bytes([frame[12], frame[13]]);
+    let mut packet_table = PACKET_TABLE.exclusive_access();
+    for queue in packet_table.iter_mut().flatten() {
+        if queue.ethertype != ETH_P_ALL && queue.ethertype != ethertype {
+            continue;
+        }
+        if queue.frames.len() == PACKET_QUEUE_LEN {
+            queue.frames.pop_front();
+        }
+        queue.frames.push_back(frame.to_vec());
+    }
+}
+
+pub struct PacketSocket {
+    pub queue_index: usize,
+}
+
+impl PacketSocket {
+    pub fn new(ethertype: u16) -> Self {
+        Self {
+            queue_index: add_packet_queue(ethertype),
+        }
+    }
+}
+
+impl File for PacketSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    // read one whole frame, truncated to the size of the buffer
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        loop {
+            if let Some(frame) = pop_frame(self.queue_index) {
+                let mut left = 0;
+                for buffer in buf.buffers.iter_mut() {
+                    let len = buffer.len().min(frame.len() - left);
+                    buffer[..len].copy_from_slice(&frame[left..(left + len)]);
+                    left += len;
+                    if left == frame.len() {
+                        break;
+                    }
+                }
+                return left;
+            } else {
+                poll_or_yield();
+            }
+        }
+    }
+
+    // the buffer must hold a complete ethernet frame, header included
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut frame = Vec::with_capacity(buf.len());
+        for buffer in buf.buffers.iter() {
+            frame.extend_from_slice(buffer);
+        }
+        if frame.len() < 14 || !link::is_up(WAN_INTERFACE) {
+            return 0;
+        }
+        transmit(&frame);
+        frame.len()
+    }
+}
+
+impl Drop for PacketSocket {
+    fn drop(&mut self) {
+        PACKET_TABLE.exclusive_access()[self.queue_index] = None;
+    }
+}
@@ -4,13 +4,15 @@ use lose_net_stack::{IPv4, packets::udp::UDPPacket};
 
 use crate::sync::UPSafeCell;
 
+use super::IpAddr;
+
 
 // TODO: specify the protocol, TCP or UDP
 pub struct Socket {
-    pub raddr: IPv4,    // remote address
+    pub raddr: Option<IpAddr>,  // remote address, None accepts any sender
     pub lport: u16,     // local port
     pub rport: u16,      // rempote port
-    pub buffers: VecDeque<Vec<u8>>   // datas
+    pub buffers: VecDeque<(IpAddr, u16, Vec<u8>)>   // datas with their source
 }
 
 lazy_static! {
@@ -19,8 +21,10 @@ lazy_static! {
     };
 }
 
-pub fn get_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
+// find the socket for a datagram, a connected socket wins over a bound one
+pub fn get_socket(raddr: IpAddr, lport: u16, rport: u16) -> Option<usize> {
     let socket_table = SOCKET_TABLE.exclusive_access();
+    let mut bound = None;
     for i in 0..socket_table.len() {
         let sock = &socket_table[i];
         if sock.is_none() {
@@ -28,15 +32,27 @@ pub fn get_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
         }
 
         let sock = sock.as_ref().unwrap();
-        if sock.raddr == raddr && sock.lport == lport && sock.rport == rport {
-            return Some(i)
+        if sock.lport != lport {
+            continue;
+        }
+        match sock.raddr {
+            Some(addr) if addr == raddr && sock.rport == rport => return Some(i),
+            None if bound.is_none() => bound = Some(i),
+            _ => {}
         }
     }
-    None
+    bound
 }
 
-pub fn add_socket(raddr: IPv4, lport: u16, rport: u16) -> Option<usize> {
-    if get_socket(raddr, lport, rport).is_some() {
+fn socket_exists(raddr: Option<IpAddr>, lport: u16, rport: u16) -> bool {
+    let socket_table = SOCKET_TABLE.exclusive_access();
+    socket_table.iter().flatten().any(|sock| {
+        sock.lport == lport && sock.raddr == raddr && (raddr.is_none() || sock.rport == rport)
+    })
+}
+
+pub fn add_socket(raddr: Option<IpAddr>, lport: u16, rport: u16) -> Option<usize> {
+    if socket_exists(raddr, lport, rport) {
         return None;
     }
 
@@ -73,16 +89,28 @@ pub fn remove_socket(index: usize) {
     socket_table[index] = None;
 }
 
-pub fn push_data(index: usize, data: Vec<u8>) {
+// fix the remote end of a bound socket
+pub fn connect_socket(index: usize, raddr: IpAddr, rport: u16) {
+    let mut socket_table = SOCKET_TABLE.exclusive_access();
+
+    assert!(socket_table.len() > index);
+    assert!(socket_table[index].is_some());
+
+    let sock = socket_table[index].as_mut().unwrap();
+    sock.raddr = Some(raddr);
+    sock.rport = rport;
+}
+
+pub fn push_data(index: usize, raddr: IpAddr, rport: u16, data: Vec<u8>) {
     let mut socket_table = SOCKET_TABLE.exclusive_access();
 
     assert!(socket_table.len() > index);
     assert!(socket_table[index].is_some());
 
-    socket_table[index].as_mut().unwrap().buffers.push_back(data);
+    socket_table[index].as_mut().unwrap().buffers.push_back((raddr, rport, data));
 }
 
-pub fn pop_data(index: usize) -> Option<Vec<u8>> {
+pub fn pop_data(index: usize) -> Option<(IpAddr, u16, Vec<u8>)> {
     let mut socket_table = SOCKET_TABLE.exclusive_access();
 
     assert!(socket_table.len() > index);
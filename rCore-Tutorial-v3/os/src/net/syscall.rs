@@ -1,18 +1,349 @@
-use alloc::sync::Arc;
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::mem::size_of;
 use lose_net_stack::IPv4;
 
-use crate::task::{current_user_token, current_task};
+use crate::{task::{current_user_token, current_task, ROOT_UID}, mm::{translated_byte_buffer, translated_refmut, UserBuffer}};
 
-use super::udp::UDP;
+use super::{firewall::{self, FwRule, FW_APPEND, FW_DELETE, FW_FLUSH, FW_LIST}, vlan::{self, VlanConfig, VLAN_ADD, VLAN_DELETE, VLAN_LIST}, udp::UDP, tcp::TcpSocket, IpAddr, ipv6::Ipv6Addr, packet::{PacketSocket, AF_PACKET, SOCK_RAW}, unix::{UnixSocket, AF_UNIX}, SockAddr, Socket, AF_INET, AF_INET6, SOCK_STREAM, SOCK_DGRAM};
 
+/// `sun_path` length of `struct sockaddr_un`
+const UNIX_PATH_MAX: usize = 108;
+/// size of `struct sockaddr_in`
+const SOCKADDR_IN_LEN: usize = 16;
+/// size of `struct sockaddr_in6`
+const SOCKADDR_IN6_LEN: usize = 28;
 
-// syscall connect with target addr、source port and target port. 
+fn copy_from_user(token: usize, ptr: *const u8, len: usize) -> Vec<u8> {
+    let mut data = Vec::with_capacity(len);
+    for buffer in translated_byte_buffer(token, ptr, len) {
+        data.extend_from_slice(buffer);
+    }
+    data
+}
+
+fn copy_to_user(token: usize, ptr: *mut u8, data: &[u8]) {
+    let mut left = 0;
+    for buffer in translated_byte_buffer(token, ptr, data.len()) {
+        buffer.copy_from_slice(&data[left..(left + buffer.len())]);
+        left += buffer.len();
+    }
+}
+
+// decode a user `struct sockaddr`, the family is the first native-endian u16.
+// ports and addresses of the inet families are in network byte order.
+fn read_sockaddr(token: usize, addr: *const u8, addrlen: usize) -> Option<SockAddr> {
+    if addr.is_null() || addrlen < 2 {
+        return None;
+    }
+    let data = copy_from_user(token, addr, addrlen);
+    match u16::from_ne_bytes([data[0], data[1]]) as usize {
+        AF_UNIX => {
+            let path = &data[2..];
+            let len = path.iter().position(|c| *c == 0).unwrap_or(path.len());
+            core::str::from_utf8(&path[..len])
+                .ok()
+                .map(|path| SockAddr::Unix(String::from(path)))
+        }
+        AF_INET if addrlen >= SOCKADDR_IN_LEN => {
+            let port = u16::from_be_bytes([data[2], data[3]]);
+            let ip = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+            Some(SockAddr::Inet(IpAddr::V4(IPv4::from_u32(ip)), port))
+        }
+        AF_INET6 if addrlen >= SOCKADDR_IN6_LEN => {
+            let port = u16::from_be_bytes([data[2], data[3]]);
+            Some(SockAddr::Inet(IpAddr::V6(Ipv6Addr::from_slice(&data[8..24])), port))
+        }
+        _ => None,
+    }
+}
+
+// encode `sockaddr` into user memory, truncated to `*addrlen`, which is
+// updated to the full length like linux does.
+fn write_sockaddr(token: usize, addr: *mut u8, addrlen: *mut u32, sockaddr: &SockAddr) {
+    if addr.is_null() || addrlen.is_null() {
+        return;
+    }
+    let mut data = Vec::new();
+    match sockaddr {
+        SockAddr::Unix(path) => {
+            data.extend_from_slice(&(AF_UNIX as u16).to_ne_bytes());
+            data.extend_from_slice(path.as_bytes());
+            data.resize(2 + UNIX_PATH_MAX, 0);
+        }
+        SockAddr::Inet(IpAddr::V4(ip), port) => {
+            data.extend_from_slice(&(AF_INET as u16).to_ne_bytes());
+            data.extend_from_slice(&port.to_be_bytes());
+            data.extend_from_slice(&ip.to_u32().to_be_bytes());
+            data.resize(SOCKADDR_IN_LEN, 0);
+        }
+        SockAddr::Inet(IpAddr::V6(ip), port) => {
+            data.extend_from_slice(&(AF_INET6 as u16).to_ne_bytes());
+            data.extend_from_slice(&port.to_be_bytes());
+            // flow info
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&ip.0);
+            // scope id
+            data.extend_from_slice(&[0; 4]);
+        }
+    }
+    let addrlen = translated_refmut(token, addrlen);
+    let len = (*addrlen as usize).min(data.len());
+    copy_to_user(token, addr, &data[..len]);
+    *addrlen = data.len() as u32;
+}
+
+/// Whether the calling process may bypass or change what protects the
+/// others, raw frames, the packet filter and the interfaces. initproc and
+/// what it starts run as root until they give it up with setuid.
+fn privileged() -> bool {
+    current_task().unwrap().inner_exclusive_access().uid == ROOT_UID
+}
+
+fn get_socket_file(fd: usize) -> Option<Arc<dyn Socket>> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    inner.fd_table.get(fd)?.clone()?.as_socket()
+}
+
+// syscall socket with domain, type and protocol.
+// udp (SOCK_DGRAM) and tcp (SOCK_STREAM) sockets of AF_INET and AF_INET6,
+// unix sockets and raw packet sockets (AF_PACKET, SOCK_RAW) are supported.
+// for packet sockets the protocol is the ethertype to receive (ETH_P_ALL for
+// every frame) in host byte order, otherwise it is ignored.
 // return socket fd allocated.
+pub fn sys_socket(domain: usize, socket_type: usize, protocol: usize) -> isize {
+    let task = current_task().unwrap();
+    match (domain, socket_type) {
+        (AF_PACKET, SOCK_RAW) => {
+            // raw frames bypass every port check, keep them for root
+            if !privileged() {
+                return -1;
+            }
+            let mut inner = task.inner_exclusive_access();
+            let fd = inner.alloc_fd();
+            inner.fd_table[fd] = Some(Arc::new(PacketSocket::new(protocol as u16)));
+            fd as isize
+        }
+        (AF_UNIX, SOCK_STREAM) | (AF_UNIX, SOCK_DGRAM) => {
+            let mut inner = task.inner_exclusive_access();
+            let fd = inner.alloc_fd();
+            inner.fd_table[fd] = Some(Arc::new(UnixSocket::new(socket_type)));
+            fd as isize
+        }
+        (AF_INET, SOCK_DGRAM) | (AF_INET6, SOCK_DGRAM) => {
+            let mut inner = task.inner_exclusive_access();
+            let fd = inner.alloc_fd();
+            inner.fd_table[fd] = Some(Arc::new(UDP::unbound(domain == AF_INET6)));
+            fd as isize
+        }
+        (AF_INET, SOCK_STREAM) | (AF_INET6, SOCK_STREAM) => {
+            let mut inner = task.inner_exclusive_access();
+            let fd = inner.alloc_fd();
+            inner.fd_table[fd] = Some(Arc::new(TcpSocket::new(domain == AF_INET6)));
+            fd as isize
+        }
+        _ => -1,
+    }
+}
+
+pub fn sys_bind(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    let token = current_user_token();
+    match (get_socket_file(fd), read_sockaddr(token, addr, addrlen)) {
+        (Some(socket), Some(addr)) => socket.bind(addr),
+        _ => -1,
+    }
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    match get_socket_file(fd) {
+        Some(socket) => socket.listen(backlog),
+        None => -1,
+    }
+}
+
+// syscall accept blocks until a peer connects and returns the fd of the
+// new connection, the peer address is written to `addr` if it is not null.
+pub fn sys_accept(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    let token = current_user_token();
+    let socket = match get_socket_file(fd) {
+        Some(socket) => socket,
+        None => return -1,
+    };
+    match socket.accept() {
+        Some((file, peer)) => {
+            write_sockaddr(token, addr, addrlen, &peer);
+            let task = current_task().unwrap();
+            let mut inner = task.inner_exclusive_access();
+            let fd = inner.alloc_fd();
+            inner.fd_table[fd] = Some(file);
+            fd as isize
+        }
+        None => -1,
+    }
+}
+
+// syscall connect on a socket created by sys_socket
+pub fn sys_sock_connect(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    let token = current_user_token();
+    match (get_socket_file(fd), read_sockaddr(token, addr, addrlen)) {
+        (Some(socket), Some(addr)) => socket.connect(addr),
+        _ => -1,
+    }
+}
+
+// syscall sendto sends `len` bytes of `buf` to `addr`.
+// flags are not supported and must be 0.
+// return the number of bytes sent.
+pub fn sys_sendto(fd: usize, buf: *const u8, len: usize, _flags: usize, addr: *const u8, addrlen: usize) -> isize {
+    let token = current_user_token();
+    match (get_socket_file(fd), read_sockaddr(token, addr, addrlen)) {
+        (Some(socket), Some(addr)) => socket.send_to(&copy_from_user(token, buf, len), addr),
+        _ => -1,
+    }
+}
+
+// syscall recvfrom blocks for one datagram and copies it into `buf`,
+// the sender is written to `addr` if it is not null.
+// return the number of bytes received.
+pub fn sys_recvfrom(fd: usize, buf: *mut u8, len: usize, _flags: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    let token = current_user_token();
+    let socket = match get_socket_file(fd) {
+        Some(socket) => socket,
+        None => return -1,
+    };
+    match socket.recv_from(UserBuffer::new(translated_byte_buffer(token, buf, len))) {
+        Some((len, source)) => {
+            write_sockaddr(token, addr, addrlen, &source);
+            len as isize
+        }
+        None => -1,
+    }
+}
+
+// syscall shutdown ends the sending side of a socket, a tcp peer sees
+// end of file and a process blocked reading the socket is woken up.
+pub fn sys_shutdown(fd: usize) -> isize {
+    match get_socket_file(fd) {
+        Some(socket) => socket.shutdown(),
+        None => -1,
+    }
+}
+
+// syscall setsockopt, the only options are IP_ADD_MEMBERSHIP and
+// IP_DROP_MEMBERSHIP on inet udp sockets, with a `struct ip_mreq` in `value`.
+pub fn sys_setsockopt(fd: usize, level: usize, name: usize, value: *const u8, len: usize) -> isize {
+    let token = current_user_token();
+    match get_socket_file(fd) {
+        Some(socket) if !value.is_null() => socket.set_option(level, name, &copy_from_user(token, value, len)),
+        _ => -1,
+    }
+}
+
+// syscall firewall edits the packet filter. FW_APPEND inserts the rule at
+// `rule` before `index` and returns where it went, FW_DELETE removes rule
+// `index`, FW_FLUSH removes all of them. FW_LIST copies as many rules as fit
+// into the `len` bytes at `rule` and returns how many there are in total.
+// only root may change the rules, anyone may list them.
+pub fn sys_firewall(op: usize, index: usize, rule: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let rule_len = size_of::<FwRule>();
+    if op != FW_LIST && !privileged() {
+        return -1;
+    }
+    match op {
+        FW_APPEND => {
+            if rule.is_null() || len < rule_len {
+                return -1;
+            }
+            let data = copy_from_user(token, rule, rule_len);
+            let rule = unsafe { core::ptr::read_unaligned(data.as_ptr() as *const FwRule) };
+            firewall::insert_rule(index, rule)
+        }
+        FW_DELETE => firewall::delete_rule(index),
+        FW_LIST => {
+            let rules = firewall::rules();
+            let count = rules.len().min(len / rule_len);
+            if count > 0 && !rule.is_null() {
+                let data = unsafe {
+                    core::slice::from_raw_parts(rules.as_ptr() as *const u8, count * rule_len)
+                };
+                copy_to_user(token, rule, data);
+            }
+            rules.len() as isize
+        }
+        FW_FLUSH => {
+            firewall::flush();
+            0
+        }
+        _ => -1,
+    }
+}
+
+// syscall vlan manages the sub-interfaces. VLAN_ADD creates one from the
+// `struct VlanConfig` at `config` and returns its interface number,
+// VLAN_DELETE removes `interface`. VLAN_LIST copies as many configurations
+// as fit into the `len` bytes at `config` and returns how many there are.
+// only root may add or remove sub-interfaces, anyone may list them.
+pub fn sys_vlan(op: usize, interface: usize, config: *mut u8, len: usize) -> isize {
+    let token = current_user_token();
+    let config_len = size_of::<VlanConfig>();
+    if op != VLAN_LIST && !privileged() {
+        return -1;
+    }
+    match op {
+        VLAN_ADD => {
+            if config.is_null() || len < config_len {
+                return -1;
+            }
+            let data = copy_from_user(token, config, config_len);
+            vlan::add(unsafe { core::ptr::read_unaligned(data.as_ptr() as *const VlanConfig) })
+        }
+        VLAN_DELETE => match u8::try_from(interface) {
+            Ok(interface) => vlan::remove(interface),
+            Err(_) => -1,
+        },
+        VLAN_LIST => {
+            let configs = vlan::list();
+            let count = configs.len().min(len / config_len);
+            if count > 0 && !config.is_null() {
+                let data = unsafe {
+                    core::slice::from_raw_parts(configs.as_ptr() as *const u8, count * config_len)
+                };
+                copy_to_user(token, config, data);
+            }
+            configs.len() as isize
+        }
+        _ => -1,
+    }
+}
+
+// syscall connect with target addr、source port and target port. 
+// return socket fd allocated, or -1 if a socket with the same ports exists.
 pub fn sys_connect(raddr: u32, lport: u16, rport: u16) -> isize {
+    let udp_node = match UDP::new(IpAddr::V4(IPv4::from_u32(raddr)), lport, rport) {
+        Some(udp_node) => udp_node,
+        None => return -1,
+    };
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access();
     let fd = inner.alloc_fd();
-    let udp_node = UDP::new(IPv4::from_u32(raddr), lport, rport);
     inner.fd_table[fd] = Some(Arc::new(udp_node));
     fd as isize    
+}
+
+// syscall connect6 with a pointer to the 16-byte target addr, source port and target port.
+// return socket fd allocated, or -1 if a socket with the same ports exists.
+pub fn sys_connect6(raddr: *const u8, lport: u16, rport: u16) -> isize {
+    let token = current_user_token();
+    let addr = Ipv6Addr::from_slice(&copy_from_user(token, raddr, 16));
+    let udp_node = match UDP::new(IpAddr::V6(addr), lport, rport) {
+        Some(udp_node) => udp_node,
+        None => return -1,
+    };
+
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    let fd = inner.alloc_fd();
+    inner.fd_table[fd] = Some(Arc::new(udp_node));
+    fd as isize
 }
\ No newline at end of file
@@ -0,0 +1,785 @@
+//! A small TCP over IPv4 and IPv6
+//!
+//! Segments are only accepted in order. Our SYN, data and FIN segments are
+//! kept until they are acknowledged and sent again when the retransmission
+//! timeout runs out, which doubles every time; there is no round trip
+//! estimate. Writes send what fits into the window of the peer, while it is
+//! closed the persist timer probes it. [`poll`] drives the timers from the
+//! poll loop of the stack.
+//! Every connection in the table is a [`Tcb`]; [`TcpSocket`] is the `File`
+//! that user space holds on to.
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use lazy_static::lazy_static;
+
+use crate::{fs::{File, WRITE_FAILED}, mm::UserBuffer, random, sync::UPSafeCell, timer::get_time_ms};
+
+use super::{
+    alloc_port, ipv4::{self, PROTOCOL_TCP}, ipv6::{self, NEXT_HEADER_TCP, IPV6_INTERFACE}, link, offload, poll_or_yield,
+    rx_checksum_verified, IpAddr, Offload, SockAddr, Socket,
+};
+
+pub const TCP_HEADER_LEN: usize = 20;
+/// Maximum segment size we advertise and send, small enough for both families
+const TCP_MSS: usize = 1400;
+const TCP_RECV_BUFFER: usize = 16 * 1024;
+const SYN_RETRIES: usize = 3;
+/// Retransmissions of data or a FIN before the peer is given up
+const TCP_RETRIES: usize = 8;
+const TCP_RTO_INITIAL_MS: usize = 1000;
+const TCP_RTO_MAX_MS: usize = 60_000;
+/// Two maximum segment lifetimes of 30 seconds
+const TIME_WAIT_MS: usize = 60_000;
+/// How long a closed socket waits in FIN-WAIT-2 for the FIN of the peer
+const FIN_TIMEOUT_MS: usize = 60_000;
+
+const TCP_OPT_END: u8 = 0;
+const TCP_OPT_MSS: u8 = 2;
+
+bitflags! {
+    pub struct TcpFlags: u8 {
+        const FIN = 1 << 0;
+        const SYN = 1 << 1;
+        const RST = 1 << 2;
+        const PSH = 1 << 3;
+        const ACK = 1 << 4;
+        const URG = 1 << 5;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TcpState {
+    Closed,
+    Listen,
+    SynSent,
+    SynReceived,
+    Established,
+    FinWait1,
+    FinWait2,
+    Closing,
+    TimeWait,
+    CloseWait,
+    LastAck,
+}
+
+/// A segment that occupies sequence space and is waiting for its ACK
+struct Segment {
+    seq: u32,
+    flags: TcpFlags,
+    data: Vec<u8>,
+}
+
+impl Segment {
+    /// Sequence number after the segment, SYN and FIN take one each
+    fn end(&self) -> u32 {
+        let mut len = self.data.len() as u32;
+        if self.flags.intersects(TcpFlags::SYN | TcpFlags::FIN) {
+            len += 1;
+        }
+        self.seq.wrapping_add(len)
+    }
+}
+
+/// TCP control block
+pub struct Tcb {
+    pub state: TcpState,
+    pub v6: bool,
+    pub local_ip: Option<IpAddr>,
+    pub local_port: u16,
+    pub remote: Option<(IpAddr, u16)>,
+    snd_una: u32,
+    snd_nxt: u32,
+    rcv_nxt: u32,
+    remote_window: u16,
+    fin_received: bool,
+    recv_buffer: VecDeque<u8>,
+    /// listener only: established connections waiting for accept
+    accept_queue: VecDeque<usize>,
+    backlog: usize,
+    /// the listener of a connection that has not been accepted yet
+    parent: Option<usize>,
+    /// the socket is closed, free the block once the connection is down
+    orphan: bool,
+    /// segments sent but not acknowledged, oldest first
+    retransmit: VecDeque<Segment>,
+    /// retransmission timeout, doubled on every retransmission
+    rto: usize,
+    /// when the oldest unacknowledged segment was last sent
+    sent_at: usize,
+    retries: usize,
+    /// when TIME-WAIT, or the FIN-WAIT-2 of an orphan, is over
+    deadline: Option<usize>,
+    /// when to probe the zero window of the peer, armed by a blocked write
+    persist_at: Option<usize>,
+    /// time between window probes, doubled like the retransmission timeout
+    persist_interval: usize,
+}
+
+impl Tcb {
+    fn new(v6: bool) -> Self {
+        Self {
+            state: TcpState::Closed,
+            v6,
+            local_ip: None,
+            local_port: 0,
+            remote: None,
+            snd_una: 0,
+            snd_nxt: 0,
+            rcv_nxt: 0,
+            remote_window: 0,
+            fin_received: false,
+            recv_buffer: VecDeque::new(),
+            accept_queue: VecDeque::new(),
+            backlog: 0,
+            parent: None,
+            orphan: false,
+            retransmit: VecDeque::new(),
+            rto: TCP_RTO_INITIAL_MS,
+            sent_at: 0,
+            retries: 0,
+            deadline: None,
+            persist_at: None,
+            persist_interval: TCP_RTO_INITIAL_MS,
+        }
+    }
+
+    fn window(&self) -> u16 {
+        (TCP_RECV_BUFFER - self.recv_buffer.len()).min(u16::MAX as usize) as u16
+    }
+
+    /// Send a segment with sequence number `seq`
+    fn send_at(&self, seq: u32, flags: TcpFlags, data: &[u8]) {
+        let (remote_ip, remote_port) = self.remote.unwrap();
+        let ack = if flags.contains(TcpFlags::ACK) { self.rcv_nxt } else { 0 };
+        transmit(self.local_ip.unwrap(), remote_ip, self.local_port, remote_port, seq, ack, flags, self.window(), data);
+    }
+
+    /// Send a segment at `snd_nxt` and advance it over the data (and SYN/FIN),
+    /// a segment that takes sequence space is kept for retransmission
+    fn send(&mut self, flags: TcpFlags, data: &[u8]) {
+        self.send_at(self.snd_nxt, flags, data);
+        let segment = Segment { seq: self.snd_nxt, flags, data: data.to_vec() };
+        self.snd_nxt = segment.end();
+        if self.snd_nxt != segment.seq {
+            if self.retransmit.is_empty() {
+                self.sent_at = get_time_ms();
+            }
+            self.retransmit.push_back(segment);
+        }
+    }
+
+    /// The peer acknowledged up to `snd_una`, forget the segments it has
+    fn acked(&mut self) {
+        while let Some(segment) = self.retransmit.front() {
+            if seq_gt(segment.end(), self.snd_una) {
+                break;
+            }
+            self.retransmit.pop_front();
+        }
+        self.rto = TCP_RTO_INITIAL_MS;
+        self.retries = 0;
+        self.sent_at = get_time_ms();
+    }
+
+    fn enter_time_wait(&mut self) {
+        self.state = TcpState::TimeWait;
+        self.deadline = Some(get_time_ms() + TIME_WAIT_MS);
+    }
+
+    /// Whether `seq` is inside the receive window
+    fn in_window(&self, seq: u32) -> bool {
+        seq.wrapping_sub(self.rcv_nxt) < self.window().max(1) as u32
+    }
+}
+
+lazy_static! {
+    static ref TCP_TABLE: UPSafeCell<Vec<Option<Tcb>>> = unsafe { UPSafeCell::new(vec![]) };
+}
+
+/// Initial send sequence number, random so that it can't be guessed from
+/// an earlier connection
+fn next_iss() -> u32 {
+    random::next_u32()
+}
+
+/// `a > b` in sequence space
+fn seq_gt(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+fn insert(table: &mut Vec<Option<Tcb>>, tcb: Tcb) -> usize {
+    if let Some(index) = (0..table.len()).find(|i| table[*i].is_none()) {
+        table[index] = Some(tcb);
+        index
+    } else {
+        table.push(Some(tcb));
+        table.len() - 1
+    }
+}
+
+fn source_ip(remote: &IpAddr) -> IpAddr {
+    match remote {
+        IpAddr::V4(remote) => IpAddr::V4(ipv4::source_for(*remote)),
+        IpAddr::V6(remote) => IpAddr::V6(IPV6_INTERFACE.exclusive_access().source_for(remote)),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn transmit(
+    local: IpAddr,
+    remote: IpAddr,
+    sport: u16,
+    dport: u16,
+    seq: u32,
+    ack: u32,
+    flags: TcpFlags,
+    window: u16,
+    data: &[u8],
+) {
+    // announce our mss on syn segments
+    let options: &[u8] = if flags.contains(TcpFlags::SYN) {
+        &[TCP_OPT_MSS, 4, (TCP_MSS >> 8) as u8, TCP_MSS as u8]
+    } else {
+        &[]
+    };
+    let header_len = TCP_HEADER_LEN + options.len();
+    let mut segment = Vec::with_capacity(header_len + data.len());
+    segment.extend_from_slice(&sport.to_be_bytes());
+    segment.extend_from_slice(&dport.to_be_bytes());
+    segment.extend_from_slice(&seq.to_be_bytes());
+    segment.extend_from_slice(&ack.to_be_bytes());
+    segment.push(((header_len / 4) as u8) << 4);
+    segment.push(flags.bits());
+    segment.extend_from_slice(&window.to_be_bytes());
+    // checksum and urgent pointer
+    segment.extend_from_slice(&[0, 0, 0, 0]);
+    segment.extend_from_slice(options);
+    segment.extend_from_slice(data);
+
+    match (local, remote) {
+        (IpAddr::V4(local), IpAddr::V4(remote)) => {
+            // the NIC fills it in from the pseudo header on its own
+            if !offload(ipv4::interface_for(remote)).contains(Offload::TX_CHECKSUM) {
+                let csum = ipv4::checksum(local, remote, PROTOCOL_TCP, &segment);
+                segment[16..18].copy_from_slice(&csum.to_be_bytes());
+            }
+            ipv4::send(local, remote, PROTOCOL_TCP, &segment);
+        }
+        (IpAddr::V6(local), IpAddr::V6(remote)) => {
+            let csum = ipv6::checksum(&local, &remote, NEXT_HEADER_TCP, &segment);
+            segment[16..18].copy_from_slice(&csum.to_be_bytes());
+            ipv6::send(&local, &remote, NEXT_HEADER_TCP, &segment);
+        }
+        _ => {}
+    }
+}
+
+fn verify_checksum(source: &IpAddr, dest: &IpAddr, segment: &[u8]) -> bool {
+    match (source, dest) {
+        (IpAddr::V4(source), IpAddr::V4(dest)) => ipv4::checksum(*source, *dest, PROTOCOL_TCP, segment) == 0,
+        (IpAddr::V6(source), IpAddr::V6(dest)) => ipv6::checksum(source, dest, NEXT_HEADER_TCP, segment) == 0,
+        _ => false,
+    }
+}
+
+/// Answer a segment that doesn't belong to any connection with a reset
+fn reset(source: IpAddr, dest: IpAddr, sport: u16, dport: u16, seq: u32, ack: u32, flags: TcpFlags, len: usize) {
+    if flags.contains(TcpFlags::RST) {
+        return;
+    }
+    if flags.contains(TcpFlags::ACK) {
+        transmit(dest, source, dport, sport, ack, 0, TcpFlags::RST, 0, &[]);
+    } else {
+        let mut seg_len = len as u32;
+        if flags.intersects(TcpFlags::SYN | TcpFlags::FIN) {
+            seg_len += 1;
+        }
+        transmit(dest, source, dport, sport, 0, seq.wrapping_add(seg_len), TcpFlags::RST | TcpFlags::ACK, 0, &[]);
+    }
+}
+
+/// Answer a segment the firewall rejected with a reset, `local` is the
+/// address it was sent to
+pub fn reject(local: IpAddr, remote: IpAddr, segment: &[u8]) {
+    if segment.len() < TCP_HEADER_LEN {
+        return;
+    }
+    let sport = u16::from_be_bytes([segment[0], segment[1]]);
+    let dport = u16::from_be_bytes([segment[2], segment[3]]);
+    let seq = u32::from_be_bytes([segment[4], segment[5], segment[6], segment[7]]);
+    let ack = u32::from_be_bytes([segment[8], segment[9], segment[10], segment[11]]);
+    let header_len = (((segment[12] >> 4) as usize) * 4).clamp(TCP_HEADER_LEN, segment.len());
+    let flags = TcpFlags::from_bits_truncate(segment[13]);
+    reset(remote, local, sport, dport, seq, ack, flags, segment.len() - header_len);
+}
+
+/// Free a connection, one that is waiting to be accepted leaves the accept
+/// queue of its listener
+fn free(table: &mut [Option<Tcb>], index: usize) {
+    if let Some(parent) = table[index].as_ref().and_then(|tcb| tcb.parent) {
+        if let Some(listener) = table[parent].as_mut() {
+            listener.accept_queue.retain(|waiting| *waiting != index);
+        }
+    }
+    table[index] = None;
+}
+
+/// The connection is down, free it if no socket refers to it
+fn abort(table: &mut [Option<Tcb>], index: usize) {
+    let tcb = table[index].as_mut().unwrap();
+    tcb.state = TcpState::Closed;
+    tcb.retransmit.clear();
+    tcb.deadline = None;
+    if tcb.orphan || tcb.parent.is_some() {
+        free(table, index);
+    }
+}
+
+/// Free a listener together with the connections nobody has accepted yet
+fn free_listener(table: &mut Vec<Option<Tcb>>, index: usize) {
+    for i in 0..table.len() {
+        if table[i].as_ref().map_or(false, |tcb| tcb.parent == Some(index)) {
+            table[i] = None;
+        }
+    }
+    table[index] = None;
+}
+
+/// Handle a received segment, `source` and `dest` come from the ip header
+pub fn handle(source: IpAddr, dest: IpAddr, segment: &[u8]) {
+    if segment.len() < TCP_HEADER_LEN || (!rx_checksum_verified() && !verify_checksum(&source, &dest, segment)) {
+        return;
+    }
+    let sport = u16::from_be_bytes([segment[0], segment[1]]);
+    let dport = u16::from_be_bytes([segment[2], segment[3]]);
+    let seq = u32::from_be_bytes([segment[4], segment[5], segment[6], segment[7]]);
+    let ack = u32::from_be_bytes([segment[8], segment[9], segment[10], segment[11]]);
+    let header_len = ((segment[12] >> 4) as usize) * 4;
+    let flags = TcpFlags::from_bits_truncate(segment[13]);
+    let window = u16::from_be_bytes([segment[14], segment[15]]);
+    if header_len < TCP_HEADER_LEN || header_len > segment.len() {
+        return;
+    }
+    let data = &segment[header_len..];
+    let v6 = matches!(source, IpAddr::V6(_));
+
+    let mut table = TCP_TABLE.exclusive_access();
+    let connection = table.iter().position(|tcb| {
+        tcb.as_ref().map_or(false, |tcb| tcb.local_port == dport && tcb.remote == Some((source, sport)))
+    });
+    let index = match connection {
+        Some(index) => index,
+        None => {
+            let listener = table.iter().position(|tcb| {
+                tcb.as_ref().map_or(false, |tcb| {
+                    tcb.state == TcpState::Listen && tcb.local_port == dport && tcb.v6 == v6
+                })
+            });
+            match listener {
+                Some(index) if flags.contains(TcpFlags::SYN) && !flags.contains(TcpFlags::ACK) => {
+                    let waiting = table.iter().flatten().filter(|tcb| tcb.parent == Some(index)).count();
+                    if waiting >= table[index].as_ref().unwrap().backlog {
+                        return;
+                    }
+                    let iss = next_iss();
+                    let mut child = Tcb::new(v6);
+                    child.state = TcpState::SynReceived;
+                    child.local_ip = Some(dest);
+                    child.local_port = dport;
+                    child.remote = Some((source, sport));
+                    child.rcv_nxt = seq.wrapping_add(1);
+                    child.snd_una = iss;
+                    child.snd_nxt = iss;
+                    child.remote_window = window;
+                    child.parent = Some(index);
+                    child.send(TcpFlags::SYN | TcpFlags::ACK, &[]);
+                    insert(&mut table, child);
+                }
+                _ => reset(source, dest, sport, dport, seq, ack, flags, data.len()),
+            }
+            return;
+        }
+    };
+
+    let tcb = table[index].as_mut().unwrap();
+    if flags.contains(TcpFlags::RST) {
+        let acceptable = match tcb.state {
+            TcpState::SynSent => flags.contains(TcpFlags::ACK) && ack == tcb.snd_nxt,
+            _ => seq == tcb.rcv_nxt,
+        };
+        if acceptable {
+            abort(&mut table, index);
+        } else if tcb.state != TcpState::SynSent && tcb.in_window(seq) {
+            // RFC 5961: a blind reset may have guessed the window, a real
+            // peer answers this ACK with a reset at the exact sequence number
+            tcb.send(TcpFlags::ACK, &[]);
+        }
+        return;
+    }
+
+    match tcb.state {
+        TcpState::SynSent => {
+            if flags.contains(TcpFlags::SYN | TcpFlags::ACK) && ack == tcb.snd_nxt {
+                tcb.rcv_nxt = seq.wrapping_add(1);
+                tcb.snd_una = ack;
+                tcb.acked();
+                tcb.remote_window = window;
+                tcb.state = TcpState::Established;
+                tcb.send(TcpFlags::ACK, &[]);
+            }
+            return;
+        }
+        TcpState::SynReceived => {
+            if !flags.contains(TcpFlags::ACK) || ack != tcb.snd_nxt {
+                return;
+            }
+            tcb.state = TcpState::Established;
+            let parent = tcb.parent.unwrap();
+            table[parent].as_mut().unwrap().accept_queue.push_back(index);
+        }
+        TcpState::TimeWait => {
+            // our last ACK got lost, the peer sends its FIN again
+            if flags.contains(TcpFlags::FIN) {
+                tcb.send(TcpFlags::ACK, &[]);
+                tcb.enter_time_wait();
+            }
+            return;
+        }
+        TcpState::Closed | TcpState::Listen => return,
+        _ => {}
+    }
+
+    let tcb = table[index].as_mut().unwrap();
+    if flags.contains(TcpFlags::ACK) {
+        if seq_gt(ack, tcb.snd_una) && !seq_gt(ack, tcb.snd_nxt) {
+            tcb.snd_una = ack;
+            tcb.acked();
+        }
+        tcb.remote_window = window;
+        let fin_acked = ack == tcb.snd_nxt;
+        match tcb.state {
+            TcpState::FinWait1 if fin_acked => {
+                tcb.state = TcpState::FinWait2;
+                if tcb.orphan {
+                    tcb.deadline = Some(get_time_ms() + FIN_TIMEOUT_MS);
+                }
+            }
+            TcpState::Closing if fin_acked => {
+                tcb.enter_time_wait();
+                return;
+            }
+            TcpState::LastAck if fin_acked => {
+                tcb.state = TcpState::Closed;
+                if tcb.orphan {
+                    free(&mut table, index);
+                }
+                return;
+            }
+            _ => {}
+        }
+    }
+
+    let mut need_ack = false;
+    if !data.is_empty() {
+        need_ack = true;
+        let receiving = matches!(
+            tcb.state,
+            TcpState::Established | TcpState::FinWait1 | TcpState::FinWait2
+        );
+        if seq != tcb.rcv_nxt || !receiving {
+            // out of order or unexpected, tell the peer what we expect
+            tcb.send(TcpFlags::ACK, &[]);
+            return;
+        }
+        let len = data.len().min(TCP_RECV_BUFFER - tcb.recv_buffer.len());
+        tcb.recv_buffer.extend(&data[..len]);
+        tcb.rcv_nxt = tcb.rcv_nxt.wrapping_add(len as u32);
+        if len < data.len() {
+            // the rest (and a fin behind it) comes again once we have room
+            tcb.send(TcpFlags::ACK, &[]);
+            return;
+        }
+    }
+
+    if flags.contains(TcpFlags::FIN) && seq.wrapping_add(data.len() as u32) == tcb.rcv_nxt {
+        tcb.rcv_nxt = tcb.rcv_nxt.wrapping_add(1);
+        tcb.fin_received = true;
+        need_ack = true;
+        match tcb.state {
+            TcpState::Established => tcb.state = TcpState::CloseWait,
+            // both ends close at once, our FIN is still unacknowledged
+            TcpState::FinWait1 => tcb.state = TcpState::Closing,
+            TcpState::FinWait2 => tcb.enter_time_wait(),
+            _ => {}
+        }
+    }
+
+    if need_ack {
+        tcb.send(TcpFlags::ACK, &[]);
+    }
+}
+
+/// Retransmit the segments whose timeout ran out, give up on peers that
+/// don't answer and end TIME-WAIT and the FIN-WAIT-2 of orphans
+pub fn poll() {
+    let now = get_time_ms();
+    let mut table = TCP_TABLE.exclusive_access();
+    for index in 0..table.len() {
+        let tcb = match table[index].as_mut() {
+            Some(tcb) => tcb,
+            None => continue,
+        };
+        if tcb.deadline.map_or(false, |deadline| now >= deadline) {
+            abort(&mut table, index);
+            continue;
+        }
+        if tcb.persist_at.map_or(false, |persist_at| now >= persist_at) {
+            if tcb.remote_window != 0
+                || !tcb.retransmit.is_empty()
+                || !matches!(tcb.state, TcpState::Established | TcpState::CloseWait)
+            {
+                tcb.persist_at = None;
+            } else {
+                // an old sequence number, the peer answers with its window
+                tcb.send_at(tcb.snd_nxt.wrapping_sub(1), TcpFlags::ACK, &[]);
+                tcb.persist_interval = (tcb.persist_interval * 2).min(TCP_RTO_MAX_MS);
+                tcb.persist_at = Some(now + tcb.persist_interval);
+            }
+        }
+        let segment = match tcb.retransmit.front() {
+            Some(segment) if now >= tcb.sent_at + tcb.rto => segment,
+            _ => continue,
+        };
+        let retries = if segment.flags.contains(TcpFlags::SYN) { SYN_RETRIES } else { TCP_RETRIES };
+        if tcb.retries == retries {
+            // a socket that failed to connect may connect again
+            if tcb.state == TcpState::SynSent {
+                tcb.remote = None;
+            }
+            abort(&mut table, index);
+            continue;
+        }
+        tcb.send_at(segment.seq, segment.flags, &segment.data);
+        tcb.retries += 1;
+        tcb.rto = (tcb.rto * 2).min(TCP_RTO_MAX_MS);
+        tcb.sent_at = now;
+    }
+}
+
+pub struct TcpSocket {
+    pub index: usize,
+}
+
+impl TcpSocket {
+    pub fn new(v6: bool) -> Self {
+        Self {
+            index: insert(&mut TCP_TABLE.exclusive_access(), Tcb::new(v6)),
+        }
+    }
+
+    fn state(&self) -> TcpState {
+        TCP_TABLE.exclusive_access()[self.index].as_ref().unwrap().state
+    }
+}
+
+impl Socket for TcpSocket {
+    fn bind(self: Arc<Self>, addr: SockAddr) -> isize {
+        let port = match addr {
+            SockAddr::Inet(_, port) => port,
+            _ => return -1,
+        };
+        let mut table = TCP_TABLE.exclusive_access();
+        let v6 = table[self.index].as_ref().unwrap().v6;
+        let in_use = table
+            .iter()
+            .flatten()
+            .any(|tcb| tcb.v6 == v6 && tcb.local_port == port && tcb.remote.is_none());
+        let tcb = table[self.index].as_mut().unwrap();
+        if in_use || tcb.local_port != 0 || tcb.state != TcpState::Closed {
+            return -1;
+        }
+        tcb.local_port = if port == 0 { alloc_port() } else { port };
+        0
+    }
+
+    fn listen(self: Arc<Self>, backlog: usize) -> isize {
+        let mut table = TCP_TABLE.exclusive_access();
+        let tcb = table[self.index].as_mut().unwrap();
+        if tcb.local_port == 0 || !matches!(tcb.state, TcpState::Closed | TcpState::Listen) {
+            return -1;
+        }
+        tcb.state = TcpState::Listen;
+        tcb.backlog = backlog.max(1);
+        0
+    }
+
+    fn accept(self: Arc<Self>) -> Option<(Arc<dyn File + Send + Sync>, SockAddr)> {
+        loop {
+            {
+                let mut table = TCP_TABLE.exclusive_access();
+                let listener = table[self.index].as_mut().unwrap();
+                if listener.state != TcpState::Listen {
+                    return None;
+                }
+                // children leave the queue when they are freed, the index is live
+                if let Some(index) = listener.accept_queue.pop_front() {
+                    let child = table[index].as_mut().unwrap();
+                    child.parent = None;
+                    let (ip, port) = child.remote.unwrap();
+                    return Some((Arc::new(TcpSocket { index }), SockAddr::Inet(ip, port)));
+                }
+            }
+            poll_or_yield();
+        }
+    }
+
+    fn connect(self: Arc<Self>, addr: SockAddr) -> isize {
+        let (ip, port) = match addr {
+            SockAddr::Inet(ip, port) => (ip, port),
+            _ => return -1,
+        };
+        {
+            let mut table = TCP_TABLE.exclusive_access();
+            let tcb = table[self.index].as_mut().unwrap();
+            if tcb.state != TcpState::Closed || tcb.v6 != matches!(ip, IpAddr::V6(_)) {
+                return -1;
+            }
+            if tcb.local_port == 0 {
+                tcb.local_port = alloc_port();
+            }
+            let iss = next_iss();
+            tcb.local_ip = Some(source_ip(&ip));
+            tcb.remote = Some((ip, port));
+            tcb.snd_una = iss;
+            tcb.snd_nxt = iss;
+            tcb.state = TcpState::SynSent;
+            tcb.send(TcpFlags::SYN, &[]);
+        }
+
+        // `poll` retransmits the SYN and closes the socket if nobody answers
+        loop {
+            match self.state() {
+                TcpState::SynSent => {}
+                TcpState::Closed => return -1,
+                _ => return 0,
+            }
+            poll_or_yield();
+        }
+    }
+
+    fn shutdown(self: Arc<Self>) -> isize {
+        let mut table = TCP_TABLE.exclusive_access();
+        let tcb = table[self.index].as_mut().unwrap();
+        match tcb.state {
+            TcpState::Established => tcb.state = TcpState::FinWait1,
+            TcpState::CloseWait => tcb.state = TcpState::LastAck,
+            _ => return -1,
+        }
+        tcb.send(TcpFlags::FIN | TcpFlags::ACK, &[]);
+        0
+    }
+}
+
+impl File for TcpSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    // block until some data arrives, return 0 once the peer has closed
+    fn read(&self, buf: UserBuffer) -> usize {
+        loop {
+            {
+                let mut table = TCP_TABLE.exclusive_access();
+                let tcb = table[self.index].as_mut().unwrap();
+                if !tcb.recv_buffer.is_empty() {
+                    let old_window = tcb.window() as usize;
+                    let mut read_size = 0;
+                    for byte in buf {
+                        match tcb.recv_buffer.pop_front() {
+                            Some(data) => unsafe { *byte = data },
+                            None => break,
+                        }
+                        read_size += 1;
+                    }
+                    // reopen the window if the peer may have stopped sending
+                    if old_window < TCP_MSS && tcb.state == TcpState::Established {
+                        tcb.send(TcpFlags::ACK, &[]);
+                    }
+                    return read_size;
+                }
+                if tcb.fin_received || tcb.state == TcpState::Closed {
+                    return 0;
+                }
+            }
+            poll_or_yield();
+        }
+    }
+
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut data = Vec::with_capacity(buf.len());
+        for buffer in buf.buffers.iter() {
+            data.extend_from_slice(buffer);
+        }
+
+        let mut sent = 0;
+        while sent < data.len() {
+            {
+                let mut table = TCP_TABLE.exclusive_access();
+                let tcb = table[self.index].as_mut().unwrap();
+                // a write that sent nothing fails, the connection stays up
+                // while the link is down
+                if !matches!(tcb.state, TcpState::Established | TcpState::CloseWait)
+                    || !tcb.remote.map_or(false, |(remote, _)| link::route_up(&remote))
+                {
+                    return if sent == 0 { WRITE_FAILED } else { sent };
+                }
+                let in_flight = tcb.snd_nxt.wrapping_sub(tcb.snd_una) as usize;
+                let usable = (tcb.remote_window as usize).saturating_sub(in_flight);
+                let len = (data.len() - sent).min(TCP_MSS).min(usable);
+                if len > 0 {
+                    tcb.send(TcpFlags::PSH | TcpFlags::ACK, &data[sent..sent + len]);
+                    sent += len;
+                    continue;
+                }
+                // with nothing in flight no ACK will tell us when the window
+                // opens again, the persist timer asks for it
+                if tcb.remote_window == 0 && tcb.retransmit.is_empty() && tcb.persist_at.is_none() {
+                    tcb.persist_interval = TCP_RTO_INITIAL_MS;
+                    tcb.persist_at = Some(get_time_ms() + tcb.persist_interval);
+                }
+            }
+            // wait for the peer to open its window
+            poll_or_yield();
+        }
+        sent
+    }
+
+    fn as_socket(self: Arc<Self>) -> Option<Arc<dyn Socket>> {
+        Some(self)
+    }
+}
+
+impl Drop for TcpSocket {
+    fn drop(&mut self) {
+        let mut table = TCP_TABLE.exclusive_access();
+        let tcb = table[self.index].as_mut().unwrap();
+        tcb.orphan = true;
+        match tcb.state {
+            TcpState::Established => {
+                tcb.send(TcpFlags::FIN | TcpFlags::ACK, &[]);
+                tcb.state = TcpState::FinWait1;
+            }
+            TcpState::CloseWait => {
+                tcb.send(TcpFlags::FIN | TcpFlags::ACK, &[]);
+                tcb.state = TcpState::LastAck;
+            }
+            TcpState::Listen => free_listener(&mut table, self.index),
+            TcpState::FinWait2 => tcb.deadline = Some(get_time_ms() + FIN_TIMEOUT_MS),
+            TcpState::FinWait1 | TcpState::Closing | TcpState::TimeWait | TcpState::LastAck => {}
+            _ => table[self.index] = None,
+        }
+    }
+}
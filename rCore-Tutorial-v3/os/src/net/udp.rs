@@ -1,26 +1,232 @@
-use alloc::{boxed::Box, vec};
-use lose_net_stack::{IPv4, packets::udp::UDPPacket, MacAddress, results::Packet};
+use alloc::{sync::Arc, vec};
+use alloc::vec::Vec;
+use lose_net_stack::{packets::udp::UDPPacket, IPv4, MacAddress};
 
-use crate::{fs::File, mm::UserBuffer};
+use crate::{fs::{File, WRITE_FAILED}, mm::UserBuffer, sync::UPSafeCell};
 
-use super::{NET_DEVICE, LOSE_NET_STACK, socket::{add_socket, remove_socket, pop_data}, net_interrupt_handler};
+use super::{transmit, LOSE_NET_STACK, socket::{add_socket, remove_socket, pop_data, connect_socket}, poll_or_yield, alloc_port, IpAddr, igmp, link, vlan, ipv4::{self, PROTOCOL_UDP}, ipv6::{self, UDP_HEADER_LEN}, SockAddr, Socket, IPPROTO_IP, IP_ADD_MEMBERSHIP, IP_DROP_MEMBERSHIP};
 
-pub struct UDP{
-    pub target: IPv4,
+pub struct UDP {
+    pub v6: bool,
+    inner: UPSafeCell<UDPInner>,
+}
+
+pub struct UDPInner {
+    pub target: Option<IpAddr>,
     pub sport: u16,
     pub dport: u16,
-    pub socket_index: usize
+    pub socket_index: Option<usize>,
+    pub shutdown: bool,
+    /// multicast groups joined through this socket
+    pub groups: Vec<IPv4>
 }
 
 impl UDP {
-    pub fn new(target: IPv4, sport: u16, dport: u16) -> Self {
-        let index = add_socket(target, sport, dport).expect("can't add socket");
+    /// A connected socket, `None` if another one already uses the same ports
+    pub fn new(target: IpAddr, sport: u16, dport: u16) -> Option<Self> {
+        let index = add_socket(Some(target), sport, dport)?;
+
+        Some(Self {
+            v6: matches!(target, IpAddr::V6(_)),
+            inner: unsafe {
+                UPSafeCell::new(UDPInner {
+                    target: Some(target),
+                    sport,
+                    dport,
+                    socket_index: Some(index),
+                    shutdown: false,
+                    groups: Vec::new()
+                })
+            }
+        })
+    }
 
+    // a socket from sys_socket, it gets a port from bind, connect or the first send_to
+    pub fn unbound(v6: bool) -> Self {
         Self {
-            target,
-            sport,
-            dport,
-            socket_index: index
+            v6,
+            inner: unsafe {
+                UPSafeCell::new(UDPInner {
+                    target: None,
+                    sport: 0,
+                    dport: 0,
+                    socket_index: None,
+                    shutdown: false,
+                    groups: Vec::new()
+                })
+            }
+        }
+    }
+
+    fn family_matches(&self, addr: &IpAddr) -> bool {
+        self.v6 == matches!(addr, IpAddr::V6(_))
+    }
+
+    fn bind_port(&self, port: u16) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        if inner.socket_index.is_some() {
+            return -1;
+        }
+        let port = if port == 0 { alloc_port() } else { port };
+        match add_socket(None, port, 0) {
+            Some(index) => {
+                inner.sport = port;
+                inner.socket_index = Some(index);
+                0
+            }
+            None => -1
+        }
+    }
+
+    // block for the next datagram
+    fn recv(&self) -> Option<(IpAddr, u16, Vec<u8>)> {
+        let socket_index = self.inner.exclusive_access().socket_index?;
+        loop {
+            if let Some(datagram) = pop_data(socket_index) {
+                return Some(datagram);
+            }
+            if self.inner.exclusive_access().shutdown {
+                return None;
+            }
+            poll_or_yield();
+        }
+    }
+}
+
+fn copy_datagram(buf: &mut UserBuffer, data: &[u8]) -> usize {
+    let data_len = data.len();
+    let mut left = 0;
+    for i in 0..buf.buffers.len() {
+        let buffer_i_len = buf.buffers[i].len().min(data_len - left);
+
+        buf.buffers[i][..buffer_i_len].copy_from_slice(&data[left..(left + buffer_i_len)]);
+
+        left += buffer_i_len;
+        if left == data_len {
+            break;
+        }
+    }
+    left
+}
+
+fn send_datagram(target: IpAddr, sport: u16, dport: u16, data: &[u8]) {
+    let target = match target {
+        IpAddr::V4(target) => target,
+        IpAddr::V6(target) => {
+            ipv6::send_udp(&target, sport, dport, data);
+            return;
+        }
+    };
+
+    // lose_net_stack only builds broadcast frames from its own address
+    if igmp::is_multicast(target) || vlan::route(target).is_some() {
+        let mut segment = Vec::with_capacity(UDP_HEADER_LEN + data.len());
+        segment.extend_from_slice(&sport.to_be_bytes());
+        segment.extend_from_slice(&dport.to_be_bytes());
+        segment.extend_from_slice(&((UDP_HEADER_LEN + data.len()) as u16).to_be_bytes());
+        segment.extend_from_slice(&[0, 0]);
+        segment.extend_from_slice(data);
+        let source = ipv4::source_for(target);
+        let csum = match ipv4::checksum(source, target, PROTOCOL_UDP, &segment) {
+            0 => 0xffff,
+            csum => csum,
+        };
+        segment[6..8].copy_from_slice(&csum.to_be_bytes());
+        ipv4::send(source, target, PROTOCOL_UDP, &segment);
+        return;
+    }
+
+    let lose_net_stack = LOSE_NET_STACK.exclusive_access();
+    let udp_packet = UDPPacket::new(
+        lose_net_stack.ip, 
+        lose_net_stack.mac, 
+        sport, 
+        target, 
+        MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]), 
+        dport, 
+        data.len(), 
+        data
+    );
+    let frame = udp_packet.build_data();
+    drop(lose_net_stack);
+    transmit(&frame);
+}
+
+impl Socket for UDP {
+    fn bind(self: Arc<Self>, addr: SockAddr) -> isize {
+        match addr {
+            SockAddr::Inet(ip, port) if self.family_matches(&ip) => self.bind_port(port),
+            _ => -1
+        }
+    }
+
+    fn connect(self: Arc<Self>, addr: SockAddr) -> isize {
+        let (ip, port) = match addr {
+            SockAddr::Inet(ip, port) if self.family_matches(&ip) => (ip, port),
+            _ => return -1
+        };
+        let bound = self.inner.exclusive_access().socket_index.is_some();
+        if !bound && self.bind_port(0) != 0 {
+            return -1;
+        }
+        let mut inner = self.inner.exclusive_access();
+        connect_socket(inner.socket_index.unwrap(), ip, port);
+        inner.target = Some(ip);
+        inner.dport = port;
+        0
+    }
+
+    fn send_to(self: Arc<Self>, data: &[u8], addr: SockAddr) -> isize {
+        let (ip, port) = match addr {
+            SockAddr::Inet(ip, port) if self.family_matches(&ip) => (ip, port),
+            _ => return -1
+        };
+        let bound = self.inner.exclusive_access().socket_index.is_some();
+        if !bound && self.bind_port(0) != 0 {
+            return -1;
+        }
+        if !link::route_up(&ip) {
+            return -1;
+        }
+        let sport = self.inner.exclusive_access().sport;
+        send_datagram(ip, sport, port, data);
+        data.len() as isize
+    }
+
+    fn recv_from(self: Arc<Self>, mut buf: UserBuffer) -> Option<(usize, SockAddr)> {
+        let (ip, port, data) = self.recv()?;
+        Some((copy_datagram(&mut buf, &data), SockAddr::Inet(ip, port)))
+    }
+
+    fn shutdown(self: Arc<Self>) -> isize {
+        self.inner.exclusive_access().shutdown = true;
+        0
+    }
+
+    // `struct ip_mreq`: the group, then the interface, which is ignored
+    fn set_option(self: Arc<Self>, level: usize, name: usize, value: &[u8]) -> isize {
+        if level != IPPROTO_IP || self.v6 || value.len() < 8 {
+            return -1;
+        }
+        let group = IPv4::from_u32(u32::from_be_bytes([value[0], value[1], value[2], value[3]]));
+        let mut inner = self.inner.exclusive_access();
+        match name {
+            IP_ADD_MEMBERSHIP => {
+                if inner.groups.contains(&group) || !igmp::join(group) {
+                    return -1;
+                }
+                inner.groups.push(group);
+                0
+            }
+            IP_DROP_MEMBERSHIP => match inner.groups.iter().position(|joined| *joined == group) {
+                Some(index) => {
+                    inner.groups.remove(index);
+                    igmp::leave(group);
+                    0
+                }
+                None => -1
+            },
+            _ => -1
         }
     }
 }
@@ -34,31 +240,14 @@ impl File for UDP {
         true
     }
 
-    fn read(&self, mut buf: crate::mm::UserBuffer) -> usize {
-        loop {
-            if let Some(data) = pop_data(self.socket_index) {
-                let data_len = data.len();
-                let mut left = 0;
-                for i in 0..buf.buffers.len() {
-                    let buffer_i_len = buf.buffers[i].len().min(data_len - left);
-                    
-                    buf.buffers[i][..buffer_i_len].copy_from_slice(&data[left..(left + buffer_i_len)]);
-    
-                    left += buffer_i_len;
-                    if left == data_len {
-                        break;
-                    }
-                }
-                return left;
-            } else {
-                net_interrupt_handler();
-            }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        match self.recv() {
+            Some((_, _, data)) => copy_datagram(&mut buf, &data),
+            None => 0
         }
     }
 
-    fn write(&self, buf: crate::mm::UserBuffer) -> usize {
-        let lose_net_stack = LOSE_NET_STACK.exclusive_access();
-
+    fn write(&self, buf: UserBuffer) -> usize {
         let mut data = vec![0u8; buf.len()];
         
         let mut left = 0;
@@ -67,27 +256,29 @@ impl File for UDP {
             left += buf.buffers[i].len();
         }
 
-        let len = data.len();
+        let inner = self.inner.exclusive_access();
+        let target = match inner.target {
+            Some(target) if !inner.shutdown && link::route_up(&target) => target,
+            _ => return WRITE_FAILED
+        };
+        send_datagram(target, inner.sport, inner.dport, &data);
+        data.len()
+    }
 
-        let mut t = NET_DEVICE.exclusive_access();
-        let udp_packet = UDPPacket::new(
-            lose_net_stack.ip, 
-            lose_net_stack.mac, 
-            self.sport, 
-            self.target, 
-            MacAddress::new([0xff, 0xff, 0xff, 0xff, 0xff, 0xff]), 
-            self.dport, 
-            len, 
-            data.as_ref()
-        );
-        t.send(&udp_packet.build_data()).expect("can't send to net device");
-        len
+    fn as_socket(self: Arc<Self>) -> Option<Arc<dyn Socket>> {
+        Some(self)
     }
 }
 
 impl Drop for UDP {
     fn drop(&mut self) {
-        remove_socket(self.socket_index)
+        let mut inner = self.inner.exclusive_access();
+        for group in inner.groups.drain(..) {
+            igmp::leave(group);
+        }
+        if let Some(socket_index) = inner.socket_index {
+            remove_socket(socket_index)
+        }
     }
 }
 
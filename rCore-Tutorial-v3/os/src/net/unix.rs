@@ -0,0 +1,342 @@
+//! Local (unix domain) sockets
+//!
+//! Sockets are named by a path in the root directory. The names live in a
+//! kernel table rather than on the FAT volume, but a name that is already
+//! taken by a file cannot be bound.
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use lazy_static::lazy_static;
+
+use crate::{
+    fs::{open_file, File, OpenFlags, WRITE_FAILED},
+    mm::UserBuffer,
+    sync::UPSafeCell,
+    task::suspend_current_and_run_next,
+};
+
+use super::{SockAddr, Socket, SOCK_DGRAM, SOCK_STREAM};
+
+pub const AF_UNIX: usize = 1;
+
+/// Bytes a stream end buffers before writers block, like a pipe
+const STREAM_BUFFER_SIZE: usize = 4096;
+/// Datagrams a socket queues before sends to it fail
+const DATAGRAM_QUEUE_LEN: usize = 64;
+
+lazy_static! {
+    static ref UNIX_NAMESPACE: UPSafeCell<BTreeMap<String, Weak<UnixSocket>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+fn lookup(path: &str) -> Option<Arc<UnixSocket>> {
+    UNIX_NAMESPACE
+        .exclusive_access()
+        .get(path)
+        .and_then(|socket| socket.upgrade())
+}
+
+/// Names are relative to the root directory, with or without a leading `/`
+fn normalize(path: &str) -> Option<String> {
+    let name = path.strip_prefix('/').unwrap_or(path);
+    if name.is_empty() || name.contains('/') {
+        None
+    } else {
+        Some(String::from(name))
+    }
+}
+
+pub struct UnixSocket {
+    pub socket_type: usize,
+    inner: UPSafeCell<UnixSocketInner>,
+}
+
+pub struct UnixSocketInner {
+    path: Option<String>,
+    /// `Some` once `listen` has been called on a stream socket
+    pending: Option<VecDeque<Arc<UnixSocket>>>,
+    backlog: usize,
+    /// the other end of a stream connection, or the default datagram target
+    peer: Option<Weak<UnixSocket>>,
+    stream_buffer: VecDeque<u8>,
+    datagrams: VecDeque<(Option<String>, Vec<u8>)>,
+}
+
+/// Copy as much of a datagram as fits, the rest of it is lost
+fn copy_datagram(data: &[u8], buf: &mut UserBuffer) -> usize {
+    let mut copied = 0;
+    for buffer in buf.buffers.iter_mut() {
+        let len = buffer.len().min(data.len() - copied);
+        buffer[..len].copy_from_slice(&data[copied..(copied + len)]);
+        copied += len;
+    }
+    copied
+}
+
+impl UnixSocket {
+    pub fn new(socket_type: usize) -> Self {
+        Self {
+            socket_type,
+            inner: unsafe {
+                UPSafeCell::new(UnixSocketInner {
+                    path: None,
+                    pending: None,
+                    backlog: 0,
+                    peer: None,
+                    stream_buffer: VecDeque::new(),
+                    datagrams: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    fn peer(&self) -> Option<Arc<UnixSocket>> {
+        self.inner
+            .exclusive_access()
+            .peer
+            .as_ref()
+            .and_then(|peer| peer.upgrade())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.exclusive_access().peer.is_some()
+    }
+}
+
+impl Socket for UnixSocket {
+    fn bind(self: Arc<Self>, addr: SockAddr) -> isize {
+        let path = match addr {
+            SockAddr::Unix(path) => path,
+            _ => return -1,
+        };
+        let path = match normalize(&path) {
+            Some(path) => path,
+            None => return -1,
+        };
+        if self.inner.exclusive_access().path.is_some()
+            || lookup(&path).is_some()
+            || open_file(&path, OpenFlags::RDONLY).is_some()
+        {
+            return -1;
+        }
+        UNIX_NAMESPACE
+            .exclusive_access()
+            .insert(path.clone(), Arc::downgrade(&self));
+        self.inner.exclusive_access().path = Some(path);
+        0
+    }
+
+    fn listen(self: Arc<Self>, backlog: usize) -> isize {
+        let mut inner = self.inner.exclusive_access();
+        if self.socket_type != SOCK_STREAM || inner.path.is_none() || inner.peer.is_some() {
+            return -1;
+        }
+        inner.backlog = backlog.max(1);
+        if inner.pending.is_none() {
+            inner.pending = Some(VecDeque::new());
+        }
+        0
+    }
+
+    fn accept(self: Arc<Self>) -> Option<(Arc<dyn File + Send + Sync>, SockAddr)> {
+        if self.inner.exclusive_access().pending.is_none() {
+            return None;
+        }
+        loop {
+            let server_end = self
+                .inner
+                .exclusive_access()
+                .pending
+                .as_mut()
+                .unwrap()
+                .pop_front();
+            if let Some(server_end) = server_end {
+                // unnamed clients are reported with an empty path
+                return Some((server_end, SockAddr::Unix(String::new())));
+            }
+            // only this call holds the listener, every descriptor of it has
+            // been closed and nobody will see a connection
+            if Arc::strong_count(&self) == 1 {
+                return None;
+            }
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn connect(self: Arc<Self>, addr: SockAddr) -> isize {
+        let path = match addr {
+            SockAddr::Unix(path) => path,
+            _ => return -1,
+        };
+        let target = match normalize(&path).and_then(|path| lookup(&path)) {
+            Some(target) => target,
+            None => return -1,
+        };
+        if Arc::ptr_eq(&self, &target) || target.socket_type != self.socket_type || self.is_connected() {
+            return -1;
+        }
+
+        if self.socket_type == SOCK_DGRAM {
+            self.inner.exclusive_access().peer = Some(Arc::downgrade(&target));
+            return 0;
+        }
+
+        let mut target_inner = target.inner.exclusive_access();
+        let backlog = target_inner.backlog;
+        let pending = match target_inner.pending.as_mut() {
+            Some(pending) if pending.len() < backlog => pending,
+            _ => return -1,
+        };
+        // the listener hands this end out from accept
+        let server_end = Arc::new(UnixSocket::new(SOCK_STREAM));
+        {
+            let mut server_inner = server_end.inner.exclusive_access();
+            server_inner.path = Some(path);
+            server_inner.peer = Some(Arc::downgrade(&self));
+        }
+        self.inner.exclusive_access().peer = Some(Arc::downgrade(&server_end));
+        pending.push_back(server_end);
+        0
+    }
+
+    fn send_to(self: Arc<Self>, data: &[u8], addr: SockAddr) -> isize {
+        let path = match addr {
+            SockAddr::Unix(path) => path,
+            _ => return -1,
+        };
+        if self.socket_type != SOCK_DGRAM {
+            return -1;
+        }
+        let target = match normalize(&path).and_then(|path| lookup(&path)) {
+            Some(target) if target.socket_type == SOCK_DGRAM && !Arc::ptr_eq(&self, &target) => target,
+            _ => return -1,
+        };
+        let source = self.inner.exclusive_access().path.clone();
+        let mut target_inner = target.inner.exclusive_access();
+        if target_inner.datagrams.len() >= DATAGRAM_QUEUE_LEN {
+            return -1;
+        }
+        target_inner.datagrams.push_back((source, data.to_vec()));
+        data.len() as isize
+    }
+
+    fn recv_from(self: Arc<Self>, mut buf: UserBuffer) -> Option<(usize, SockAddr)> {
+        if self.socket_type != SOCK_DGRAM {
+            return None;
+        }
+        loop {
+            let datagram = self.inner.exclusive_access().datagrams.pop_front();
+            if let Some((source, data)) = datagram {
+                return Some((copy_datagram(&data, &mut buf), SockAddr::Unix(source.unwrap_or_default())));
+            }
+            suspend_current_and_run_next();
+        }
+    }
+}
+
+impl File for UnixSocket {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if self.socket_type == SOCK_DGRAM {
+                // like on linux the sender is dropped, `recv_from` reports it
+                if let Some((_, data)) = inner.datagrams.pop_front() {
+                    return copy_datagram(&data, &mut buf);
+                }
+            } else {
+                if !inner.stream_buffer.is_empty() {
+                    let mut read_size = 0;
+                    for buffer in buf.buffers.iter_mut() {
+                        for byte in buffer.iter_mut() {
+                            match inner.stream_buffer.pop_front() {
+                                Some(data) => *byte = data,
+                                None => return read_size,
+                            }
+                            read_size += 1;
+                        }
+                    }
+                    return read_size;
+                }
+                // end of file once the other end has been closed, or if
+                // there never was one
+                if inner.peer.as_ref().map_or(true, |peer| peer.strong_count() == 0) {
+                    return 0;
+                }
+            }
+            drop(inner);
+            suspend_current_and_run_next();
+        }
+    }
+
+    /// Fails when there is no peer or it has been closed, or for datagrams
+    /// when its queue is full. Streams block while the peer's buffer is
+    /// full, like a pipe, and return what was written if it goes away.
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut data = Vec::with_capacity(buf.len());
+        for buffer in buf.buffers.iter() {
+            data.extend_from_slice(buffer);
+        }
+        let len = data.len();
+        if self.socket_type == SOCK_DGRAM {
+            let peer = match self.peer() {
+                Some(peer) => peer,
+                None => return WRITE_FAILED,
+            };
+            let source = self.inner.exclusive_access().path.clone();
+            let mut peer_inner = peer.inner.exclusive_access();
+            if peer_inner.datagrams.len() >= DATAGRAM_QUEUE_LEN {
+                return WRITE_FAILED;
+            }
+            peer_inner.datagrams.push_back((source, data));
+            return len;
+        }
+
+        let mut written = 0;
+        loop {
+            let peer = match self.peer() {
+                Some(peer) => peer,
+                None if written == 0 => return WRITE_FAILED,
+                None => return written,
+            };
+            let mut peer_inner = peer.inner.exclusive_access();
+            let room = STREAM_BUFFER_SIZE - peer_inner.stream_buffer.len();
+            let chunk = room.min(len - written);
+            peer_inner
+                .stream_buffer
+                .extend(&data[written..written + chunk]);
+            written += chunk;
+            if written == len {
+                return len;
+            }
+            drop(peer_inner);
+            drop(peer);
+            suspend_current_and_run_next();
+        }
+    }
+
+    fn as_socket(self: Arc<Self>) -> Option<Arc<dyn Socket>> {
+        Some(self)
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        if let Some(path) = self.inner.exclusive_access().path.as_ref() {
+            let mut namespace = UNIX_NAMESPACE.exclusive_access();
+            if namespace.get(path).map_or(false, |socket| socket.strong_count() == 0) {
+                namespace.remove(path);
+            }
+        }
+    }
+}
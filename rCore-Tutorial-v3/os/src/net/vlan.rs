@@ -0,0 +1,373 @@
+//! 802.1Q VLAN sub-interfaces
+//!
+//! `ethX.N` carries the frames of VLAN N on interface X and has an IPv4
+//! address of its own. Packets to its subnet leave through it, with its
+//! address as the source. The e1000 inserts and strips the tags itself,
+//! through the `special` field of its descriptors; on the other NICs the
+//! stack does it. Only IPv4 runs over sub-interfaces: ARP, ICMP echo, UDP
+//! and TCP.
+//!
+//! Each sub-interface resolves its next hops with ARP like the IPv6 side
+//! does with neighbour discovery: packets wait for the reply, and only
+//! ARP from hosts of the subnet fills the bounded neighbour table.
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    vec::Vec,
+};
+use lazy_static::lazy_static;
+use lose_net_stack::IPv4;
+
+use crate::{sync::UPSafeCell, timer::get_time_ms};
+
+use super::{
+    arp::{self, ETH_TYPE_ARP},
+    deliver_udp,
+    ipv4::{self, icmp_checksum, DEFAULT_TTL, ICMP_ECHO_REPLY, ICMP_ECHO_REQUEST, PROTOCOL_ICMP, PROTOCOL_TCP, PROTOCOL_UDP},
+    ipv6::ETH_HEADER_LEN,
+    send_frame_vlan, tcp, transmit_on, IpAddr, LAN_INTERFACE, LOCAL_MAC, WAN_INTERFACE,
+};
+
+pub const ETH_TYPE_VLAN: u16 = 0x8100;
+const VLAN_TAG_LEN: usize = 4;
+const VLAN_ID_MAX: u16 = 4094;
+
+/// Interface numbers of sub-interfaces start here, after the NICs
+pub const VLAN_INTERFACE_BASE: u8 = 16;
+const MAX_VLANS: usize = 16;
+/// Neighbours remembered per sub-interface, and resolved at once
+const MAX_NEIGHBOURS: usize = 64;
+/// Frames kept per unresolved neighbour, older ones are dropped
+const PENDING_FRAMES: usize = 3;
+/// ARP requests sent before a neighbour is given up
+const MAX_REQUESTS: usize = 3;
+const ARP_RETRY_MS: usize = 1000;
+
+/// A sub-interface as it is exchanged with user space, `interface` is
+/// filled in by the kernel
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct VlanConfig {
+    pub parent: u8,
+    pub interface: u8,
+    pub id: u16,
+    pub ip: [u8; 4],
+    pub prefix: u8,
+    pub _reserved: [u8; 3],
+}
+
+impl VlanConfig {
+    fn ip(&self) -> u32 {
+        u32::from_be_bytes(self.ip)
+    }
+
+    fn mask(&self) -> u32 {
+        if self.prefix == 0 { 0 } else { u32::MAX << (32 - self.prefix) }
+    }
+
+    fn contains(&self, addr: IPv4) -> bool {
+        (addr.to_u32() ^ self.ip()) & self.mask() == 0
+    }
+
+    /// The limited and the subnet broadcast, they go to every host
+    fn is_broadcast(&self, addr: u32) -> bool {
+        let in_subnet = self.contains(IPv4::from_u32(addr));
+        addr == u32::MAX || (self.prefix < 31 && in_subnet && addr | self.mask() == u32::MAX)
+    }
+}
+
+/// A neighbour an ARP request is out for
+struct PendingNeighbour {
+    frames: VecDeque<Vec<u8>>,
+    requests: usize,
+    sent_at: usize,
+}
+
+struct Vlan {
+    config: VlanConfig,
+    /// MAC addresses of the hosts on the VLAN, from their ARP packets
+    neighbours: BTreeMap<u32, [u8; 6]>,
+    /// packets waiting for an ARP reply, keyed by the neighbour
+    pending: BTreeMap<u32, PendingNeighbour>,
+}
+
+lazy_static! {
+    /// Slot `i` is interface `VLAN_INTERFACE_BASE + i`
+    static ref VLANS: UPSafeCell<Vec<Option<Vlan>>> = unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+pub fn is_vlan(interface: u8) -> bool {
+    interface >= VLAN_INTERFACE_BASE
+}
+
+pub fn is_tagged(frame: &[u8]) -> bool {
+    frame.len() >= ETH_HEADER_LEN && u16::from_be_bytes([frame[12], frame[13]]) == ETH_TYPE_VLAN
+}
+
+pub const VLAN_ADD: usize = 1;
+pub const VLAN_DELETE: usize = 2;
+pub const VLAN_LIST: usize = 3;
+
+/// Create a sub-interface, return its interface number
+pub fn add(mut config: VlanConfig) -> isize {
+    if (config.parent != WAN_INTERFACE && config.parent != LAN_INTERFACE)
+        || config.id == 0
+        || config.id > VLAN_ID_MAX
+        || config.prefix > 32
+    {
+        return -1;
+    }
+    let mut vlans = VLANS.exclusive_access();
+    let taken = vlans.iter().flatten().any(|vlan| {
+        (vlan.config.parent == config.parent && vlan.config.id == config.id) || vlan.config.ip == config.ip
+    });
+    if taken {
+        return -1;
+    }
+    let index = match vlans.iter().position(Option::is_none) {
+        Some(index) => index,
+        None if vlans.len() < MAX_VLANS => {
+            vlans.push(None);
+            vlans.len() - 1
+        }
+        None => return -1,
+    };
+    config.interface = VLAN_INTERFACE_BASE + index as u8;
+    vlans[index] = Some(Vlan {
+        config,
+        neighbours: BTreeMap::new(),
+        pending: BTreeMap::new(),
+    });
+    config.interface as isize
+}
+
+pub fn remove(interface: u8) -> isize {
+    let mut vlans = VLANS.exclusive_access();
+    match vlans.get_mut(interface.wrapping_sub(VLAN_INTERFACE_BASE) as usize) {
+        Some(slot) if slot.is_some() => {
+            *slot = None;
+            0
+        }
+        _ => -1,
+    }
+}
+
+pub fn list() -> Vec<VlanConfig> {
+    VLANS.exclusive_access().iter().flatten().map(|vlan| vlan.config).collect()
+}
+
+fn config(interface: u8) -> Option<VlanConfig> {
+    let vlans = VLANS.exclusive_access();
+    let vlan = vlans.get(interface.wrapping_sub(VLAN_INTERFACE_BASE) as usize)?.as_ref()?;
+    Some(vlan.config)
+}
+
+/// The NIC a sub-interface sits on
+pub fn parent(interface: u8) -> Option<u8> {
+    config(interface).map(|config| config.parent)
+}
+
+/// The sub-interface whose subnet holds `dest`
+pub fn route(dest: IPv4) -> Option<u8> {
+    VLANS
+        .exclusive_access()
+        .iter()
+        .flatten()
+        .find(|vlan| vlan.config.contains(dest))
+        .map(|vlan| vlan.config.interface)
+}
+
+/// Our address on the sub-interface `dest` is reached through
+pub fn address_for(dest: IPv4) -> Option<IPv4> {
+    route(dest).and_then(config).map(|config| IPv4::from_u32(config.ip()))
+}
+
+/// The low 12 bits of the tag control information are the VLAN id
+pub fn vlan_id(tci: u16) -> u16 {
+    tci & 0xfff
+}
+
+/// The sub-interface of VLAN `id` on `parent`
+pub fn sub_interface(parent: u8, id: u16) -> Option<u8> {
+    VLANS
+        .exclusive_access()
+        .iter()
+        .flatten()
+        .find(|vlan| vlan.config.parent == parent && vlan.config.id == id)
+        .map(|vlan| vlan.config.interface)
+}
+
+/// `frame` with a tag of `tci` after the MAC addresses
+pub fn insert_tag(frame: &[u8], tci: u16) -> Vec<u8> {
+    let mut tagged = Vec::with_capacity(frame.len() + VLAN_TAG_LEN);
+    tagged.extend_from_slice(&frame[..12]);
+    tagged.extend_from_slice(&ETH_TYPE_VLAN.to_be_bytes());
+    tagged.extend_from_slice(&tci.to_be_bytes());
+    tagged.extend_from_slice(&frame[12..]);
+    tagged
+}
+
+/// Strip the tag of a frame received on `parent`. None if there is no
+/// sub-interface for its VLAN.
+pub fn untag(parent: u8, frame: &[u8]) -> Option<(u8, Vec<u8>)> {
+    if !is_tagged(frame) || frame.len() < ETH_HEADER_LEN + VLAN_TAG_LEN {
+        return None;
+    }
+    let interface = sub_interface(parent, vlan_id(u16::from_be_bytes([frame[14], frame[15]])))?;
+    let mut untagged = Vec::with_capacity(frame.len() - VLAN_TAG_LEN);
+    untagged.extend_from_slice(&frame[..12]);
+    untagged.extend_from_slice(&frame[16..]);
+    Some((interface, untagged))
+}
+
+/// Tag a frame of `interface` and put it on the wire of its parent
+pub fn send_tagged(interface: u8, frame: &[u8]) {
+    if let Some(config) = config(interface) {
+        // priority 0
+        send_frame_vlan(config.parent, frame, config.id);
+    }
+}
+
+/// Send an IP packet out of `interface`. If the MAC of the neighbour is
+/// not known yet, an ARP request is sent and the packet is queued until
+/// the reply arrives, only the last few are kept.
+pub fn send_ipv4(interface: u8, source: IPv4, dest: IPv4, protocol: u8, payload: &[u8]) {
+    let mut vlans = VLANS.exclusive_access();
+    let vlan = match vlans.get_mut(interface.wrapping_sub(VLAN_INTERFACE_BASE) as usize) {
+        Some(Some(vlan)) => vlan,
+        _ => return,
+    };
+    let dest_ip = dest.to_u32();
+    let mac = if vlan.config.is_broadcast(dest_ip) {
+        Some([0xff; 6])
+    } else {
+        vlan.neighbours.get(&dest_ip).copied()
+    };
+    if let Some(mac) = mac {
+        drop(vlans);
+        let frame = ipv4::build_frame(mac, source, dest, protocol, DEFAULT_TTL, payload);
+        transmit_on(interface, &frame);
+        return;
+    }
+
+    // the real destination mac is filled in when the packet is flushed
+    let frame = ipv4::build_frame([0; 6], source, dest, protocol, DEFAULT_TTL, payload);
+    let first = !vlan.pending.contains_key(&dest_ip);
+    if first && vlan.pending.len() >= MAX_NEIGHBOURS {
+        return;
+    }
+    let pending = vlan.pending.entry(dest_ip).or_insert_with(|| PendingNeighbour {
+        frames: VecDeque::new(),
+        requests: 1,
+        sent_at: get_time_ms(),
+    });
+    if pending.frames.len() == PENDING_FRAMES {
+        pending.frames.pop_front();
+    }
+    pending.frames.push_back(frame);
+    let ip = vlan.config.ip();
+    drop(vlans);
+    if first {
+        transmit_on(interface, &arp::build_request(LOCAL_MAC, ip, dest_ip));
+    }
+}
+
+/// Record `mac` for `ip` from an ARP packet and flush what waited for it.
+/// Hosts outside the subnet are ignored. A new host is only added if we
+/// asked for it or it asked for us, and once the table is full only if
+/// we asked, in place of another one.
+fn learn(interface: u8, ip: u32, mac: [u8; 6], asked_for_us: bool) {
+    let mut vlans = VLANS.exclusive_access();
+    let vlan = match vlans.get_mut(interface.wrapping_sub(VLAN_INTERFACE_BASE) as usize) {
+        Some(Some(vlan)) => vlan,
+        _ => return,
+    };
+    let config = vlan.config;
+    if !config.contains(IPv4::from_u32(ip)) || ip == config.ip() || config.is_broadcast(ip) {
+        return;
+    }
+    let asked = vlan.pending.contains_key(&ip);
+    if !vlan.neighbours.contains_key(&ip) {
+        if !asked && !asked_for_us {
+            return;
+        }
+        if vlan.neighbours.len() >= MAX_NEIGHBOURS {
+            if !asked {
+                return;
+            }
+            let victim = *vlan.neighbours.keys().next().unwrap();
+            vlan.neighbours.remove(&victim);
+        }
+    }
+    vlan.neighbours.insert(ip, mac);
+    let pending = vlan.pending.remove(&ip);
+    drop(vlans);
+
+    if let Some(pending) = pending {
+        for mut frame in pending.frames {
+            frame[..6].copy_from_slice(&mac);
+            transmit_on(interface, &frame);
+        }
+    }
+}
+
+/// Ask again for the neighbours that haven't answered, and give up on the
+/// ones that were asked often enough together with their frames
+pub fn poll() {
+    let now = get_time_ms();
+    let mut resend = Vec::new();
+    {
+        let mut vlans = VLANS.exclusive_access();
+        for vlan in vlans.iter_mut().flatten() {
+            let (interface, ip) = (vlan.config.interface, vlan.config.ip());
+            vlan.pending.retain(|addr, pending| {
+                if now < pending.sent_at + ARP_RETRY_MS {
+                    return true;
+                }
+                if pending.requests == MAX_REQUESTS {
+                    return false;
+                }
+                pending.requests += 1;
+                pending.sent_at = now;
+                resend.push((interface, ip, *addr));
+                true
+            });
+        }
+    }
+    for (interface, ip, target) in resend {
+        transmit_on(interface, &arp::build_request(LOCAL_MAC, ip, target));
+    }
+}
+
+/// Handle a frame received on a sub-interface, without its tag
+pub fn handle(interface: u8, frame: &[u8]) {
+    let config = match config(interface) {
+        Some(config) => config,
+        None => return,
+    };
+    if u16::from_be_bytes([frame[12], frame[13]]) == ETH_TYPE_ARP {
+        if let Some(request) = arp::parse(frame) {
+            let for_us = request.is_request_for(config.ip());
+            learn(interface, request.sender_ip, request.sender_mac, for_us);
+            if for_us {
+                transmit_on(interface, &arp::build_reply(&request, LOCAL_MAC, config.ip()));
+            }
+        }
+        return;
+    }
+
+    let packet = match ipv4::parse(frame) {
+        Some(packet) if packet.dest.to_u32() == config.ip() => packet,
+        _ => return,
+    };
+    match packet.protocol {
+        PROTOCOL_TCP => tcp::handle(IpAddr::V4(packet.source), IpAddr::V4(packet.dest), packet.payload),
+        PROTOCOL_UDP => deliver_udp(&packet),
+        PROTOCOL_ICMP if packet.payload.len() >= 8 && packet.payload[0] == ICMP_ECHO_REQUEST => {
+            let mut reply = packet.payload.to_vec();
+            reply[0] = ICMP_ECHO_REPLY;
+            icmp_checksum(&mut reply);
+            send_ipv4(interface, packet.dest, packet.source, PROTOCOL_ICMP, &reply);
+        }
+        _ => {}
+    }
+}
@@ -1,3 +1,242 @@
-pub fn init() {    
+//! PCI bus enumeration
+//!
+//! Nobody configures the bus before us on QEMU's virt machine, so `init`
+//! walks the ECAM the device tree names, sizes the memory BARs of every function and assigns
+//! them from the host bridge's MMIO window, turns on memory decoding and
+//! bus mastering and routes the interrupt pin. The driver registry binds
+//! drivers to the [`functions`] found, which take their addresses from there.
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
 
-}
\ No newline at end of file
+use crate::dtb::{self, Region};
+use crate::sync::UPSafeCell;
+
+/// Legacy INTA of slot 0 on the PLIC, the pins are swizzled by slot
+const PCI_IRQ_BASE: usize = 32;
+
+const PCI_VENDOR_ID: usize = 0x00;
+const PCI_COMMAND: usize = 0x04;
+const PCI_CLASS_REVISION: usize = 0x08;
+const PCI_HEADER_TYPE: usize = 0x0e;
+const PCI_BAR0: usize = 0x10;
+const PCI_INTERRUPT_LINE: usize = 0x3c;
+const PCI_INTERRUPT_PIN: usize = 0x3d;
+
+const PCI_COMMAND_MEMORY: u16 = 1 << 1;
+const PCI_COMMAND_MASTER: u16 = 1 << 2;
+const PCI_HEADER_MULTI_FUNCTION: u8 = 0x80;
+
+const PCI_BAR_IO: u32 = 0x1;
+const PCI_BAR_64: u32 = 0x4;
+const PCI_BARS: usize = 6;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Bar {
+    pub addr: usize,
+    pub size: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct PciFunction {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+    /// Assigned memory BARs, a 64-bit BAR takes its slot and leaves the
+    /// next one empty. I/O BARs are not assigned.
+    pub bars: [Option<Bar>; PCI_BARS],
+    /// PLIC interrupt of the INTx pin, None if the function has no pin
+    pub irq: Option<usize>,
+}
+
+impl PciFunction {
+    /// Address of the config space of the function
+    pub fn config_space(&self) -> usize {
+        config_base(self.bus, self.device, self.function)
+    }
+
+    pub fn read_config32(&self, offset: usize) -> u32 {
+        read32(self.config_space() + offset)
+    }
+
+    pub fn write_config32(&self, offset: usize, value: u32) {
+        write32(self.config_space() + offset, value)
+    }
+}
+
+lazy_static! {
+    static ref PCI_FUNCTIONS: UPSafeCell<Vec<PciFunction>> = unsafe { UPSafeCell::new(Vec::new()) };
+    /// ECAM and the mapped part of the MMIO window of the host bridge
+    static ref PCI_HOST: UPSafeCell<Option<dtb::PciHost>> = unsafe { UPSafeCell::new(None) };
+    /// Next free address of the MMIO window
+    static ref PCI_MMIO_NEXT: UPSafeCell<usize> = unsafe { UPSafeCell::new(0) };
+}
+
+fn config_base(bus: u8, device: u8, function: u8) -> usize {
+    let ecam = PCI_HOST.exclusive_access().expect("no pci host bridge").ecam.base;
+    ecam + ((bus as usize) << 20 | (device as usize) << 15 | (function as usize) << 12)
+}
+
+fn read32(addr: usize) -> u32 {
+    unsafe { (addr as *const u32).read_volatile() }
+}
+
+fn write32(addr: usize, value: u32) {
+    unsafe { (addr as *mut u32).write_volatile(value) }
+}
+
+fn read16(addr: usize) -> u16 {
+    unsafe { (addr as *const u16).read_volatile() }
+}
+
+fn write16(addr: usize, value: u16) {
+    unsafe { (addr as *mut u16).write_volatile(value) }
+}
+
+fn read8(addr: usize) -> u8 {
+    unsafe { (addr as *const u8).read_volatile() }
+}
+
+fn write8(addr: usize, value: u8) {
+    unsafe { (addr as *mut u8).write_volatile(value) }
+}
+
+/// Take `size` bytes of the MMIO window, BARs are aligned to their size
+fn alloc_mmio(size: usize) -> Option<usize> {
+    let window: Region = PCI_HOST.exclusive_access().expect("no pci host bridge").mmio;
+    let mut next = PCI_MMIO_NEXT.exclusive_access();
+    let addr = (*next + size - 1) & !(size - 1);
+    if addr + size > window.base + window.size {
+        return None;
+    }
+    *next = addr + size;
+    Some(addr)
+}
+
+/// Size the BARs of a function by writing all ones and reading back which
+/// address bits stick, then give each a place in the MMIO window
+fn assign_bars(config: usize) -> [Option<Bar>; PCI_BARS] {
+    let mut bars = [None; PCI_BARS];
+    let mut index = 0;
+    while index < PCI_BARS {
+        let reg = config + PCI_BAR0 + index * 4;
+        let orig = read32(reg);
+        let is_64 = orig & PCI_BAR_IO == 0 && orig & 0x6 == PCI_BAR_64;
+        write32(reg, u32::MAX);
+        let mask = read32(reg);
+        write32(reg, orig);
+        if mask == 0 || orig & PCI_BAR_IO != 0 {
+            index += 1;
+            continue;
+        }
+        let size = (!(mask & !0xf)).wrapping_add(1) as usize;
+        if let Some(addr) = alloc_mmio(size) {
+            write32(reg, addr as u32);
+            if is_64 {
+                write32(reg + 4, 0);
+            }
+            bars[index] = Some(Bar { addr, size });
+        } else {
+            println!("[pci] no room for a {:#x} byte BAR", size);
+        }
+        index += if is_64 { 2 } else { 1 };
+    }
+    bars
+}
+
+fn probe(bus: u8, device: u8, function: u8) -> Option<PciFunction> {
+    let config = config_base(bus, device, function);
+    let id = read32(config + PCI_VENDOR_ID);
+    if id & 0xffff == 0xffff {
+        return None;
+    }
+    let class = read32(config + PCI_CLASS_REVISION);
+    // only endpoints, bridges have a different header
+    let bars = if read8(config + PCI_HEADER_TYPE) & !PCI_HEADER_MULTI_FUNCTION == 0 {
+        assign_bars(config)
+    } else {
+        [None; PCI_BARS]
+    };
+
+    let pin = read8(config + PCI_INTERRUPT_PIN);
+    let irq = if pin == 0 {
+        None
+    } else {
+        let irq = PCI_IRQ_BASE + (device as usize + pin as usize - 1) % 4;
+        write8(config + PCI_INTERRUPT_LINE, irq as u8);
+        Some(irq)
+    };
+
+    let command = read16(config + PCI_COMMAND);
+    write16(config + PCI_COMMAND, command | PCI_COMMAND_MEMORY | PCI_COMMAND_MASTER);
+
+    Some(PciFunction {
+        bus,
+        device,
+        function,
+        vendor_id: id as u16,
+        device_id: (id >> 16) as u16,
+        class: (class >> 24) as u8,
+        subclass: (class >> 16) as u8,
+        prog_if: (class >> 8) as u8,
+        bars,
+        irq,
+    })
+}
+
+/// Enumerate bus 0, which is all QEMU's virt machine has without bridges
+pub fn init() {
+    let host = match dtb::pci_host() {
+        Some(host) => host,
+        None => {
+            println!("[pci] no host bridge in the device tree");
+            return;
+        }
+    };
+    *PCI_HOST.exclusive_access() = Some(host);
+    *PCI_MMIO_NEXT.exclusive_access() = host.mmio.base;
+    let mut functions = Vec::new();
+    for device in 0..32 {
+        let multi_function = read32(config_base(0, device, 0) + PCI_VENDOR_ID) & 0xffff != 0xffff
+            && read8(config_base(0, device, 0) + PCI_HEADER_TYPE) & PCI_HEADER_MULTI_FUNCTION != 0;
+        let count = if multi_function { 8 } else { 1 };
+        for function in 0..count {
+            if let Some(found) = probe(0, device, function) {
+                println!(
+                    "[pci] {:02x}:{:02x}.{} {:04x}:{:04x} class {:02x}.{:02x} irq {:?}",
+                    found.bus, found.device, found.function, found.vendor_id, found.device_id, found.class,
+                    found.subclass, found.irq
+                );
+                functions.push(found);
+            }
+        }
+    }
+    *PCI_FUNCTIONS.exclusive_access() = functions;
+}
+
+/// All functions found on the bus
+pub fn functions() -> Vec<PciFunction> {
+    PCI_FUNCTIONS.exclusive_access().clone()
+}
+
+#[allow(unused)]
+pub fn find(vendor_id: u16, device_id: u16) -> Option<PciFunction> {
+    PCI_FUNCTIONS
+        .exclusive_access()
+        .iter()
+        .find(|function| function.vendor_id == vendor_id && function.device_id == device_id)
+        .cloned()
+}
+
+#[allow(unused)]
+pub fn find_class(class: u8, subclass: u8) -> Option<PciFunction> {
+    PCI_FUNCTIONS
+        .exclusive_access()
+        .iter()
+        .find(|function| function.class == class && function.subclass == subclass)
+        .cloned()
+}
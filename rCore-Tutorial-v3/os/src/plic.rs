@@ -0,0 +1,117 @@
+//! Platform-level interrupt controller
+//!
+//! Drivers hand a handler for their interrupt to [`register_irq`], which
+//! gives the source a priority and enables it in the S-mode context of the
+//! boot hart. The trap handler calls [`handle_external`] on a supervisor
+//! external interrupt, which claims the pending sources one by one, runs
+//! their handlers and completes them.
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use riscv::register::sie;
+
+use crate::dtb;
+use crate::sync::UPSafeCell;
+
+/// Register layout of the SiFive PLIC that QEMU's virt machine has
+const PRIORITY: usize = 0x0000;
+const ENABLE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+const CONTEXT_THRESHOLD: usize = 0x0;
+const CONTEXT_CLAIM: usize = 0x4;
+
+/// Sources go from 1 up to this on QEMU's virt machine, 0 means none
+const MAX_IRQ: usize = 127;
+/// Every source gets the same priority, above the threshold of 0
+const DEFAULT_PRIORITY: u32 = 1;
+
+pub type IrqHandler = Arc<dyn Fn() + Send + Sync>;
+
+lazy_static! {
+    static ref HANDLERS: UPSafeCell<BTreeMap<usize, Vec<IrqHandler>>> = unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Base of the PLIC, 0 if the device tree has none
+static PLIC_BASE: AtomicUsize = AtomicUsize::new(0);
+static BOOT_HART: AtomicUsize = AtomicUsize::new(0);
+
+fn read(offset: usize) -> u32 {
+    let base = PLIC_BASE.load(Ordering::Relaxed);
+    unsafe { ((base + offset) as *const u32).read_volatile() }
+}
+
+fn write(offset: usize, value: u32) {
+    let base = PLIC_BASE.load(Ordering::Relaxed);
+    unsafe { ((base + offset) as *mut u32).write_volatile(value) }
+}
+
+/// S-mode context of a hart, each hart has an M-mode one before it
+fn context(hart: usize) -> usize {
+    hart * 2 + 1
+}
+
+fn enable_offset(irq: usize) -> usize {
+    ENABLE + context(BOOT_HART.load(Ordering::Relaxed)) * ENABLE_STRIDE + irq / 32 * 4
+}
+
+fn context_offset(register: usize) -> usize {
+    CONTEXT + context(BOOT_HART.load(Ordering::Relaxed)) * CONTEXT_STRIDE + register
+}
+
+/// Take external interrupts on `hart`, with every source masked until a
+/// driver registers for it
+pub fn init(hart: usize) {
+    let plic = match dtb::plic() {
+        Some(plic) => plic,
+        None => {
+            println!("[kernel] no plic in the device tree, external interrupts stay off");
+            return;
+        }
+    };
+    PLIC_BASE.store(plic.base, Ordering::Relaxed);
+    BOOT_HART.store(hart, Ordering::Relaxed);
+    for irq in 1..=MAX_IRQ {
+        write(PRIORITY + irq * 4, 0);
+    }
+    for word in 0..=MAX_IRQ / 32 {
+        write(enable_offset(word * 32), 0);
+    }
+    write(context_offset(CONTEXT_THRESHOLD), 0);
+    unsafe {
+        sie::set_sext();
+    }
+}
+
+/// Run `handler` whenever `irq` fires, sources can be shared
+pub fn register_irq(irq: usize, handler: IrqHandler) {
+    assert!(irq > 0 && irq <= MAX_IRQ, "no interrupt source {}", irq);
+    HANDLERS.exclusive_access().entry(irq).or_insert_with(Vec::new).push(handler);
+    if PLIC_BASE.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    write(PRIORITY + irq * 4, DEFAULT_PRIORITY);
+    let enable = read(enable_offset(irq));
+    write(enable_offset(irq), enable | 1 << (irq % 32));
+}
+
+/// Claim the pending sources, dispatch them to their handlers and
+/// complete them
+pub fn handle_external() {
+    loop {
+        let irq = read(context_offset(CONTEXT_CLAIM)) as usize;
+        if irq == 0 {
+            break;
+        }
+        // handlers may register others, so the table isn't held
+        let handlers = HANDLERS.exclusive_access().get(&irq).cloned().unwrap_or_default();
+        if handlers.is_empty() {
+            println!("[kernel] unexpected interrupt {}", irq);
+        }
+        for handler in handlers.iter() {
+            handler();
+        }
+        write(context_offset(CONTEXT_CLAIM), irq as u32);
+    }
+}
@@ -0,0 +1,180 @@
+//! Kernel random numbers
+//!
+//! A ChaCha20 stream generator with fast key erasure: every request runs
+//! ChaCha20 under the current key, the first 32 bytes of the stream become
+//! the next key and only the rest is handed out, so a key found later
+//! tells nothing about earlier output. The key is seeded from the first
+//! hardware entropy source, virtio-rng on QEMU, and reseeded from it after
+//! every `RESEED_BYTES` of output. Without one, or if it gives nothing, it
+//! is seeded from timer jitter, which is no good against anyone who can
+//! guess boot timing.
+use lazy_static::lazy_static;
+
+use crate::drivers::registry;
+use crate::sync::UPSafeCell;
+use crate::timer::get_time;
+
+const KEY_LEN: usize = 32;
+const BLOCK_LEN: usize = 64;
+/// Output handed out under one key before it is replaced
+const CHUNK: usize = 256;
+const RESEED_BYTES: usize = 1 << 20;
+/// Timer samples taken for a seed when there is no entropy source
+const JITTER_SAMPLES: usize = 1024;
+/// Fills of the hardware source in a row that may come back empty
+const SEED_ATTEMPTS: usize = 8;
+
+struct ChaCha20Rng {
+    key: [u8; KEY_LEN],
+    /// bytes handed out since the last reseed
+    output: usize,
+    /// seeded from a hardware source
+    hardware: bool,
+}
+
+lazy_static! {
+    static ref RNG: UPSafeCell<ChaCha20Rng> = unsafe {
+        UPSafeCell::new(ChaCha20Rng { key: [0; KEY_LEN], output: 0, hardware: false })
+    };
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// One ChaCha20 block of `key` at `counter`, the nonce is always zero
+/// since every key is used for one request only
+fn chacha20_block(key: &[u8; KEY_LEN], counter: u32) -> [u8; BLOCK_LEN] {
+    let mut input = [0u32; 16];
+    // "expand 32-byte k"
+    input[..4].copy_from_slice(&[0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574]);
+    for (i, word) in key.chunks(4).enumerate() {
+        input[4 + i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    input[12] = counter;
+
+    let mut state = input;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+    let mut block = [0u8; BLOCK_LEN];
+    for i in 0..16 {
+        block[i * 4..i * 4 + 4].copy_from_slice(&state[i].wrapping_add(input[i]).to_le_bytes());
+    }
+    block
+}
+
+impl ChaCha20Rng {
+    /// Fill at most `CHUNK` bytes of `buf` and replace the key
+    fn generate(&mut self, buf: &mut [u8]) {
+        let key = self.key;
+        let first = chacha20_block(&key, 0);
+        self.key.copy_from_slice(&first[..KEY_LEN]);
+        let (head, rest) = buf.split_at_mut(buf.len().min(BLOCK_LEN - KEY_LEN));
+        head.copy_from_slice(&first[KEY_LEN..KEY_LEN + head.len()]);
+        for (counter, chunk) in rest.chunks_mut(BLOCK_LEN).enumerate() {
+            let block = chacha20_block(&key, counter as u32 + 1);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        self.output += buf.len();
+    }
+
+    /// Mix `data` into the key, every 32 bytes go through a rekey so
+    /// that the key depends on all of them
+    fn mix(&mut self, data: &[u8]) {
+        for chunk in data.chunks(KEY_LEN) {
+            for (key, byte) in self.key.iter_mut().zip(chunk) {
+                *key ^= byte;
+            }
+            let block = chacha20_block(&self.key, 0);
+            self.key.copy_from_slice(&block[..KEY_LEN]);
+        }
+    }
+}
+
+/// Seed from the hardware source, false if there is none or it didn't
+/// give a whole key. What it did give is mixed in either way.
+fn seed_from_device(rng: &mut ChaCha20Rng) -> bool {
+    let device = match registry::rng_device(0) {
+        Some(device) => device,
+        None => return false,
+    };
+    let mut seed = [0u8; KEY_LEN];
+    let mut filled = 0;
+    let mut empty = 0;
+    while filled < KEY_LEN && empty < SEED_ATTEMPTS {
+        match device.fill(&mut seed[filled..]) {
+            0 => empty += 1,
+            size => filled += size,
+        }
+    }
+    rng.mix(&seed[..filled]);
+    rng.output = 0;
+    if filled < KEY_LEN {
+        return false;
+    }
+    rng.hardware = true;
+    true
+}
+
+fn seed_from_jitter(rng: &mut ChaCha20Rng) {
+    for _ in 0..JITTER_SAMPLES {
+        let start = get_time();
+        // how long the block takes varies with caches and the host
+        let block = chacha20_block(&rng.key, start as u32);
+        let sample = get_time().wrapping_sub(start) ^ block[0] as usize;
+        rng.mix(&sample.to_le_bytes());
+    }
+}
+
+/// Seed the generator, after the drivers are bound
+pub fn init() {
+    let mut rng = RNG.exclusive_access();
+    if seed_from_device(&mut rng) {
+        println!("[kernel] random: seeded from rng0");
+        return;
+    }
+    if registry::rng_device(0).is_some() {
+        println!("[kernel] random: rng0 gives no entropy, seeding from timer jitter");
+    } else {
+        println!("[kernel] random: no entropy source, seeding from timer jitter");
+    }
+    seed_from_jitter(&mut rng);
+}
+
+/// Fill `buf` with random bytes
+pub fn fill(buf: &mut [u8]) {
+    let mut rng = RNG.exclusive_access();
+    for chunk in buf.chunks_mut(CHUNK) {
+        if rng.hardware && rng.output >= RESEED_BYTES {
+            seed_from_device(&mut rng);
+        }
+        rng.generate(chunk);
+    }
+}
+
+/// Mix bytes from elsewhere into the key, what user space writes to
+/// `/dev/urandom`
+pub fn add_entropy(data: &[u8]) {
+    RNG.exclusive_access().mix(data);
+}
+
+pub fn next_u32() -> u32 {
+    let mut bytes = [0u8; 4];
+    fill(&mut bytes);
+    u32::from_le_bytes(bytes)
+}
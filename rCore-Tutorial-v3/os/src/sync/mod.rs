@@ -1,4 +1,6 @@
 //! Synchronization and interior mutability primitives
+mod sleep;
 mod up;
 
+pub use sleep::SleepLock;
 pub use up::UPSafeCell;
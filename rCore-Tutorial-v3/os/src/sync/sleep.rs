@@ -0,0 +1,39 @@
+//! A lock that lets other tasks run while it is taken
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::task::{current_task, suspend_current_and_run_next};
+
+/// Guards code that may yield, like waiting for a disk, where a spinning
+/// lock would never be released on a single hart
+pub struct SleepLock {
+    locked: AtomicBool,
+}
+
+pub struct SleepLockGuard<'a> {
+    lock: &'a SleepLock,
+}
+
+impl SleepLock {
+    pub const fn new() -> Self {
+        Self { locked: AtomicBool::new(false) }
+    }
+
+    /// Yield until the lock is free. Before the first task runs nobody
+    /// else can hold it.
+    pub fn lock(&self) -> SleepLockGuard<'_> {
+        while self.locked.swap(true, Ordering::Acquire) {
+            if current_task().is_some() {
+                suspend_current_and_run_next();
+            } else {
+                core::hint::spin_loop();
+            }
+        }
+        SleepLockGuard { lock: self }
+    }
+}
+
+impl Drop for SleepLockGuard<'_> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+    }
+}
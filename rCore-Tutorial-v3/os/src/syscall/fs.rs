@@ -1,7 +1,9 @@
 //! File and filesystem-related syscalls
-use crate::fs::{open_file, OpenFlags};
-use crate::mm::{translated_byte_buffer, translated_str, UserBuffer};
+use crate::config::MAX_FD;
+use crate::fs::{make_pipe, mount_image, open_device, open_file, File, OpenFlags};
+use crate::mm::{translated_byte_buffer, translated_refmut, translated_str, UserBuffer};
 use crate::task::{current_task, current_user_token};
+use alloc::sync::Arc;
 
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
@@ -47,7 +49,11 @@ pub fn sys_open(path: *const u8, flags: u32) -> isize {
     let task = current_task().unwrap();
     let token = current_user_token();
     let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
+    let file: Option<Arc<dyn File + Send + Sync>> = match open_device(path.as_str()) {
+        Some(device) => Some(device),
+        None => open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()).map(|inode| inode as _),
+    };
+    if let Some(inode) = file {
         let mut inner = task.inner_exclusive_access();
         let fd = inner.alloc_fd();
         inner.fd_table[fd] = Some(inode);
@@ -57,6 +63,19 @@ pub fn sys_open(path: *const u8, flags: u32) -> isize {
     }
 }
 
+/// Mount the FAT image file `image` on `target`, its files are then
+/// opened as `target/name`
+pub fn sys_mount(image: *const u8, target: *const u8) -> isize {
+    let token = current_user_token();
+    let image = translated_str(token, image);
+    let target = translated_str(token, target);
+    if mount_image(image.as_str(), target.as_str()) {
+        0
+    } else {
+        -1
+    }
+}
+
 pub fn sys_close(fd: usize) -> isize {
     let task = current_task().unwrap();
     let mut inner = task.inner_exclusive_access();
@@ -69,3 +88,70 @@ pub fn sys_close(fd: usize) -> isize {
     inner.fd_table[fd].take();
     0
 }
+
+/// Create a pipe, write the read end fd to `pipe[0]` and the write end fd to `pipe[1]`
+pub fn sys_pipe(pipe: *mut usize) -> isize {
+    let task = current_task().unwrap();
+    let token = current_user_token();
+    let mut inner = task.inner_exclusive_access();
+    let (pipe_read, pipe_write) = make_pipe();
+    let read_fd = inner.alloc_fd();
+    inner.fd_table[read_fd] = Some(pipe_read);
+    let write_fd = inner.alloc_fd();
+    inner.fd_table[write_fd] = Some(pipe_write);
+    *translated_refmut(token, pipe) = read_fd;
+    *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
+    0
+}
+
+/// Duplicate `fd` into the lowest free fd
+pub fn sys_dup(fd: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if inner.fd_table[fd].is_none() {
+        return -1;
+    }
+    let new_fd = inner.alloc_fd();
+    inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
+    new_fd as isize
+}
+
+/// Duplicate `old_fd` into `new_fd`, closing whatever `new_fd` referred to
+pub fn sys_dup2(old_fd: usize, new_fd: usize) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if old_fd >= inner.fd_table.len() {
+        return -1;
+    }
+    let file = match &inner.fd_table[old_fd] {
+        Some(file) => file.clone(),
+        None => return -1,
+    };
+    if old_fd == new_fd {
+        return new_fd as isize;
+    }
+    if new_fd >= MAX_FD {
+        return -1;
+    }
+    while inner.fd_table.len() <= new_fd {
+        inner.fd_table.push(None);
+    }
+    let old_file = inner.fd_table[new_fd].replace(file);
+    // release the TCB before the replaced file gets dropped
+    drop(inner);
+    drop(old_file);
+    new_fd as isize
+}
+
+/// Fill `buf` from the kernel CSPRNG, `flags` are accepted and ignored
+/// since it never blocks once seeded
+pub fn sys_getrandom(buf: *const u8, len: usize, _flags: u32) -> isize {
+    let token = current_user_token();
+    for buffer in translated_byte_buffer(token, buf, len) {
+        crate::random::fill(buffer);
+    }
+    len as isize
+}
@@ -9,17 +9,25 @@
 //! For clarity, each single syscall is implemented as its own function, named
 //! `sys_` then the name of the syscall. You can find functions like this in
 //! submodules, and you should also implement syscalls this way.
+// dup and dup2 take the numbers of linux's dup and dup3
+const SYSCALL_DUP: usize = 23;
+const SYSCALL_DUP2: usize = 24;
+const SYSCALL_MOUNT: usize = 40;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_PIPE: usize = 59;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_SETUID: usize = 146;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETUID: usize = 174;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_GETRANDOM: usize = 278;
 
 mod fs;
 mod process;
@@ -27,23 +35,43 @@ mod process;
 use fs::*;
 use process::*;
 
-use crate::net::{SYS_CONNECT, syscall::sys_connect};
+use crate::net::{SYS_CONNECT, SYS_CONNECT6, SYS_SOCKET, SYS_SOCK_CONNECT, SYS_ACCEPT, SYS_SENDTO, SYS_RECVFROM, SYS_SHUTDOWN, SYS_BIND, SYS_LISTEN, SYS_FIREWALL, SYS_VLAN, SYS_SETSOCKOPT};
+use crate::net::syscall::{sys_connect, sys_connect6, sys_socket, sys_sock_connect, sys_accept, sys_sendto, sys_recvfrom, sys_shutdown, sys_bind, sys_listen, sys_firewall, sys_vlan, sys_setsockopt};
 /// handle syscall exception with `syscall_id` and other arguments
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
     match syscall_id {
+        SYSCALL_DUP => sys_dup(args[0]),
+        SYSCALL_DUP2 => sys_dup2(args[0], args[1]),
+        SYSCALL_MOUNT => sys_mount(args[0] as *const u8, args[1] as *const u8),
         SYSCALL_OPEN => sys_open(args[0] as *const u8, args[1] as u32),
         SYSCALL_CLOSE => sys_close(args[0]),
+        SYSCALL_PIPE => sys_pipe(args[0] as *mut usize),
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
         SYSCALL_YIELD => sys_yield(),
         SYSCALL_GET_TIME => sys_get_time(),
         SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_GETUID => sys_getuid(),
+        SYSCALL_SETUID => sys_setuid(args[0]),
         SYSCALL_FORK => sys_fork(),
-        SYSCALL_EXEC => sys_exec(args[0] as *const u8),
+        SYSCALL_EXEC => sys_exec(args[0] as *const u8, args[1] as *const usize),
         SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_GETRANDOM => sys_getrandom(args[0] as *const u8, args[1], args[2] as u32),
         // NET SYSCALL
         SYS_CONNECT => sys_connect(args[0] as _, args[1] as _, args[2] as _),
+        SYS_CONNECT6 => sys_connect6(args[0] as _, args[1] as _, args[2] as _),
+        SYS_SOCKET => sys_socket(args[0], args[1], args[2]),
+        SYS_SOCK_CONNECT => sys_sock_connect(args[0], args[1] as _, args[2]),
+        SYS_ACCEPT => sys_accept(args[0], args[1] as _, args[2] as _),
+        SYS_SENDTO => sys_sendto(args[0], args[1] as _, args[2], args[3], args[4] as _, args[5]),
+        SYS_RECVFROM => sys_recvfrom(args[0], args[1] as _, args[2], args[3], args[4] as _, args[5] as _),
+        SYS_SHUTDOWN => sys_shutdown(args[0]),
+        SYS_SETSOCKOPT => sys_setsockopt(args[0], args[1], args[2], args[3] as *const u8, args[4]),
+        SYS_FIREWALL => sys_firewall(args[0], args[1], args[2] as *mut u8, args[3]),
+        SYS_VLAN => sys_vlan(args[0], args[1], args[2] as *mut u8, args[3]),
+        SYS_BIND => sys_bind(args[0], args[1] as _, args[2]),
+        SYS_LISTEN => sys_listen(args[0], args[1]),
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     }
 }
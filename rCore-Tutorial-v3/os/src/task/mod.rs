@@ -30,6 +30,7 @@ use lazy_static::*;
 pub use manager::{fetch_task, TaskManager};
 use switch::__switch;
 use task::{TaskControlBlock, TaskStatus};
+pub use task::ROOT_UID;
 
 pub use manager::add_task;
 pub use pid::{pid_alloc, KernelStack, PidAllocator, PidHandle};
@@ -102,7 +103,10 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     inner.children.clear();
     // deallocate user space
     inner.memory_set.recycle_data_pages();
+    // close file descriptors, so that pipe readers and socket peers see EOF
+    let fd_table = core::mem::take(&mut inner.fd_table);
     drop(inner);
+    drop(fd_table);
     // **** release current PCB
     // drop task manually to maintain rc correctly
     drop(task);
@@ -14,7 +14,7 @@
 mod context;
 
 use crate::config::{TRAMPOLINE, TRAP_CONTEXT};
-// use crate::pci::e1000::NET_DEVICE;
+use crate::{imsic, plic};
 use crate::syscall::syscall;
 use crate::task::{
     current_trap_cx, current_user_token, exit_current_and_run_next, suspend_current_and_run_next, TaskContext,
@@ -72,7 +72,10 @@ pub fn trap_handler() -> ! {
             let mut cx = current_trap_cx();
             cx.sepc += 4;
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]);
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
@@ -102,19 +105,7 @@ pub fn trap_handler() -> ! {
             suspend_current_and_run_next();
         }
         Trap::Interrupt(Interrupt::SupervisorExternal) => {
-            // let irq = plic_claim();
-            // println!("irq: {:?}", irq);
-            // let mut net_container = NET_DEVICE.exclusive_access();
-            // let net = net_container.as_mut().unwrap();
-            // net.handle_interrupt();         
-            // let value = net.receive();
-            // if let Some(arr) = value {
-            //     hexdump(&arr);
-            //     net.send(&arr);
-            // }
-
-
-            // plic_complete(irq.unwrap());
+            handle_external();
         }
         _ => {
             panic!(
@@ -153,11 +144,24 @@ pub fn trap_return() -> ! {
     }
 }
 
+/// Machines with AIA have MSIs in the IMSIC instead of a PLIC
+fn handle_external() {
+    if imsic::present() {
+        imsic::handle_external();
+    } else {
+        plic::handle_external();
+    }
+}
+
 #[no_mangle]
 /// Unimplement: traps/interrupts/exceptions from kernel mode
 /// Todo: Chapter 9: I/O device
 pub fn trap_from_kernel(context: &mut TrapContext, scause: Scause, stval: usize) {
     use riscv::register::sepc;
+    if let Trap::Interrupt(Interrupt::SupervisorExternal) = scause.cause() {
+        handle_external();
+        return;
+    }
     println!("stval = {:#x}, sepc = {:#x}, scause = {:?}", stval, sepc::read(), scause.cause());
     match scause.cause() {
         Trap::Exception(Exception::Breakpoint) => context.sepc += 2,
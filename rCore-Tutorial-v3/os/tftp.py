@@ -0,0 +1,56 @@
+import os
+import socket
+import struct
+import sys
+
+# read-only tftp server (RFC 1350) for files in the directory given as
+# the second argument, octet mode only
+OP_RRQ, OP_DATA, OP_ACK, OP_ERROR = 1, 3, 4, 5
+BLOCK_SIZE = 512
+
+port = int(sys.argv[1])
+root = sys.argv[2] if len(sys.argv) > 2 else "."
+
+sock = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+sock.bind(('localhost', port))
+
+
+def send_file(raddr, path):
+        # every transfer gets its own port, the transfer id of the rfc
+        conn = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+        conn.settimeout(1)
+        try:
+                data = open(path, "rb").read()
+        except OSError:
+                conn.sendto(struct.pack("!HH", OP_ERROR, 1) + b"file not found\0", raddr)
+                return
+        block = 1
+        while True:
+                chunk = data[(block - 1) * BLOCK_SIZE:block * BLOCK_SIZE]
+                for _ in range(5):
+                        conn.sendto(struct.pack("!HH", OP_DATA, block & 0xffff) + chunk, raddr)
+                        try:
+                                buf, _ = conn.recvfrom(4)
+                        except socket.timeout:
+                                continue
+                        opcode, acked = struct.unpack("!HH", buf[:4])
+                        if opcode == OP_ACK and acked == block & 0xffff:
+                                break
+                else:
+                        print("transfer timed out", file=sys.stderr)
+                        return
+                if len(chunk) < BLOCK_SIZE:
+                        print("sent %s, %d bytes" % (path, len(data)), file=sys.stderr)
+                        return
+                block += 1
+
+
+print("serving %s over tftp..." % root, file=sys.stderr)
+while True:
+        buf, raddr = sock.recvfrom(4096)
+        opcode = struct.unpack("!H", buf[:2])[0]
+        if opcode != OP_RRQ:
+                continue
+        name = buf[2:].split(b"\0")[0].decode("utf-8")
+        print("read request for " + name, file=sys.stderr)
+        send_file(raddr, os.path.join(root, os.path.basename(name)))
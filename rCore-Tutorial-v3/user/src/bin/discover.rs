@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+
+/// The mDNS group and port
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const PORT: u16 = 5353;
+
+const QUERY: &[u8] = b"WHO?";
+const ANSWER: &str = "HERE ";
+
+const USAGE: &str = "usage: discover name [peers]";
+
+// mDNS-style discovery: announce `name` to the group, answer queries and
+// print the other hosts that answer until `peers` of them were found.
+// Run it on two guests that share a multicast netdev, see NET=mcast in
+// os/Makefile.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        println!("{}", USAGE);
+        return -1;
+    }
+    let name = argv[1];
+    let peers: usize = match argv.get(2).map(|peers| peers.parse()) {
+        None => 1,
+        Some(Ok(peers)) => peers,
+        Some(Err(_)) => {
+            println!("{}", USAGE);
+            return -1;
+        }
+    };
+
+    let socket = match UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT)) {
+        Ok(socket) => socket,
+        Err(_) => {
+            println!("[discover] can't bind port {}", PORT);
+            return -1;
+        }
+    };
+    if socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED).is_err() {
+        println!("[discover] can't join {}", GROUP);
+        return -1;
+    }
+    let group = SocketAddrV4::new(GROUP, PORT);
+    let answer = format!("{}{}", ANSWER, name);
+    let _ = socket.send_to(answer.as_bytes(), group);
+    let _ = socket.send_to(QUERY, group);
+
+    let mut found: Vec<String> = Vec::new();
+    let mut buf = [0u8; 512];
+    while found.len() < peers {
+        let (len, source) = match socket.recv_from(&mut buf) {
+            Ok(datagram) => datagram,
+            Err(_) => break,
+        };
+        let message = &buf[..len];
+        if message == QUERY {
+            let _ = socket.send_to(answer.as_bytes(), group);
+            continue;
+        }
+        let peer = match core::str::from_utf8(message).ok().and_then(|m| m.strip_prefix(ANSWER)) {
+            // our own answers come back too
+            Some(peer) if peer != name => peer,
+            _ => continue,
+        };
+        if !found.iter().any(|known| known == peer) {
+            println!("[discover] found {} at {}", peer, source);
+            found.push(String::from(peer));
+        }
+    }
+    let _ = socket.leave_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED);
+    0
+}
@@ -0,0 +1,219 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::firewall::{
+    self, FwRule, FW_ACTION_ACCEPT, FW_ACTION_DROP, FW_ACTION_REJECT, FW_ANY_INTERFACE, FW_CHAIN_INPUT,
+    FW_CHAIN_OUTPUT,
+};
+use user_lib::net::{Ipv4Addr, Ipv6Addr};
+
+const USAGE: &str = "usage: fw list
+       fw flush
+       fw del <n>
+       fw add <input|output> [options] -j <accept|drop|reject>
+       fw insert <n> <input|output> [options] -j <accept|drop|reject>
+options: -i ethN, -p tcp|udp|icmp|icmpv6|<number>, -s addr[/prefix], -d addr[/prefix],
+         --sport port[:port], --dport port[:port]";
+
+fn protocol_name(protocol: u8) -> String {
+    match protocol {
+        0 => String::from("all"),
+        1 => String::from("icmp"),
+        6 => String::from("tcp"),
+        17 => String::from("udp"),
+        58 => String::from("icmpv6"),
+        _ => format!("{}", protocol),
+    }
+}
+
+fn parse_protocol(name: &str) -> Option<u8> {
+    match name {
+        "all" => Some(0),
+        "icmp" => Some(1),
+        "tcp" => Some(6),
+        "udp" => Some(17),
+        "icmpv6" => Some(58),
+        _ => name.parse().ok(),
+    }
+}
+
+/// Parse `addr[/prefix]` into family, address bytes and prefix length
+fn parse_cidr(s: &str) -> Option<(u8, [u8; 16], u8)> {
+    let (addr, prefix) = match s.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix.parse::<u8>().ok()?)),
+        None => (s, None),
+    };
+    let mut octets = [0u8; 16];
+    if let Ok(addr) = addr.parse::<Ipv4Addr>() {
+        octets[..4].copy_from_slice(&addr.octets());
+        let prefix = prefix.unwrap_or(32);
+        return if prefix <= 32 { Some((4, octets, prefix)) } else { None };
+    }
+    let addr = addr.parse::<Ipv6Addr>().ok()?;
+    octets.copy_from_slice(&addr.octets());
+    let prefix = prefix.unwrap_or(128);
+    if prefix <= 128 {
+        Some((6, octets, prefix))
+    } else {
+        None
+    }
+}
+
+fn format_cidr(family: u8, addr: &[u8; 16], prefix: u8) -> String {
+    match family {
+        4 if prefix == 0 => String::from("0.0.0.0/0"),
+        6 if prefix == 0 => String::from("::/0"),
+        4 => format!("{}/{}", Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]), prefix),
+        6 => format!("{}/{}", Ipv6Addr::from(*addr), prefix),
+        _ => String::from("any"),
+    }
+}
+
+/// Parse `port` or `first:last`
+fn parse_ports(s: &str) -> Option<[u16; 2]> {
+    match s.split_once(':') {
+        Some((first, last)) => Some([first.parse().ok()?, last.parse().ok()?]),
+        None => {
+            let port = s.parse().ok()?;
+            Some([port, port])
+        }
+    }
+}
+
+fn format_ports(ports: &[u16; 2]) -> String {
+    if *ports == [0, u16::MAX] {
+        String::from("*")
+    } else if ports[0] == ports[1] {
+        format!("{}", ports[0])
+    } else {
+        format!("{}:{}", ports[0], ports[1])
+    }
+}
+
+fn parse_rule(args: &[&str]) -> Option<FwRule> {
+    let chain = match *args.first()? {
+        "input" => FW_CHAIN_INPUT,
+        "output" => FW_CHAIN_OUTPUT,
+        _ => return None,
+    };
+    let mut rule = FwRule::new(chain, FW_ACTION_ACCEPT);
+    let mut action = None;
+    let mut options = args[1..].iter();
+    while let Some(option) = options.next() {
+        let value = *options.next()?;
+        match *option {
+            "-i" => rule.interface = value.strip_prefix("eth")?.parse().ok()?,
+            "-p" => rule.protocol = parse_protocol(value)?,
+            "-s" | "-d" => {
+                let (family, addr, prefix) = parse_cidr(value)?;
+                if rule.family != 0 && rule.family != family {
+                    return None;
+                }
+                rule.family = family;
+                if *option == "-s" {
+                    rule.src_addr = addr;
+                    rule.src_prefix = prefix;
+                } else {
+                    rule.dst_addr = addr;
+                    rule.dst_prefix = prefix;
+                }
+            }
+            "--sport" => rule.sport = parse_ports(value)?,
+            "--dport" => rule.dport = parse_ports(value)?,
+            "-j" => {
+                action = Some(match value {
+                    "accept" => FW_ACTION_ACCEPT,
+                    "drop" => FW_ACTION_DROP,
+                    "reject" => FW_ACTION_REJECT,
+                    _ => return None,
+                })
+            }
+            _ => return None,
+        }
+    }
+    rule.action = action?;
+    Some(rule)
+}
+
+fn list() {
+    println!(
+        "{:>3} {:<6} {:<6} {:<6} {:<24} {:<24} {:<11} {:<11} {:<6} {:>8}",
+        "num", "chain", "iface", "proto", "source", "destination", "sport", "dport", "action", "hits"
+    );
+    for (index, rule) in firewall::list().iter().enumerate() {
+        let chain = if rule.chain == FW_CHAIN_INPUT { "input" } else { "output" };
+        let interface = if rule.interface == FW_ANY_INTERFACE {
+            String::from("*")
+        } else {
+            format!("eth{}", rule.interface)
+        };
+        let action = match rule.action {
+            FW_ACTION_ACCEPT => "accept",
+            FW_ACTION_DROP => "drop",
+            _ => "reject",
+        };
+        println!(
+            "{:>3} {:<6} {:<6} {:<6} {:<24} {:<24} {:<11} {:<11} {:<6} {:>8}",
+            index,
+            chain,
+            interface,
+            protocol_name(rule.protocol),
+            format_cidr(rule.family, &rule.src_addr, rule.src_prefix),
+            format_cidr(rule.family, &rule.dst_addr, rule.dst_prefix),
+            format_ports(&rule.sport),
+            format_ports(&rule.dport),
+            action,
+            rule.hits
+        );
+    }
+}
+
+// Manage the kernel packet filter, rules are evaluated in order and the
+// first match decides. Unmatched packets are accepted.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        println!("{}", USAGE);
+        return -1;
+    }
+    let ret = match (argv[1], &argv[2..]) {
+        ("list", []) => {
+            list();
+            0
+        }
+        ("flush", []) => firewall::flush(),
+        ("del", [index]) => match index.parse() {
+            Ok(index) => firewall::delete(index),
+            Err(_) => -1,
+        },
+        ("add", rule) => match parse_rule(rule) {
+            Some(rule) => firewall::append(&rule),
+            None => {
+                println!("{}", USAGE);
+                return -1;
+            }
+        },
+        ("insert", [index, rule @ ..]) => match (index.parse(), parse_rule(rule)) {
+            (Ok(index), Some(rule)) => firewall::insert(index, &rule),
+            _ => {
+                println!("{}", USAGE);
+                return -1;
+            }
+        },
+        _ => {
+            println!("{}", USAGE);
+            return -1;
+        }
+    };
+    if ret < 0 {
+        println!("[fw] the kernel refused the request, only root may change rules");
+        return -1;
+    }
+    0
+}
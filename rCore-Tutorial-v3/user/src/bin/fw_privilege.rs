@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{exec, firewall, fork, setuid, waitpid};
+
+const RULE: &[&str] = &["add", "input", "-p", "udp", "--dport", "9", "-j", "drop"];
+const GUEST_UID: usize = 1000;
+
+/// Run `fw` with `args` the way the shell does, as `uid` if given
+fn fw(args: &[&str], uid: Option<usize>) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        if let Some(uid) = uid {
+            setuid(uid);
+        }
+        let mut strings: Vec<String> = Vec::new();
+        for arg in core::iter::once(&"fw").chain(args.iter()) {
+            let mut string = String::from(*arg);
+            string.push('\0');
+            strings.push(string);
+        }
+        let mut argv: Vec<*const u8> = strings.iter().map(|string| string.as_ptr()).collect();
+        argv.push(core::ptr::null::<u8>());
+        exec("fw\0", &argv);
+        panic!("can't exec fw");
+    }
+    let mut exit_code: i32 = 0;
+    waitpid(pid as usize, &mut exit_code);
+    exit_code
+}
+
+// A shell started by initproc is root and its fw changes the rules, one
+// that gave up root, like the shells of telnetd, can't
+#[no_mangle]
+pub fn main() -> i32 {
+    let rules = firewall::list().len();
+    assert_eq!(fw(RULE, None), 0, "root can't add a rule");
+    assert_eq!(firewall::list().len(), rules + 1);
+
+    assert_ne!(fw(RULE, Some(GUEST_UID)), 0, "uid {} added a rule", GUEST_UID);
+    assert_eq!(firewall::list().len(), rules + 1);
+
+    let index = format!("{}", rules);
+    assert_eq!(fw(&["del", index.as_str()], None), 0, "root can't delete a rule");
+    assert_eq!(firewall::list().len(), rules);
+    println!("fw_privilege passed!");
+    0
+}
@@ -0,0 +1,207 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::net::{Ipv4Addr, SocketAddrV4, TcpListener, TcpStream};
+use user_lib::{close, fork, open, read, try_waitpid, OpenFlags};
+
+/// The guest side of the tcp `hostfwd` in os/Makefile
+const DEFAULT_PORT: u16 = 2000;
+/// Longest request header we accept
+const REQUEST_MAX: usize = 4096;
+
+fn mime_type(name: &str) -> &'static str {
+    let extension = match name.rsplit_once('.') {
+        Some((_, extension)) => extension,
+        None => "",
+    };
+    match extension.to_ascii_lowercase().as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "txt" | "rs" | "md" => "text/plain; charset=utf-8",
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "json" => "application/json",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "ico" => "image/x-icon",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+fn send_response(stream: &TcpStream, status: &str, content_type: &str, content_length: usize) {
+    let header = format!(
+        "HTTP/1.1 {}\r\nServer: rCore-httpd\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, content_type, content_length
+    );
+    let _ = stream.write_all(header.as_bytes());
+}
+
+fn send_error(stream: &TcpStream, status: &str, head_only: bool) {
+    let body = format!("<html><body><h1>{}</h1></body></html>\n", status);
+    send_response(stream, status, "text/html; charset=utf-8", body.len());
+    if !head_only {
+        let _ = stream.write_all(body.as_bytes());
+    }
+}
+
+/// Map a request path to a file in the root directory, which has no subdirectories
+fn file_name(path: &str) -> Option<String> {
+    let path = path.split('?').next().unwrap();
+    let name = match path.strip_prefix('/')? {
+        "" => "index.html",
+        name => name,
+    };
+    if name.contains('/') || name.starts_with('.') {
+        return None;
+    }
+    let mut name = String::from(name);
+    name.push('\0');
+    Some(name)
+}
+
+/// Size of a file, found by reading it once since there is no stat
+fn file_size(name: &str) -> Option<usize> {
+    let fd = open(name, OpenFlags::RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let mut buf = [0u8; 1024];
+    let mut size = 0;
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 {
+            break;
+        }
+        size += len as usize;
+    }
+    close(fd as usize);
+    Some(size)
+}
+
+fn send_file(stream: &TcpStream, name: &str) {
+    let fd = open(name, OpenFlags::RDONLY);
+    if fd < 0 {
+        return;
+    }
+    let mut buf = [0u8; 1024];
+    loop {
+        let len = read(fd as usize, &mut buf);
+        if len <= 0 || stream.write_all(&buf[..len as usize]).is_err() {
+            break;
+        }
+    }
+    close(fd as usize);
+}
+
+/// Read up to the end of the request header, the body of a GET is ignored
+fn read_request(stream: &TcpStream) -> Option<String> {
+    let mut request: Vec<u8> = Vec::new();
+    let mut buf = [0u8; 512];
+    loop {
+        let len = stream.read(&mut buf).ok()?;
+        if len == 0 {
+            return None;
+        }
+        request.extend_from_slice(&buf[..len]);
+        if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+            request.truncate(end);
+            return String::from_utf8(request).ok();
+        }
+        if request.len() > REQUEST_MAX {
+            return None;
+        }
+    }
+}
+
+fn serve(stream: TcpStream) {
+    let request = match read_request(&stream) {
+        Some(request) => request,
+        None => {
+            send_error(&stream, "400 Bad Request", false);
+            return;
+        }
+    };
+    let mut request_line = request.split("\r\n").next().unwrap().split(' ');
+    let (method, path) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(path)) => (method, path),
+        _ => {
+            send_error(&stream, "400 Bad Request", false);
+            return;
+        }
+    };
+    let head_only = match method {
+        "GET" => false,
+        "HEAD" => true,
+        _ => {
+            println!("[httpd] {} {} 501", method, path);
+            send_error(&stream, "501 Not Implemented", false);
+            return;
+        }
+    };
+
+    let found = file_name(path).and_then(|name| file_size(&name).map(|size| (name, size)));
+    match found {
+        Some((name, size)) => {
+            println!("[httpd] {} {} 200", method, path);
+            send_response(&stream, "200 OK", mime_type(name.trim_end_matches('\0')), size);
+            if !head_only {
+                send_file(&stream, &name);
+            }
+        }
+        None => {
+            println!("[httpd] {} {} 404", method, path);
+            send_error(&stream, "404 Not Found", head_only);
+        }
+    }
+}
+
+// Serve files of the root directory over HTTP, one forked process per connection
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let port = if argc > 1 {
+        match argv[1].parse() {
+            Ok(port) => port,
+            Err(_) => {
+                println!("usage: httpd [port]");
+                return -1;
+            }
+        }
+    } else {
+        DEFAULT_PORT
+    };
+    let listener = match TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)) {
+        Ok(listener) => listener,
+        Err(_) => {
+            println!("[httpd] can't listen on port {}", port);
+            return -1;
+        }
+    };
+    println!("[httpd] listening on port {}", port);
+
+    loop {
+        let (stream, peer) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(_) => continue,
+        };
+        println!("[httpd] connection from {}", peer);
+        if fork() == 0 {
+            serve(stream);
+            return 0;
+        }
+        // the child owns the connection now
+        drop(stream);
+
+        // reap the children that are done
+        let mut exit_code: i32 = 0;
+        while try_waitpid(-1, &mut exit_code) > 0 {}
+    }
+}
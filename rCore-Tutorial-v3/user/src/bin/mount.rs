@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use user_lib::mount;
+
+const USAGE: &str = "usage: mount image dir";
+
+// Mount a FAT image file of the root volume, `dir/file` opens its files
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc != 3 {
+        println!("{}", USAGE);
+        return -1;
+    }
+    let mut image = String::from(argv[1]);
+    image.push('\0');
+    let mut target = String::from(argv[2]);
+    target.push('\0');
+    if mount(image.as_str(), target.as_str()) < 0 {
+        println!("[mount] can't mount {} on {}, it has to be a FAT image and {} unused", argv[1], argv[2], argv[2]);
+        return -1;
+    }
+    println!("[mount] {} on {}", argv[1], argv[2]);
+    0
+}
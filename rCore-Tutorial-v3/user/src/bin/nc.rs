@@ -0,0 +1,157 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use user_lib::console::getchar;
+use user_lib::net::{Ipv4Addr, SocketAddrV4, TcpListener, TcpStream, UdpSocket};
+use user_lib::{fork, try_waitpid, waitpid, write};
+
+const STDOUT: usize = 1;
+
+const LF: u8 = 0x0au8;
+const CR: u8 = 0x0du8;
+const DL: u8 = 0x7fu8;
+const BS: u8 = 0x08u8;
+/// ctrl-d ends the input
+const EOT: u8 = 0x04u8;
+
+const USAGE: &str = "usage: nc [-u] host port\n       nc [-u] -l port";
+
+enum Connection {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+impl Connection {
+    fn send(&self, data: &[u8]) -> bool {
+        match self {
+            Connection::Tcp(stream) => stream.write_all(data).is_ok(),
+            Connection::Udp(socket) => socket.send(data).is_ok(),
+        }
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> usize {
+        match self {
+            Connection::Tcp(stream) => stream.read(buf).unwrap_or(0),
+            Connection::Udp(socket) => socket.recv(buf).unwrap_or(0),
+        }
+    }
+
+    fn shutdown(&self) {
+        let _ = match self {
+            Connection::Tcp(stream) => stream.shutdown(),
+            Connection::Udp(socket) => socket.shutdown(),
+        };
+    }
+}
+
+fn listen(udp: bool, port: u16) -> Option<Connection> {
+    let local = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port);
+    if udp {
+        // the first datagram picks the peer
+        let socket = UdpSocket::bind(local).ok()?;
+        let mut buf = [0u8; 1500];
+        let (len, peer) = socket.recv_from(&mut buf).ok()?;
+        write(STDOUT, &buf[..len]);
+        socket.connect(peer).ok()?;
+        Some(Connection::Udp(socket))
+    } else {
+        let listener = TcpListener::bind(local).ok()?;
+        let (stream, peer) = listener.accept().ok()?;
+        println!("[nc] connection from {}", peer);
+        Some(Connection::Tcp(stream))
+    }
+}
+
+fn connect(udp: bool, host: &str, port: u16) -> Option<Connection> {
+    if udp {
+        let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+        socket.connect((host, port)).ok()?;
+        Some(Connection::Udp(socket))
+    } else {
+        TcpStream::connect((host, port)).ok().map(Connection::Tcp)
+    }
+}
+
+#[no_mangle]
+pub fn main(_argc: usize, argv: &[&str]) -> i32 {
+    let mut udp = false;
+    let mut listen_mode = false;
+    let mut positional = Vec::new();
+    for arg in argv.iter().skip(1) {
+        match *arg {
+            "-u" => udp = true,
+            "-l" => listen_mode = true,
+            _ => positional.push(*arg),
+        }
+    }
+
+    let connection = match (listen_mode, positional.as_slice()) {
+        (true, [port]) => port.parse().ok().and_then(|port| listen(udp, port)),
+        (false, [host, port]) => port.parse().ok().and_then(|port| connect(udp, host, port)),
+        _ => {
+            println!("{}", USAGE);
+            return -1;
+        }
+    };
+    let connection = match connection {
+        Some(connection) => connection,
+        None => {
+            println!("[nc] can't open connection");
+            return -1;
+        }
+    };
+
+    // the child copies the socket to stdout, we copy stdin to the socket
+    let pid = fork();
+    if pid == 0 {
+        let mut buf = [0u8; 1024];
+        loop {
+            let len = connection.recv(&mut buf);
+            if len == 0 {
+                return 0;
+            }
+            write(STDOUT, &buf[..len]);
+        }
+    }
+
+    let mut line: Vec<u8> = Vec::new();
+    let mut exit_code: i32 = 0;
+    loop {
+        let c = getchar();
+        if try_waitpid(pid, &mut exit_code) != -2 {
+            println!("\n[nc] connection closed by peer");
+            return 0;
+        }
+        match c {
+            EOT => break,
+            LF | CR => {
+                println!("");
+                line.push(LF);
+                if !connection.send(&line) {
+                    break;
+                }
+                line.clear();
+            }
+            BS | DL => {
+                if line.pop().is_some() {
+                    print!("{} {}", BS as char, BS as char);
+                }
+            }
+            _ => {
+                print!("{}", c as char);
+                line.push(c);
+            }
+        }
+    }
+    if !line.is_empty() {
+        connection.send(&line);
+    }
+    connection.shutdown();
+    waitpid(pid as usize, &mut exit_code);
+    0
+}
@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, dup, dup2, fork, pipe, read, wait, write};
+
+static STR: &str = "Hello, world!";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // create pipe
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    // read end
+    assert_eq!(pipe_fd[0], 3);
+    // write end
+    assert_eq!(pipe_fd[1], 4);
+    if fork() == 0 {
+        // child process, read from parent through stdin
+        close(pipe_fd[1]);
+        assert_eq!(dup2(pipe_fd[0], 0), 0);
+        close(pipe_fd[0]);
+        let mut buffer = [0u8; 32];
+        let len_read = read(0, &mut buffer) as usize;
+        assert_eq!(core::str::from_utf8(&buffer[..len_read]).unwrap(), STR);
+        // the parent has closed its write end, so this is end of file
+        assert_eq!(read(0, &mut buffer), 0);
+        println!("Read OK, child process exited!");
+        0
+    } else {
+        // parent process, write to child through a duplicated fd
+        close(pipe_fd[0]);
+        let write_fd = dup(pipe_fd[1]);
+        assert!(write_fd > 0);
+        close(pipe_fd[1]);
+        assert_eq!(write(write_fd as usize, STR.as_bytes()), STR.len() as isize);
+        close(write_fd as usize);
+        let mut child_exit_code: i32 = 0;
+        wait(&mut child_exit_code);
+        assert_eq!(child_exit_code, 0);
+        println!("pipetest passed!");
+        0
+    }
+}
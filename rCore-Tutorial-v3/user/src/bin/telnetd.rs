@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::net::{Ipv4Addr, SocketAddrV4, TcpListener};
+use user_lib::{close, dup2, exec, fork, setuid, try_waitpid};
+
+/// The guest side of the tcp `hostfwd` in os/Makefile
+const DEFAULT_PORT: u16 = 2000;
+/// What the remote shells run as, not root
+const GUEST_UID: usize = 1000;
+
+// Remote shell: every connection gets a user_shell whose stdin, stdout and
+// stderr are the socket. There is no telnet option negotiation, the bytes
+// go to the shell as they are, so connect from a raw terminal with
+// `make rshell` in os/ rather than with a telnet client.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let port = if argc > 1 {
+        match argv[1].parse() {
+            Ok(port) => port,
+            Err(_) => {
+                println!("usage: telnetd [port]");
+                return -1;
+            }
+        }
+    } else {
+        DEFAULT_PORT
+    };
+    let listener = match TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)) {
+        Ok(listener) => listener,
+        Err(_) => {
+            println!("[telnetd] can't listen on port {}", port);
+            return -1;
+        }
+    };
+    println!("[telnetd] listening on port {}", port);
+
+    loop {
+        let (stream, peer) = match listener.accept() {
+            Ok(connection) => connection,
+            Err(_) => continue,
+        };
+        println!("[telnetd] connection from {}", peer);
+        if fork() == 0 {
+            let fd = stream.as_raw_fd();
+            for std_fd in 0..3 {
+                dup2(fd, std_fd);
+            }
+            // the shell only needs the copies on 0, 1 and 2
+            close(fd);
+            close(listener.as_raw_fd());
+            // a shell from the network can't change the firewall
+            setuid(GUEST_UID);
+            exec("user_shell\0", &[core::ptr::null::<u8>()]);
+            return -1;
+        }
+        drop(stream);
+
+        let mut exit_code: i32 = 0;
+        while try_waitpid(-1, &mut exit_code) > 0 {}
+    }
+}
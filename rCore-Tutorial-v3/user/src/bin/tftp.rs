@@ -0,0 +1,127 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use user_lib::{close, open, write, OpenFlags};
+
+const TFTP_PORT: u16 = 69;
+const BLOCK_SIZE: usize = 512;
+
+const OP_RRQ: u16 = 1;
+const OP_DATA: u16 = 3;
+const OP_ACK: u16 = 4;
+const OP_ERROR: u16 = 5;
+
+const USAGE: &str = "usage: tftp host file [port]";
+
+fn read_request(file: &str) -> Vec<u8> {
+    let mut request = Vec::new();
+    request.extend_from_slice(&OP_RRQ.to_be_bytes());
+    request.extend_from_slice(file.as_bytes());
+    request.push(0);
+    request.extend_from_slice(b"octet");
+    request.push(0);
+    request
+}
+
+fn ack(block: u16) -> [u8; 4] {
+    let mut ack = [0u8; 4];
+    ack[..2].copy_from_slice(&OP_ACK.to_be_bytes());
+    ack[2..].copy_from_slice(&block.to_be_bytes());
+    ack
+}
+
+// Download `file` from a tftp server (RFC 1350) into the root directory.
+// There are no timeouts: if the request is lost, run it again. The server
+// resends data blocks whose ack got lost.
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 3 {
+        println!("{}", USAGE);
+        return -1;
+    }
+    let host: Ipv4Addr = match argv[1].parse() {
+        Ok(host) => host,
+        Err(_) => {
+            println!("{}", USAGE);
+            return -1;
+        }
+    };
+    let port = match argv.get(3).map(|port| port.parse()) {
+        None => TFTP_PORT,
+        Some(Ok(port)) => port,
+        Some(Err(_)) => {
+            println!("{}", USAGE);
+            return -1;
+        }
+    };
+    let file = argv[2];
+    let mut local = String::from(file.rsplit('/').next().unwrap());
+    local.push('\0');
+
+    let socket = match UdpSocket::bind("0.0.0.0:0") {
+        Ok(socket) => socket,
+        Err(_) => {
+            println!("[tftp] can't create socket");
+            return -1;
+        }
+    };
+    if socket.send_to(&read_request(file), (host, port)).is_err() {
+        println!("[tftp] can't send request");
+        return -1;
+    }
+
+    let fd = open(local.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    if fd < 0 {
+        println!("[tftp] can't create {}", local.trim_end_matches('\0'));
+        return -1;
+    }
+    let fd = fd as usize;
+
+    let mut buf = [0u8; 4 + BLOCK_SIZE];
+    let mut expected: u16 = 1;
+    let mut total = 0;
+    let result = loop {
+        let (len, peer) = match socket.recv_from(&mut buf) {
+            Ok(datagram) => datagram,
+            Err(_) => break -1,
+        };
+        // the server answers from a new port, but it must be the same host
+        let from_host = matches!(peer, SocketAddr::V4(addr) if *addr.ip() == host);
+        if len < 4 || !from_host {
+            continue;
+        }
+        let opcode = u16::from_be_bytes([buf[0], buf[1]]);
+        let block = u16::from_be_bytes([buf[2], buf[3]]);
+        match opcode {
+            OP_DATA => {
+                if block == expected {
+                    write(fd, &buf[4..len]);
+                    total += len - 4;
+                    expected = expected.wrapping_add(1);
+                }
+                let _ = socket.send_to(&ack(block), peer);
+                if block == expected.wrapping_sub(1) && len - 4 < BLOCK_SIZE {
+                    break 0;
+                }
+            }
+            OP_ERROR => {
+                let message = core::str::from_utf8(&buf[4..len]).unwrap_or("");
+                println!("[tftp] error {}: {}", block, message.trim_end_matches('\0'));
+                break -1;
+            }
+            _ => {}
+        }
+    };
+    close(fd);
+    if result == 0 {
+        println!("[tftp] received {} bytes", total);
+    }
+    result
+}
@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+use alloc::string::String;
+use user_lib::{connect6, write, read};
+
+#[macro_use]
+extern crate user_lib;
+#[macro_use]
+extern crate alloc;
+
+// slirp's address for the host on its default fec0::/64 prefix
+const HOST: [u8; 16] = [0xfe, 0xc0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+
+#[no_mangle]
+pub fn main() -> i32 {
+    println!("udp6 test open!");
+
+    let udp_fd = connect6(&HOST, 2001, 26099);
+
+    if udp_fd < 0 {
+        println!("failed to create udp6 connection.");
+        return -1;
+    }
+
+    let buf = "Hello rCoreOS user program over ipv6!";
+
+    write(udp_fd as usize, buf.as_bytes());
+
+    println!("udp6 send done");
+
+    let mut buf = vec![0u8; 1024];
+
+    let len = read(udp_fd as usize, &mut buf);
+
+    if len < 0 {
+        println!("can't receive udp6 packet");
+        return -1;
+    }
+
+    let recv_str = String::from_utf8_lossy(&buf[..len as usize]);
+
+    println!("{}", recv_str);
+
+    0
+}
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    accept, bind, close, exit, fork, listen, read, sock_connect, socket, wait, write, SockAddrUn,
+    AF_UNIX, SOCK_STREAM,
+};
+
+const SOCKET_PATH: &str = "unix_sock.test";
+const REQUEST: &str = "ping from client";
+const REPLY: &str = "pong from server";
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let server = socket(AF_UNIX, SOCK_STREAM, 0);
+    assert!(server >= 0);
+    let addr = SockAddrUn::new(SOCKET_PATH);
+    // bind and listen before fork so that the client can't race the server
+    assert_eq!(bind(server as usize, &addr), 0);
+    assert_eq!(listen(server as usize, 1), 0);
+
+    if fork() == 0 {
+        let client = socket(AF_UNIX, SOCK_STREAM, 0);
+        assert!(client >= 0);
+        assert_eq!(sock_connect(client as usize, &addr), 0);
+        write(client as usize, REQUEST.as_bytes());
+        let mut buf = [0u8; 32];
+        let len = read(client as usize, &mut buf);
+        assert_eq!(&buf[..len as usize], REPLY.as_bytes());
+        close(client as usize);
+        exit(0);
+    }
+
+    let conn = accept(server as usize);
+    assert!(conn >= 0);
+    let mut buf = [0u8; 32];
+    let len = read(conn as usize, &mut buf);
+    assert_eq!(&buf[..len as usize], REQUEST.as_bytes());
+    write(conn as usize, REPLY.as_bytes());
+
+    let mut exit_code: i32 = 0;
+    wait(&mut exit_code);
+    assert_eq!(exit_code, 0);
+    // the client has closed its end, so the next read sees end of file
+    assert_eq!(read(conn as usize, &mut buf), 0);
+    close(conn as usize);
+    close(server as usize);
+
+    // a stream that was never connected has nothing to wait for
+    let unconnected = socket(AF_UNIX, SOCK_STREAM, 0);
+    assert!(unconnected >= 0);
+    assert_eq!(read(unconnected as usize, &mut buf), 0);
+    close(unconnected as usize);
+    println!("unix socket test passed!");
+    0
+}
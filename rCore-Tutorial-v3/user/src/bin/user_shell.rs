@@ -13,7 +13,8 @@ const DL: u8 = 0x7fu8;
 const BS: u8 = 0x08u8;
 
 use alloc::string::String;
-use user_lib::console::getchar;
+use alloc::vec::Vec;
+use user_lib::console::try_getchar;
 use user_lib::{exec, fork, waitpid};
 
 #[no_mangle]
@@ -21,17 +22,29 @@ pub fn main() -> i32 {
     println!("Rust user shell");
     let mut line: String = String::new();
     print!(">> ");
-    loop {
-        let c = getchar();
+    // stdin may be a socket (see telnetd), leave when it is closed
+    while let Some(c) = try_getchar() {
         match c {
             LF | CR => {
                 println!("");
                 if !line.is_empty() {
-                    line.push('\0');
+                    // arguments are separated by spaces, each is passed nul-terminated
+                    let args_copy: Vec<String> = line
+                        .split(' ')
+                        .filter(|arg| !arg.is_empty())
+                        .map(|arg| {
+                            let mut string = String::from(arg);
+                            string.push('\0');
+                            string
+                        })
+                        .collect();
+                    let mut args_addr: Vec<*const u8> =
+                        args_copy.iter().map(|arg| arg.as_ptr()).collect();
+                    args_addr.push(core::ptr::null::<u8>());
                     let pid = fork();
                     if pid == 0 {
                         // child process
-                        if exec(line.as_str()) == -1 {
+                        if args_copy.is_empty() || exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
                             println!("Error when executing!");
                             return -4;
                         }
@@ -60,4 +73,5 @@ pub fn main() -> i32 {
             }
         }
     }
+    0
 }
@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::net::Ipv4Addr;
+use user_lib::vlan::{self, VlanConfig};
+
+const USAGE: &str = "usage: vlan list
+       vlan add ethX.N addr/prefix
+       vlan del ethX.N";
+
+/// Parse `ethX.N` into the parent interface and the VLAN id
+fn parse_name(name: &str) -> Option<(u8, u16)> {
+    let (parent, id) = name.strip_prefix("eth")?.split_once('.')?;
+    Some((parent.parse().ok()?, id.parse().ok()?))
+}
+
+fn parse_cidr(s: &str) -> Option<(Ipv4Addr, u8)> {
+    let (addr, prefix) = s.split_once('/')?;
+    Some((addr.parse().ok()?, prefix.parse().ok()?))
+}
+
+fn list() {
+    for config in vlan::list() {
+        let [a, b, c, d] = config.ip;
+        println!(
+            "eth{}.{}\tinterface {}\t{}/{}",
+            config.parent,
+            config.id,
+            config.interface,
+            Ipv4Addr::new(a, b, c, d),
+            config.prefix
+        );
+    }
+}
+
+// Manage 802.1Q sub-interfaces, each has an address and reaches its subnet
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        println!("{}", USAGE);
+        return -1;
+    }
+    match (argv[1], &argv[2..]) {
+        ("list", []) => {
+            list();
+            0
+        }
+        ("add", [name, cidr]) => match (parse_name(name), parse_cidr(cidr)) {
+            (Some((parent, id)), Some((ip, prefix))) => {
+                let interface = vlan::add(&VlanConfig::new(parent, id, ip.octets(), prefix));
+                if interface < 0 {
+                    println!("[vlan] can't add {}, only root may change interfaces", name);
+                    return -1;
+                }
+                println!("[vlan] {} is interface {}", name, interface);
+                0
+            }
+            _ => {
+                println!("{}", USAGE);
+                -1
+            }
+        },
+        ("del", [name]) => {
+            let found = parse_name(name).and_then(|(parent, id)| {
+                vlan::list().into_iter().find(|config| config.parent == parent && config.id == id)
+            });
+            match found {
+                Some(config) if vlan::delete(config.interface) == 0 => 0,
+                _ => {
+                    println!("[vlan] can't delete {}, it doesn't exist or this isn't root", name);
+                    -1
+                }
+            }
+        }
+        _ => {
+            println!("{}", USAGE);
+            -1
+        }
+    }
+}
@@ -0,0 +1,150 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::net::TcpStream;
+use user_lib::{close, open, write, OpenFlags};
+
+const HTTP_PORT: u16 = 80;
+/// Longest response header we accept
+const HEADER_MAX: usize = 4096;
+
+const USAGE: &str = "usage: wget http://host[:port]/path [file]";
+
+/// Split `http://host[:port]/path` into host, port and path
+fn parse_url(url: &str) -> Option<(&str, u16, &str)> {
+    let rest = url.strip_prefix("http://").unwrap_or(url);
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    match authority.split_once(':') {
+        Some((host, port)) => Some((host, port.parse().ok()?, path)),
+        None => Some((authority, HTTP_PORT, path)),
+    }
+}
+
+fn find_header_end(data: &[u8]) -> Option<usize> {
+    data.windows(4).position(|window| window == b"\r\n\r\n").map(|index| index + 4)
+}
+
+/// Return the status code and the content length, if the server sent one
+fn parse_header(header: &str) -> Option<(u16, Option<usize>)> {
+    let mut lines = header.split("\r\n");
+    let status = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+    let mut content_length = None;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok();
+            } else if name.eq_ignore_ascii_case("transfer-encoding") && value.trim() != "identity" {
+                // chunked bodies are not supported, HTTP/1.0 servers don't use them
+                return None;
+            }
+        }
+    }
+    Some((status, content_length))
+}
+
+// Fetch a URL with HTTP/1.1 and save the body into a file in the root directory
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        println!("{}", USAGE);
+        return -1;
+    }
+    let (host, port, path) = match parse_url(argv[1]) {
+        Some(url) => url,
+        None => {
+            println!("{}", USAGE);
+            return -1;
+        }
+    };
+    let mut local = String::from(match argv.get(2) {
+        Some(file) => *file,
+        None => match path.rsplit('/').next() {
+            Some(name) if !name.is_empty() => name,
+            _ => "index.html",
+        },
+    });
+    local.push('\0');
+
+    let stream = match TcpStream::connect((host, port)) {
+        Ok(stream) => stream,
+        Err(_) => {
+            println!("[wget] can't connect to {}:{}", host, port);
+            return -1;
+        }
+    };
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}:{}\r\nUser-Agent: rCore-wget\r\nConnection: close\r\n\r\n",
+        path, host, port
+    );
+    if stream.write_all(request.as_bytes()).is_err() {
+        println!("[wget] can't send request");
+        return -1;
+    }
+
+    // read until the end of the header, the rest is the start of the body
+    let mut header: Vec<u8> = Vec::new();
+    let mut buf = [0u8; 1024];
+    let body_start = loop {
+        let len = stream.read(&mut buf).unwrap_or(0);
+        if len == 0 {
+            println!("[wget] connection closed before the response header");
+            return -1;
+        }
+        header.extend_from_slice(&buf[..len]);
+        if let Some(end) = find_header_end(&header) {
+            break end;
+        }
+        if header.len() > HEADER_MAX {
+            println!("[wget] response header too long");
+            return -1;
+        }
+    };
+    let (status, content_length) =
+        match parse_header(core::str::from_utf8(&header[..body_start]).unwrap_or("")) {
+            Some(response) => response,
+            None => {
+                println!("[wget] bad response header");
+                return -1;
+            }
+        };
+    if status != 200 {
+        println!("[wget] server returned {}", status);
+        return -1;
+    }
+
+    let fd = open(local.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC);
+    if fd < 0 {
+        println!("[wget] can't create {}", local.trim_end_matches('\0'));
+        return -1;
+    }
+    let fd = fd as usize;
+    let mut total = header.len() - body_start;
+    write(fd, &header[body_start..]);
+    drop(header);
+    while content_length.map_or(true, |length| total < length) {
+        let len = stream.read(&mut buf).unwrap_or(0);
+        if len == 0 {
+            break;
+        }
+        write(fd, &buf[..len]);
+        total += len;
+    }
+    close(fd);
+
+    if content_length.map_or(false, |length| total < length) {
+        println!("[wget] connection closed after {} bytes", total);
+        return -1;
+    }
+    println!("[wget] saved {} bytes to {}", total, local.trim_end_matches('\0'));
+    0
+}
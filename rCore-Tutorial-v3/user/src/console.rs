@@ -37,3 +37,14 @@ pub fn getchar() -> u8 {
     read(STDIN, &mut c);
     c[0]
 }
+
+/// Like [`getchar`], but `None` once stdin is at end of file,
+/// for example when it is a socket whose peer has gone away
+pub fn try_getchar() -> Option<u8> {
+    let mut c = [0u8; 1];
+    if read(STDIN, &mut c) <= 0 {
+        None
+    } else {
+        Some(c[0])
+    }
+}
@@ -0,0 +1,107 @@
+//! Rules of the kernel packet filter, see the `fw` program
+use alloc::vec::Vec;
+
+use super::sys_firewall;
+
+pub const FW_CHAIN_INPUT: u8 = 0;
+pub const FW_CHAIN_OUTPUT: u8 = 1;
+
+pub const FW_ACTION_ACCEPT: u8 = 0;
+pub const FW_ACTION_DROP: u8 = 1;
+pub const FW_ACTION_REJECT: u8 = 2;
+
+/// `interface` of a rule that matches every interface
+pub const FW_ANY_INTERFACE: u8 = 0xff;
+
+const FW_APPEND: usize = 1;
+const FW_DELETE: usize = 2;
+const FW_LIST: usize = 3;
+const FW_FLUSH: usize = 4;
+
+/// Mirror of the kernel's rule. Zero in `protocol` or `family` (4 or 6)
+/// matches anything, addresses are only compared when `family` is set and
+/// IPv4 addresses use the first 4 bytes. Port ranges are inclusive.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FwRule {
+    pub chain: u8,
+    pub action: u8,
+    pub protocol: u8,
+    pub family: u8,
+    pub interface: u8,
+    pub src_prefix: u8,
+    pub dst_prefix: u8,
+    pub _reserved: u8,
+    pub src_addr: [u8; 16],
+    pub dst_addr: [u8; 16],
+    pub sport: [u16; 2],
+    pub dport: [u16; 2],
+    /// packets that matched the rule, filled in by `list`
+    pub hits: u64,
+}
+
+impl FwRule {
+    /// A rule of `chain` that matches every packet
+    pub fn new(chain: u8, action: u8) -> Self {
+        Self {
+            chain,
+            action,
+            protocol: 0,
+            family: 0,
+            interface: FW_ANY_INTERFACE,
+            src_prefix: 0,
+            dst_prefix: 0,
+            _reserved: 0,
+            src_addr: [0; 16],
+            dst_addr: [0; 16],
+            sport: [0, u16::MAX],
+            dport: [0, u16::MAX],
+            hits: 0,
+        }
+    }
+}
+
+/// Insert `rule` before rule `index`, return the index it got
+pub fn insert(index: usize, rule: &FwRule) -> isize {
+    sys_firewall(
+        FW_APPEND,
+        index,
+        rule as *const FwRule as *mut u8,
+        core::mem::size_of::<FwRule>(),
+    )
+}
+
+pub fn append(rule: &FwRule) -> isize {
+    insert(usize::MAX, rule)
+}
+
+pub fn delete(index: usize) -> isize {
+    sys_firewall(FW_DELETE, index, core::ptr::null_mut(), 0)
+}
+
+pub fn flush() -> isize {
+    sys_firewall(FW_FLUSH, 0, core::ptr::null_mut(), 0)
+}
+
+/// All rules in the order they are evaluated
+pub fn list() -> Vec<FwRule> {
+    let mut rules = Vec::new();
+    loop {
+        let capacity = rules.capacity();
+        let count = sys_firewall(
+            FW_LIST,
+            0,
+            rules.as_mut_ptr() as *mut u8,
+            capacity * core::mem::size_of::<FwRule>(),
+        );
+        if count < 0 {
+            return Vec::new();
+        }
+        let count = count as usize;
+        if count <= capacity {
+            unsafe { rules.set_len(count) };
+            return rules;
+        }
+        rules.reserve(count);
+    }
+}
@@ -5,13 +5,17 @@
 
 #[macro_use]
 pub mod console;
+pub mod firewall;
 mod lang_items;
+pub mod net;
+pub mod vlan;
 mod syscall;
 
 extern crate alloc;
 #[macro_use]
 extern crate bitflags;
 
+use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
 use syscall::*;
 
@@ -29,17 +33,31 @@ pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
 
 #[no_mangle]
 #[link_section = ".text.entry"]
-pub extern "C" fn _start() -> ! {
+pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
     unsafe {
         HEAP.lock()
             .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
     }
-    exit(main());
+    let mut v: Vec<&'static str> = Vec::new();
+    for i in 0..argc {
+        let str_start =
+            unsafe { ((argv + i * core::mem::size_of::<usize>()) as *const usize).read_volatile() };
+        let len = (0usize..)
+            .find(|i| unsafe { ((str_start + *i) as *const u8).read_volatile() == 0 })
+            .unwrap();
+        v.push(
+            core::str::from_utf8(unsafe {
+                core::slice::from_raw_parts(str_start as *const u8, len)
+            })
+            .unwrap(),
+        );
+    }
+    exit(main(argc, v.as_slice()));
 }
 
 #[linkage = "weak"]
 #[no_mangle]
-fn main() -> i32 {
+fn main(_argc: usize, _argv: &[&str]) -> i32 {
     panic!("Cannot find main!");
 }
 
@@ -56,9 +74,22 @@ bitflags! {
 pub fn open(path: &str, flags: OpenFlags) -> isize {
     sys_open(path, flags.bits)
 }
+/// Mount the FAT image file `image` on `target`, both nul-terminated
+pub fn mount(image: &str, target: &str) -> isize {
+    sys_mount(image, target)
+}
 pub fn close(fd: usize) -> isize {
     sys_close(fd)
 }
+pub fn pipe(pipe_fd: &mut [usize]) -> isize {
+    sys_pipe(pipe_fd)
+}
+pub fn dup(fd: usize) -> isize {
+    sys_dup(fd)
+}
+pub fn dup2(old_fd: usize, new_fd: usize) -> isize {
+    sys_dup2(old_fd, new_fd)
+}
 pub fn read(fd: usize, buf: &mut [u8]) -> isize {
     sys_read(fd, buf)
 }
@@ -77,15 +108,112 @@ pub fn get_time() -> isize {
 pub fn getpid() -> isize {
     sys_getpid()
 }
+/// Fill `buf` with random bytes from the kernel
+pub fn getrandom(buf: &mut [u8]) -> isize {
+    sys_getrandom(buf, 0)
+}
+pub fn getuid() -> isize {
+    sys_getuid()
+}
+/// Become `uid`, only root (uid 0) may and there is no way back
+pub fn setuid(uid: usize) -> isize {
+    sys_setuid(uid)
+}
 pub fn fork() -> isize {
     sys_fork()
 }
-pub fn exec(path: &str) -> isize {
-    sys_exec(path)
+/// `args` are nul-terminated strings, the last pointer must be null
+pub fn exec(path: &str, args: &[*const u8]) -> isize {
+    sys_exec(path, args)
 }
 pub fn connect(ip: u32, sport: u16, dport: u16) -> isize {
     sys_connect(ip, sport, dport)
 }
+pub fn connect6(ip: &[u8; 16], sport: u16, dport: u16) -> isize {
+    sys_connect6(ip, sport, dport)
+}
+
+pub const AF_PACKET: usize = 17;
+pub const SOCK_RAW: usize = 3;
+pub const ETH_P_ALL: u16 = 0x0003;
+
+pub const AF_INET: usize = 2;
+pub const AF_INET6: usize = 10;
+
+/// For `AF_PACKET`/`SOCK_RAW`, `protocol` is the ethertype to receive, or
+/// `ETH_P_ALL`, and reads and writes carry whole ethernet frames. See
+/// [`net`] for typed inet sockets.
+pub fn socket(domain: usize, socket_type: usize, protocol: usize) -> isize {
+    sys_socket(domain, socket_type, protocol)
+}
+
+pub const AF_UNIX: usize = 1;
+pub const SOCK_STREAM: usize = 1;
+pub const SOCK_DGRAM: usize = 2;
+
+/// `struct sockaddr_un`, a local socket named by a path in the root directory
+#[repr(C)]
+pub struct SockAddrUn {
+    pub family: u16,
+    pub path: [u8; 108],
+}
+
+impl SockAddrUn {
+    pub fn new(path: &str) -> Self {
+        let mut addr = Self {
+            family: AF_UNIX as u16,
+            path: [0; 108],
+        };
+        let len = path.len().min(addr.path.len() - 1);
+        addr.path[..len].copy_from_slice(&path.as_bytes()[..len]);
+        addr
+    }
+}
+
+pub fn bind<T>(fd: usize, addr: &T) -> isize {
+    sys_bind(fd, addr as *const T as *const u8, core::mem::size_of::<T>())
+}
+pub fn listen(fd: usize, backlog: usize) -> isize {
+    sys_listen(fd, backlog)
+}
+/// Wait for a connection on a listening socket, return the new fd
+pub fn accept(fd: usize) -> isize {
+    sys_accept(fd, core::ptr::null_mut(), core::ptr::null_mut())
+}
+/// Like [`accept`], the address of the peer is written to `addr`
+pub fn accept_from(fd: usize, addr: &mut [u8], addrlen: &mut u32) -> isize {
+    *addrlen = addr.len() as u32;
+    sys_accept(fd, addr.as_mut_ptr(), addrlen as *mut u32)
+}
+pub fn sock_connect<T>(fd: usize, addr: &T) -> isize {
+    sys_sock_connect(fd, addr as *const T as *const u8, core::mem::size_of::<T>())
+}
+pub fn sendto<T>(fd: usize, buf: &[u8], addr: &T) -> isize {
+    sys_sendto(fd, buf, 0, addr as *const T as *const u8, core::mem::size_of::<T>())
+}
+pub fn shutdown(fd: usize) -> isize {
+    sys_shutdown(fd)
+}
+
+pub const IPPROTO_IP: usize = 0;
+pub const IP_ADD_MEMBERSHIP: usize = 35;
+pub const IP_DROP_MEMBERSHIP: usize = 36;
+
+/// `struct ip_mreq`, both addresses in network byte order
+#[repr(C)]
+pub struct IpMreq {
+    pub multiaddr: [u8; 4],
+    pub interface: [u8; 4],
+}
+
+pub fn setsockopt<T>(fd: usize, level: usize, name: usize, value: &T) -> isize {
+    sys_setsockopt(fd, level, name, value as *const T as *const u8, core::mem::size_of::<T>())
+}
+/// Receive one datagram, the sender's address is written to `addr`
+pub fn recvfrom(fd: usize, buf: &mut [u8], addr: &mut [u8], addrlen: &mut u32) -> isize {
+    *addrlen = addr.len() as u32;
+    sys_recvfrom(fd, buf, 0, addr.as_mut_ptr(), addrlen as *mut u32)
+}
 pub fn wait(exit_code: &mut i32) -> isize {
     loop {
         match sys_waitpid(-1, exit_code as *mut _) {
@@ -109,6 +237,11 @@ pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
         }
     }
 }
+/// Like [`waitpid`], but return -2 right away if the child is still running.
+/// A `pid` of -1 waits for any child.
+pub fn try_waitpid(pid: isize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid, exit_code as *mut _)
+}
 pub fn sleep(period_ms: usize) {
     let start = sys_get_time();
     while sys_get_time() < start + period_ms as isize {
@@ -0,0 +1,555 @@
+//! Networking in the style of `std::net`
+//!
+//! The types wrap the socket syscalls and close their fd when dropped.
+//! Addresses are converted through [`ToSocketAddrs`], so a listener can be
+//! bound with `TcpListener::bind("0.0.0.0:80")` just like with std.
+use core::fmt;
+use core::str::FromStr;
+
+use super::{
+    accept_from, bind, close, listen, read, recvfrom, sendto, setsockopt, shutdown, sock_connect, socket, write,
+    IpMreq, AF_INET, AF_INET6, IPPROTO_IP, IP_ADD_MEMBERSHIP, IP_DROP_MEMBERSHIP, SOCK_DGRAM, SOCK_STREAM,
+};
+
+/// Errors of this module, syscall failures keep the kernel's return value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    InvalidAddress,
+    Syscall(isize),
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+fn check(ret: isize) -> Result<usize> {
+    if ret < 0 {
+        Err(Error::Syscall(ret))
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddrParseError;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Ipv4Addr([u8; 4]);
+
+impl Ipv4Addr {
+    pub const UNSPECIFIED: Self = Self([0, 0, 0, 0]);
+    pub const LOCALHOST: Self = Self([127, 0, 0, 1]);
+    pub const BROADCAST: Self = Self([255, 255, 255, 255]);
+
+    pub const fn new(a: u8, b: u8, c: u8, d: u8) -> Self {
+        Self([a, b, c, d])
+    }
+
+    pub fn octets(&self) -> [u8; 4] {
+        self.0
+    }
+
+    /// 224.0.0.0/4
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] >> 4 == 0xe
+    }
+}
+
+impl From<u32> for Ipv4Addr {
+    fn from(ip: u32) -> Self {
+        Self(ip.to_be_bytes())
+    }
+}
+
+impl From<Ipv4Addr> for u32 {
+    fn from(ip: Ipv4Addr) -> Self {
+        u32::from_be_bytes(ip.0)
+    }
+}
+
+impl FromStr for Ipv4Addr {
+    type Err = AddrParseError;
+
+    /// Parse dotted decimal, like `10.0.2.2`
+    fn from_str(s: &str) -> core::result::Result<Self, AddrParseError> {
+        let mut octets = [0u8; 4];
+        let mut parts = s.split('.');
+        for octet in octets.iter_mut() {
+            let part = parts.next().ok_or(AddrParseError)?;
+            if part.is_empty() || part.len() > 3 || !part.bytes().all(|c| c.is_ascii_digit()) {
+                return Err(AddrParseError);
+            }
+            *octet = part.parse().map_err(|_| AddrParseError)?;
+        }
+        if parts.next().is_some() {
+            return Err(AddrParseError);
+        }
+        Ok(Self(octets))
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.0[0], self.0[1], self.0[2], self.0[3])
+    }
+}
+
+impl fmt::Debug for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Ipv6Addr([u8; 16]);
+
+impl Ipv6Addr {
+    pub const UNSPECIFIED: Self = Self([0; 16]);
+
+    /// Build an address from its eight 16-bit segments
+    pub const fn new(a: u16, b: u16, c: u16, d: u16, e: u16, f: u16, g: u16, h: u16) -> Self {
+        let segments = [a, b, c, d, e, f, g, h];
+        let mut octets = [0u8; 16];
+        let mut i = 0;
+        while i < 8 {
+            octets[i * 2] = (segments[i] >> 8) as u8;
+            octets[i * 2 + 1] = segments[i] as u8;
+            i += 1;
+        }
+        Self(octets)
+    }
+
+    pub fn octets(&self) -> [u8; 16] {
+        self.0
+    }
+
+    pub fn segments(&self) -> [u16; 8] {
+        let mut segments = [0u16; 8];
+        for (i, segment) in segments.iter_mut().enumerate() {
+            *segment = u16::from_be_bytes([self.0[i * 2], self.0[i * 2 + 1]]);
+        }
+        segments
+    }
+}
+
+impl From<[u8; 16]> for Ipv6Addr {
+    fn from(octets: [u8; 16]) -> Self {
+        Self(octets)
+    }
+}
+
+impl FromStr for Ipv6Addr {
+    type Err = AddrParseError;
+
+    /// Parse eight hex segments separated by `:`, a run of zero segments
+    /// may be written as `::` once
+    fn from_str(s: &str) -> core::result::Result<Self, AddrParseError> {
+        fn parse_segments(s: &str, segments: &mut [u16; 8]) -> core::result::Result<usize, AddrParseError> {
+            if s.is_empty() {
+                return Ok(0);
+            }
+            let mut count = 0;
+            for part in s.split(':') {
+                if count == 8 || part.is_empty() || part.len() > 4 {
+                    return Err(AddrParseError);
+                }
+                segments[count] = u16::from_str_radix(part, 16).map_err(|_| AddrParseError)?;
+                count += 1;
+            }
+            Ok(count)
+        }
+
+        let mut segments = [0u16; 8];
+        match s.split_once("::") {
+            Some((head, tail)) => {
+                let mut tail_segments = [0u16; 8];
+                let head_len = parse_segments(head, &mut segments)?;
+                let tail_len = parse_segments(tail, &mut tail_segments)?;
+                if head_len + tail_len > 7 {
+                    return Err(AddrParseError);
+                }
+                segments[8 - tail_len..].copy_from_slice(&tail_segments[..tail_len]);
+            }
+            None => {
+                if parse_segments(s, &mut segments)? != 8 {
+                    return Err(AddrParseError);
+                }
+            }
+        }
+        let [a, b, c, d, e, f, g, h] = segments;
+        Ok(Self::new(a, b, c, d, e, f, g, h))
+    }
+}
+
+impl fmt::Display for Ipv6Addr {
+    // full form without `::` compression
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let segments = self.segments();
+        for (i, segment) in segments.iter().enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            write!(f, "{:x}", segment)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Ipv6Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketAddrV4 {
+    ip: Ipv4Addr,
+    port: u16,
+}
+
+impl SocketAddrV4 {
+    pub const fn new(ip: Ipv4Addr, port: u16) -> Self {
+        Self { ip, port }
+    }
+    pub fn ip(&self) -> &Ipv4Addr {
+        &self.ip
+    }
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl FromStr for SocketAddrV4 {
+    type Err = AddrParseError;
+
+    /// Parse `a.b.c.d:port`
+    fn from_str(s: &str) -> core::result::Result<Self, AddrParseError> {
+        let (ip, port) = s.rsplit_once(':').ok_or(AddrParseError)?;
+        Ok(Self::new(ip.parse()?, port.parse().map_err(|_| AddrParseError)?))
+    }
+}
+
+impl fmt::Display for SocketAddrV4 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.ip, self.port)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketAddrV6 {
+    ip: Ipv6Addr,
+    port: u16,
+}
+
+impl SocketAddrV6 {
+    pub const fn new(ip: Ipv6Addr, port: u16) -> Self {
+        Self { ip, port }
+    }
+    pub fn ip(&self) -> &Ipv6Addr {
+        &self.ip
+    }
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl fmt::Display for SocketAddrV6 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}]:{}", self.ip, self.port)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketAddr {
+    V4(SocketAddrV4),
+    V6(SocketAddrV6),
+}
+
+/// `struct sockaddr_in` and `struct sockaddr_in6`, family in host byte order,
+/// port and address in network byte order
+const SOCKADDR_IN_LEN: usize = 16;
+const SOCKADDR_IN6_LEN: usize = 28;
+
+impl SocketAddr {
+    pub fn port(&self) -> u16 {
+        match self {
+            SocketAddr::V4(addr) => addr.port,
+            SocketAddr::V6(addr) => addr.port,
+        }
+    }
+
+    fn domain(&self) -> usize {
+        match self {
+            SocketAddr::V4(_) => AF_INET,
+            SocketAddr::V6(_) => AF_INET6,
+        }
+    }
+
+    // the raw sockaddr is always passed with the size of the larger one,
+    // the kernel only looks at what the family needs
+    fn to_raw(self) -> [u8; SOCKADDR_IN6_LEN] {
+        let mut raw = [0u8; SOCKADDR_IN6_LEN];
+        raw[0..2].copy_from_slice(&(self.domain() as u16).to_ne_bytes());
+        raw[2..4].copy_from_slice(&self.port().to_be_bytes());
+        match self {
+            SocketAddr::V4(addr) => raw[4..8].copy_from_slice(&addr.ip.0),
+            SocketAddr::V6(addr) => raw[8..24].copy_from_slice(&addr.ip.0),
+        }
+        raw
+    }
+
+    fn from_raw(raw: &[u8]) -> Option<Self> {
+        if raw.len() < 4 {
+            return None;
+        }
+        let port = u16::from_be_bytes([raw[2], raw[3]]);
+        match u16::from_ne_bytes([raw[0], raw[1]]) as usize {
+            AF_INET if raw.len() >= SOCKADDR_IN_LEN => {
+                let ip = Ipv4Addr([raw[4], raw[5], raw[6], raw[7]]);
+                Some(SocketAddr::V4(SocketAddrV4::new(ip, port)))
+            }
+            AF_INET6 if raw.len() >= SOCKADDR_IN6_LEN => {
+                let mut ip = [0u8; 16];
+                ip.copy_from_slice(&raw[8..24]);
+                Some(SocketAddr::V6(SocketAddrV6::new(Ipv6Addr(ip), port)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for SocketAddr {
+    type Err = AddrParseError;
+
+    fn from_str(s: &str) -> core::result::Result<Self, AddrParseError> {
+        s.parse().map(SocketAddr::V4)
+    }
+}
+
+impl fmt::Display for SocketAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SocketAddr::V4(addr) => fmt::Display::fmt(addr, f),
+            SocketAddr::V6(addr) => fmt::Display::fmt(addr, f),
+        }
+    }
+}
+
+/// Things that name a socket address, std's trait minus name resolution
+pub trait ToSocketAddrs {
+    fn to_socket_addr(&self) -> Result<SocketAddr>;
+}
+
+impl ToSocketAddrs for SocketAddr {
+    fn to_socket_addr(&self) -> Result<SocketAddr> {
+        Ok(*self)
+    }
+}
+
+impl ToSocketAddrs for SocketAddrV4 {
+    fn to_socket_addr(&self) -> Result<SocketAddr> {
+        Ok(SocketAddr::V4(*self))
+    }
+}
+
+impl ToSocketAddrs for SocketAddrV6 {
+    fn to_socket_addr(&self) -> Result<SocketAddr> {
+        Ok(SocketAddr::V6(*self))
+    }
+}
+
+impl ToSocketAddrs for (Ipv4Addr, u16) {
+    fn to_socket_addr(&self) -> Result<SocketAddr> {
+        Ok(SocketAddr::V4(SocketAddrV4::new(self.0, self.1)))
+    }
+}
+
+impl ToSocketAddrs for (Ipv6Addr, u16) {
+    fn to_socket_addr(&self) -> Result<SocketAddr> {
+        Ok(SocketAddr::V6(SocketAddrV6::new(self.0, self.1)))
+    }
+}
+
+impl ToSocketAddrs for (&str, u16) {
+    fn to_socket_addr(&self) -> Result<SocketAddr> {
+        let ip: Ipv4Addr = self.0.parse().map_err(|_| Error::InvalidAddress)?;
+        Ok(SocketAddr::V4(SocketAddrV4::new(ip, self.1)))
+    }
+}
+
+impl ToSocketAddrs for str {
+    fn to_socket_addr(&self) -> Result<SocketAddr> {
+        self.parse().map_err(|_| Error::InvalidAddress)
+    }
+}
+
+impl<T: ToSocketAddrs + ?Sized> ToSocketAddrs for &T {
+    fn to_socket_addr(&self) -> Result<SocketAddr> {
+        (**self).to_socket_addr()
+    }
+}
+
+/// An open socket fd, closed on drop
+struct Fd(usize);
+
+impl Fd {
+    fn socket(addr: &SocketAddr, socket_type: usize) -> Result<Self> {
+        check(socket(addr.domain(), socket_type, 0)).map(Fd)
+    }
+}
+
+impl Drop for Fd {
+    fn drop(&mut self) {
+        close(self.0);
+    }
+}
+
+pub struct TcpStream {
+    fd: Fd,
+    peer: SocketAddr,
+}
+
+impl TcpStream {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let peer = addr.to_socket_addr()?;
+        let fd = Fd::socket(&peer, SOCK_STREAM)?;
+        check(sock_connect(fd.0, &peer.to_raw()))?;
+        Ok(Self { fd, peer })
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// The underlying fd, for example to `dup2` it over stdin
+    pub fn as_raw_fd(&self) -> usize {
+        self.fd.0
+    }
+
+    /// Read what has arrived, blocking until there is something.
+    /// `Ok(0)` means the peer closed the connection.
+    pub fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        check(read(self.fd.0, buf))
+    }
+
+    pub fn write(&self, buf: &[u8]) -> Result<usize> {
+        check(write(self.fd.0, buf))
+    }
+
+    pub fn write_all(&self, mut buf: &[u8]) -> Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(Error::Syscall(0)),
+                len => buf = &buf[len..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Close the sending side, the peer reads end of file
+    pub fn shutdown(&self) -> Result<()> {
+        check(shutdown(self.fd.0)).map(|_| ())
+    }
+}
+
+pub struct TcpListener {
+    fd: Fd,
+    local: SocketAddr,
+}
+
+/// Connections waiting for `accept`
+const LISTEN_BACKLOG: usize = 8;
+
+impl TcpListener {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let local = addr.to_socket_addr()?;
+        let fd = Fd::socket(&local, SOCK_STREAM)?;
+        check(bind(fd.0, &local.to_raw()))?;
+        check(listen(fd.0, LISTEN_BACKLOG))?;
+        Ok(Self { fd, local })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+
+    pub fn as_raw_fd(&self) -> usize {
+        self.fd.0
+    }
+
+    /// Block until a client connects
+    pub fn accept(&self) -> Result<(TcpStream, SocketAddr)> {
+        let mut raw = [0u8; SOCKADDR_IN6_LEN];
+        let mut len = 0;
+        let fd = Fd(check(accept_from(self.fd.0, &mut raw, &mut len))?);
+        let peer = SocketAddr::from_raw(&raw[..(len as usize).min(raw.len())]).ok_or(Error::InvalidAddress)?;
+        Ok((TcpStream { fd, peer }, peer))
+    }
+}
+
+pub struct UdpSocket {
+    fd: Fd,
+}
+
+impl UdpSocket {
+    /// Bind to a local port, port 0 picks a free one
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let local = addr.to_socket_addr()?;
+        let fd = Fd::socket(&local, SOCK_DGRAM)?;
+        check(bind(fd.0, &local.to_raw()))?;
+        Ok(Self { fd })
+    }
+
+    pub fn as_raw_fd(&self) -> usize {
+        self.fd.0
+    }
+
+    pub fn send_to<A: ToSocketAddrs>(&self, buf: &[u8], addr: A) -> Result<usize> {
+        let addr = addr.to_socket_addr()?;
+        check(sendto(self.fd.0, buf, &addr.to_raw()))
+    }
+
+    /// Block for one datagram, the part that doesn't fit in `buf` is lost
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        let mut raw = [0u8; SOCKADDR_IN6_LEN];
+        let mut len = 0;
+        let size = check(recvfrom(self.fd.0, buf, &mut raw, &mut len))?;
+        let source = SocketAddr::from_raw(&raw[..(len as usize).min(raw.len())]).ok_or(Error::InvalidAddress)?;
+        Ok((size, source))
+    }
+
+    /// Set the default peer of `send` and only receive from it
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> Result<()> {
+        let addr = addr.to_socket_addr()?;
+        check(sock_connect(self.fd.0, &addr.to_raw())).map(|_| ())
+    }
+
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        check(write(self.fd.0, buf))
+    }
+
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        check(read(self.fd.0, buf))
+    }
+
+    /// Wake up everyone blocked in `recv`, which then returns `Ok(0)`
+    pub fn shutdown(&self) -> Result<()> {
+        check(shutdown(self.fd.0)).map(|_| ())
+    }
+
+    /// Receive what is sent to `multiaddr` on the bound port, the kernel
+    /// has one interface so `interface` is ignored
+    pub fn join_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> Result<()> {
+        self.membership(IP_ADD_MEMBERSHIP, multiaddr, interface)
+    }
+
+    pub fn leave_multicast_v4(&self, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> Result<()> {
+        self.membership(IP_DROP_MEMBERSHIP, multiaddr, interface)
+    }
+
+    fn membership(&self, name: usize, multiaddr: &Ipv4Addr, interface: &Ipv4Addr) -> Result<()> {
+        let mreq = IpMreq {
+            multiaddr: multiaddr.octets(),
+            interface: interface.octets(),
+        };
+        check(setsockopt(self.fd.0, IPPROTO_IP, name, &mreq)).map(|_| ())
+    }
+}
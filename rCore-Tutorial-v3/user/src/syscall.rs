@@ -1,18 +1,37 @@
 use core::arch::asm;
 
+const SYSCALL_DUP: usize = 23;
+const SYSCALL_DUP2: usize = 24;
+const SYSCALL_MOUNT: usize = 40;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_PIPE: usize = 59;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_SETUID: usize = 146;
 const SYSCALL_YIELD: usize = 124;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETUID: usize = 174;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_GETRANDOM: usize = 278;
 
 const SYSCALL_CONNECT: usize = 29;
+const SYSCALL_CONNECT6: usize = 30;
+const SYSCALL_FIREWALL: usize = 31;
+const SYSCALL_VLAN: usize = 32;
+const SYSCALL_SOCKET: usize = 41;
+const SYSCALL_SOCK_CONNECT: usize = 42;
+const SYSCALL_ACCEPT: usize = 43;
+const SYSCALL_SENDTO: usize = 44;
+const SYSCALL_RECVFROM: usize = 45;
+const SYSCALL_SHUTDOWN: usize = 48;
+const SYSCALL_BIND: usize = 49;
+const SYSCALL_LISTEN: usize = 50;
+const SYSCALL_SETSOCKOPT: usize = 54;
 
 fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -28,14 +47,47 @@ fn syscall(id: usize, args: [usize; 3]) -> isize {
     ret
 }
 
+fn syscall6(id: usize, args: [usize; 6]) -> isize {
+    let mut ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") args[0] => ret,
+            in("x11") args[1],
+            in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
+            in("x17") id
+        );
+    }
+    ret
+}
+
 pub fn sys_open(path: &str, flags: u32) -> isize {
     syscall(SYSCALL_OPEN, [path.as_ptr() as usize, flags as usize, 0])
 }
 
+pub fn sys_mount(image: &str, target: &str) -> isize {
+    syscall(SYSCALL_MOUNT, [image.as_ptr() as usize, target.as_ptr() as usize, 0])
+}
+
 pub fn sys_close(fd: usize) -> isize {
     syscall(SYSCALL_CLOSE, [fd, 0, 0])
 }
 
+pub fn sys_dup(fd: usize) -> isize {
+    syscall(SYSCALL_DUP, [fd, 0, 0])
+}
+
+pub fn sys_dup2(old_fd: usize, new_fd: usize) -> isize {
+    syscall(SYSCALL_DUP2, [old_fd, new_fd, 0])
+}
+
+pub fn sys_pipe(pipe: &mut [usize]) -> isize {
+    syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
+}
+
 pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
     syscall(
         SYSCALL_READ,
@@ -64,18 +116,87 @@ pub fn sys_getpid() -> isize {
     syscall(SYSCALL_GETPID, [0, 0, 0])
 }
 
+pub fn sys_getuid() -> isize {
+    syscall(SYSCALL_GETUID, [0, 0, 0])
+}
+
+pub fn sys_setuid(uid: usize) -> isize {
+    syscall(SYSCALL_SETUID, [uid, 0, 0])
+}
+
 pub fn sys_fork() -> isize {
     syscall(SYSCALL_FORK, [0, 0, 0])
 }
 
-pub fn sys_exec(path: &str) -> isize {
-    syscall(SYSCALL_EXEC, [path.as_ptr() as usize, 0, 0])
+pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
+    syscall(
+        SYSCALL_EXEC,
+        [path.as_ptr() as usize, args.as_ptr() as usize, 0],
+    )
 }
 
 pub fn sys_waitpid(pid: isize, exit_code: *mut i32) -> isize {
     syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, 0])
 }
 
+pub fn sys_getrandom(buf: &mut [u8], flags: u32) -> isize {
+    syscall(SYSCALL_GETRANDOM, [buf.as_mut_ptr() as usize, buf.len(), flags as usize])
+}
+
 pub fn sys_connect(dest: u32, sport: u16, dport: u16) -> isize {
     syscall(SYSCALL_CONNECT, [dest as usize, sport as usize, dport as usize])
-}
\ No newline at end of file
+}
+
+pub fn sys_connect6(dest: &[u8; 16], sport: u16, dport: u16) -> isize {
+    syscall(SYSCALL_CONNECT6, [dest.as_ptr() as usize, sport as usize, dport as usize])
+}
+
+pub fn sys_socket(domain: usize, socket_type: usize, protocol: usize) -> isize {
+    syscall(SYSCALL_SOCKET, [domain, socket_type, protocol])
+}
+
+pub fn sys_bind(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    syscall(SYSCALL_BIND, [fd, addr as usize, addrlen])
+}
+
+pub fn sys_listen(fd: usize, backlog: usize) -> isize {
+    syscall(SYSCALL_LISTEN, [fd, backlog, 0])
+}
+
+pub fn sys_accept(fd: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    syscall(SYSCALL_ACCEPT, [fd, addr as usize, addrlen as usize])
+}
+
+pub fn sys_sock_connect(fd: usize, addr: *const u8, addrlen: usize) -> isize {
+    syscall(SYSCALL_SOCK_CONNECT, [fd, addr as usize, addrlen])
+}
+
+pub fn sys_sendto(fd: usize, buffer: &[u8], flags: usize, addr: *const u8, addrlen: usize) -> isize {
+    syscall6(
+        SYSCALL_SENDTO,
+        [fd, buffer.as_ptr() as usize, buffer.len(), flags, addr as usize, addrlen],
+    )
+}
+
+pub fn sys_recvfrom(fd: usize, buffer: &mut [u8], flags: usize, addr: *mut u8, addrlen: *mut u32) -> isize {
+    syscall6(
+        SYSCALL_RECVFROM,
+        [fd, buffer.as_mut_ptr() as usize, buffer.len(), flags, addr as usize, addrlen as usize],
+    )
+}
+
+pub fn sys_shutdown(fd: usize) -> isize {
+    syscall(SYSCALL_SHUTDOWN, [fd, 0, 0])
+}
+
+pub fn sys_firewall(op: usize, index: usize, rule: *mut u8, len: usize) -> isize {
+    syscall6(SYSCALL_FIREWALL, [op, index, rule as usize, len, 0, 0])
+}
+
+pub fn sys_setsockopt(fd: usize, level: usize, name: usize, value: *const u8, len: usize) -> isize {
+    syscall6(SYSCALL_SETSOCKOPT, [fd, level, name, value as usize, len, 0])
+}
+
+pub fn sys_vlan(op: usize, interface: usize, config: *mut u8, len: usize) -> isize {
+    syscall6(SYSCALL_VLAN, [op, interface, config as usize, len, 0, 0])
+}
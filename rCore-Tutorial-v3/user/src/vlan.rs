@@ -0,0 +1,70 @@
+//! VLAN sub-interfaces of the kernel, see the `vlan` program
+use alloc::vec::Vec;
+
+use super::sys_vlan;
+
+const VLAN_ADD: usize = 1;
+const VLAN_DELETE: usize = 2;
+const VLAN_LIST: usize = 3;
+
+/// Mirror of the kernel's configuration of `eth<parent>.<id>`, `ip` is in
+/// network byte order and `interface` is filled in by the kernel
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct VlanConfig {
+    pub parent: u8,
+    pub interface: u8,
+    pub id: u16,
+    pub ip: [u8; 4],
+    pub prefix: u8,
+    pub _reserved: [u8; 3],
+}
+
+impl VlanConfig {
+    pub fn new(parent: u8, id: u16, ip: [u8; 4], prefix: u8) -> Self {
+        Self {
+            parent,
+            interface: 0,
+            id,
+            ip,
+            prefix,
+            _reserved: [0; 3],
+        }
+    }
+}
+
+/// Create a sub-interface, return its interface number
+pub fn add(config: &VlanConfig) -> isize {
+    sys_vlan(
+        VLAN_ADD,
+        0,
+        config as *const VlanConfig as *mut u8,
+        core::mem::size_of::<VlanConfig>(),
+    )
+}
+
+pub fn delete(interface: u8) -> isize {
+    sys_vlan(VLAN_DELETE, interface as usize, core::ptr::null_mut(), 0)
+}
+
+pub fn list() -> Vec<VlanConfig> {
+    let mut configs = Vec::new();
+    loop {
+        let capacity = configs.capacity();
+        let count = sys_vlan(
+            VLAN_LIST,
+            0,
+            configs.as_mut_ptr() as *mut u8,
+            capacity * core::mem::size_of::<VlanConfig>(),
+        );
+        if count < 0 {
+            return Vec::new();
+        }
+        let count = count as usize;
+        if count <= capacity {
+            unsafe { configs.set_len(count) };
+            return configs;
+        }
+        configs.reserve(count);
+    }
+}